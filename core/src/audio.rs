@@ -1,6 +1,8 @@
 pub mod capture;
 pub mod channel;
 mod io;
+pub mod sdat;
+pub mod sinc;
 
 use crate::{
     cpu::{self, arm7, Schedule as _},
@@ -29,6 +31,7 @@ proc_bitfield::bitfield! {
 pub enum ChannelInterpMethod {
     Nearest,
     Cubic,
+    Sinc,
 }
 
 type RawChannelSample = i32;
@@ -52,6 +55,39 @@ const CYCLES_PER_SAMPLE: RawTimestamp = 1024;
 // Default to at most 15.625 ms of audio, assuming the default sample rate
 pub const DEFAULT_OUTPUT_SAMPLE_CHUNK_SIZE: u16 = 0x200;
 
+#[inline]
+fn pan_raw_sample(sample: RawChannelSample, pan: u8) -> [RawMixerInterpSample; 2] {
+    let sample = sample as RawMixerInterpSample;
+    let l_vol = (128 - pan) as RawMixerInterpSample;
+    let r_vol = pan as RawMixerInterpSample;
+    [(sample * l_vol) >> 10, (sample * r_vol) >> 10]
+}
+
+#[cfg(not(feature = "xq-audio"))]
+#[inline]
+fn raw_mixer_sample_to_output(
+    sample: RawMixerInterpSample,
+    master_volume: u8,
+    bias: u16,
+) -> OutputSample {
+    (((sample * master_volume as RawMixerInterpSample) >> 21) + bias as RawMixerInterpSample)
+        .clamp(0, 0x3FF) as OutputSample
+}
+
+// Equivalent to the regular integer output path, scaled to the same range as XQ audio output
+#[cfg(feature = "xq-audio")]
+#[inline]
+fn raw_mixer_sample_to_output(
+    sample: RawMixerInterpSample,
+    master_volume: u8,
+    bias: u16,
+) -> OutputSample {
+    #[allow(clippy::cast_precision_loss)]
+    let sample = (sample * master_volume as RawMixerInterpSample) as InterpSample
+        * (1.0 / (1 << 30) as InterpSample);
+    ((sample + bias as InterpSample * (1.0 / 512.0)).clamp(0.0, 2.0) - 1.0) as OutputSample
+}
+
 pub trait Backend {
     #[allow(clippy::ptr_arg)] // Intended behavior, the Vec gets drained
     fn handle_sample_chunk(&mut self, samples: &mut Vec<[OutputSample; 2]>);
@@ -68,7 +104,24 @@ impl Backend for DummyBackend {
 #[cfg(feature = "channel-audio-capture")]
 pub struct ChannelAudioCaptureData {
     pub mask: u16,
+    // Kept separate from `mask` so that exporting channels doesn't interfere with the channels
+    // selected for debugging
+    pub export_mask: u16,
     pub buffers: [Vec<i16>; 16],
+    // The final output (at the output sample rate) and the samples written to memory by the
+    // capture units (at their timer rates) can also be captured for debugging
+    pub output_enabled: bool,
+    pub output_buffer: Vec<[OutputSample; 2]>,
+    pub capture_unit_mask: u8,
+    pub capture_unit_buffers: [Vec<i16>; 2],
+}
+
+#[cfg(feature = "channel-audio-capture")]
+impl ChannelAudioCaptureData {
+    #[inline]
+    fn is_capturing(&self, i: usize) -> bool {
+        (self.mask | self.export_mask) & 1 << i != 0
+    }
 }
 
 #[derive(Savestate)]
@@ -83,6 +136,11 @@ pub struct Audio {
     sample_chunk: Vec<[OutputSample; 2]>,
     #[savestate(skip)]
     pub sample_chunk_size: u16,
+    // Only affect the final output, the mixer output seen by capture units stays unchanged
+    #[savestate(skip)]
+    pub channel_mute_mask: u16,
+    #[savestate(skip)]
+    pub channel_solo_mask: u16,
     pub channels: [Channel; 16],
     pub capture: [CaptureUnit; 2],
     control: Control,
@@ -142,6 +200,8 @@ impl Audio {
             backend,
             sample_chunk: Vec::with_capacity(sample_chunk_size as usize),
             sample_chunk_size,
+            channel_mute_mask: 0,
+            channel_solo_mask: 0,
             channels,
             capture: [CaptureUnit::new(), CaptureUnit::new()],
             control: Control(0),
@@ -167,7 +227,12 @@ impl Audio {
                 }
                 ChannelAudioCaptureData {
                     mask: 0,
+                    export_mask: 0,
                     buffers: buffers!(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15),
+                    output_enabled: false,
+                    output_buffer: Vec::new(),
+                    capture_unit_mask: 0,
+                    capture_unit_buffers: [Vec::new(), Vec::new()],
                 }
             },
         }
@@ -177,6 +242,15 @@ impl Audio {
         self.sample_chunk.clear();
     }
 
+    #[inline]
+    fn silenced_channel_mask(&self) -> u16 {
+        if self.channel_solo_mask != 0 {
+            !self.channel_solo_mask | self.channel_mute_mask
+        } else {
+            self.channel_mute_mask
+        }
+    }
+
     cfg_if::cfg_if! {
         if #[cfg(feature = "xq-audio")] {
             pub(super) fn update_next_scaled_sample_index<E: cpu::Engine>(emu: &mut Emu<E>) {
@@ -259,23 +333,15 @@ impl Audio {
 
         #[allow(unused_variables)]
         let output = if emu.audio.control.master_enable() {
-            fn raw_channel_sample_to_i16(sample: RawChannelSample) -> i16 {
-                (sample >> 11) as i16
-            }
-
-            fn raw_mixer_interp_sample_to_i32(sample: RawMixerInterpSample) -> i32 {
-                (sample >> 8) as i32
-            }
-
             macro_rules! channel_output {
                 ($i: expr$(, |$ident: ident| $code: expr)?) => {
                     if emu.audio.channels[$i].control().running() {
                         Channel::run::<_, true>(emu, channel::Index::new($i as u8), time);
                         let sample = emu.audio.channels[$i].raw_output();
                         #[cfg(feature = "channel-audio-capture")]
-                        if emu.audio.channel_audio_capture_data.mask & 1 << $i != 0 {
+                        if emu.audio.channel_audio_capture_data.is_capturing($i) {
                             emu.audio.channel_audio_capture_data.buffers[$i].push(
-                                raw_channel_sample_to_i16(sample),
+                                capture::raw_channel_sample_to_i16(sample),
                             );
                         }
                         #[allow(path_statements)]
@@ -289,7 +355,7 @@ impl Audio {
                         }
                     } else {
                         #[cfg(feature = "channel-audio-capture")]
-                        if emu.audio.channel_audio_capture_data.mask & 1 << $i != 0 {
+                        if emu.audio.channel_audio_capture_data.is_capturing($i) {
                             emu.audio.channel_audio_capture_data.buffers[$i].push(0);
                         }
                         Default::default()
@@ -298,30 +364,33 @@ impl Audio {
             }
 
             macro_rules! pan {
-                ($sample: expr, $i: expr) => {{
-                    let sample = $sample as RawMixerInterpSample;
-                    let r_vol = emu.audio.channels[$i].pan();
-                    let l_vol = (128 - r_vol) as RawMixerInterpSample;
-                    let r_vol = r_vol as RawMixerInterpSample;
-                    [(sample * l_vol) >> 10, (sample * r_vol) >> 10]
-                }};
+                ($sample: expr, $i: expr) => {
+                    pan_raw_sample($sample, emu.audio.channels[$i].pan())
+                };
             }
 
+            // Silenced channels only get removed from the final output, which is produced separately
+            // when XQ audio is enabled
+            #[cfg(not(feature = "xq-audio"))]
+            let silenced_channel_mask = emu.audio.silenced_channel_mask();
             let mut mixer_output = [0; 2];
+            #[cfg(not(feature = "xq-audio"))]
+            let mut silenced_mixer_output = [0; 2];
 
             macro_rules! output_to_mixer {
-                ($samples: expr) => {{
+                ($samples: expr, $i: expr) => {{
                     let samples = $samples;
                     mixer_output[0] += samples[0];
                     mixer_output[1] += samples[1];
+                    #[cfg(not(feature = "xq-audio"))]
+                    if silenced_channel_mask & 1 << $i != 0 {
+                        silenced_mixer_output[0] += samples[0];
+                        silenced_mixer_output[1] += samples[1];
+                    }
                 }};
             }
 
-            let mut channel_0_output = 0;
-            channel_output!(0, |sample| {
-                channel_0_output = sample;
-                output_to_mixer!(pan!(sample, 0));
-            });
+            channel_output!(0, |sample| output_to_mixer!(pan!(sample, 0), 0));
 
             let channel_1_output = channel_output!(1);
             let channel_1_panned_output = pan!(channel_1_output, 1);
@@ -330,14 +399,10 @@ impl Audio {
                 && (!emu.audio.capture[0].addition_enabled()
                     || emu.audio.channels[0].control().running())
             {
-                output_to_mixer!(channel_1_panned_output);
+                output_to_mixer!(channel_1_panned_output, 1);
             }
 
-            let mut channel_2_output = 0;
-            channel_output!(2, |sample| {
-                channel_2_output = sample;
-                output_to_mixer!(pan!(sample, 2));
-            });
+            channel_output!(2, |sample| output_to_mixer!(pan!(sample, 2), 2));
 
             let channel_3_output = channel_output!(3);
             let channel_3_panned_output = pan!(channel_3_output, 3);
@@ -346,55 +411,18 @@ impl Audio {
                 && (!emu.audio.capture[1].addition_enabled()
                     || emu.audio.channels[1].control().running())
             {
-                output_to_mixer!(channel_3_panned_output);
+                output_to_mixer!(channel_3_panned_output, 3);
             }
 
             for i in 4..16 {
-                channel_output!(i, |sample| output_to_mixer!(pan!(sample, i)));
+                channel_output!(i, |sample| output_to_mixer!(pan!(sample, i), i));
             }
 
-            macro_rules! update_capture_unit {
-                ($i: literal, $sample: expr) => {
-                    if emu.audio.capture[$i].control().running() {
-                        emu.audio.capture[$i].timer_counter += 512;
-                        if emu.audio.capture[$i].timer_counter >> 16 != 0 {
-                            CaptureUnit::run(emu, capture::Index::new($i), $sample);
-                        }
-                    }
-                };
-            }
-
-            update_capture_unit!(0, {
-                if emu.audio.capture[0].control().capture_channel() {
-                    let channel_0_capture_output = raw_channel_sample_to_i16(channel_0_output);
-                    if emu.audio.capture[0].addition_enabled() {
-                        channel_0_capture_output
-                            .wrapping_add(raw_channel_sample_to_i16(channel_1_output))
-                    } else if channel_0_capture_output < 0 && channel_1_output < 0 {
-                        -0x8000
-                    } else {
-                        channel_0_capture_output
-                    }
-                } else {
-                    raw_mixer_interp_sample_to_i32(mixer_output[0]).clamp(-0x8000, 0x7FFF) as i16
-                }
-            });
-
-            update_capture_unit!(1, {
-                if emu.audio.capture[1].control().capture_channel() {
-                    let channel_2_capture_output = raw_channel_sample_to_i16(channel_2_output);
-                    if emu.audio.capture[1].addition_enabled() {
-                        channel_2_capture_output
-                            .wrapping_add(raw_channel_sample_to_i16(channel_3_output))
-                    } else if channel_2_capture_output < 0 && channel_3_output < 0 {
-                        -0x8000
-                    } else {
-                        channel_2_capture_output
-                    }
-                } else {
-                    raw_mixer_interp_sample_to_i32(mixer_output[1]).clamp(-0x8000, 0x7FFF) as i16
+            for i in 0..2 {
+                if emu.audio.capture[i].control().running() {
+                    CaptureUnit::run(emu, capture::Index::new(i as u8), time, mixer_output[i]);
                 }
-            });
+            }
 
             #[cfg(not(feature = "xq-audio"))]
             {
@@ -403,15 +431,23 @@ impl Audio {
                     (1, emu.audio.control.r_output_src()),
                 ]
                 .map(|(i, src)| {
+                    let channel_1_output = if silenced_channel_mask & 1 << 1 != 0 {
+                        0
+                    } else {
+                        channel_1_panned_output[i]
+                    };
+                    let channel_3_output = if silenced_channel_mask & 1 << 3 != 0 {
+                        0
+                    } else {
+                        channel_3_panned_output[i]
+                    };
                     let sample = match src {
-                        0 => mixer_output[i],
-                        1 => channel_1_panned_output[i],
-                        2 => channel_3_panned_output[i],
-                        _ => channel_1_panned_output[i] + channel_3_panned_output[i],
+                        0 => mixer_output[i] - silenced_mixer_output[i],
+                        1 => channel_1_output,
+                        2 => channel_3_output,
+                        _ => channel_1_output + channel_3_output,
                     };
-                    (((sample * emu.audio.master_volume as RawMixerInterpSample) >> 21)
-                        + emu.audio.bias as RawMixerInterpSample)
-                        .clamp(0, 0x3FF) as OutputSample
+                    raw_mixer_sample_to_output(sample, emu.audio.master_volume, emu.audio.bias)
                 })
             }
         } else {
@@ -420,8 +456,18 @@ impl Audio {
                 [0; 2]
             }
         };
+        for channel in &mut emu.audio.channels[..4] {
+            channel.reset_capture_hist();
+        }
         #[cfg(not(feature = "xq-audio"))]
         {
+            #[cfg(feature = "channel-audio-capture")]
+            if emu.audio.channel_audio_capture_data.output_enabled {
+                emu.audio
+                    .channel_audio_capture_data
+                    .output_buffer
+                    .push(output);
+            }
             emu.audio.sample_chunk.push(output);
             if emu.audio.sample_chunk.len() >= emu.audio.sample_chunk_size as usize {
                 emu.audio
@@ -438,6 +484,12 @@ impl Audio {
     #[inline(never)]
     #[cfg(feature = "xq-audio")]
     pub(crate) fn handle_xq_sample_ready<E: cpu::Engine>(emu: &mut Emu<E>, time: arm7::Timestamp) {
+        let output_interval = emu
+            .audio
+            .custom_sample_rate
+            .map_or(CYCLES_PER_SAMPLE, |sample_rate| {
+                SYS_CLOCK_RATE / sample_rate.get() as RawTimestamp
+            });
         let output = if emu.audio.control.master_enable() {
             macro_rules! channel_output {
                 ($i: expr$(, |$ident: ident| $code: expr)?) => {
@@ -448,6 +500,7 @@ impl Audio {
                         let sample = emu.audio.channels[$i].interp_output(
                             time,
                             emu.audio.channel_interp_method,
+                            output_interval,
                         );
                         #[allow(path_statements)]
                         {
@@ -476,17 +529,23 @@ impl Audio {
                 }};
             }
 
+            let silenced_channel_mask = emu.audio.silenced_channel_mask();
             let mut mixer_output = [0.0; 2];
+            let mut silenced_mixer_output = [0.0; 2];
 
             macro_rules! output_to_mixer {
-                ($samples: expr) => {{
+                ($samples: expr, $i: expr) => {{
                     let samples = $samples;
                     mixer_output[0] += samples[0];
                     mixer_output[1] += samples[1];
+                    if silenced_channel_mask & 1 << $i != 0 {
+                        silenced_mixer_output[0] += samples[0];
+                        silenced_mixer_output[1] += samples[1];
+                    }
                 }};
             }
 
-            channel_output!(0, |sample| output_to_mixer!(pan!(sample, 0)));
+            channel_output!(0, |sample| output_to_mixer!(pan!(sample, 0), 0));
 
             let channel_1_output = channel_output!(1);
             let channel_1_panned_output = pan!(channel_1_output, 1);
@@ -495,10 +554,10 @@ impl Audio {
                 && (!emu.audio.capture[0].addition_enabled()
                     || emu.audio.channels[0].control().running())
             {
-                output_to_mixer!(channel_1_panned_output);
+                output_to_mixer!(channel_1_panned_output, 1);
             }
 
-            channel_output!(2, |sample| output_to_mixer!(pan!(sample, 2)));
+            channel_output!(2, |sample| output_to_mixer!(pan!(sample, 2), 2));
 
             let channel_3_output = channel_output!(3);
             let channel_3_panned_output = pan!(channel_3_output, 3);
@@ -507,11 +566,11 @@ impl Audio {
                 && (!emu.audio.capture[1].addition_enabled()
                     || emu.audio.channels[1].control().running())
             {
-                output_to_mixer!(channel_3_panned_output);
+                output_to_mixer!(channel_3_panned_output, 3);
             }
 
             for i in 4..16 {
-                channel_output!(i, |sample| output_to_mixer!(pan!(sample, i)));
+                channel_output!(i, |sample| output_to_mixer!(pan!(sample, i), i));
             }
 
             let volume_factor = emu.audio.master_volume as InterpSample * (1.0 / 128.0);
@@ -522,11 +581,21 @@ impl Audio {
                 (1, emu.audio.control.r_output_src()),
             ]
             .map(|(i, src)| {
+                let channel_1_output = if silenced_channel_mask & 1 << 1 != 0 {
+                    0.0
+                } else {
+                    channel_1_panned_output[i]
+                };
+                let channel_3_output = if silenced_channel_mask & 1 << 3 != 0 {
+                    0.0
+                } else {
+                    channel_3_panned_output[i]
+                };
                 let sample = match src {
-                    0 => mixer_output[i],
-                    1 => channel_1_panned_output[i],
-                    2 => channel_3_panned_output[i],
-                    _ => channel_1_panned_output[i] + channel_3_panned_output[i],
+                    0 => mixer_output[i] - silenced_mixer_output[i],
+                    1 => channel_1_output,
+                    2 => channel_3_output,
+                    _ => channel_1_output + channel_3_output,
                 };
                 ((sample * volume_factor + bias).clamp(0.0, 2.0) - 1.0) as OutputSample
             })
@@ -534,6 +603,13 @@ impl Audio {
             [0.0; 2]
         };
 
+        #[cfg(feature = "channel-audio-capture")]
+        if emu.audio.channel_audio_capture_data.output_enabled {
+            emu.audio
+                .channel_audio_capture_data
+                .output_buffer
+                .push(output);
+        }
         emu.audio.sample_chunk.push(output);
         if emu.audio.sample_chunk.len() >= emu.audio.sample_chunk_size as usize {
             emu.audio
@@ -1,12 +1,30 @@
+use super::{RawChannelSample, RawMixerInterpSample, CYCLES_PER_SAMPLE};
 use crate::{
     cpu::{self, arm7, bus::DmaAccess},
     emu::Emu,
-    utils::{Bytes, Savestate},
+    utils::{schedule::RawTimestamp, Bytes, Savestate},
 };
 
-// TODO: The capture units could actually need a higher resolution than the mixer (capturing
-// channel output, for example), maybe they should get their own events on the scheduler in that
-// case?
+// Capture units run on their own timer, so they can sample faster than the mixer does; the mixer
+// output only changes once per mixer sample, but channel output is sampled at the exact time of
+// each capture timer overflow (see `Channel::raw_output_at`).
+//
+// As described by GBATEK, no rounding is performed: channel output (after the volume divider and
+// factor, but before panning) and mixer output (before the master volume) get their fractional
+// bits stripped by an arithmetic shift, after which the mixer output is clipped to 16 bits while
+// channel additions just wrap around; PCM8 captures then keep the upper 8 bits.
+//
+// UNVERIFIED: GBATEK doesn't say when channel output is sampled relative to the capture timer, so
+// sampling it at the exact overflow time is an assumption; this hasn't been checked against
+// hardware or a capture test ROM.
+
+pub(super) fn raw_channel_sample_to_i16(sample: RawChannelSample) -> i16 {
+    (sample >> 11) as i16
+}
+
+pub(super) fn raw_mixer_sample_to_i16(sample: RawMixerInterpSample) -> i16 {
+    (sample >> 8).clamp(-0x8000, 0x7FFF) as i16
+}
 
 proc_bitfield::bitfield! {
     #[derive(Clone, Copy, PartialEq, Eq, Savestate)]
@@ -40,7 +58,7 @@ pub struct CaptureUnit {
     dst_end_addr: u32,
     cur_dst_addr: u32,
     pub timer_reload: u16,
-    pub(super) timer_counter: u32,
+    timer_counter: u32,
     fifo_read_half: bool,
     fifo_write_pos: FifoWritePos,
     fifo: Bytes<0x20>,
@@ -133,51 +151,93 @@ impl CaptureUnit {
         capture.cur_dst_addr = cur_dst_addr;
     }
 
-    pub(super) fn run(emu: &mut Emu<impl cpu::Engine>, i: Index, sample: i16) {
-        // TODO: The exact sample rounding algorithm is off from the one on GBATEK, both inside this
-        // function and in `Audio::handle_sample_ready`.
-        loop {
-            let capture = &mut emu.audio.capture[i.get() as usize];
+    fn channel_sample(emu: &Emu<impl cpu::Engine>, i: Index, time: arm7::Timestamp) -> i16 {
+        let channels = &emu.audio.channels[(i.get() as usize) << 1..];
+        let output = raw_channel_sample_to_i16(channels[0].raw_output_at(time));
+        let added_output = channels[1].raw_output_at(time);
+        if emu.audio.capture[i.get() as usize].addition_enabled {
+            output.wrapping_add(raw_channel_sample_to_i16(added_output))
+        } else if output < 0 && added_output < 0 {
+            -0x8000
+        } else {
+            output
+        }
+    }
+
+    fn write_sample(emu: &mut Emu<impl cpu::Engine>, i: Index, sample: i16) {
+        #[cfg(feature = "channel-audio-capture")]
+        if emu.audio.channel_audio_capture_data.capture_unit_mask & 1 << i.get() != 0 {
+            emu.audio.channel_audio_capture_data.capture_unit_buffers[i.get() as usize]
+                .push(sample);
+        }
 
-            if capture.control.pcm8() {
-                capture.fifo[capture.fifo_write_pos.get() as usize] = (sample >> 8) as u8;
-                capture.buffer_pos += 1;
-                capture.fifo_write_pos =
-                    FifoWritePos::new((capture.fifo_write_pos.get() + 1) & 0x1F);
+        let capture = &mut emu.audio.capture[i.get() as usize];
+
+        if capture.control.pcm8() {
+            capture.fifo[capture.fifo_write_pos.get() as usize] = (sample >> 8) as u8;
+            capture.buffer_pos += 1;
+            capture.fifo_write_pos = FifoWritePos::new((capture.fifo_write_pos.get() + 1) & 0x1F);
+        } else {
+            capture
+                .fifo
+                .write_le(capture.fifo_write_pos.get() as usize & !1, sample);
+            capture.buffer_pos += 2;
+            capture.fifo_write_pos = FifoWritePos::new((capture.fifo_write_pos.get() + 2) & 0x1E);
+        }
+
+        if capture.buffer_pos >= capture.buffer_len {
+            Self::flush_fifo(emu, i);
+            let capture = &mut emu.audio.capture[i.get() as usize];
+            if capture.control.one_shot() {
+                capture.control.set_running(false);
+                capture.addition_enabled = false;
             } else {
-                capture
-                    .fifo
-                    .write_le(capture.fifo_write_pos.get() as usize & !1, sample);
-                capture.buffer_pos += 2;
-                capture.fifo_write_pos =
-                    FifoWritePos::new((capture.fifo_write_pos.get() + 2) & 0x1E);
+                capture.buffer_pos = 0;
+                capture.cur_dst_addr = capture.dst_start_addr;
+                capture.fifo_read_half = false;
+                capture.fifo_write_pos = FifoWritePos::new(0);
             }
+        } else if capture
+            .fifo_write_pos
+            .get()
+            .wrapping_sub((capture.fifo_read_half as u8) << 4)
+            & 0x1F
+            >= 16
+        {
+            Self::flush_fifo(emu, i);
+        }
+    }
+
+    // Advances the capture timer up to the given mixer sample time, capturing a sample on every
+    // overflow.
+    pub(super) fn run(
+        emu: &mut Emu<impl cpu::Engine>,
+        i: Index,
+        time: arm7::Timestamp,
+        mixer_sample: RawMixerInterpSample,
+    ) {
+        // Like the channel timers, the capture timer runs at half the ARM7 clock rate
+        emu.audio.capture[i.get() as usize].timer_counter += (CYCLES_PER_SAMPLE >> 1) as u32;
 
-            if capture.buffer_pos >= capture.buffer_len {
-                Self::flush_fifo(emu, i);
-                let capture = &mut emu.audio.capture[i.get() as usize];
-                if capture.control.one_shot() {
-                    capture.control.set_running(false);
-                    capture.addition_enabled = false;
-                } else {
-                    capture.buffer_pos = 0;
-                    capture.cur_dst_addr = capture.dst_start_addr;
-                    capture.fifo_read_half = false;
-                    capture.fifo_write_pos = FifoWritePos::new(0);
-                }
-            } else if capture
-                .fifo_write_pos
-                .get()
-                .wrapping_sub((capture.fifo_read_half as u8) << 4)
-                & 0x1F
-                >= 16
-            {
-                Self::flush_fifo(emu, i);
+        loop {
+            let capture = &emu.audio.capture[i.get() as usize];
+            if capture.timer_counter >> 16 == 0 {
+                break;
             }
+            let sample = if capture.control.capture_channel() {
+                // The timer overflowed (timer_counter - 0x10000) ticks before the current time
+                let sample_time = arm7::Timestamp(
+                    time.0 - ((capture.timer_counter - (1 << 16)) << 1) as RawTimestamp,
+                );
+                Self::channel_sample(emu, i, sample_time)
+            } else {
+                raw_mixer_sample_to_i16(mixer_sample)
+            };
+            Self::write_sample(emu, i, sample);
 
             let capture = &mut emu.audio.capture[i.get() as usize];
             capture.timer_counter = capture.timer_counter - (1 << 16) + capture.timer_reload as u32;
-            if capture.timer_counter >> 16 == 0 {
+            if !capture.control.running() {
                 break;
             }
         }
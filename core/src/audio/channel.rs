@@ -1,15 +1,17 @@
 use super::RawChannelSample;
 #[cfg(feature = "xq-audio")]
-use super::{ChannelInterpMethod, InterpSample};
-#[cfg(feature = "xq-audio")]
-use crate::utils::schedule::RawTimestamp;
+use super::{sinc, ChannelInterpMethod, InterpSample};
 use crate::{
     cpu::{self, arm7, bus::DmaAccess},
     emu::Emu,
-    utils::{Bytes, MemValue, Savestate},
+    utils::{schedule::RawTimestamp, Bytes, MemValue, Savestate},
 };
 use core::mem;
 
+// Enough history for all interpolation methods, the newest sample being the last one
+#[cfg(feature = "xq-audio")]
+const HIST_LEN: usize = sinc::MAX_TAPS;
+
 // TODO: Check behavior when:
 // - Using format 3 (PSG) for channels 0..=7 (melonDS seems to output silence, which is what is
 //   attempted here right now too)
@@ -147,6 +149,47 @@ static PSG_TABLE: [i16; 64] = [
     -0x7FFF, -0x7FFF, -0x7FFF, -0x7FFF, -0x7FFF, -0x7FFF, -0x7FFF, -0x7FFF,
 ];
 
+// Decodes a single 4-bit ADPCM sample, returning the new sample value and table index
+#[inline]
+pub fn decode_adpcm_sample(value: i16, index: AdpcmIndex, sample: u8) -> (i16, AdpcmIndex) {
+    let adpcm_table_entry = ADPCM_TABLE[index.get() as usize] as u32;
+    let mut diff = adpcm_table_entry >> 3;
+    if sample & 1 != 0 {
+        diff += adpcm_table_entry >> 2;
+    }
+    if sample & 2 != 0 {
+        diff += adpcm_table_entry >> 1;
+    }
+    if sample & 4 != 0 {
+        diff += adpcm_table_entry;
+    }
+    let value = if sample & 8 == 0 {
+        (value as i32 + diff as i32).min(0x7FFF)
+    } else {
+        (value as i32 - diff as i32).max(-0x7FFF)
+    } as i16;
+    let index = AdpcmIndex::new(
+        (index.get() as i8 + ADPCM_INDEX_TABLE[sample as usize & 7]).clamp(0, 88) as u8,
+    );
+    (value, index)
+}
+
+#[inline]
+pub(super) fn psg_wave_sample(duty: u8, step: usize) -> i16 {
+    PSG_TABLE[(duty as usize & 7) << 3 | (step & 7)]
+}
+
+#[inline]
+pub(super) fn psg_noise_sample(lfsr: &mut u16) -> i16 {
+    if *lfsr & 1 == 0 {
+        *lfsr >>= 1;
+        0x7FFF
+    } else {
+        *lfsr = *lfsr >> 1 ^ 0x6000;
+        -0x7FFF
+    }
+}
+
 #[derive(Savestate)]
 #[load(in_place_only)]
 pub struct Channel {
@@ -184,13 +227,20 @@ pub struct Channel {
     noise_lfsr: u16,
     #[cfg(feature = "xq-audio")]
     #[savestate(skip)]
-    hist: [InterpSample; 4],
+    hist: [InterpSample; HIST_LEN],
     #[cfg(feature = "xq-audio")]
     #[load(value = "None")]
     #[store(skip)]
     last_sample_time: Option<arm7::Timestamp>,
     #[cfg(feature = "xq-audio")]
     sample_interval: arm7::Timestamp,
+    // Samples produced since the last mixer sample along with the time they were produced at, only
+    // recorded for channels 0..=3 while their capture unit is capturing channel output, so that it
+    // can be sampled at the capture timer's rate instead of the mixer's
+    #[savestate(skip)]
+    capture_hist: Vec<(arm7::Timestamp, i16)>,
+    #[savestate(skip)]
+    capture_hist_start_sample: i16,
 }
 
 impl Channel {
@@ -228,11 +278,13 @@ impl Channel {
             adpcm_byte: 0,
             noise_lfsr: 0,
             #[cfg(feature = "xq-audio")]
-            hist: [0.0; 4],
+            hist: [0.0; HIST_LEN],
             #[cfg(feature = "xq-audio")]
             last_sample_time: None,
             #[cfg(feature = "xq-audio")]
             sample_interval: arm7::Timestamp(0x2_0000),
+            capture_hist: Vec::new(),
+            capture_hist_start_sample: 0,
         }
     }
 
@@ -347,7 +399,7 @@ impl Channel {
         #[cfg(feature = "xq-audio")]
         {
             self.hist.copy_within(1.., 0);
-            self.hist[3] = sample as InterpSample / 32768.0;
+            self.hist[HIST_LEN - 1] = sample as InterpSample / 32768.0;
         }
     }
 
@@ -569,26 +621,8 @@ impl Channel {
             channel.adpcm_byte >> 4
         };
         let channel = &mut emu.audio.channels[i.get() as usize];
-        let adpcm_table_entry = ADPCM_TABLE[channel.adpcm_index.get() as usize] as u32;
-        let mut diff = adpcm_table_entry >> 3;
-        if sample & 1 != 0 {
-            diff += adpcm_table_entry >> 2;
-        }
-        if sample & 2 != 0 {
-            diff += adpcm_table_entry >> 1;
-        }
-        if sample & 4 != 0 {
-            diff += adpcm_table_entry;
-        }
-        channel.adpcm_value = if sample & 8 == 0 {
-            (channel.adpcm_value as i32 + diff as i32).min(0x7FFF)
-        } else {
-            (channel.adpcm_value as i32 - diff as i32).max(-0x7FFF)
-        } as i16;
-        channel.adpcm_index = AdpcmIndex::new(
-            (channel.adpcm_index.get() as i8 + ADPCM_INDEX_TABLE[sample as usize & 7]).clamp(0, 88)
-                as u8,
-        );
+        (channel.adpcm_value, channel.adpcm_index) =
+            decode_adpcm_sample(channel.adpcm_value, channel.adpcm_index, sample);
         if channel.cur_sample_index as u32 == channel.loop_start_sample_index {
             channel.loop_start_adpcm_value = channel.adpcm_value;
             channel.loop_start_adpcm_index = channel.adpcm_index;
@@ -599,21 +633,15 @@ impl Channel {
     fn run_psg_wave(emu: &mut Emu<impl cpu::Engine>, i: Index) {
         let channel = &mut emu.audio.channels[i.get() as usize];
         channel.cur_sample_index += 1;
-        channel.push_sample(
-            PSG_TABLE[(channel.control.psg_wave_duty() as usize) << 3
-                | (channel.cur_sample_index as usize & 7)],
-        );
+        channel.push_sample(psg_wave_sample(
+            channel.control.psg_wave_duty(),
+            channel.cur_sample_index as usize,
+        ));
     }
 
     fn run_psg_noise(emu: &mut Emu<impl cpu::Engine>, i: Index) {
         let channel = &mut emu.audio.channels[i.get() as usize];
-        let sample = if channel.noise_lfsr & 1 == 0 {
-            channel.noise_lfsr >>= 1;
-            0x7FFF
-        } else {
-            channel.noise_lfsr = channel.noise_lfsr >> 1 ^ 0x6000;
-            -0x7FFF
-        };
+        let sample = psg_noise_sample(&mut channel.noise_lfsr);
         channel.push_sample(sample);
     }
 
@@ -627,26 +655,57 @@ impl Channel {
             * self.volume as RawChannelSample
     }
 
+    // Returns the output the channel had at the given time, which must lie between the last two
+    // mixer samples
+    pub(super) fn raw_output_at(&self, time: arm7::Timestamp) -> RawChannelSample {
+        let sample = self
+            .capture_hist
+            .iter()
+            .rev()
+            .find(|(sample_time, _)| sample_time.0 <= time.0)
+            .map_or(self.capture_hist_start_sample, |&(_, sample)| sample);
+        ((sample as RawChannelSample) << self.volume_shift) * self.volume as RawChannelSample
+    }
+
+    pub(super) fn reset_capture_hist(&mut self) {
+        self.capture_hist.clear();
+        self.capture_hist_start_sample = if self.control.running() {
+            self.last_sample
+        } else {
+            0
+        };
+    }
+
     #[cfg(feature = "xq-audio")]
     pub(super) fn interp_output(
         &self,
         time: arm7::Timestamp,
         interp_method: ChannelInterpMethod,
+        output_interval: RawTimestamp,
     ) -> InterpSample {
+        #[allow(clippy::cast_precision_loss)]
+        let mu = || {
+            self.last_sample_time.map_or(1.0, |last_sample_time| {
+                (time.0 - last_sample_time.0) as InterpSample
+                    / self.sample_interval.0 as InterpSample
+            })
+        };
         let interp_result = match interp_method {
-            ChannelInterpMethod::Nearest => self.hist[3],
+            ChannelInterpMethod::Nearest => self.hist[HIST_LEN - 1],
             ChannelInterpMethod::Cubic => {
-                #[allow(clippy::cast_precision_loss)]
-                let mu = self.last_sample_time.map_or(1.0, |last_sample_time| {
-                    (time.0 - last_sample_time.0) as InterpSample
-                        / self.sample_interval.0 as InterpSample
-                });
-                let a = self.hist[3] - self.hist[2] - self.hist[0] + self.hist[1];
-                let b = self.hist[0] - self.hist[1] - a;
-                let c = self.hist[2] - self.hist[0];
-                let d = self.hist[1];
+                let mu = mu();
+                let hist = &self.hist[HIST_LEN - 4..];
+                let a = hist[3] - hist[2] - hist[0] + hist[1];
+                let b = hist[0] - hist[1] - a;
+                let c = hist[2] - hist[0];
+                let d = hist[1];
                 (((a * mu + b) * mu + c) * mu + d).clamp(-1.0, 1.0)
             }
+            ChannelInterpMethod::Sinc => {
+                #[allow(clippy::cast_precision_loss)]
+                let ratio = output_interval as f64 / self.sample_interval.0 as f64;
+                sinc::interp(&self.hist, mu(), ratio)
+            }
         };
         interp_result * (1 << self.volume_shift) as InterpSample * self.volume as InterpSample
     }
@@ -673,7 +732,7 @@ impl Channel {
             channel.fifo_write_pos = FifoWritePos::new(0);
             #[cfg(feature = "xq-audio")]
             {
-                channel.hist = [0.0; 4];
+                channel.hist = [0.0; HIST_LEN];
                 channel.last_sample_time = None;
             }
             if matches!(channel.format, Format::PsgNoise | Format::PsgWave) {
@@ -694,6 +753,11 @@ impl Channel {
             channel.timer_counter as u32 + ((time.0 - channel.last_update_time.0) >> 1) as u32;
         channel.last_update_time.0 = time.0 & !1;
         let timer_reload = channel.timer_reload as u32;
+        let record_capture_hist = i.get() < 4 && {
+            let capture_control = emu.audio.capture[i.get() as usize >> 1].control();
+            capture_control.running() && capture_control.capture_channel()
+        };
+        let channel = &mut emu.audio.channels[i.get() as usize];
         if channel.control.running() {
             let f = match channel.format {
                 Format::Pcm8 => Self::run_pcm8,
@@ -705,8 +769,18 @@ impl Channel {
             };
             if cfg!(not(feature = "xq-audio")) || timer_counter >> 16 != 0 {
                 while timer_counter >> 16 != 0 {
+                    // The timer overflowed (timer_counter - 0x10000) ticks before the current time
+                    let sample_time = arm7::Timestamp(
+                        (time.0 & !1) - ((timer_counter - (1 << 16)) << 1) as RawTimestamp,
+                    );
                     timer_counter = timer_counter - (1 << 16) + timer_reload;
                     f(emu, i);
+                    if record_capture_hist {
+                        let channel = &mut emu.audio.channels[i.get() as usize];
+                        channel
+                            .capture_hist
+                            .push((sample_time, channel.last_sample));
+                    }
                 }
                 #[cfg(feature = "xq-audio")]
                 {
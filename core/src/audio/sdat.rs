@@ -0,0 +1,853 @@
+pub mod player;
+
+use super::channel::{decode_adpcm_sample, AdpcmIndex};
+use core::fmt;
+use std::error::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SdatError {
+    NotSdat,
+    Truncated,
+    InvalidFileId(u16),
+    MissingSequence(usize),
+    MissingBank(u16),
+    InvalidSequence,
+    InvalidBank,
+    InvalidWaveArchive,
+    InvalidWave,
+}
+
+impl Error for SdatError {}
+
+impl fmt::Display for SdatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SdatError::NotSdat => f.write_str("not an SDAT file"),
+            SdatError::Truncated => f.write_str("truncated data"),
+            SdatError::InvalidFileId(id) => write!(f, "invalid file ID {id}"),
+            SdatError::MissingSequence(i) => write!(f, "missing sequence {i}"),
+            SdatError::MissingBank(i) => write!(f, "missing bank {i}"),
+            SdatError::InvalidSequence => f.write_str("invalid sequence (SSEQ) file"),
+            SdatError::InvalidBank => f.write_str("invalid bank (SBNK) file"),
+            SdatError::InvalidWaveArchive => f.write_str("invalid wave archive (SWAR) file"),
+            SdatError::InvalidWave => f.write_str("invalid wave (SWAV) data"),
+        }
+    }
+}
+
+fn read_bytes<const LEN: usize>(data: &[u8], offset: usize) -> Result<[u8; LEN], SdatError> {
+    data.get(offset..offset + LEN)
+        .map(|bytes| bytes.try_into().unwrap())
+        .ok_or(SdatError::Truncated)
+}
+
+fn read_u8(data: &[u8], offset: usize) -> Result<u8, SdatError> {
+    data.get(offset).copied().ok_or(SdatError::Truncated)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, SdatError> {
+    read_bytes(data, offset).map(u16::from_le_bytes)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, SdatError> {
+    read_bytes(data, offset).map(u32::from_le_bytes)
+}
+
+// Checks the magic of a Nitro file and of its first block, which is the only one for all
+// SDAT-contained file types; offsets inside the block are relative to the start of the file
+fn check_file(file: &[u8], magic: &[u8; 4], err: SdatError) -> Result<(), SdatError> {
+    if file.len() < 0x18 || &file[..4] != magic || &file[0x10..0x14] != b"DATA" {
+        return Err(err);
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+pub struct SequenceInfo {
+    pub name: Option<String>,
+    pub file_id: u16,
+    pub bank: u16,
+    pub volume: u8,
+    pub channel_priority: u8,
+    pub player_priority: u8,
+    pub player: u8,
+}
+
+#[derive(Clone, Debug)]
+pub struct BankInfo {
+    pub name: Option<String>,
+    pub file_id: u16,
+    // 0xFFFF for unused slots
+    pub wave_archives: [u16; 4],
+}
+
+#[derive(Clone, Debug)]
+pub struct WaveArchiveInfo {
+    pub name: Option<String>,
+    pub file_id: u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoteKind {
+    Pcm,
+    Psg,
+    Noise,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct NoteDef {
+    pub kind: NoteKind,
+    // Wave index for PCM notes, duty cycle for PSG ones
+    pub wave: u16,
+    pub wave_archive: u16,
+    pub base_key: u8,
+    pub attack: u8,
+    pub decay: u8,
+    pub sustain: u8,
+    pub release: u8,
+    pub pan: u8,
+}
+
+impl NoteDef {
+    fn parse(kind: u8, data: &[u8], offset: usize) -> Result<Option<Self>, SdatError> {
+        let kind = match kind {
+            1 | 4 => NoteKind::Pcm,
+            2 => NoteKind::Psg,
+            3 => NoteKind::Noise,
+            _ => return Ok(None),
+        };
+        let bytes = read_bytes::<10>(data, offset)?;
+        Ok(Some(NoteDef {
+            kind,
+            wave: u16::from_le_bytes([bytes[0], bytes[1]]),
+            wave_archive: u16::from_le_bytes([bytes[2], bytes[3]]),
+            base_key: bytes[4],
+            attack: bytes[5],
+            decay: bytes[6],
+            sustain: bytes[7],
+            release: bytes[8],
+            pan: bytes[9],
+        }))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Instrument {
+    Single(NoteDef),
+    DrumSet {
+        low_key: u8,
+        defs: Vec<Option<NoteDef>>,
+    },
+    KeySplit {
+        // Upper (inclusive) key of each region, in ascending order
+        regions: Vec<(u8, Option<NoteDef>)>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct Bank {
+    pub instruments: Vec<Option<Instrument>>,
+}
+
+impl Bank {
+    pub fn parse(data: &[u8]) -> Result<Self, SdatError> {
+        check_file(data, b"SBNK", SdatError::InvalidBank)?;
+        let count = read_u32(data, 0x38)? as usize;
+        if count > 0x10000 {
+            return Err(SdatError::InvalidBank);
+        }
+        let mut instruments = Vec::with_capacity(count);
+        for i in 0..count {
+            let record_offset = 0x3C + i * 4;
+            let kind = read_u8(data, record_offset)?;
+            let offset = read_u16(data, record_offset + 1)? as usize;
+            let instrument = match kind {
+                0 => None,
+                16 => {
+                    let low_key = read_u8(data, offset)?;
+                    let high_key = read_u8(data, offset + 1)?;
+                    if high_key < low_key {
+                        return Err(SdatError::InvalidBank);
+                    }
+                    let defs = (0..=(high_key - low_key) as usize)
+                        .map(|j| -> Result<_, SdatError> {
+                            let def_offset = offset + 2 + j * 12;
+                            NoteDef::parse(read_u8(data, def_offset)?, data, def_offset + 2)
+                        })
+                        .collect::<Result<_, _>>()?;
+                    Some(Instrument::DrumSet { low_key, defs })
+                }
+                17 => {
+                    let upper_keys = read_bytes::<8>(data, offset)?;
+                    let regions = upper_keys
+                        .iter()
+                        .take_while(|&&key| key != 0)
+                        .enumerate()
+                        .map(|(j, &key)| -> Result<_, SdatError> {
+                            let def_offset = offset + 8 + j * 12;
+                            Ok((
+                                key,
+                                NoteDef::parse(read_u8(data, def_offset)?, data, def_offset + 2)?,
+                            ))
+                        })
+                        .collect::<Result<_, _>>()?;
+                    Some(Instrument::KeySplit { regions })
+                }
+                _ => NoteDef::parse(kind, data, offset)?.map(Instrument::Single),
+            };
+            instruments.push(instrument);
+        }
+        Ok(Bank { instruments })
+    }
+
+    pub fn note_def(&self, program: u16, key: u8) -> Option<&NoteDef> {
+        match self.instruments.get(program as usize)?.as_ref()? {
+            Instrument::Single(def) => Some(def),
+            Instrument::DrumSet { low_key, defs } => defs
+                .get(key.checked_sub(*low_key)? as usize)
+                .and_then(Option::as_ref),
+            Instrument::KeySplit { regions } => regions
+                .iter()
+                .find(|(upper_key, _)| key <= *upper_key)
+                .and_then(|(_, def)| def.as_ref()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Wave {
+    pub samples: Box<[i16]>,
+    pub loop_start: Option<usize>,
+    // Timer period at the wave's base key, in 16.756 MHz cycles
+    pub timer: u16,
+}
+
+impl Wave {
+    // Decodes the whole wave upfront; since ADPCM state at the loop start is always the same, this
+    // produces the same output as the hardware's on-the-fly decoding
+    pub fn decode(data: &[u8]) -> Result<Self, SdatError> {
+        let format = read_u8(data, 0)?;
+        let looping = read_u8(data, 1)? != 0;
+        let timer = read_u16(data, 4)?;
+        let loop_offset = read_u16(data, 6)? as usize * 4;
+        let len = loop_offset + read_u32(data, 8)? as usize * 4;
+        let bytes = data.get(0xC..0xC + len).ok_or(SdatError::InvalidWave)?;
+        let (samples, loop_start): (Box<[i16]>, _) = match format {
+            0 => (
+                bytes.iter().map(|&byte| (byte as i8 as i16) << 8).collect(),
+                loop_offset,
+            ),
+            1 => (
+                bytes
+                    .chunks_exact(2)
+                    .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+                    .collect(),
+                loop_offset / 2,
+            ),
+            2 => {
+                if bytes.len() < 4 {
+                    return Err(SdatError::InvalidWave);
+                }
+                let mut value = i16::from_le_bytes([bytes[0], bytes[1]]).max(-0x7FFF);
+                let mut index = AdpcmIndex::new(bytes[2].min(88));
+                let mut samples = Vec::with_capacity((bytes.len() - 4) * 2);
+                for &byte in &bytes[4..] {
+                    for nibble in [byte & 0xF, byte >> 4] {
+                        (value, index) = decode_adpcm_sample(value, index, nibble);
+                        samples.push(value);
+                    }
+                }
+                (
+                    samples.into_boxed_slice(),
+                    loop_offset.saturating_sub(4) * 2,
+                )
+            }
+            _ => return Err(SdatError::InvalidWave),
+        };
+        if timer == 0 || samples.is_empty() {
+            return Err(SdatError::InvalidWave);
+        }
+        Ok(Wave {
+            loop_start: (looping && loop_start < samples.len()).then_some(loop_start),
+            samples,
+            timer,
+        })
+    }
+}
+
+pub fn parse_wave_archive(data: &[u8]) -> Result<Vec<Option<Wave>>, SdatError> {
+    check_file(data, b"SWAR", SdatError::InvalidWaveArchive)?;
+    let count = read_u32(data, 0x38)? as usize;
+    if count > 0x10000 {
+        return Err(SdatError::InvalidWaveArchive);
+    }
+    (0..count)
+        .map(|i| -> Result<_, SdatError> {
+            let offset = read_u32(data, 0x3C + i * 4)? as usize;
+            // Keep going if a single wave is broken, it'll just be silent
+            Ok(data.get(offset..).and_then(|data| Wave::decode(data).ok()))
+        })
+        .collect()
+}
+
+// Everything needed to play back a single sequence
+#[derive(Clone, Debug)]
+pub struct Sequence {
+    pub data: Box<[u8]>,
+    pub bank: Bank,
+    pub waves: [Vec<Option<Wave>>; 4],
+    pub volume: u8,
+    pub channel_priority: u8,
+}
+
+pub struct Sdat {
+    data: Box<[u8]>,
+    files: Vec<(u32, u32)>,
+    pub sequences: Vec<Option<SequenceInfo>>,
+    pub banks: Vec<Option<BankInfo>>,
+    pub wave_archives: Vec<Option<WaveArchiveInfo>>,
+}
+
+impl Sdat {
+    pub fn new(data: Box<[u8]>) -> Result<Self, SdatError> {
+        if data.len() < 0x40 || &data[..4] != b"SDAT" {
+            return Err(SdatError::NotSdat);
+        }
+        let symb_offset = read_u32(&data, 0x10)? as usize;
+        let info_offset = read_u32(&data, 0x18)? as usize;
+        let fat_offset = read_u32(&data, 0x20)? as usize;
+
+        if data.get(info_offset..info_offset + 4) != Some(&b"INFO"[..])
+            || data.get(fat_offset..fat_offset + 4) != Some(&b"FAT "[..])
+        {
+            return Err(SdatError::NotSdat);
+        }
+
+        let file_count = read_u32(&data, fat_offset + 8)? as usize;
+        let files = (0..file_count.min(0x10000))
+            .map(|i| -> Result<_, SdatError> {
+                let entry_offset = fat_offset + 0xC + i * 0x10;
+                Ok((
+                    read_u32(&data, entry_offset)?,
+                    read_u32(&data, entry_offset + 4)?,
+                ))
+            })
+            .collect::<Result<_, _>>()?;
+
+        // Symbols are optional, some games strip them
+        let has_symbols =
+            symb_offset != 0 && data.get(symb_offset..symb_offset + 4) == Some(&b"SYMB"[..]);
+        let names = |record: usize| -> Vec<Option<String>> {
+            if !has_symbols {
+                return Vec::new();
+            }
+            let Ok(record_offset) = read_u32(&data, symb_offset + 8 + record * 4) else {
+                return Vec::new();
+            };
+            let record_offset = symb_offset + record_offset as usize;
+            let count = read_u32(&data, record_offset).unwrap_or(0) as usize;
+            (0..count.min(0x10000))
+                .map(|i| {
+                    let name_offset = read_u32(&data, record_offset + 4 + i * 4).ok()?;
+                    if name_offset == 0 {
+                        return None;
+                    }
+                    let name = data.get(symb_offset + name_offset as usize..)?;
+                    let len = name.iter().position(|&b| b == 0)?;
+                    Some(String::from_utf8_lossy(&name[..len]).into_owned())
+                })
+                .collect()
+        };
+
+        let entries = |record: usize| -> Result<Vec<Option<usize>>, SdatError> {
+            let record_offset =
+                info_offset + read_u32(&data, info_offset + 8 + record * 4)? as usize;
+            let count = read_u32(&data, record_offset)? as usize;
+            (0..count.min(0x10000))
+                .map(|i| -> Result<_, SdatError> {
+                    let entry_offset = read_u32(&data, record_offset + 4 + i * 4)?;
+                    Ok((entry_offset != 0).then_some(info_offset + entry_offset as usize))
+                })
+                .collect()
+        };
+
+        let mut seq_names = names(0);
+        let sequences = entries(0)?
+            .into_iter()
+            .enumerate()
+            .map(|(i, offset)| -> Result<_, SdatError> {
+                let Some(offset) = offset else {
+                    return Ok(None);
+                };
+                Ok(Some(SequenceInfo {
+                    name: seq_names.get_mut(i).and_then(Option::take),
+                    file_id: read_u16(&data, offset)?,
+                    bank: read_u16(&data, offset + 4)?,
+                    volume: read_u8(&data, offset + 6)?,
+                    channel_priority: read_u8(&data, offset + 7)?,
+                    player_priority: read_u8(&data, offset + 8)?,
+                    player: read_u8(&data, offset + 9)?,
+                }))
+            })
+            .collect::<Result<_, _>>()?;
+
+        let mut bank_names = names(2);
+        let banks = entries(2)?
+            .into_iter()
+            .enumerate()
+            .map(|(i, offset)| -> Result<_, SdatError> {
+                let Some(offset) = offset else {
+                    return Ok(None);
+                };
+                Ok(Some(BankInfo {
+                    name: bank_names.get_mut(i).and_then(Option::take),
+                    file_id: read_u16(&data, offset)?,
+                    wave_archives: [
+                        read_u16(&data, offset + 4)?,
+                        read_u16(&data, offset + 6)?,
+                        read_u16(&data, offset + 8)?,
+                        read_u16(&data, offset + 10)?,
+                    ],
+                }))
+            })
+            .collect::<Result<_, _>>()?;
+
+        let mut wave_archive_names = names(3);
+        let wave_archives = entries(3)?
+            .into_iter()
+            .enumerate()
+            .map(|(i, offset)| -> Result<_, SdatError> {
+                let Some(offset) = offset else {
+                    return Ok(None);
+                };
+                Ok(Some(WaveArchiveInfo {
+                    name: wave_archive_names.get_mut(i).and_then(Option::take),
+                    file_id: read_u16(&data, offset)?,
+                }))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Sdat {
+            data,
+            files,
+            sequences,
+            banks,
+            wave_archives,
+        })
+    }
+
+    pub fn file(&self, id: u16) -> Result<&[u8], SdatError> {
+        let &(offset, size) = self
+            .files
+            .get(id as usize)
+            .ok_or(SdatError::InvalidFileId(id))?;
+        self.data
+            .get(offset as usize..offset as usize + size as usize)
+            .ok_or(SdatError::InvalidFileId(id))
+    }
+
+    pub fn load_sequence(&self, index: usize) -> Result<Sequence, SdatError> {
+        let info = self
+            .sequences
+            .get(index)
+            .and_then(Option::as_ref)
+            .ok_or(SdatError::MissingSequence(index))?;
+
+        let sseq = self.file(info.file_id)?;
+        check_file(sseq, b"SSEQ", SdatError::InvalidSequence)?;
+        let data_offset = read_u32(sseq, 0x18)? as usize;
+        let data = sseq
+            .get(data_offset..)
+            .ok_or(SdatError::InvalidSequence)?
+            .into();
+
+        let bank_info = self
+            .banks
+            .get(info.bank as usize)
+            .and_then(Option::as_ref)
+            .ok_or(SdatError::MissingBank(info.bank))?;
+        let bank = Bank::parse(self.file(bank_info.file_id)?)?;
+
+        let mut waves: [Vec<Option<Wave>>; 4] = Default::default();
+        for (waves, &wave_archive) in waves.iter_mut().zip(&bank_info.wave_archives) {
+            if let Some(Some(wave_archive_info)) = self.wave_archives.get(wave_archive as usize) {
+                *waves = parse_wave_archive(self.file(wave_archive_info.file_id)?)?;
+            }
+        }
+
+        Ok(Sequence {
+            data,
+            bank,
+            waves,
+            volume: info.volume,
+            channel_priority: info.channel_priority,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_u16(data: &mut Vec<u8>, value: u16) {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn push_u32(data: &mut Vec<u8>, value: u32) {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn set_u32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    // A Nitro file header followed by the header of its DATA block
+    fn nitro_file(magic: &[u8; 4]) -> Vec<u8> {
+        let mut file = magic.to_vec();
+        file.resize(0x10, 0);
+        file.extend_from_slice(b"DATA\0\0\0\0");
+        file
+    }
+
+    fn note_def(wave: u16, base_key: u8) -> [u8; 10] {
+        let [wave_low, wave_high] = wave.to_le_bytes();
+        [wave_low, wave_high, 0, 0, base_key, 127, 100, 90, 80, 64]
+    }
+
+    fn set_record(sbnk: &mut [u8], i: usize, kind: u8, offset: usize) {
+        sbnk[0x3C + i * 4] = kind;
+        sbnk[0x3D + i * 4..0x3F + i * 4].copy_from_slice(&(offset as u16).to_le_bytes());
+    }
+
+    // A bank with a single PSG note, a two-key drum set with only its first key defined, a
+    // two-region key split and an unused instrument slot
+    fn sbnk() -> Vec<u8> {
+        let mut sbnk = nitro_file(b"SBNK");
+        sbnk.resize(0x38, 0);
+        push_u32(&mut sbnk, 4);
+        sbnk.resize(0x4C, 0);
+
+        let offset = sbnk.len();
+        set_record(&mut sbnk, 0, 2, offset);
+        sbnk.extend_from_slice(&note_def(3, 69));
+
+        let offset = sbnk.len();
+        set_record(&mut sbnk, 1, 16, offset);
+        sbnk.extend_from_slice(&[60, 61, 1, 0]);
+        sbnk.extend_from_slice(&note_def(1, 60));
+        sbnk.extend_from_slice(&[0; 12]);
+
+        let offset = sbnk.len();
+        set_record(&mut sbnk, 2, 17, offset);
+        sbnk.extend_from_slice(&[40, 127, 0, 0, 0, 0, 0, 0, 1, 0]);
+        sbnk.extend_from_slice(&note_def(5, 50));
+        sbnk.extend_from_slice(&[3, 0]);
+        sbnk.extend_from_slice(&note_def(0, 60));
+
+        sbnk
+    }
+
+    fn swav(format: u8, looping: bool, timer: u16, loop_words: u16, data: &[u8]) -> Vec<u8> {
+        let mut swav = vec![format, looping as u8, 0, 0];
+        push_u16(&mut swav, timer);
+        push_u16(&mut swav, loop_words);
+        push_u32(&mut swav, (data.len() / 4) as u32 - loop_words as u32);
+        swav.extend_from_slice(data);
+        swav
+    }
+
+    fn swar(waves: &[Vec<u8>], extra_offsets: &[u32]) -> Vec<u8> {
+        let mut swar = nitro_file(b"SWAR");
+        swar.resize(0x38, 0);
+        let count = waves.len() + extra_offsets.len();
+        push_u32(&mut swar, count as u32);
+        let mut offset = 0x3C + count * 4;
+        for wave in waves {
+            push_u32(&mut swar, offset as u32);
+            offset += wave.len();
+        }
+        for &extra_offset in extra_offsets {
+            push_u32(&mut swar, extra_offset);
+        }
+        for wave in waves {
+            swar.extend_from_slice(wave);
+        }
+        swar
+    }
+
+    fn sseq(data: &[u8]) -> Vec<u8> {
+        let mut sseq = nitro_file(b"SSEQ");
+        push_u32(&mut sseq, 0x1C);
+        sseq.extend_from_slice(data);
+        sseq
+    }
+
+    // Builds an SDAT without symbols containing a single sequence, bank and wave archive, stored
+    // as files 0, 1 and 2
+    fn sdat_file(seq_bank: u16, files: &[Vec<u8>]) -> Vec<u8> {
+        let mut info = b"INFO\0\0\0\0".to_vec();
+        info.resize(0x28, 0);
+        let empty_record = info.len();
+        push_u32(&mut info, 0);
+        let mut records = [empty_record; 8];
+        for (record, entry) in [(0, 0x44), (2, 0x50), (3, 0x5C)] {
+            records[record] = info.len();
+            push_u32(&mut info, 1);
+            push_u32(&mut info, entry);
+        }
+        assert_eq!(info.len(), 0x44);
+        for (i, record) in records.into_iter().enumerate() {
+            set_u32(&mut info, 8 + i * 4, record as u32);
+        }
+        // Sequence: file ID, bank, volume, channel priority, player priority, player
+        push_u32(&mut info, 0);
+        push_u16(&mut info, seq_bank);
+        info.extend_from_slice(&[100, 64, 32, 1, 0, 0]);
+        // Bank: file ID, wave archives
+        push_u32(&mut info, 1);
+        for wave_archive in [0, 0xFFFF, 0xFFFF, 0xFFFF] {
+            push_u16(&mut info, wave_archive);
+        }
+        // Wave archive: file ID
+        push_u32(&mut info, 2);
+
+        let info_offset = 0x40;
+        let fat_offset = info_offset + info.len();
+        let mut file_offset = fat_offset + 0xC + files.len() * 0x10;
+
+        let mut data = b"SDAT".to_vec();
+        data.resize(0x40, 0);
+        set_u32(&mut data, 0x18, info_offset as u32);
+        set_u32(&mut data, 0x20, fat_offset as u32);
+        data.extend_from_slice(&info);
+        data.extend_from_slice(b"FAT \0\0\0\0");
+        push_u32(&mut data, files.len() as u32);
+        for file in files {
+            push_u32(&mut data, file_offset as u32);
+            push_u32(&mut data, file.len() as u32);
+            data.extend_from_slice(&[0; 8]);
+            file_offset += file.len();
+        }
+        for file in files {
+            data.extend_from_slice(file);
+        }
+        data
+    }
+
+    #[test]
+    fn parses_bank_instruments() {
+        let bank = Bank::parse(&sbnk()).unwrap();
+        assert_eq!(bank.instruments.len(), 4);
+        assert!(bank.instruments[3].is_none());
+
+        let def = bank.note_def(0, 10).unwrap();
+        assert_eq!(def.kind, NoteKind::Psg);
+        assert_eq!((def.wave, def.base_key, def.pan), (3, 69, 64));
+
+        let def = bank.note_def(1, 60).unwrap();
+        assert_eq!((def.kind, def.wave), (NoteKind::Pcm, 1));
+        assert!(bank.note_def(1, 61).is_none());
+        assert!(bank.note_def(1, 59).is_none());
+        assert!(bank.note_def(1, 62).is_none());
+
+        let def = bank.note_def(2, 40).unwrap();
+        assert_eq!((def.kind, def.wave, def.base_key), (NoteKind::Pcm, 5, 50));
+        assert_eq!(bank.note_def(2, 41).unwrap().kind, NoteKind::Noise);
+        assert_eq!(bank.note_def(2, 127).unwrap().kind, NoteKind::Noise);
+
+        assert!(bank.note_def(3, 60).is_none());
+        assert!(bank.note_def(4, 60).is_none());
+    }
+
+    #[test]
+    fn rejects_malformed_banks() {
+        let mut data = sbnk();
+        data[0] = b'X';
+        assert_eq!(Bank::parse(&data).err(), Some(SdatError::InvalidBank));
+
+        let mut data = sbnk();
+        set_u32(&mut data, 0x38, 0x10001);
+        assert_eq!(Bank::parse(&data).err(), Some(SdatError::InvalidBank));
+
+        let mut data = sbnk();
+        set_u32(&mut data, 0x38, 5);
+        assert_eq!(Bank::parse(&data[..0x4C]).err(), Some(SdatError::Truncated));
+
+        // Drum set with its high key below its low one
+        let mut data = sbnk();
+        data[0x57] = 59;
+        assert_eq!(Bank::parse(&data).err(), Some(SdatError::InvalidBank));
+
+        let data = sbnk();
+        assert_eq!(
+            Bank::parse(&data[..data.len() - 1]).err(),
+            Some(SdatError::Truncated)
+        );
+    }
+
+    #[test]
+    fn decodes_pcm_waves() {
+        let wave =
+            Wave::decode(&swav(0, true, 0x100, 1, &[1, 0xFF, 0x80, 0x7F, 0, 0, 0, 0])).unwrap();
+        assert_eq!(
+            &*wave.samples,
+            &[0x100, -0x100, -0x8000, 0x7F00, 0, 0, 0, 0]
+        );
+        assert_eq!(wave.loop_start, Some(4));
+        assert_eq!(wave.timer, 0x100);
+
+        let wave = Wave::decode(&swav(1, false, 0x100, 0, &[0x34, 0x12, 0x00, 0x80])).unwrap();
+        assert_eq!(&*wave.samples, &[0x1234, -0x8000]);
+        assert_eq!(wave.loop_start, None);
+    }
+
+    #[test]
+    fn decodes_adpcm_waves() {
+        let wave = Wave::decode(&swav(2, true, 0x200, 1, &[0, 0, 0, 0, 0x74, 0, 0, 0])).unwrap();
+        assert_eq!(&*wave.samples, &[7, 23, 25, 27, 29, 30, 31, 32]);
+        assert_eq!(wave.loop_start, Some(0));
+    }
+
+    #[test]
+    fn rejects_malformed_waves() {
+        assert_eq!(
+            Wave::decode(&swav(0, false, 0, 0, &[0; 4])).err(),
+            Some(SdatError::InvalidWave)
+        );
+        assert_eq!(
+            Wave::decode(&swav(3, false, 0x100, 0, &[0; 4])).err(),
+            Some(SdatError::InvalidWave)
+        );
+        assert_eq!(
+            Wave::decode(&swav(0, false, 0x100, 0, &[])).err(),
+            Some(SdatError::InvalidWave)
+        );
+        let data = swav(1, false, 0x100, 0, &[0; 8]);
+        assert_eq!(
+            Wave::decode(&data[..data.len() - 1]).err(),
+            Some(SdatError::InvalidWave)
+        );
+        assert_eq!(Wave::decode(&data[..8]).err(), Some(SdatError::Truncated));
+    }
+
+    #[test]
+    fn parses_wave_archives_skipping_broken_waves() {
+        let waves = parse_wave_archive(&swar(
+            &[
+                swav(0, false, 0x100, 0, &[1, 2, 3, 4]),
+                swav(0, false, 0, 0, &[1, 2, 3, 4]),
+            ],
+            &[0xFFFF_FFFF],
+        ))
+        .unwrap();
+        assert_eq!(waves.len(), 3);
+        assert_eq!(
+            &*waves[0].as_ref().unwrap().samples,
+            &[0x100, 0x200, 0x300, 0x400]
+        );
+        assert!(waves[1].is_none());
+        assert!(waves[2].is_none());
+
+        let mut data = swar(&[], &[]);
+        data[0] = b'X';
+        assert_eq!(
+            parse_wave_archive(&data).err(),
+            Some(SdatError::InvalidWaveArchive)
+        );
+        let mut data = swar(&[], &[]);
+        data[0x10] = b'X';
+        assert_eq!(
+            parse_wave_archive(&data).err(),
+            Some(SdatError::InvalidWaveArchive)
+        );
+        assert_eq!(
+            parse_wave_archive(&nitro_file(b"SWAR")).err(),
+            Some(SdatError::Truncated)
+        );
+        let mut data = swar(&[], &[]);
+        set_u32(&mut data, 0x38, 2);
+        assert_eq!(parse_wave_archive(&data).err(), Some(SdatError::Truncated));
+    }
+
+    #[test]
+    fn loads_sequences() {
+        let files = [
+            sseq(&[0xFE, 0x01, 0x00, 0xFF]),
+            sbnk(),
+            swar(&[swav(0, false, 0x100, 0, &[1, 2, 3, 4])], &[]),
+        ];
+        let sdat = Sdat::new(sdat_file(0, &files).into()).unwrap();
+
+        assert_eq!(sdat.sequences.len(), 1);
+        let info = sdat.sequences[0].as_ref().unwrap();
+        assert!(info.name.is_none());
+        assert_eq!(
+            (info.file_id, info.bank, info.volume, info.player),
+            (0, 0, 100, 1)
+        );
+        assert_eq!(sdat.banks[0].as_ref().unwrap().wave_archives[0], 0);
+        assert_eq!(sdat.wave_archives[0].as_ref().unwrap().file_id, 2);
+        assert_eq!(sdat.file(1).unwrap(), &files[1][..]);
+        assert_eq!(sdat.file(3).err(), Some(SdatError::InvalidFileId(3)));
+
+        let sequence = sdat.load_sequence(0).unwrap();
+        assert_eq!(&*sequence.data, &[0xFE, 0x01, 0x00, 0xFF]);
+        assert_eq!(sequence.bank.instruments.len(), 4);
+        assert_eq!(sequence.waves[0].len(), 1);
+        assert!(sequence.waves[1..].iter().all(Vec::is_empty));
+        assert_eq!((sequence.volume, sequence.channel_priority), (100, 64));
+
+        assert_eq!(
+            sdat.load_sequence(1).err(),
+            Some(SdatError::MissingSequence(1))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_archives() {
+        let files = [sseq(&[0xFF]), sbnk(), swar(&[], &[])];
+
+        let mut data = sdat_file(0, &files);
+        data[3] = b'X';
+        assert_eq!(Sdat::new(data.into()).err(), Some(SdatError::NotSdat));
+        assert!(matches!(
+            Sdat::new(b"SDAT"[..].into()),
+            Err(SdatError::NotSdat)
+        ));
+        let mut data = sdat_file(0, &files);
+        data[0x40] = b'X';
+        assert!(matches!(Sdat::new(data.into()), Err(SdatError::NotSdat)));
+
+        // FAT cut off in the middle of the last entry's size
+        let data = sdat_file(0, &files);
+        let last_fat_entry = 0x40 + 0x60 + 0xC + 2 * 0x10;
+        assert!(matches!(
+            Sdat::new(data[..last_fat_entry + 7].into()),
+            Err(SdatError::Truncated)
+        ));
+
+        let sdat = Sdat::new(sdat_file(7, &files).into()).unwrap();
+        assert!(matches!(
+            sdat.load_sequence(0),
+            Err(SdatError::MissingBank(7))
+        ));
+
+        let mut bad_sseq = sseq(&[0xFF]);
+        bad_sseq[0] = b'X';
+        let sdat = Sdat::new(sdat_file(0, &[bad_sseq, sbnk(), swar(&[], &[])]).into()).unwrap();
+        assert!(matches!(
+            sdat.load_sequence(0),
+            Err(SdatError::InvalidSequence)
+        ));
+
+        // File extending past the end of the archive
+        let mut data = sdat_file(0, &files);
+        let fat_entry = 0x40 + 0x60 + 0xC;
+        set_u32(&mut data, fat_entry + 4, 0x1000);
+        let sdat = Sdat::new(data.into()).unwrap();
+        assert!(matches!(
+            sdat.load_sequence(0),
+            Err(SdatError::InvalidFileId(0))
+        ));
+    }
+}
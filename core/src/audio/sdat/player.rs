@@ -0,0 +1,1007 @@
+use super::{NoteKind, Sequence};
+use crate::audio::{
+    channel::{self, psg_noise_sample, psg_wave_sample},
+    pan_raw_sample, raw_mixer_sample_to_output, OutputSample, RawChannelSample,
+    RawMixerInterpSample, CYCLES_PER_SAMPLE,
+};
+
+// The sequencer gets updated every 174592 ARM7 cycles (~5.2 ms), like in the official sound driver
+const UPDATE_INTERVAL: u32 = 174_592;
+// Tempo is measured in quarter notes per minute; a quarter note is 48 ticks, and the tempo counter
+// needs to reach 240 for a tick to happen
+const TEMPO_TICK_THRESHOLD: u16 = 240;
+
+// Envelope levels are in units of 1/128 of a tenth of a decibel, the lowest one being silence
+const AMPLITUDE_MIN: i32 = -723 << 7;
+const DECIBELS_MIN: i32 = -723;
+
+// Timer periods (in 16.756 MHz cycles) for PSG notes at middle C
+const PSG_BASE_TIMER: u16 = 8006;
+
+const TRACK_COUNT: usize = 16;
+const STACK_DEPTH: usize = 3;
+
+static ATTACK_TABLE: [u8; 19] = [
+    0, 1, 5, 14, 26, 38, 51, 63, 73, 84, 92, 100, 109, 116, 123, 127, 132, 137, 143,
+];
+
+fn attack_level(attack: u8) -> u8 {
+    if attack < 109 {
+        255 - attack
+    } else {
+        ATTACK_TABLE[127 - attack.min(127) as usize]
+    }
+}
+
+fn fall_rate(value: u8) -> i32 {
+    match value {
+        127..=u8::MAX => 0xFFFF,
+        126 => 0x3C00,
+        0..=49 => value as i32 * 2 + 1,
+        _ => 0x1E00 / (126 - value as i32),
+    }
+}
+
+// Converts a linear 0-127 level to tenths of a decibel, treating it as a power ratio
+fn level_to_decibels(level: u8) -> i32 {
+    if level == 0 {
+        return DECIBELS_MIN;
+    }
+    ((400.0 * (level.min(127) as f64 / 127.0).log10()).round() as i32).max(DECIBELS_MIN)
+}
+
+// Converts an attenuation in tenths of a decibel to the closest hardware volume and divider
+fn decibels_to_volume(decibels: i32) -> (u8, u8) {
+    if decibels <= DECIBELS_MIN {
+        return (0, 0);
+    }
+    let (shift_raw, decibels) = if decibels < -240 {
+        (3, decibels + 240)
+    } else if decibels < -120 {
+        (2, decibels + 120)
+    } else if decibels < -60 {
+        (1, decibels + 60)
+    } else {
+        (0, decibels.min(0))
+    };
+    let volume = (127.0 * 10_f64.powf(decibels as f64 / 200.0)).round() as u8;
+    (volume.min(127), shift_raw)
+}
+
+// Applies a pitch offset in 1/64ths of a semitone to a timer period
+fn adjust_timer(timer: u16, pitch: i32) -> u16 {
+    let timer = timer as f64 * (-pitch as f64 / 768.0).exp2();
+    timer.round().clamp(0x10 as f64, 0xFFFF as f64) as u16
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EnvelopeStage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Pcm { wave_archive: usize, wave: usize },
+    Psg { duty: u8 },
+    Noise,
+}
+
+#[derive(Clone, Copy)]
+struct Voice {
+    active: bool,
+    track: usize,
+    source: Source,
+    base_timer: u16,
+    base_key: u8,
+    key: u8,
+    velocity: u8,
+    // In ticks, 0 if the note should keep playing until explicitly released
+    length: u32,
+    priority: u8,
+    pan: u8,
+
+    stage: EnvelopeStage,
+    amplitude: i32,
+    attack_level: u8,
+    decay_rate: i32,
+    sustain_level: i32,
+    release_rate: i32,
+
+    sweep_pitch: i32,
+    sweep_len: u32,
+    sweep_counter: u32,
+    mod_delay_counter: u16,
+    mod_counter: u16,
+
+    control: channel::Control,
+    timer_reload: u16,
+    timer_counter: u32,
+    position: usize,
+    last_sample: i16,
+    noise_lfsr: u16,
+}
+
+impl Voice {
+    const fn new() -> Self {
+        Voice {
+            active: false,
+            track: 0,
+            source: Source::Noise,
+            base_timer: 0,
+            base_key: 60,
+            key: 60,
+            velocity: 0,
+            length: 0,
+            priority: 0,
+            pan: 64,
+            stage: EnvelopeStage::Release,
+            amplitude: AMPLITUDE_MIN,
+            attack_level: 0,
+            decay_rate: 0,
+            sustain_level: 0,
+            release_rate: 0,
+            sweep_pitch: 0,
+            sweep_len: 0,
+            sweep_counter: 0,
+            mod_delay_counter: 0,
+            mod_counter: 0,
+            control: channel::Control(0),
+            timer_reload: 0,
+            timer_counter: 0,
+            position: 0,
+            last_sample: 0,
+            noise_lfsr: 0x7FFF,
+        }
+    }
+
+    fn release(&mut self) {
+        if self.active {
+            self.stage = EnvelopeStage::Release;
+        }
+    }
+
+    fn update_envelope(&mut self) {
+        match self.stage {
+            EnvelopeStage::Attack => {
+                // Truncating division, like the official driver; an arithmetic shift would round
+                // towards negative infinity and never reach 0
+                self.amplitude = self.attack_level as i32 * self.amplitude / 256;
+                if self.amplitude == 0 {
+                    self.stage = EnvelopeStage::Decay;
+                }
+            }
+            EnvelopeStage::Decay => {
+                self.amplitude -= self.decay_rate;
+                if self.amplitude <= self.sustain_level {
+                    self.amplitude = self.sustain_level;
+                    self.stage = EnvelopeStage::Sustain;
+                }
+            }
+            EnvelopeStage::Sustain => {}
+            EnvelopeStage::Release => {
+                self.amplitude -= self.release_rate;
+                if self.amplitude <= AMPLITUDE_MIN {
+                    self.active = false;
+                }
+            }
+        }
+    }
+
+    fn current_sweep_pitch(&self) -> i32 {
+        if self.sweep_counter >= self.sweep_len {
+            return 0;
+        }
+        (self.sweep_pitch as i64 * (self.sweep_len - self.sweep_counter) as i64
+            / self.sweep_len as i64) as i32
+    }
+
+    fn step(&mut self, sequence: &Sequence) {
+        self.last_sample = match self.source {
+            Source::Pcm { wave_archive, wave } => {
+                let Some(Some(wave)) = sequence.waves[wave_archive].get(wave) else {
+                    self.active = false;
+                    return;
+                };
+                self.position += 1;
+                if self.position >= wave.samples.len() {
+                    match wave.loop_start {
+                        Some(loop_start) => self.position = loop_start,
+                        None => {
+                            self.active = false;
+                            self.last_sample = 0;
+                            return;
+                        }
+                    }
+                }
+                wave.samples[self.position]
+            }
+            Source::Psg { duty } => {
+                let sample = psg_wave_sample(duty, self.position);
+                self.position += 1;
+                sample
+            }
+            Source::Noise => psg_noise_sample(&mut self.noise_lfsr),
+        };
+    }
+
+    fn run(&mut self, sequence: &Sequence) -> RawChannelSample {
+        // Same timer behavior as the hardware channels, incremented 512 times per mixer sample
+        self.timer_counter += 512;
+        while self.timer_counter >> 16 != 0 && self.active {
+            self.timer_counter = self.timer_counter - (1 << 16) + self.timer_reload as u32;
+            self.step(sequence);
+        }
+        if !self.active {
+            return 0;
+        }
+        ((self.last_sample as RawChannelSample) << self.control.volume_shift())
+            * self.control.volume() as RawChannelSample
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ArgMode {
+    Normal,
+    Random,
+    Variable,
+}
+
+#[derive(Clone, Copy)]
+enum ArgKind {
+    U8,
+    S16,
+    VarLen,
+}
+
+#[derive(Clone, Copy)]
+struct StackEntry {
+    pos: usize,
+    // Remaining loop iterations, 0 for calls or infinite loops
+    loop_count: u8,
+    is_loop: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Track {
+    active: bool,
+    pos: usize,
+    wait: u32,
+    wait_for_voice: Option<usize>,
+    stack: [StackEntry; STACK_DEPTH],
+    stack_len: usize,
+    loops: u32,
+    cond: bool,
+
+    note_wait: bool,
+    tie: bool,
+    tied_voice: Option<usize>,
+    portamento: bool,
+    porta_key: u8,
+    porta_time: u8,
+    sweep_pitch: i16,
+
+    program: u16,
+    transpose: i8,
+    pitch_bend: i8,
+    bend_range: u8,
+    volume: u8,
+    expression: u8,
+    pan: u8,
+    priority: u8,
+    attack: Option<u8>,
+    decay: Option<u8>,
+    sustain: Option<u8>,
+    release: Option<u8>,
+
+    mod_depth: u8,
+    mod_speed: u8,
+    mod_type: u8,
+    mod_range: u8,
+    mod_delay: u16,
+}
+
+impl Track {
+    const fn new() -> Self {
+        Track {
+            active: false,
+            pos: 0,
+            wait: 0,
+            wait_for_voice: None,
+            stack: [StackEntry {
+                pos: 0,
+                loop_count: 0,
+                is_loop: false,
+            }; STACK_DEPTH],
+            stack_len: 0,
+            loops: 0,
+            cond: false,
+            note_wait: true,
+            tie: false,
+            tied_voice: None,
+            portamento: false,
+            porta_key: 60,
+            porta_time: 0,
+            sweep_pitch: 0,
+            program: 0,
+            transpose: 0,
+            pitch_bend: 0,
+            bend_range: 2,
+            volume: 127,
+            expression: 127,
+            pan: 64,
+            priority: 64,
+            attack: None,
+            decay: None,
+            sustain: None,
+            release: None,
+            mod_depth: 0,
+            mod_speed: 16,
+            mod_type: 0,
+            mod_range: 1,
+            mod_delay: 0,
+        }
+    }
+
+    fn start(&mut self, pos: usize) {
+        *self = Track::new();
+        self.active = true;
+        self.pos = pos;
+    }
+}
+
+// Plays back SSEQ sequences the way the official sound driver does, mixing the output like the
+// hardware would
+pub struct Player {
+    sequence: Sequence,
+    tracks: [Track; TRACK_COUNT],
+    voices: [Voice; 16],
+    variables: [i16; 32],
+    tempo: u16,
+    tempo_counter: u16,
+    update_counter: u32,
+    rng_state: u32,
+}
+
+impl Player {
+    pub fn new(sequence: Sequence) -> Self {
+        let mut player = Player {
+            sequence,
+            tracks: [Track::new(); TRACK_COUNT],
+            voices: [Voice::new(); 16],
+            variables: [-1; 32],
+            tempo: 120,
+            tempo_counter: 0,
+            update_counter: UPDATE_INTERVAL,
+            rng_state: 0x1234_5678,
+        };
+        player.tracks[0].start(0);
+        player
+    }
+
+    #[inline]
+    pub fn sequence(&self) -> &Sequence {
+        &self.sequence
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.tracks.iter().any(|track| track.active) && !self.voices.iter().any(|v| v.active)
+    }
+
+    // How many times the sequence has looped back, as seen by the track that looped the most
+    pub fn loop_count(&self) -> u32 {
+        self.tracks
+            .iter()
+            .map(|track| track.loops)
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Stops all tracks, letting voices fade out according to their release rates
+    pub fn release(&mut self) {
+        for track in &mut self.tracks {
+            track.active = false;
+        }
+        for voice in &mut self.voices {
+            voice.release();
+        }
+    }
+
+    fn random(&mut self) -> u32 {
+        self.rng_state = self
+            .rng_state
+            .wrapping_mul(1_664_525)
+            .wrapping_add(1_013_904_223);
+        self.rng_state >> 16
+    }
+
+    fn read_u8(&mut self, t: usize) -> u8 {
+        let track = &mut self.tracks[t];
+        let value = self
+            .sequence
+            .data
+            .get(track.pos)
+            .copied()
+            .unwrap_or_else(|| {
+                // Running past the end of the sequence data means it's broken, stop the track
+                track.active = false;
+                0xFF
+            });
+        track.pos += 1;
+        value
+    }
+
+    fn read_u16(&mut self, t: usize) -> u16 {
+        self.read_u8(t) as u16 | (self.read_u8(t) as u16) << 8
+    }
+
+    fn read_u24(&mut self, t: usize) -> u32 {
+        self.read_u16(t) as u32 | (self.read_u8(t) as u32) << 16
+    }
+
+    fn read_var_len(&mut self, t: usize) -> u32 {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.read_u8(t);
+            value = value << 7 | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        value
+    }
+
+    fn read_arg(&mut self, t: usize, kind: ArgKind, mode: ArgMode) -> i32 {
+        match mode {
+            ArgMode::Normal => match kind {
+                ArgKind::U8 => self.read_u8(t) as i32,
+                ArgKind::S16 => self.read_u16(t) as i16 as i32,
+                ArgKind::VarLen => self.read_var_len(t) as i32,
+            },
+            ArgMode::Random => {
+                let min = self.read_u16(t) as i16 as i32;
+                let max = self.read_u16(t) as i16 as i32;
+                if max <= min {
+                    min
+                } else {
+                    min + (self.random() % (max - min + 1) as u32) as i32
+                }
+            }
+            ArgMode::Variable => {
+                let index = self.read_u8(t) as usize;
+                self.variables.get(index).copied().unwrap_or(0) as i32
+            }
+        }
+    }
+
+    fn alloc_voice(&self, kind: NoteKind, priority: u8) -> Option<usize> {
+        let candidates: &[usize] = match kind {
+            NoteKind::Pcm => &[4, 5, 6, 7, 2, 0, 3, 1, 8, 9, 10, 11, 14, 12, 15, 13],
+            NoteKind::Psg => &[8, 9, 10, 11, 12, 13],
+            NoteKind::Noise => &[14, 15],
+        };
+        if let Some(&i) = candidates.iter().find(|&&i| !self.voices[i].active) {
+            return Some(i);
+        }
+        // Steal the quietest of the lowest-priority voices, as long as it doesn't have a higher
+        // priority than the new note
+        candidates
+            .iter()
+            .copied()
+            .filter(|&i| self.voices[i].priority <= priority)
+            .min_by_key(|&i| (self.voices[i].priority, self.voices[i].amplitude))
+    }
+
+    fn note_on(&mut self, t: usize, key: u8, velocity: u8, length: u32) {
+        let track = self.tracks[t];
+        let key = (key as i32 + track.transpose as i32).clamp(0, 127) as u8;
+
+        if track.tie {
+            if let Some(i) = track.tied_voice {
+                let voice = &mut self.voices[i];
+                if voice.active && voice.track == t {
+                    let prev_key = voice.key;
+                    voice.key = key;
+                    voice.velocity = velocity;
+                    self.start_sweep(t, i, prev_key, length);
+                    self.tracks[t].porta_key = key;
+                    return;
+                }
+            }
+        }
+
+        let Some(def) = self.sequence.bank.note_def(track.program, key).copied() else {
+            return;
+        };
+        let priority = track
+            .priority
+            .saturating_add(self.sequence.channel_priority);
+        let Some(i) = self.alloc_voice(def.kind, priority) else {
+            return;
+        };
+
+        let (source, base_timer, first_sample) = match def.kind {
+            NoteKind::Pcm => {
+                let wave_archive = def.wave_archive as usize & 3;
+                let Some(Some(wave)) = self.sequence.waves[wave_archive].get(def.wave as usize)
+                else {
+                    return;
+                };
+                (
+                    Source::Pcm {
+                        wave_archive,
+                        wave: def.wave as usize,
+                    },
+                    wave.timer,
+                    wave.samples[0],
+                )
+            }
+            NoteKind::Psg => (
+                Source::Psg {
+                    duty: def.wave as u8 & 7,
+                },
+                PSG_BASE_TIMER,
+                0,
+            ),
+            NoteKind::Noise => (Source::Noise, PSG_BASE_TIMER, 0),
+        };
+
+        let prev_key = track.porta_key;
+        self.voices[i] = Voice {
+            active: true,
+            track: t,
+            source,
+            base_timer,
+            base_key: def.base_key,
+            key,
+            velocity,
+            length: if track.tie { 0 } else { length },
+            priority,
+            pan: def.pan,
+            stage: EnvelopeStage::Attack,
+            amplitude: AMPLITUDE_MIN,
+            attack_level: attack_level(track.attack.unwrap_or(def.attack)),
+            decay_rate: fall_rate(track.decay.unwrap_or(def.decay)),
+            sustain_level: level_to_decibels(track.sustain.unwrap_or(def.sustain)) << 7,
+            release_rate: fall_rate(track.release.unwrap_or(def.release)),
+            mod_delay_counter: track.mod_delay,
+            last_sample: first_sample,
+            ..Voice::new()
+        };
+        self.start_sweep(t, i, prev_key, length);
+        self.update_voice(i);
+        self.voices[i].timer_counter = self.voices[i].timer_reload as u32;
+
+        let track = &mut self.tracks[t];
+        track.porta_key = key;
+        if track.tie {
+            track.tied_voice = Some(i);
+        }
+        if track.note_wait && length == 0 && !track.tie {
+            track.wait_for_voice = Some(i);
+        }
+    }
+
+    fn start_sweep(&mut self, t: usize, i: usize, prev_key: u8, length: u32) {
+        let track = &self.tracks[t];
+        let voice = &mut self.voices[i];
+        let mut sweep_pitch = track.sweep_pitch as i32;
+        if track.portamento {
+            sweep_pitch += (prev_key as i32 - voice.key as i32) << 6;
+        }
+        voice.sweep_pitch = sweep_pitch;
+        voice.sweep_counter = 0;
+        voice.sweep_len = if track.porta_time == 0 {
+            // Glide over the whole note, converting its length from ticks to updates at the
+            // current tempo
+            length * TEMPO_TICK_THRESHOLD as u32 / self.tempo.max(1) as u32
+        } else {
+            let time = track.porta_time as u32;
+            (time * time * sweep_pitch.unsigned_abs()) >> 11
+        };
+    }
+
+    fn release_track_voices(&mut self, t: usize, only_untimed: bool) {
+        for voice in &mut self.voices {
+            if voice.active && voice.track == t && (!only_untimed || voice.length == 0) {
+                voice.release();
+            }
+        }
+        self.tracks[t].tied_voice = None;
+    }
+
+    fn run_track(&mut self, t: usize) {
+        if let Some(i) = self.tracks[t].wait_for_voice {
+            if self.voices[i].active && self.voices[i].track == t {
+                return;
+            }
+            self.tracks[t].wait_for_voice = None;
+        }
+        if self.tracks[t].wait > 0 {
+            self.tracks[t].wait -= 1;
+            if self.tracks[t].wait > 0 {
+                return;
+            }
+        }
+
+        let mut mode = ArgMode::Normal;
+        let mut skip = false;
+        // Bound the number of commands per tick, so broken sequences can't hang the player
+        for _ in 0..0x1000 {
+            if !self.tracks[t].active || self.tracks[t].wait > 0 {
+                return;
+            }
+            if self.tracks[t].wait_for_voice.is_some() {
+                return;
+            }
+            let cmd_pos = self.tracks[t].pos;
+            let cmd = self.read_u8(t);
+
+            if cmd < 0x80 {
+                let velocity = self.read_u8(t) & 0x7F;
+                let length = self.read_arg(t, ArgKind::VarLen, mode).max(0) as u32;
+                if !skip {
+                    self.note_on(t, cmd, velocity, length);
+                    if self.tracks[t].note_wait {
+                        self.tracks[t].wait = length;
+                    }
+                }
+            } else {
+                match cmd {
+                    0xA0 => {
+                        mode = ArgMode::Random;
+                        continue;
+                    }
+                    0xA1 => {
+                        mode = ArgMode::Variable;
+                        continue;
+                    }
+                    0xA2 => {
+                        skip = !self.tracks[t].cond;
+                        continue;
+                    }
+                    _ => {}
+                }
+                self.run_command(t, cmd, cmd_pos, mode, skip);
+            }
+            mode = ArgMode::Normal;
+            skip = false;
+        }
+    }
+
+    fn run_command(&mut self, t: usize, cmd: u8, cmd_pos: usize, mode: ArgMode, skip: bool) {
+        macro_rules! arg {
+            ($kind: ident) => {
+                self.read_arg(t, ArgKind::$kind, mode)
+            };
+        }
+        macro_rules! set {
+            ($field: ident, $value: expr) => {{
+                let value = $value;
+                if !skip {
+                    self.tracks[t].$field = value;
+                }
+            }};
+        }
+
+        match cmd {
+            0x80 => set!(wait, arg!(VarLen).max(0) as u32),
+            0x81 => set!(program, arg!(VarLen) as u16),
+            0x93 => {
+                let track_index = self.read_u8(t) as usize;
+                let pos = self.read_u24(t) as usize;
+                if !skip && track_index != t && track_index < TRACK_COUNT {
+                    self.tracks[track_index].start(pos);
+                }
+            }
+            0x94 => {
+                let pos = self.read_u24(t) as usize;
+                if !skip {
+                    let track = &mut self.tracks[t];
+                    if pos <= cmd_pos {
+                        track.loops += 1;
+                    }
+                    track.pos = pos;
+                }
+            }
+            0x95 => {
+                let pos = self.read_u24(t) as usize;
+                if !skip {
+                    let track = &mut self.tracks[t];
+                    if track.stack_len < STACK_DEPTH {
+                        track.stack[track.stack_len] = StackEntry {
+                            pos: track.pos,
+                            loop_count: 0,
+                            is_loop: false,
+                        };
+                        track.stack_len += 1;
+                        track.pos = pos;
+                    }
+                }
+            }
+            0xB0..=0xBD => {
+                let index = self.read_u8(t) as usize;
+                let value = arg!(S16);
+                if skip || index >= self.variables.len() {
+                    return;
+                }
+                if cmd == 0xB6 {
+                    let random = (self.random() % (value.unsigned_abs() + 1)) as i32;
+                    self.variables[index] = (if value < 0 { -random } else { random }) as i16;
+                    return;
+                }
+                let var = &mut self.variables[index];
+                let mut cond = None;
+                match cmd {
+                    0xB0 => *var = value as i16,
+                    0xB1 => *var = var.wrapping_add(value as i16),
+                    0xB2 => *var = var.wrapping_sub(value as i16),
+                    0xB3 => *var = var.wrapping_mul(value as i16),
+                    0xB4 => {
+                        if value != 0 {
+                            *var = var.wrapping_div(value as i16);
+                        }
+                    }
+                    0xB5 => {
+                        *var = if value >= 0 {
+                            var.wrapping_shl(value as u32)
+                        } else {
+                            var.wrapping_shr(value.unsigned_abs())
+                        };
+                    }
+                    0xB8 => cond = Some(*var as i32 == value),
+                    0xB9 => cond = Some(*var as i32 >= value),
+                    0xBA => cond = Some(*var as i32 > value),
+                    0xBB => cond = Some(*var as i32 <= value),
+                    0xBC => cond = Some((*var as i32) < value),
+                    0xBD => cond = Some(*var as i32 != value),
+                    _ => {}
+                }
+                if let Some(cond) = cond {
+                    self.tracks[t].cond = cond;
+                }
+            }
+            0xC0 => set!(pan, arg!(U8).clamp(0, 127) as u8),
+            0xC1 => set!(volume, arg!(U8).clamp(0, 127) as u8),
+            // Master volume, unused by this player
+            0xC2 => {
+                arg!(U8);
+            }
+            0xC3 => set!(transpose, arg!(U8) as i8),
+            0xC4 => set!(pitch_bend, arg!(U8) as i8),
+            0xC5 => set!(bend_range, arg!(U8) as u8),
+            0xC6 => set!(priority, arg!(U8) as u8),
+            0xC7 => set!(note_wait, arg!(U8) & 1 != 0),
+            0xC8 => {
+                let tie = arg!(U8) & 1 != 0;
+                if !skip {
+                    self.release_track_voices(t, true);
+                    self.tracks[t].tie = tie;
+                }
+            }
+            0xC9 => {
+                let key = arg!(U8) as i32 + self.tracks[t].transpose as i32;
+                if !skip {
+                    let track = &mut self.tracks[t];
+                    track.porta_key = key.clamp(0, 127) as u8;
+                    track.portamento = true;
+                }
+            }
+            0xCA => set!(mod_depth, arg!(U8) as u8),
+            0xCB => set!(mod_speed, arg!(U8) as u8),
+            0xCC => set!(mod_type, arg!(U8) as u8),
+            0xCD => set!(mod_range, arg!(U8) as u8),
+            0xCE => set!(portamento, arg!(U8) & 1 != 0),
+            0xCF => set!(porta_time, arg!(U8) as u8),
+            0xD0 => set!(attack, Some(arg!(U8) as u8)),
+            0xD1 => set!(decay, Some(arg!(U8) as u8)),
+            0xD2 => set!(sustain, Some(arg!(U8) as u8)),
+            0xD3 => set!(release, Some(arg!(U8) as u8)),
+            0xD4 => {
+                let loop_count = arg!(U8) as u8;
+                if !skip {
+                    let track = &mut self.tracks[t];
+                    if track.stack_len < STACK_DEPTH {
+                        track.stack[track.stack_len] = StackEntry {
+                            pos: track.pos,
+                            loop_count,
+                            is_loop: true,
+                        };
+                        track.stack_len += 1;
+                    }
+                }
+            }
+            0xD5 => set!(expression, arg!(U8).clamp(0, 127) as u8),
+            // Variable printing, only meant for debugging
+            0xD6 => {
+                arg!(U8);
+            }
+            0xE0 => set!(mod_delay, arg!(S16) as u16),
+            0xE1 => {
+                let tempo = arg!(S16).clamp(1, 0x3FF) as u16;
+                if !skip {
+                    self.tempo = tempo;
+                }
+            }
+            0xE3 => set!(sweep_pitch, arg!(S16) as i16),
+            0xFC => {
+                if skip {
+                    return;
+                }
+                let track = &mut self.tracks[t];
+                if track.stack_len == 0 || !track.stack[track.stack_len - 1].is_loop {
+                    return;
+                }
+                let entry = &mut track.stack[track.stack_len - 1];
+                match entry.loop_count {
+                    0 => {
+                        track.loops += 1;
+                        track.pos = entry.pos;
+                    }
+                    1 => track.stack_len -= 1,
+                    _ => {
+                        entry.loop_count -= 1;
+                        track.pos = entry.pos;
+                    }
+                }
+            }
+            0xFD => {
+                if skip {
+                    return;
+                }
+                let track = &mut self.tracks[t];
+                // Unwind any loops left unfinished inside the called subroutine
+                while track.stack_len != 0 && track.stack[track.stack_len - 1].is_loop {
+                    track.stack_len -= 1;
+                }
+                if track.stack_len != 0 {
+                    track.stack_len -= 1;
+                    track.pos = track.stack[track.stack_len].pos;
+                }
+            }
+            // Track allocation mask, tracks get opened on demand anyway
+            0xFE => {
+                self.read_u16(t);
+            }
+            0xFF => {
+                if !skip {
+                    self.tracks[t].active = false;
+                    self.release_track_voices(t, true);
+                }
+            }
+            // Unknown command, there's no way to tell its length
+            _ => {
+                self.tracks[t].active = false;
+                self.release_track_voices(t, true);
+            }
+        }
+    }
+
+    fn tick(&mut self) {
+        for voice in &mut self.voices {
+            if voice.active && voice.stage != EnvelopeStage::Release && voice.length != 0 {
+                voice.length -= 1;
+                if voice.length == 0 {
+                    voice.release();
+                }
+            }
+        }
+        for t in 0..TRACK_COUNT {
+            if self.tracks[t].active {
+                self.run_track(t);
+            }
+        }
+    }
+
+    fn update_voice(&mut self, i: usize) {
+        let voice = &mut self.voices[i];
+        let track = &self.tracks[voice.track];
+
+        let mut mod_value = 0.0;
+        if track.mod_depth != 0 {
+            if voice.mod_delay_counter != 0 {
+                voice.mod_delay_counter -= 1;
+            } else {
+                voice.mod_counter = voice
+                    .mod_counter
+                    .wrapping_add((track.mod_speed as u16) << 6);
+                mod_value = (voice.mod_counter as f64 * (core::f64::consts::TAU / 65536.0)).sin()
+                    * track.mod_depth as f64
+                    * track.mod_range as f64
+                    / 128.0;
+            }
+        }
+
+        let mut decibels = level_to_decibels(track.volume)
+            + level_to_decibels(track.expression)
+            + level_to_decibels(self.sequence.volume)
+            + level_to_decibels(voice.velocity)
+            + (voice.amplitude >> 7);
+        let mut pitch = (voice.key as i32 - voice.base_key as i32) * 64
+            + (track.pitch_bend as i32 * track.bend_range as i32) / 2
+            + voice.current_sweep_pitch();
+        let mut pan = track.pan as i32 + voice.pan as i32 - 64;
+        match track.mod_type {
+            0 => pitch += (mod_value * 64.0) as i32,
+            1 => decibels += (mod_value * 60.0) as i32,
+            _ => pan += (mod_value * 64.0) as i32,
+        }
+
+        if voice.sweep_counter < voice.sweep_len {
+            voice.sweep_counter += 1;
+        }
+
+        let (volume, volume_shift_raw) = decibels_to_volume(decibels);
+        let pan = pan.clamp(0, 127) as u8;
+        voice.control = channel::Control(0)
+            .with_volume_raw(volume)
+            .with_volume_shift_raw(volume_shift_raw)
+            .with_pan_raw(pan)
+            .with_running(true);
+        voice.timer_reload = 0_u16.wrapping_sub(adjust_timer(voice.base_timer, pitch));
+    }
+
+    fn update(&mut self) {
+        self.tempo_counter += self.tempo;
+        while self.tempo_counter >= TEMPO_TICK_THRESHOLD {
+            self.tempo_counter -= TEMPO_TICK_THRESHOLD;
+            self.tick();
+        }
+        for i in 0..self.voices.len() {
+            if self.voices[i].active {
+                self.voices[i].update_envelope();
+                if self.voices[i].active {
+                    self.update_voice(i);
+                }
+            }
+        }
+    }
+
+    pub fn render(&mut self, samples: &mut Vec<[OutputSample; 2]>, len: usize) {
+        samples.reserve(len);
+        for _ in 0..len {
+            if self.update_counter >= UPDATE_INTERVAL {
+                self.update_counter -= UPDATE_INTERVAL;
+                self.update();
+            }
+            self.update_counter += CYCLES_PER_SAMPLE as u32;
+
+            let mut mixer_output: [RawMixerInterpSample; 2] = [0; 2];
+            for voice in &mut self.voices {
+                if !voice.active {
+                    continue;
+                }
+                let sample = voice.run(&self.sequence);
+                let [l, r] = pan_raw_sample(sample, voice.control.pan());
+                mixer_output[0] += l;
+                mixer_output[1] += r;
+            }
+            samples.push(mixer_output.map(|sample| raw_mixer_sample_to_output(sample, 128, 0x200)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attack_envelope_reaches_decay() {
+        for attack in [0, 64, 108, 109, 120, 126, 127] {
+            let mut voice = Voice::new();
+            voice.active = true;
+            voice.stage = EnvelopeStage::Attack;
+            voice.attack_level = attack_level(attack);
+            let mut updates = 0;
+            while voice.stage == EnvelopeStage::Attack {
+                voice.update_envelope();
+                updates += 1;
+                assert!(updates < 10_000, "attack {attack} never finished");
+            }
+            assert_eq!(voice.amplitude, 0);
+            assert!(voice.stage == EnvelopeStage::Decay);
+        }
+    }
+}
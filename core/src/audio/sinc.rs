@@ -0,0 +1,235 @@
+#[cfg(feature = "xq-audio")]
+use super::InterpSample;
+use core::f64::consts::PI;
+#[cfg(feature = "xq-audio")]
+use std::sync::LazyLock;
+
+// Slightly below the input's Nyquist frequency, to leave room for the window's transition band
+const CUTOFF: f64 = 0.9;
+// Downsampling kernels are generated in quarter-octave steps, down to a quarter of the input's
+// Nyquist frequency; past that, the cutoff stops being lowered and some aliasing is let through
+const LEVELS_PER_OCTAVE: usize = 4;
+const LEVELS: usize = 2 * LEVELS_PER_OCTAVE + 1;
+
+// A Blackman-windowed sinc filter kernel, precomputed for evenly spaced fractional positions
+// (phases) between the two samples at its center
+pub struct Table {
+    taps: usize,
+    phases: usize,
+    coeffs: Box<[f64]>,
+}
+
+impl Table {
+    // `cutoff` is relative to the input's Nyquist frequency
+    pub fn new(taps: usize, phases: usize, cutoff: f64) -> Self {
+        let mut coeffs = vec![0.0; taps * (phases + 1)].into_boxed_slice();
+        for (phase, coeffs) in coeffs.chunks_exact_mut(taps).enumerate() {
+            let fract = phase as f64 / phases as f64;
+            for (i, coeff) in coeffs.iter_mut().enumerate() {
+                let x = i as f64 - (taps / 2 - 1) as f64 - fract;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    let angle = PI * cutoff * x;
+                    angle.sin() / angle
+                };
+                // Blackman window
+                let window_pos = x / (taps / 2) as f64;
+                let window =
+                    0.42 + 0.5 * (PI * window_pos).cos() + 0.08 * (2.0 * PI * window_pos).cos();
+                *coeff = sinc * window;
+            }
+            // Normalize each phase to unity gain at DC
+            let sum: f64 = coeffs.iter().sum();
+            for coeff in coeffs {
+                *coeff /= sum;
+            }
+        }
+        Table {
+            taps,
+            phases,
+            coeffs,
+        }
+    }
+
+    #[inline]
+    pub fn taps(&self) -> usize {
+        self.taps
+    }
+
+    // Returns the coefficients to apply to a `taps`-long history buffer to interpolate between the
+    // two samples at its center, `fract` being the position between the two in the 0.0..=1.0 range;
+    // the two closest precomputed phases are linearly interpolated
+    pub fn coeffs(&self, fract: f64) -> impl Iterator<Item = f64> + '_ {
+        let pos = fract.clamp(0.0, 1.0) * self.phases as f64;
+        let phase = (pos as usize).min(self.phases - 1);
+        let phase_fract = pos - phase as f64;
+        let coeffs_a = &self.coeffs[phase * self.taps..(phase + 1) * self.taps];
+        let coeffs_b = &self.coeffs[(phase + 1) * self.taps..(phase + 2) * self.taps];
+        coeffs_a
+            .iter()
+            .zip(coeffs_b)
+            .map(move |(a, b)| a + (b - a) * phase_fract)
+    }
+}
+
+// The width of the widest kernel in `Tables::new(base_taps, _)`
+pub const fn max_taps(base_taps: usize) -> usize {
+    base_taps << ((LEVELS - 1) / LEVELS_PER_OCTAVE)
+}
+
+// Kernels for increasing downsampling ratios, with their cutoff frequency scaled down by the ratio
+// (so that frequencies above the output's Nyquist frequency are filtered out), and their width
+// scaled up by the same amount
+pub struct Tables {
+    tables: Box<[Table]>,
+}
+
+impl Tables {
+    pub fn new(base_taps: usize, phases: usize) -> Self {
+        Tables {
+            tables: (0..LEVELS)
+                .map(|level| {
+                    let scale = (-(level as f64) / LEVELS_PER_OCTAVE as f64).exp2();
+                    let taps = ((base_taps as f64 / scale / 2.0).ceil() as usize) << 1;
+                    Table::new(taps, phases, CUTOFF * scale)
+                })
+                .collect(),
+        }
+    }
+
+    // Returns the kernel to use when resampling with the given input to output sample rate ratio,
+    // picking the closest one whose cutoff frequency is at or below the output's Nyquist frequency
+    pub fn get(&self, ratio: f64) -> &Table {
+        let level = if ratio > 1.0 {
+            ((ratio.log2() * LEVELS_PER_OCTAVE as f64 - 1e-6).ceil() as usize).min(LEVELS - 1)
+        } else {
+            0
+        };
+        &self.tables[level]
+    }
+}
+
+#[cfg(feature = "xq-audio")]
+const BASE_TAPS: usize = 8;
+#[cfg(feature = "xq-audio")]
+pub(super) const MAX_TAPS: usize = max_taps(BASE_TAPS);
+#[cfg(feature = "xq-audio")]
+const PHASES: usize = 256;
+
+#[cfg(feature = "xq-audio")]
+static TABLES: LazyLock<Tables> = LazyLock::new(|| Tables::new(BASE_TAPS, PHASES));
+
+// Interpolates between the two samples at the center of the last `taps` samples of `hist` (where
+// `taps` depends on `ratio`, the channel's sample rate divided by the output's), `fract` being
+// the position between the two in the 0.0..=1.0 range
+#[cfg(feature = "xq-audio")]
+pub(super) fn interp(hist: &[InterpSample], fract: InterpSample, ratio: f64) -> InterpSample {
+    let table = TABLES.get(ratio);
+    let hist = &hist[hist.len() - table.taps()..];
+    let mut result = 0.0;
+    for (coeff, sample) in table.coeffs(fract).zip(hist) {
+        result += coeff * sample;
+    }
+    result.clamp(-1.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Magnitude of the kernel's response at `freq` (relative to the input's Nyquist frequency)
+    fn response(table: &Table, fract: f64, freq: f64) -> f64 {
+        let (mut re, mut im) = (0.0, 0.0);
+        for (i, coeff) in table.coeffs(fract).enumerate() {
+            let angle = PI * freq * (i as f64 - (table.taps() / 2 - 1) as f64 - fract);
+            re += coeff * angle.cos();
+            im += coeff * angle.sin();
+        }
+        re.hypot(im)
+    }
+
+    #[test]
+    fn coeffs_have_unity_dc_gain() {
+        let table = Table::new(8, 16, CUTOFF);
+        assert_eq!(table.taps(), 8);
+        for fract in [0.0, 0.03, 0.25, 0.5, 0.71, 1.0] {
+            assert_eq!(table.coeffs(fract).count(), 8);
+            let sum: f64 = table.coeffs(fract).sum();
+            assert!((sum - 1.0).abs() < 1e-9, "DC gain {sum} at {fract}");
+        }
+    }
+
+    #[test]
+    fn coeffs_are_mirrored_around_center() {
+        let table = Table::new(12, 256, CUTOFF);
+        for (fract, mirrored_fract) in [(0.25, 0.75), (0.5, 0.5), (0.125, 0.875)] {
+            let coeffs = table.coeffs(fract).collect::<Vec<_>>();
+            let mirrored = table.coeffs(mirrored_fract).collect::<Vec<_>>();
+            for (a, b) in coeffs.iter().zip(mirrored.iter().rev()) {
+                assert!((a - b).abs() < 1e-12);
+            }
+        }
+        // At the ends, the kernel peaks at the sample being interpolated
+        let coeffs = table.coeffs(0.0).collect::<Vec<_>>();
+        let peak = coeffs
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap()
+            .0;
+        assert_eq!(peak, 5);
+        let coeffs = table.coeffs(1.0).collect::<Vec<_>>();
+        assert!(coeffs[6] > coeffs[5]);
+    }
+
+    #[test]
+    fn coeffs_interpolate_between_phases() {
+        let table = Table::new(8, 4, CUTOFF);
+        let a = table.coeffs(0.25).collect::<Vec<_>>();
+        let b = table.coeffs(0.5).collect::<Vec<_>>();
+        for ((coeff, a), b) in table.coeffs(0.375).zip(&a).zip(&b) {
+            assert!((coeff - (a + b) / 2.0).abs() < 1e-12);
+        }
+        // Out-of-range positions are clamped
+        assert!(table.coeffs(-1.0).eq(table.coeffs(0.0)));
+        assert!(table.coeffs(2.0).eq(table.coeffs(1.0)));
+    }
+
+    #[test]
+    fn tables_are_picked_by_ratio() {
+        let tables = Tables::new(8, 16);
+        assert_eq!(max_taps(8), 32);
+        for (ratio, taps) in [
+            (0.5, 8),
+            (1.0, 8),
+            (1.01, 10),
+            (2f64.powf(0.25), 10),
+            (1.5, 14),
+            (2.0, 16),
+            (3.0, 28),
+            (4.0, 32),
+            (100.0, 32),
+        ] {
+            assert_eq!(tables.get(ratio).taps(), taps, "ratio {ratio}");
+        }
+    }
+
+    #[test]
+    fn downsampling_tables_filter_out_aliases() {
+        let tables = Tables::new(8, 256);
+        for (ratio, passband) in [(1.0, 0.1), (2.0, 0.05), (4.0, 0.025)] {
+            let table = tables.get(ratio);
+            for fract in [0.0, 0.25, 0.5, 0.8] {
+                assert!((response(table, fract, passband) - 1.0).abs() < 1e-3);
+            }
+        }
+        // Past the output's Nyquist frequency (half the input's for a ratio of 2), the signal is
+        // attenuated
+        let table = tables.get(2.0);
+        for fract in [0.0, 0.25, 0.5, 0.8] {
+            assert!(response(table, fract, 1.0) < 1e-3);
+            assert!(response(table, fract, 0.75) < 1e-2);
+        }
+    }
+}
@@ -882,7 +882,7 @@ pub fn write_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32, value: u8)
                             emu.arm7.irqs.halt(&mut emu.arm7.schedule);
                         }
                         _ => {
-                            todo!("Sleep mode switch");
+                            emu.arm7.irqs.sleep(&mut emu.arm7.schedule);
                         }
                     },
 
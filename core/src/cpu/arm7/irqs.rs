@@ -46,12 +46,20 @@ proc_bitfield::bitfield! {
     }
 }
 
+// IRQs that can wake the ARM7 up from sleep mode
+const SLEEP_WAKE_IRQS: IrqFlags = IrqFlags(0)
+    .with_sio_rtc(true)
+    .with_keypad(true)
+    .with_gba_slot_ext(true)
+    .with_lid_opened(true);
+
 #[derive(Savestate)]
 pub struct Irqs {
     enabled: IrqFlags,
     requested: IrqFlags,
     master_enable: bool,
     halted: bool,
+    sleeping: bool,
     cpu_irq_line: bool,
     enabled_in_cpsr: bool,
     triggered: bool,
@@ -64,6 +72,7 @@ impl Irqs {
             requested: IrqFlags(0),
             master_enable: false,
             halted: false,
+            sleeping: false,
             cpu_irq_line: false,
             enabled_in_cpsr: false,
             triggered: false,
@@ -98,6 +107,34 @@ impl Irqs {
         }
     }
 
+    #[inline]
+    pub fn sleeping(&self) -> bool {
+        self.sleeping
+    }
+
+    // While sleeping, the CPU stays halted and doesn't see any IRQs until one of the wakeup sources
+    // is requested.
+    pub fn sleep<S: ScheduleUpdate>(&mut self, schedule: S) {
+        self.sleeping = true;
+        self.halted = true;
+        self.update_pending(());
+        if self.halted || self.triggered {
+            schedule.stop_execution();
+        }
+    }
+
+    #[inline]
+    fn pending(&mut self) -> u32 {
+        let pending = self.enabled.0 & self.requested.0;
+        if self.sleeping {
+            if pending & SLEEP_WAKE_IRQS.0 == 0 {
+                return 0;
+            }
+            self.sleeping = false;
+        }
+        pending
+    }
+
     #[inline]
     pub fn cpu_irq_line(&self) -> bool {
         self.cpu_irq_line
@@ -138,7 +175,7 @@ impl Irqs {
 
     #[inline]
     fn update_pending<S: ScheduleUpdate>(&mut self, schedule: S) {
-        let pending = self.enabled.0 & self.requested.0;
+        let pending = self.pending();
         self.halted &= pending == 0;
         if self.master_enable {
             self.set_irq_line(pending != 0, schedule);
@@ -160,7 +197,8 @@ impl Irqs {
     #[inline]
     pub fn write_master_enable<S: ScheduleUpdate>(&mut self, value: bool, schedule: S) {
         self.master_enable = value;
-        self.set_irq_line(value && self.enabled.0 & self.requested.0 != 0, schedule);
+        let pending = self.pending();
+        self.set_irq_line(value && pending != 0, schedule);
     }
 }
 
@@ -58,6 +58,8 @@ pub struct Arm9<E: Engine> {
     post_boot_flag: PostBootFlag,
     pub dma: cpu::dma::Controller<dma::Timing, u32>,
     pub dma_fill: Bytes<16>,
+    // Units still requested by the display FIFO for the current scanline, for each DMA channel
+    display_fifo_dma_line_units: [u32; 4],
     pub div_engine: DivEngine,
     pub sqrt_engine: SqrtEngine,
     #[cfg(feature = "debugger-hooks")]
@@ -134,6 +136,7 @@ impl<E: Engine> Arm9<E> {
                 running_channels: 0,
             },
             dma_fill: Bytes::new([0; 16]),
+            display_fifo_dma_line_units: [0; 4],
             div_engine,
             sqrt_engine,
             #[cfg(feature = "debugger-hooks")]
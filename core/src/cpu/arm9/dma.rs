@@ -18,7 +18,7 @@ pub enum Timing {
     VBlank,       // x
     HBlank,       // x
     DisplayStart, // -
-    DisplayFifo,  // -
+    DisplayFifo,  // x
     DsSlot,       // x
     GbaSlot,      // -
     GxFifo,       // x
@@ -82,6 +82,8 @@ impl<E: Engine> Arm9<E> {
                 slog::warn!(self.logger, "GX FIFO DMA with 16-bit units");
             }
             channel.remaining_units = channel.unit_count;
+        } else if channel.timing == Timing::DisplayFifo {
+            channel.remaining_units = channel.unit_count;
         } else {
             channel.remaining_batch_units = channel.unit_count;
         }
@@ -114,6 +116,12 @@ impl<E: Engine> Arm9<E> {
                 .remaining_units
                 .min(112 << channel.control.is_32_bit() as u8);
             channel.remaining_units -= channel.remaining_batch_units;
+        } else if TIMING == Timing::DisplayFifo {
+            // The display FIFO requests 4 words every 8 pixels; as it's only filled once per
+            // scanline, all of a scanline's requests get merged into a single one
+            self.display_fifo_dma_line_units[i.get() as usize] =
+                0x80 << !channel.control.is_32_bit() as u8;
+            self.start_display_fifo_dma_batch(i);
         }
         self.dma.running_channels |= 1 << i.get();
         if let Some(cur_i) = self.dma.cur_channel {
@@ -137,15 +145,28 @@ impl<E: Engine> Arm9<E> {
         }
     }
 
-    fn end_or_pause_dma_transfer(&mut self, i: Index) {
+    // Transfers as many of the units requested by the display FIFO for the current scanline as
+    // are left in the transfer
+    fn start_display_fifo_dma_batch(&mut self, i: Index) {
         let channel = &mut self.dma.channels[i.get() as usize];
-        if channel.timing != Timing::GxFifo || channel.remaining_units == 0 {
+        let line_units = &mut self.display_fifo_dma_line_units[i.get() as usize];
+        channel.remaining_batch_units = channel.remaining_units.min(*line_units);
+        channel.remaining_units -= channel.remaining_batch_units;
+        *line_units -= channel.remaining_batch_units;
+    }
+
+    // Returns whether the channel should keep transferring data
+    fn end_or_pause_dma_transfer(&mut self, i: Index) -> bool {
+        let channel = &mut self.dma.channels[i.get() as usize];
+        if !matches!(channel.timing, Timing::GxFifo | Timing::DisplayFifo)
+            || channel.remaining_units == 0
+        {
             if channel.repeat {
                 if channel.control.dst_addr_control() == 3 {
                     let mask = !(1 | (channel.control.is_32_bit() as u32) << 1);
                     channel.cur_dst_addr = channel.dst_addr & mask;
                 }
-                if channel.timing == Timing::GxFifo {
+                if matches!(channel.timing, Timing::GxFifo | Timing::DisplayFifo) {
                     channel.remaining_units = channel.unit_count;
                 } else {
                     channel.remaining_batch_units = channel.unit_count;
@@ -158,10 +179,19 @@ impl<E: Engine> Arm9<E> {
                 self.irqs.request_dma(i);
             }
         }
+        // A repeating transfer that ran out of units in the middle of a scanline's requests
+        // restarts and keeps handling them
+        if self.dma.channels[i.get() as usize].timing == Timing::DisplayFifo
+            && self.display_fifo_dma_line_units[i.get() as usize] != 0
+        {
+            self.start_display_fifo_dma_batch(i);
+            return true;
+        }
         self.dma.running_channels &= !(1 << i.get());
         if self.dma.cur_channel == Some(i) {
             self.find_next_dma_channel();
         }
+        false
     }
 
     pub(in super::super) fn run_dma_transfer(emu: &mut Emu<E>, i: Index) {
@@ -225,8 +255,7 @@ impl<E: Engine> Arm9<E> {
                     {
                         channel.remaining_batch_units = channel.remaining_units.min(112);
                         channel.remaining_units -= channel.remaining_batch_units;
-                    } else {
-                        emu.arm9.end_or_pause_dma_transfer(i);
+                    } else if !emu.arm9.end_or_pause_dma_transfer(i) {
                         break;
                     }
                 }
@@ -287,8 +316,7 @@ impl<E: Engine> Arm9<E> {
                     {
                         channel.remaining_batch_units = channel.remaining_units.min(224);
                         channel.remaining_units -= channel.remaining_batch_units;
-                    } else {
-                        emu.arm9.end_or_pause_dma_transfer(i);
+                    } else if !emu.arm9.end_or_pause_dma_transfer(i) {
                         break;
                     }
                 }
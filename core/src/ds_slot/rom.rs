@@ -1,6 +1,7 @@
 mod empty;
 mod key1;
 pub use empty::Empty;
+pub mod fs;
 pub mod header;
 pub mod icon;
 pub mod normal;
@@ -0,0 +1,344 @@
+use super::{header::Header, Contents};
+use crate::utils::{ByteMutSlice, ByteSlice};
+use core::fmt;
+use std::error::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FsError {
+    InvalidFnt,
+    InvalidFat,
+    OutOfBounds,
+}
+
+impl Error for FsError {}
+
+impl fmt::Display for FsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FsError::InvalidFnt => f.write_str("invalid file name table"),
+            FsError::InvalidFat => f.write_str("invalid file allocation table"),
+            FsError::OutOfBounds => f.write_str("file system data out of ROM bounds"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct File {
+    pub id: u16,
+    pub path: String,
+    pub start: u32,
+    pub end: u32,
+}
+
+impl File {
+    #[inline]
+    pub fn len(&self) -> u32 {
+        self.end - self.start
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.end == self.start
+    }
+
+    pub fn read(&self, rom_contents: &mut impl Contents) -> Vec<u8> {
+        let mut data = vec![0; self.len() as usize];
+        rom_contents.read_slice(self.start as usize, ByteMutSlice::new(&mut data));
+        data
+    }
+}
+
+fn read_table(
+    rom_contents: &mut impl Contents,
+    offset: u32,
+    size: u32,
+) -> Result<Vec<u8>, FsError> {
+    if offset as u64 + size as u64 > rom_contents.len() as u64 {
+        return Err(FsError::OutOfBounds);
+    }
+    let mut data = vec![0; size as usize];
+    rom_contents.read_slice(offset as usize, ByteMutSlice::new(&mut data));
+    Ok(data)
+}
+
+// Lists all files in the NitroFS file system (excluding overlays, which aren't named), in directory
+// order
+pub fn read_files(header: &Header, rom_contents: &mut impl Contents) -> Result<Vec<File>, FsError> {
+    let fnt = read_table(rom_contents, header.fnt_offset(), header.fnt_size())?;
+    let fat = read_table(rom_contents, header.fat_offset(), header.fat_size())?;
+    let fnt_bytes = ByteSlice::new(&fnt);
+    let fat_bytes = ByteSlice::new(&fat);
+
+    if fnt.len() < 8 {
+        return Err(FsError::InvalidFnt);
+    }
+    // The root entry of the main table stores the total directory count in place of its parent ID
+    let dir_count = fnt_bytes.read_le::<u16>(6) as usize;
+    if dir_count == 0 || dir_count > 0x1000 || fnt.len() < dir_count * 8 {
+        return Err(FsError::InvalidFnt);
+    }
+
+    let mut files = Vec::new();
+    let mut visited = vec![false; dir_count];
+    let mut dir_stack = vec![(0, String::new())];
+
+    while let Some((dir_index, dir_path)) = dir_stack.pop() {
+        if visited[dir_index] {
+            return Err(FsError::InvalidFnt);
+        }
+        visited[dir_index] = true;
+
+        let mut pos = fnt_bytes.read_le::<u32>(dir_index * 8) as usize;
+        let mut file_id = fnt_bytes.read_le::<u16>(dir_index * 8 + 4);
+        let mut subdirs = Vec::new();
+
+        loop {
+            let Some(&type_len) = fnt.get(pos) else {
+                return Err(FsError::InvalidFnt);
+            };
+            pos += 1;
+            if type_len == 0 {
+                break;
+            }
+            let name_len = (type_len & 0x7F) as usize;
+            let Some(name) = fnt.get(pos..pos + name_len) else {
+                return Err(FsError::InvalidFnt);
+            };
+            pos += name_len;
+            let path = format!("{dir_path}/{}", String::from_utf8_lossy(name));
+
+            if type_len & 0x80 == 0 {
+                let fat_entry_offset = file_id as usize * 8;
+                if fat_entry_offset + 8 > fat.len() {
+                    return Err(FsError::InvalidFat);
+                }
+                let start = fat_bytes.read_le::<u32>(fat_entry_offset);
+                let end = fat_bytes.read_le::<u32>(fat_entry_offset + 4);
+                if end < start {
+                    return Err(FsError::InvalidFat);
+                }
+                if end as usize > rom_contents.len() {
+                    return Err(FsError::OutOfBounds);
+                }
+                files.push(File {
+                    id: file_id,
+                    path,
+                    start,
+                    end,
+                });
+                file_id = file_id.wrapping_add(1);
+            } else {
+                if pos + 2 > fnt.len() {
+                    return Err(FsError::InvalidFnt);
+                }
+                let subdir_index = (fnt_bytes.read_le::<u16>(pos) & 0xFFF) as usize;
+                pos += 2;
+                if subdir_index >= dir_count {
+                    return Err(FsError::InvalidFnt);
+                }
+                subdirs.push((subdir_index, path));
+            }
+        }
+
+        // Push in reverse so that subdirectories get visited in the order they're listed in
+        dir_stack.extend(subdirs.into_iter().rev());
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::BoxedByteSlice;
+
+    const FNT_OFFSET: usize = 0x200;
+    const FAT_OFFSET: usize = 0x400;
+    const ROM_LEN: usize = 0x800;
+
+    fn file_entry(name: &str) -> Vec<u8> {
+        let mut entry = vec![name.len() as u8];
+        entry.extend_from_slice(name.as_bytes());
+        entry
+    }
+
+    fn dir_entry(name: &str, dir_index: u16) -> Vec<u8> {
+        let mut entry = vec![0x80 | name.len() as u8];
+        entry.extend_from_slice(name.as_bytes());
+        entry.extend_from_slice(&(0xF000 | dir_index).to_le_bytes());
+        entry
+    }
+
+    // Builds a file name table from each directory's first file ID and entries
+    fn fnt_table(dirs: &[(u16, Vec<Vec<u8>>)]) -> Vec<u8> {
+        let mut main_table = Vec::new();
+        let mut sub_tables = Vec::new();
+        for (i, (first_file_id, entries)) in dirs.iter().enumerate() {
+            let parent = if i == 0 { dirs.len() as u16 } else { 0xF000 };
+            main_table
+                .extend_from_slice(&((dirs.len() * 8 + sub_tables.len()) as u32).to_le_bytes());
+            main_table.extend_from_slice(&first_file_id.to_le_bytes());
+            main_table.extend_from_slice(&parent.to_le_bytes());
+            for entry in entries {
+                sub_tables.extend_from_slice(entry);
+            }
+            sub_tables.push(0);
+        }
+        main_table.extend_from_slice(&sub_tables);
+        main_table
+    }
+
+    fn fat_table(files: &[(u32, u32)]) -> Vec<u8> {
+        files
+            .iter()
+            .flat_map(|&(start, end)| [start.to_le_bytes(), end.to_le_bytes()])
+            .flatten()
+            .collect()
+    }
+
+    fn read(fnt: &[u8], fat: &[u8]) -> Result<Vec<File>, FsError> {
+        read_with_sizes(fnt, fat, fnt.len() as u32, fat.len() as u32)
+    }
+
+    fn read_with_sizes(
+        fnt: &[u8],
+        fat: &[u8],
+        fnt_size: u32,
+        fat_size: u32,
+    ) -> Result<Vec<File>, FsError> {
+        let mut rom = BoxedByteSlice::new_zeroed(ROM_LEN);
+        rom.write_le(0x40, FNT_OFFSET as u32);
+        rom.write_le(0x44, fnt_size);
+        rom.write_le(0x48, FAT_OFFSET as u32);
+        rom.write_le(0x4C, fat_size);
+        rom[FNT_OFFSET..FNT_OFFSET + fnt.len()].copy_from_slice(fnt);
+        rom[FAT_OFFSET..FAT_OFFSET + fat.len()].copy_from_slice(fat);
+        let header_bytes = rom[..0x170].to_vec();
+        let header = Header::new(ByteSlice::new(&header_bytes)).unwrap();
+        read_files(&header, &mut rom)
+    }
+
+    fn valid_fnt() -> Vec<u8> {
+        fnt_table(&[
+            (0, vec![file_entry("a.bin"), dir_entry("sub", 1)]),
+            (1, vec![file_entry("b"), file_entry("c")]),
+        ])
+    }
+
+    #[test]
+    fn lists_files_in_directory_order() {
+        let files = read(
+            &valid_fnt(),
+            &fat_table(&[(0x600, 0x610), (0x610, 0x618), (0x618, 0x618)]),
+        )
+        .unwrap();
+        let files = files
+            .iter()
+            .map(|file| (file.id, file.path.as_str(), file.start, file.end))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            files,
+            [
+                (0, "/a.bin", 0x600, 0x610),
+                (1, "/sub/b", 0x610, 0x618),
+                (2, "/sub/c", 0x618, 0x618),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_file_contents() {
+        let mut rom = BoxedByteSlice::new_zeroed(ROM_LEN);
+        rom[0x600..0x604].copy_from_slice(&[1, 2, 3, 4]);
+        let file = File {
+            id: 0,
+            path: "/a".to_string(),
+            start: 0x601,
+            end: 0x604,
+        };
+        assert_eq!(file.len(), 3);
+        assert!(!file.is_empty());
+        assert_eq!(file.read(&mut rom), [2, 3, 4]);
+    }
+
+    #[test]
+    fn rejects_directory_cycles() {
+        let fnt = fnt_table(&[
+            (0, vec![dir_entry("sub", 1)]),
+            (0, vec![dir_entry("parent", 0)]),
+        ]);
+        assert_eq!(read(&fnt, &[]).err(), Some(FsError::InvalidFnt));
+
+        let fnt = fnt_table(&[(0, vec![dir_entry("x", 1), dir_entry("y", 1)]), (0, vec![])]);
+        assert_eq!(read(&fnt, &[]).err(), Some(FsError::InvalidFnt));
+    }
+
+    #[test]
+    fn rejects_invalid_file_name_tables() {
+        assert_eq!(read(&[0; 4], &[]).err(), Some(FsError::InvalidFnt));
+        assert_eq!(
+            read(
+                &fnt_table(&[(0, vec![dir_entry("sub", 2)]), (0, vec![])]),
+                &[]
+            )
+            .err(),
+            Some(FsError::InvalidFnt)
+        );
+
+        let mut fnt = valid_fnt();
+        // Directory count of 0
+        fnt[6..8].copy_from_slice(&[0, 0]);
+        assert_eq!(read(&fnt, &[]).err(), Some(FsError::InvalidFnt));
+
+        let mut fnt = valid_fnt();
+        // Directory count larger than the table
+        fnt[6..8].copy_from_slice(&0x100_u16.to_le_bytes());
+        assert_eq!(read(&fnt, &[]).err(), Some(FsError::InvalidFnt));
+
+        let mut fnt = valid_fnt();
+        // Sub-table offset past the end of the table
+        fnt[0..4].copy_from_slice(&0x1000_u32.to_le_bytes());
+        assert_eq!(read(&fnt, &[]).err(), Some(FsError::InvalidFnt));
+
+        // Missing terminator and name cut off by the end of the table
+        let fat = fat_table(&[(0x600, 0x610); 3]);
+        let fnt = valid_fnt();
+        assert_eq!(
+            read(&fnt[..fnt.len() - 1], &fat).err(),
+            Some(FsError::InvalidFnt)
+        );
+        assert_eq!(
+            read(&fnt[..fnt.len() - 2], &fat).err(),
+            Some(FsError::InvalidFnt)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_allocation_tables() {
+        assert_eq!(
+            read(&valid_fnt(), &fat_table(&[(0x600, 0x610), (0x610, 0x618)])).err(),
+            Some(FsError::InvalidFat)
+        );
+        assert_eq!(
+            read(&valid_fnt(), &fat_table(&[(0x610, 0x600); 3])).err(),
+            Some(FsError::InvalidFat)
+        );
+        assert_eq!(
+            read(&valid_fnt(), &fat_table(&[(0x600, ROM_LEN as u32 + 1); 3])).err(),
+            Some(FsError::OutOfBounds)
+        );
+    }
+
+    #[test]
+    fn rejects_tables_out_of_bounds() {
+        let fnt = valid_fnt();
+        assert_eq!(
+            read_with_sizes(&fnt, &[], (ROM_LEN - FNT_OFFSET) as u32 + 1, 0).err(),
+            Some(FsError::OutOfBounds)
+        );
+        assert_eq!(
+            read_with_sizes(&fnt, &[], fnt.len() as u32, u32::MAX).err(),
+            Some(FsError::OutOfBounds)
+        );
+    }
+}
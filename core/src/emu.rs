@@ -27,9 +27,7 @@ use crate::{
     wifi::WiFi,
     Model,
 };
-use core::fmt;
-#[cfg(feature = "xq-audio")]
-use core::num::NonZeroU32;
+use core::{fmt, num::NonZeroU32};
 use input::Input;
 use std::error::Error;
 use swram::Swram;
@@ -178,6 +176,10 @@ pub struct Builder {
     pub audio_custom_sample_rate: Option<NonZeroU32>,
     #[cfg(feature = "xq-audio")]
     pub audio_channel_interp_method: audio::ChannelInterpMethod,
+    pub battery_level: u64,
+    pub battery_drain_time: Option<NonZeroU32>,
+    pub battery_charge_time: Option<NonZeroU32>,
+    pub external_power: bool,
 }
 
 pub enum BuildError {
@@ -239,6 +241,10 @@ impl Builder {
             audio_custom_sample_rate: None,
             #[cfg(feature = "xq-audio")]
             audio_channel_interp_method: audio::ChannelInterpMethod::Nearest,
+            battery_level: spi::power::BATTERY_LEVEL_MAX,
+            battery_drain_time: None,
+            battery_charge_time: None,
+            external_power: false,
         }
     }
 
@@ -325,6 +331,10 @@ impl Builder {
             arm9,
             is_debugger: self.is_debugger,
         };
+        emu.spi.power.set_battery_level(self.battery_level);
+        emu.spi.power.battery_drain_time = self.battery_drain_time;
+        emu.spi.power.battery_charge_time = self.battery_charge_time;
+        emu.spi.power.set_external_power(self.external_power);
         Arm7::setup(&mut emu);
         Arm9::setup(&mut emu);
         emu.ds_slot.rom.setup(self.direct_boot);
@@ -483,6 +493,12 @@ impl<E: cpu::Engine> Emu<E> {
         self.swram
             .write_control(swram::Control(3), &mut self.arm7, &mut self.arm9);
         self.gpu.write_power_control(gpu::PowerControl(0x820F));
+        // Sound amplifier and both backlights enabled, as left by the firmware
+        self.spi.power.write_control(
+            spi::power::Control(0x0D),
+            &mut self.arm7.schedule,
+            &mut self.schedule,
+        );
 
         // ––––––––––––––––    Game boot code     ––––––––––––––––
 
@@ -657,6 +673,11 @@ impl<E: cpu::Engine> Emu<E> {
     ) -> RunOutput {
         if frame_starting {
             self.spi.tsc.start_frame(self.schedule.cur_time());
+            self.spi.power.update_battery(
+                self.schedule.cur_time(),
+                &mut self.arm7.schedule,
+                &mut self.schedule,
+            );
         }
         #[cfg(feature = "debugger-hooks")]
         {
@@ -1,5 +1,5 @@
 use super::Emu;
-use crate::{cpu, utils::Savestate};
+use crate::{cpu, spi::tsc::Calibration, utils::Savestate};
 use bitflags::bitflags;
 
 bitflags! {
@@ -143,12 +143,41 @@ impl<E: cpu::Engine> Emu<E> {
         self.input.key_irq_triggered[ARM9 as usize] = triggered;
     }
 
+    /// Sets the touched screen position, in 1/16 pixel units; it's converted to raw touch screen
+    /// controller values through the calibration currently stored in the firmware.
     pub fn set_touch_pos(&mut self, pos: [u16; 2]) {
-        self.spi.tsc.set_x_pos(pos[0]);
-        self.spi.tsc.set_y_pos(pos[1]);
+        let calibration = Calibration::from_firmware(self.spi.firmware.contents())
+            .unwrap_or(Calibration::IDENTITY);
+        self.set_raw_touch_pos(calibration.screen_to_adc(pos));
+    }
+
+    pub fn set_raw_touch_pos(&mut self, adc_pos: [u16; 2]) {
+        self.spi.tsc.set_x_pos(adc_pos[0]);
+        self.spi.tsc.set_y_pos(adc_pos[1]);
         self.spi.tsc.set_pen_down(true, &mut self.input.status);
     }
 
+    #[inline]
+    pub fn lid_closed(&self) -> bool {
+        self.input.status.lid_closed()
+    }
+
+    pub fn set_lid_closed(&mut self, value: bool) {
+        let was_closed = self.input.status.lid_closed();
+        self.input.status.set_lid_closed(value);
+        if was_closed && !value {
+            self.arm7.irqs.write_requested(
+                self.arm7.irqs.requested().with_lid_opened(true),
+                &mut self.arm7.schedule,
+            );
+        }
+    }
+
+    #[inline]
+    pub fn set_touch_pressure(&mut self, pressure: u16) {
+        self.spi.tsc.set_pressure(pressure);
+    }
+
     pub fn end_touch(&mut self) {
         self.spi.tsc.clear_x_pos();
         self.spi.tsc.clear_y_pos();
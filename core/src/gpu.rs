@@ -344,6 +344,7 @@ impl Gpu {
             }
         }
         if emu.gpu.vcount < SCREEN_HEIGHT as u16 {
+            emu.gpu.engine_2d_a.latch_main_mem_display_scanline();
             if emu.gpu.cur_scanline < SCREEN_HEIGHT as u32 {
                 emu.gpu.renderer_2d.start_scanline(
                     emu.gpu.cur_scanline as u8,
@@ -388,6 +389,16 @@ impl Gpu {
             emu.gpu.disp_status_7.set_vblank(false);
             emu.gpu.disp_status_9.set_vblank(false);
         }
+        // Renderers read whole scanlines at once, so the main memory display FIFO gets filled one
+        // scanline in advance
+        if emu.gpu.power_control.display_enabled()
+            && emu.gpu.engine_2d_a.main_mem_display_fifo_used()
+            && (emu.gpu.vcount < (SCREEN_HEIGHT - 1) as u16
+                || emu.gpu.vcount == (TOTAL_SCANLINES - 1) as u16)
+        {
+            emu.arm9
+                .start_dma_transfers_with_timing::<{ arm9::dma::Timing::DisplayFifo }>();
+        }
         emu.schedule
             .set_event(event_slots::GPU, emu::Event::Gpu(Event::EndHDraw));
         emu.schedule
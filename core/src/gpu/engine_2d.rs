@@ -2,6 +2,7 @@ mod io;
 mod renderer;
 pub use renderer::Renderer;
 
+use super::{Scanline, SCREEN_WIDTH};
 use crate::utils::{LoadableInPlace, Savestate, Storable};
 use core::marker::PhantomData;
 
@@ -325,6 +326,11 @@ pub struct Engine2d<R: Role> {
     capture_control: CaptureControl,
     capture_enabled_in_frame: bool,
     capture_height: u8,
+    // Pixels written through DISP_MMEM_FIFO for the next scanline, and the ones latched for the
+    // current one in main memory display mode
+    main_mem_display_fifo: Scanline<u16>,
+    main_mem_display_fifo_len: u16,
+    main_mem_display_scanline: Scanline<u16>,
 }
 
 impl<R: Role> Engine2d<R> {
@@ -370,6 +376,9 @@ impl<R: Role> Engine2d<R> {
             capture_control: CaptureControl(0),
             capture_enabled_in_frame: false,
             capture_height: 128,
+            main_mem_display_fifo: Scanline([0; SCREEN_WIDTH]),
+            main_mem_display_fifo_len: 0,
+            main_mem_display_scanline: Scanline([0; SCREEN_WIDTH]),
         }
     }
 
@@ -523,6 +532,38 @@ impl<R: Role> Engine2d<R> {
         self.capture_height
     }
 
+    #[inline]
+    pub fn main_mem_display_scanline(&self) -> &Scanline<u16> {
+        &self.main_mem_display_scanline
+    }
+
+    #[inline]
+    pub fn write_main_mem_display_fifo(&mut self, value: u16) {
+        // Pixels past the end of the scanline are dropped
+        if R::IS_A && (self.main_mem_display_fifo_len as usize) < SCREEN_WIDTH {
+            self.main_mem_display_fifo.0[self.main_mem_display_fifo_len as usize] = value;
+            self.main_mem_display_fifo_len += 1;
+        }
+    }
+
+    // The main memory display FIFO is read both by main memory display mode and by display
+    // capture, when using it as source B
+    #[inline]
+    pub(super) fn main_mem_display_fifo_used(&self) -> bool {
+        R::IS_A
+            && (self.control.display_mode_a() == 3
+                || (self.capture_control.enabled() && self.capture_control.src_b_display_fifo()))
+    }
+
+    pub(super) fn latch_main_mem_display_scanline(&mut self) {
+        if R::IS_A {
+            if self.main_mem_display_fifo_used() {
+                self.main_mem_display_scanline = self.main_mem_display_fifo;
+            }
+            self.main_mem_display_fifo_len = 0;
+        }
+    }
+
     pub(super) fn start_vblank(&mut self) {
         if R::IS_A && self.capture_enabled_in_frame {
             self.capture_control.set_enabled(false);
@@ -295,6 +295,7 @@ impl<R: Role> Engine2d<R> {
             0x66 => self.write_capture_control(CaptureControl(
                 (self.capture_control.0 & 0x0000_FFFF) | (value as u32) << 16,
             )),
+            0x68 | 0x6A => self.write_main_mem_display_fifo(value),
             0x6C => self.write_master_brightness_control(BrightnessControl(value)),
             _ =>
             {
@@ -400,6 +401,10 @@ impl<R: Role> Engine2d<R> {
             }
             0x54 => self.write_brightness_coeff(value as u8),
             0x64 => self.write_capture_control(CaptureControl(value)),
+            0x68 => {
+                self.write_main_mem_display_fifo(value as u16);
+                self.write_main_mem_display_fifo((value >> 16) as u16);
+            }
             0x6C => self.write_master_brightness_control(BrightnessControl(value as u16)),
             _ =>
             {
@@ -8,6 +8,10 @@ pub trait Renderer {
     fn uses_lcdc_vram_tracking(&self) -> bool;
 
     fn framebuffer(&self) -> &Framebuffer;
+    // Returns the resolution scale shift and contents of a framebuffer upscaled from
+    // `framebuffer`, if the renderer outputs one; screens are stacked like in `framebuffer`, with
+    // each line being `256 << resolution_scale_shift` pixels wide
+    fn hi_res_framebuffer(&self) -> Option<(u8, &[u32])>;
 
     fn start_prerendering_objs(
         &mut self,
@@ -4,6 +4,8 @@ mod vertex;
 pub use vertex::{Color, InterpColor, ScreenCoords, ScreenVertex, TexCoords};
 mod renderer;
 pub use renderer::{AccelRendererRx, RendererTx, SoftRendererRx};
+mod recorder;
+pub use recorder::{RecordedCommand, Recording};
 
 use crate::{
     cpu::{
@@ -12,14 +14,16 @@ use crate::{
         Schedule,
     },
     emu::{self, Emu},
-    gpu::vram::Vram,
+    gpu::{vram::Vram, HBLANK_DURATION, HDRAW_DURATION, SCREEN_HEIGHT},
     utils::{load_slice_in_place, schedule::RawTimestamp, store_slice, Fifo, Savestate},
 };
 use core::{
+    array,
     mem::{replace, transmute, MaybeUninit},
     simd::{i32x4, u32x2, u64x2, SimdOrd},
 };
 use matrix::{Matrix, MatrixBuffer};
+use recorder::{Recorder, ReplayRendererTx};
 use vertex::Vertex;
 
 proc_bitfield::bitfield! {
@@ -213,6 +217,51 @@ pub struct RenderingState {
     pub tex_pal_dirty: u8,
 }
 
+// The geometry engine's state that carries over between frames, saved at the start of recorded
+// frames so that their GX commands can be replayed
+#[derive(Clone, Savestate)]
+pub struct GeometryState {
+    mtx_mode: MatrixMode,
+    proj_stack: Matrix,
+    pos_vec_stack: [[Matrix; 2]; 32],
+    tex_stack: Matrix,
+    proj_stack_pointer: bool,
+    pos_vec_stack_pointer: u8,
+    cur_proj_mtx: Matrix,
+    cur_pos_vec_mtxs: [Matrix; 2],
+    cur_tex_mtx: Matrix,
+    tex_params: TextureParams,
+    tex_palette_base: u16,
+
+    viewport_origin: u32x2,
+    viewport_size: u64x2,
+
+    vert_color: Color,
+    vert_normal: [i16; 3],
+    tex_coords: TexCoords,
+    transformed_tex_coords: TexCoords,
+    last_vtx_coords: [i16; 3],
+
+    shininess_table_enabled: bool,
+    diffuse_color: i32x4,
+    ambient_color: i32x4,
+    specular_color: i32x4,
+    emission_color: i32x4,
+    shininess_table: [u8; 128],
+    lights: [Light; 4],
+
+    next_poly_attrs: PolygonAttrs,
+    cur_poly_attrs: PolygonAttrs,
+
+    cur_prim_type: PrimitiveType,
+    cur_prim_verts: [Vertex; 4],
+    last_strip_prim_vert_indices: [VertexAddr; 2],
+    cur_prim_max_verts: PrimMaxVerts,
+    cur_prim_vert_index: PrimVertIndex,
+    cur_strip_prim_is_odd: bool,
+    connect_to_last_strip_prim: bool,
+}
+
 #[derive(Savestate)]
 #[load(in_place_only)]
 pub struct Engine3d {
@@ -221,6 +270,8 @@ pub struct Engine3d {
     logger: slog::Logger,
     #[savestate(skip)]
     pub(super) renderer_tx: Box<dyn RendererTx>,
+    #[savestate(skip)]
+    recorder: Recorder,
 
     pub(super) gx_enabled: bool,
     pub(super) rendering_enabled: bool,
@@ -232,6 +283,9 @@ pub struct Engine3d {
     cur_packed_commands: u32,
     remaining_command_params: u8,
     command_finish_time: emu::Timestamp,
+    // Remaining cycles of polygon setup for the last submitted polygon; it runs in parallel with
+    // the following commands, until another vertex needs to be processed
+    poly_setup_cycles: u16,
     gx_fifo_stalled: bool,
     queued_mtx_stack_cmds: u16,
     queued_test_cmd_entries: u16,
@@ -260,6 +314,8 @@ pub struct Engine3d {
     tex_coords: TexCoords,
     transformed_tex_coords: TexCoords,
     last_vtx_coords: [i16; 3],
+    pos_test_result: [i32; 4],
+    vec_test_result: [i16; 3],
 
     shininess_table_enabled: bool,
     diffuse_color: i32x4,
@@ -294,6 +350,7 @@ pub struct Engine3d {
     #[store(with = "store_slice(&mut poly_ram[..*poly_ram_level as usize], save)?")]
     poly_ram: Box<[Polygon; 2048]>,
 
+    line_buffer_level: u8,
     rendering_state: RenderingState,
 }
 
@@ -344,6 +401,65 @@ static CMD_PARAMS: [u8; 0x100] = {
     params
 };
 
+// Cycle counts (in system clock cycles) for each command, excluding lighting, polygon setup and
+// MTX_MODE 2 costs, from GBATEK's geometry command list; SWAP_BUFFERS is special-cased, as it
+// waits for VBlank
+static CMD_CYCLES: [u16; 0x100] = {
+    let mut cycles = [1; 0x100];
+
+    macro_rules! set {
+        ($cycles: expr, [$($cmd: expr),*]) => {
+            $(
+                cycles[$cmd] = $cycles;
+            )*
+        };
+    }
+
+    set!(4, [0x30, 0x31]);
+    set!(5, [0x72]);
+    set!(6, [0x32]);
+    set!(8, [0x24, 0x25, 0x26, 0x27, 0x28]);
+    set!(9, [0x21, 0x23, 0x71]);
+    set!(17, [0x11, 0x13]);
+    set!(19, [0x15]);
+    set!(22, [0x1B, 0x1C]);
+    set!(28, [0x1A]);
+    set!(30, [0x17]);
+    set!(31, [0x19]);
+    set!(32, [0x34]);
+    set!(34, [0x16]);
+    set!(35, [0x18]);
+    set!(36, [0x12, 0x14]);
+    set!(103, [0x70]);
+
+    cycles
+};
+
+// Matrix multiplications and translations take this many extra cycles in MTX_MODE 2, as both the
+// position and vector matrices need to be updated (GBATEK)
+const POS_VEC_MTX_EXTRA_CYCLES: u16 = 30;
+
+// Polygon setup takes this many cycles for each vertex of the clipped polygon, plus an extra cost
+// for each vertex generated by clipping it against the frustum.
+// NOT HARDWARE-ACCURATE: neither GBATEK nor melonDS document these costs, so they're rough
+// estimates; they only delay following vertex commands (and thus GXSTAT's busy bit and the FIFO
+// level) by a plausible amount, and won't reproduce hardware slowdown exactly.
+const POLY_SETUP_CYCLES_PER_VERT: u16 = 9;
+const CLIP_CYCLES_PER_NEW_VERT: u16 = 9;
+
+// The rendering engine starts 48 scanlines before the first visible one and can buffer that many
+// rendered lines, but the level reported through RDLINES_COUNT never goes above 46 (GBATEK)
+const LINE_BUFFER_LINES: usize = 48;
+const MAX_LINE_BUFFER_LEVEL: u8 = 46;
+// Costs for rendering a scanline: a fixed per-pixel pass, plus a cost for each polygon that
+// intersects it.
+// NOT HARDWARE-ACCURATE: the rendering engine's timings aren't documented by GBATEK, and these are
+// placeholder values rather than measurements (the real cost likely also depends on the polygons'
+// horizontal span), so RDLINES_COUNT only drops for scenes that are heavy in a broadly similar
+// way to hardware, not at the same polygon counts.
+const LINE_BASE_RENDER_CYCLES: u32 = 512;
+const LINE_POLY_RENDER_CYCLES: u32 = 16;
+
 impl Engine3d {
     pub(super) fn new(
         renderer_tx: Box<dyn RendererTx>,
@@ -361,10 +477,22 @@ impl Engine3d {
             emu::Event::Engine3dCommandFinished,
         );
 
+        Self::new_detached(
+            renderer_tx,
+            #[cfg(feature = "log")]
+            logger,
+        )
+    }
+
+    fn new_detached(
+        renderer_tx: Box<dyn RendererTx>,
+        #[cfg(feature = "log")] logger: slog::Logger,
+    ) -> Self {
         Engine3d {
             #[cfg(feature = "log")]
             logger,
             renderer_tx,
+            recorder: Recorder::new(),
 
             gx_enabled: false,
             rendering_enabled: false,
@@ -377,6 +505,7 @@ impl Engine3d {
             cur_packed_commands: 0,
             remaining_command_params: 0,
             command_finish_time: emu::Timestamp(0),
+            poly_setup_cycles: 0,
             gx_fifo_stalled: false,
             queued_mtx_stack_cmds: 0,
             queued_test_cmd_entries: 0,
@@ -405,6 +534,8 @@ impl Engine3d {
             tex_coords: TexCoords::splat(0),
             transformed_tex_coords: TexCoords::splat(0),
             last_vtx_coords: [0; 3],
+            pos_test_result: [0; 4],
+            vec_test_result: [0; 3],
             shininess_table_enabled: false,
             diffuse_color: i32x4::splat(0),
             ambient_color: i32x4::splat(0),
@@ -433,6 +564,7 @@ impl Engine3d {
             vert_ram: Box::new([ScreenVertex::new(); 6144]),
             poly_ram: unsafe { Box::new_zeroed().assume_init() },
 
+            line_buffer_level: MAX_LINE_BUFFER_LEVEL,
             rendering_state: RenderingState {
                 control: RenderingControl(0),
                 w_buffering: false,
@@ -467,6 +599,151 @@ impl Engine3d {
         self.rendering_state.tex_pal_dirty = 0xF;
     }
 
+    // Starts recording the GX commands and rendering data for the next full frame, starting after
+    // the next SWAP_BUFFERS command gets executed; the recording can be retrieved through
+    // `take_recording` once that frame has started rendering
+    pub fn start_recording(&mut self) {
+        self.recorder.start();
+    }
+
+    pub fn take_recording(&mut self) -> Option<Box<Recording>> {
+        self.recorder.take()
+    }
+
+    fn geometry_state(&self) -> GeometryState {
+        GeometryState {
+            mtx_mode: self.mtx_mode,
+            proj_stack: self.proj_stack,
+            pos_vec_stack: self.pos_vec_stack,
+            tex_stack: self.tex_stack,
+            proj_stack_pointer: self.proj_stack_pointer,
+            pos_vec_stack_pointer: self.pos_vec_stack_pointer,
+            cur_proj_mtx: self.cur_proj_mtx,
+            cur_pos_vec_mtxs: self.cur_pos_vec_mtxs,
+            cur_tex_mtx: self.cur_tex_mtx,
+            tex_params: self.tex_params,
+            tex_palette_base: self.tex_palette_base,
+
+            viewport_origin: self.viewport_origin,
+            viewport_size: self.viewport_size,
+
+            vert_color: self.vert_color,
+            vert_normal: self.vert_normal,
+            tex_coords: self.tex_coords,
+            transformed_tex_coords: self.transformed_tex_coords,
+            last_vtx_coords: self.last_vtx_coords,
+
+            shininess_table_enabled: self.shininess_table_enabled,
+            diffuse_color: self.diffuse_color,
+            ambient_color: self.ambient_color,
+            specular_color: self.specular_color,
+            emission_color: self.emission_color,
+            shininess_table: self.shininess_table,
+            lights: self.lights,
+
+            next_poly_attrs: self.next_poly_attrs,
+            cur_poly_attrs: self.cur_poly_attrs,
+
+            cur_prim_type: self.cur_prim_type,
+            cur_prim_verts: self.cur_prim_verts,
+            last_strip_prim_vert_indices: self.last_strip_prim_vert_indices,
+            cur_prim_max_verts: self.cur_prim_max_verts,
+            cur_prim_vert_index: self.cur_prim_vert_index,
+            cur_strip_prim_is_odd: self.cur_strip_prim_is_odd,
+            connect_to_last_strip_prim: self.connect_to_last_strip_prim,
+        }
+    }
+
+    fn record_swap_buffers_executed(&mut self) {
+        if self.recorder.is_waiting_for_frame_start() {
+            let geometry = Box::new(self.geometry_state());
+            self.recorder.start_frame(geometry);
+        } else {
+            self.recorder.end_frame();
+        }
+    }
+
+    // Creates a geometry engine that isn't attached to an emulator instance, starting from the
+    // state saved at the start of a recorded frame, to replay its commands through
+    // `replay_commands`
+    #[must_use]
+    pub fn new_replay(geometry: &GeometryState) -> Self {
+        let mut engine_3d = Self::new_detached(
+            Box::new(ReplayRendererTx),
+            #[cfg(feature = "log")]
+            slog::Logger::root(slog::Discard, slog::o!()),
+        );
+        engine_3d.mtx_mode = geometry.mtx_mode;
+        engine_3d.proj_stack = geometry.proj_stack;
+        engine_3d.pos_vec_stack = geometry.pos_vec_stack;
+        engine_3d.tex_stack = geometry.tex_stack;
+        engine_3d.proj_stack_pointer = geometry.proj_stack_pointer;
+        engine_3d.pos_vec_stack_pointer = geometry.pos_vec_stack_pointer;
+        engine_3d.cur_proj_mtx = geometry.cur_proj_mtx;
+        engine_3d.cur_pos_vec_mtxs = geometry.cur_pos_vec_mtxs;
+        engine_3d.clip_mtx_needs_recalculation = true;
+        engine_3d.cur_tex_mtx = geometry.cur_tex_mtx;
+        engine_3d.tex_params = geometry.tex_params;
+        engine_3d.tex_palette_base = geometry.tex_palette_base;
+
+        engine_3d.viewport_origin = geometry.viewport_origin;
+        engine_3d.viewport_size = geometry.viewport_size;
+
+        engine_3d.vert_color = geometry.vert_color;
+        engine_3d.vert_normal = geometry.vert_normal;
+        engine_3d.tex_coords = geometry.tex_coords;
+        engine_3d.transformed_tex_coords = geometry.transformed_tex_coords;
+        engine_3d.last_vtx_coords = geometry.last_vtx_coords;
+
+        engine_3d.shininess_table_enabled = geometry.shininess_table_enabled;
+        engine_3d.diffuse_color = geometry.diffuse_color;
+        engine_3d.ambient_color = geometry.ambient_color;
+        engine_3d.specular_color = geometry.specular_color;
+        engine_3d.emission_color = geometry.emission_color;
+        engine_3d.shininess_table = geometry.shininess_table;
+        engine_3d.lights = geometry.lights;
+
+        engine_3d.next_poly_attrs = geometry.next_poly_attrs;
+        engine_3d.cur_poly_attrs = geometry.cur_poly_attrs;
+
+        engine_3d.cur_prim_type = geometry.cur_prim_type;
+        engine_3d.cur_prim_verts = geometry.cur_prim_verts;
+        engine_3d.last_strip_prim_vert_indices = geometry.last_strip_prim_vert_indices;
+        engine_3d.cur_prim_max_verts = geometry.cur_prim_max_verts;
+        engine_3d.cur_prim_vert_index = geometry.cur_prim_vert_index;
+        engine_3d.cur_strip_prim_is_odd = geometry.cur_strip_prim_is_odd;
+        engine_3d.connect_to_last_strip_prim = geometry.connect_to_last_strip_prim;
+
+        engine_3d
+    }
+
+    // Executes recorded commands up to the SWAP_BUFFERS command ending the frame, then sorts the
+    // submitted polygons like swapping buffers would, leaving the frame's contents in vertex and
+    // polygon RAM; returns `false` if the commands end before that SWAP_BUFFERS command
+    pub fn replay_commands(&mut self, commands: &[RecordedCommand]) -> bool {
+        let mut commands = commands.iter();
+        while let Some(&RecordedCommand {
+            command,
+            param: first_param,
+        }) = commands.next()
+        {
+            if command == 0 {
+                continue;
+            }
+            let params = self.params_for_command(command);
+            if commands.len() < (params as usize).saturating_sub(1) {
+                return false;
+            }
+            if self.execute_command(command, first_param, |_| {
+                commands.next().map_or(0, |command| command.param)
+            }) {
+                self.sort_polys();
+                return true;
+            }
+        }
+        false
+    }
+
     #[inline]
     pub fn gx_fifo_stalled(&self) -> bool {
         self.gx_fifo_stalled
@@ -526,14 +803,23 @@ impl Engine3d {
 
     #[inline]
     pub fn line_buffer_level(&self) -> u8 {
-        // TODO
         if self.rendering_enabled {
-            46
+            self.line_buffer_level
         } else {
             0
         }
     }
 
+    #[inline]
+    pub fn pos_test_result(&self) -> [i32; 4] {
+        self.pos_test_result
+    }
+
+    #[inline]
+    pub fn vec_test_result(&self) -> [i16; 3] {
+        self.vec_test_result
+    }
+
     #[inline]
     pub fn rendering_state(&self) -> &RenderingState {
         &self.rendering_state
@@ -701,6 +987,39 @@ impl Engine3d {
         }
     }
 
+    fn command_cycles(&mut self, command: u8) -> u16 {
+        let cycles = CMD_CYCLES[command as usize];
+        match command {
+            0x21 => {
+                // Each enabled light after the first one takes an extra cycle
+                cycles + (self.cur_poly_attrs.lights_mask().count_ones() as u16).saturating_sub(1)
+            }
+            0x23..=0x28 => {
+                // Vertices can't be processed until the setup for the last polygon is done
+                cycles + replace(&mut self.poly_setup_cycles, 0)
+            }
+            _ => {
+                let cycles = if matches!(command, 0x18 | 0x19 | 0x1A | 0x1C)
+                    && self.mtx_mode == MatrixMode::PositionVector
+                {
+                    cycles + POS_VEC_MTX_EXTRA_CYCLES
+                } else {
+                    cycles
+                };
+                self.poly_setup_cycles = self.poly_setup_cycles.saturating_sub(cycles);
+                cycles
+            }
+        }
+    }
+
+    unsafe fn read_param_unchecked(&mut self) -> u32 {
+        if self.gx_pipe.is_empty() {
+            self.gx_fifo.read_unchecked().param
+        } else {
+            self.gx_pipe.read_unchecked().param
+        }
+    }
+
     fn refill_gx_pipe(&mut self, arm9: &mut Arm9<impl cpu::Engine>, empty: usize) {
         if self.gx_pipe.len() > 2 {
             return;
@@ -791,6 +1110,115 @@ impl Engine3d {
         self.vert_color = rgb5_to_rgb6(color.simd_min(i32x4::splat(0x1F)).cast());
     }
 
+    fn box_test(&mut self, pos: [i16; 3], size: [i16; 3]) -> bool {
+        // Vertex indices of each face of the box, in winding order; bits 0-2 of a corner's index
+        // select whether the box size gets added to its X, Y and Z coordinates respectively
+        const FACES: [[usize; 4]; 6] = [
+            [0, 2, 6, 4],
+            [1, 3, 7, 5],
+            [0, 1, 5, 4],
+            [2, 3, 7, 6],
+            [0, 1, 3, 2],
+            [4, 5, 7, 6],
+        ];
+
+        if self.clip_mtx_needs_recalculation {
+            self.update_clip_mtx();
+        }
+
+        let corners: [[i64; 4]; 8] = array::from_fn(|i| {
+            let coords: [i32; 3] = array::from_fn(|axis| {
+                pos[axis] as i32
+                    + if i >> axis & 1 != 0 {
+                        size[axis] as i32
+                    } else {
+                        0
+                    }
+            });
+            self.cur_clip_mtx
+                .mul_left_vec3::<i32, i32>(coords)
+                .cast::<i64>()
+                .to_array()
+        });
+
+        // Trivially accept boxes with a corner inside the view volume, and reject the ones with all
+        // corners outside of the same plane
+        let outcodes = corners.map(|coords| {
+            (0..3).fold(0_u8, |acc, axis| {
+                acc | ((coords[axis] > coords[3]) as u8) << (axis << 1)
+                    | ((coords[axis] < -coords[3]) as u8) << (axis << 1 | 1)
+            })
+        });
+        if outcodes.contains(&0) {
+            return true;
+        }
+        if outcodes.iter().fold(0x3F, |acc, outcode| acc & outcode) != 0 {
+            return false;
+        }
+
+        // Otherwise, clip every face against the view volume and check whether any part of it is
+        // left; each plane can add at most one vertex to a convex polygon, so a quad can end up
+        // with 10 of them at most
+        FACES.iter().any(|face| {
+            let mut verts = [[0_i64; 4]; 10];
+            let mut verts_len = 4;
+            for (vert, &i) in verts.iter_mut().zip(face) {
+                *vert = corners[i];
+            }
+            for plane in 0..6 {
+                let axis = plane >> 1;
+                let sign = if plane & 1 == 0 { 1 } else { -1 };
+                let dist = |coords: &[i64; 4]| coords[3] - sign * coords[axis];
+                let mut clipped_verts = [[0_i64; 4]; 10];
+                let mut clipped_verts_len = 0;
+                for (i, &vert) in verts[..verts_len].iter().enumerate() {
+                    let next = verts[if i + 1 == verts_len { 0 } else { i + 1 }];
+                    let (vert_dist, next_dist) = (dist(&vert), dist(&next));
+                    if vert_dist >= 0 {
+                        clipped_verts[clipped_verts_len] = vert;
+                        clipped_verts_len += 1;
+                    }
+                    if (vert_dist >= 0) != (next_dist >= 0) {
+                        clipped_verts[clipped_verts_len] = array::from_fn(|j| {
+                            vert[j]
+                                + ((next[j] - vert[j]) as i128 * vert_dist as i128
+                                    / (vert_dist - next_dist) as i128)
+                                    as i64
+                        });
+                        clipped_verts_len += 1;
+                    }
+                }
+                if clipped_verts_len == 0 {
+                    return false;
+                }
+                verts = clipped_verts;
+                verts_len = clipped_verts_len;
+            }
+            true
+        })
+    }
+
+    fn pos_test(&mut self, coords: [i16; 3]) {
+        self.last_vtx_coords = coords;
+
+        if self.clip_mtx_needs_recalculation {
+            self.update_clip_mtx();
+        }
+
+        self.pos_test_result = self
+            .cur_clip_mtx
+            .mul_left_vec3::<i16, i32>(coords)
+            .to_array();
+    }
+
+    fn vec_test(&mut self, vec: [i16; 3]) {
+        // The vector is in 1.0.9 format, while the result is 4.12 with bits 12-15 set to the sign
+        let [x, y, z, _] = self.cur_pos_vec_mtxs[1]
+            .mul_left_vec3_zero::<i16, i32, 9>(vec)
+            .to_array();
+        self.vec_test_result = [x, y, z].map(|coord| (coord as i16) << 3 >> 3);
+    }
+
     fn add_vert(&mut self, coords: [i16; 3]) {
         if self.poly_ram_level as usize == self.poly_ram.len() {
             self.rendering_state
@@ -908,6 +1336,7 @@ impl Engine3d {
                         vert.coords[$axis_i] = $sign * vert.coords[3];
                         $output[clipped_verts_len] = MaybeUninit::new(vert);
                         clipped_verts_len += 1;
+                        self.poly_setup_cycles += CLIP_CYCLES_PER_NEW_VERT;
                     }
                 }
             };
@@ -1012,6 +1441,10 @@ impl Engine3d {
             return;
         }
 
+        // The vertex command that submitted this polygon already waited for the previous one's
+        // setup, so only this polygon's clipping costs have been accumulated at this point
+        self.poly_setup_cycles += POLY_SETUP_CYCLES_PER_VERT * clipped_verts_len as u16;
+
         let mut poly = &mut self.poly_ram[self.poly_ram_level as usize];
         self.poly_ram_level += 1;
         poly.vertices_len = PolyVertsLen::new(clipped_verts_len as u8);
@@ -1152,6 +1585,76 @@ impl Engine3d {
         }
     }
 
+    fn update_line_buffer_level(&mut self) {
+        const SCANLINE_CYCLES: u32 = (HDRAW_DURATION.0 + HBLANK_DURATION.0) as u32;
+
+        let mut line_poly_count_deltas = [0_i32; SCREEN_HEIGHT + 1];
+        for poly in &self.poly_ram[..self.poly_ram_level as usize] {
+            // `bot_y` is exclusive
+            let top_y = (poly.top_y as usize).min(SCREEN_HEIGHT);
+            let bot_y = (poly.bot_y as usize).min(SCREEN_HEIGHT);
+            if top_y < bot_y {
+                line_poly_count_deltas[top_y] += 1;
+                line_poly_count_deltas[bot_y] -= 1;
+            }
+        }
+
+        // Simulate rendering: a line can't start being rendered until there's space for it in the
+        // buffer, i.e. until the line LINE_BUFFER_LINES lines before it has been displayed, and
+        // the level is the minimum amount of lines ready in advance when each line is displayed
+        let display_time = |line: usize| (LINE_BUFFER_LINES + line) as u32 * SCANLINE_CYCLES;
+        let mut line_finish_times = [0; SCREEN_HEIGHT];
+        let mut line_poly_count = 0;
+        let mut time = 0;
+        for (line, finish_time) in line_finish_times.iter_mut().enumerate() {
+            line_poly_count += line_poly_count_deltas[line];
+            if let Some(freed_line) = line.checked_sub(LINE_BUFFER_LINES) {
+                time = time.max(display_time(freed_line));
+            }
+            time += LINE_BASE_RENDER_CYCLES + LINE_POLY_RENDER_CYCLES * line_poly_count as u32;
+            *finish_time = time;
+        }
+
+        let mut level = MAX_LINE_BUFFER_LEVEL;
+        let mut rendered_lines = 0;
+        for line in 0..SCREEN_HEIGHT {
+            while rendered_lines < SCREEN_HEIGHT
+                && line_finish_times[rendered_lines] <= display_time(line)
+            {
+                rendered_lines += 1;
+            }
+            // Once all lines are rendered, the buffer can't run out anymore
+            if rendered_lines == SCREEN_HEIGHT {
+                break;
+            }
+            level = level.min(rendered_lines.saturating_sub(line + 1) as u8);
+        }
+        self.line_buffer_level = level;
+    }
+
+    fn sort_polys(&mut self) {
+        // According to melonDS, the sort order is determined by these things, in order of
+        // decreasing priority:
+        // - Being translucent/opaque (opaque polygons always come first, GBATEK says this too)
+        // - Bottom Y (lower first)
+        // - Top Y (lower first)
+        // - Submit order (thus needing a stable sort)
+        let polys = &mut self.poly_ram[..self.poly_ram_level as usize];
+        if self.swap_buffers_attrs.translucent_auto_sort_disabled() {
+            polys.sort_by_key(|poly| {
+                if poly.is_translucent {
+                    0x1_0000
+                } else {
+                    (poly.bot_y as u32) << 8 | poly.top_y as u32
+                }
+            });
+        } else {
+            polys.sort_by_key(|poly| {
+                (poly.is_translucent as u32) << 16 | (poly.bot_y as u32) << 8 | poly.top_y as u32
+            });
+        }
+    }
+
     pub(super) fn swap_buffers_waiting(&self) -> bool {
         self.command_finish_time.0 == RawTimestamp::MAX
     }
@@ -1164,40 +1667,19 @@ impl Engine3d {
 
     pub(super) fn swap_buffers(emu: &mut Emu<impl cpu::Engine>) {
         if emu.gpu.engine_3d.rendering_enabled {
-            // According to melonDS, the sort order is determined by these things, in order of
-            // decreasing priority:
-            // - Being translucent/opaque (opaque polygons always come first, GBATEK says this too)
-            // - Bottom Y (lower first)
-            // - Top Y (lower first)
-            // - Submit order (thus needing a stable sort)
-            if emu
-                .gpu
-                .engine_3d
-                .swap_buffers_attrs
-                .translucent_auto_sort_disabled()
-            {
-                emu.gpu.engine_3d.poly_ram[..emu.gpu.engine_3d.poly_ram_level as usize]
-                    .sort_by_key(|poly| {
-                        if poly.is_translucent {
-                            0x1_0000
-                        } else {
-                            (poly.bot_y as u32) << 8 | poly.top_y as u32
-                        }
-                    });
-            } else {
-                emu.gpu.engine_3d.poly_ram[..emu.gpu.engine_3d.poly_ram_level as usize]
-                    .sort_by_key(|poly| {
-                        (poly.is_translucent as u32) << 16
-                            | (poly.bot_y as u32) << 8
-                            | poly.top_y as u32
-                    });
-            }
+            emu.gpu.engine_3d.sort_polys();
+            emu.gpu.engine_3d.update_line_buffer_level();
             emu.gpu.engine_3d.renderer_tx.swap_buffers(
                 &emu.gpu.engine_3d.vert_ram[..emu.gpu.engine_3d.vert_ram_level as usize],
                 &emu.gpu.engine_3d.poly_ram[..emu.gpu.engine_3d.poly_ram_level as usize],
                 &emu.gpu.engine_3d.rendering_state,
             );
         }
+        emu.gpu.engine_3d.recorder.swap_buffers(
+            &emu.gpu.engine_3d.vert_ram[..emu.gpu.engine_3d.vert_ram_level as usize],
+            &emu.gpu.engine_3d.poly_ram[..emu.gpu.engine_3d.poly_ram_level as usize],
+            &emu.gpu.engine_3d.rendering_state,
+        );
         emu.gpu.engine_3d.rendering_state.w_buffering =
             emu.gpu.engine_3d.swap_buffers_attrs.w_buffering();
         emu.gpu.engine_3d.vert_ram_level = 0;
@@ -1206,6 +1688,10 @@ impl Engine3d {
     }
 
     pub(super) fn start_rendering(&mut self, vram: &Vram) {
+        unsafe {
+            self.recorder
+                .start_rendering(&*vram.texture.as_bytes_ptr(), &*vram.tex_pal.as_bytes_ptr());
+        }
         if self.rendering_enabled {
             unsafe {
                 self.renderer_tx.start_rendering(
@@ -1221,647 +1707,629 @@ impl Engine3d {
         }
     }
 
-    pub(crate) fn process_next_command(emu: &mut Emu<impl cpu::Engine>) {
-        loop {
-            if emu.gpu.engine_3d.gx_pipe.is_empty() {
-                break;
+    // Executes a single command, reading any parameters after the first one through `read_param`;
+    // returns whether the command was SWAP_BUFFERS, which stops command processing until the
+    // buffers are swapped
+    fn execute_command(
+        &mut self,
+        command: u8,
+        first_param: u32,
+        mut read_param: impl FnMut(&mut Self) -> u32,
+    ) -> bool {
+        #[allow(clippy::match_same_arms)]
+        match command {
+            0x10 => {
+                // MTX_MODE
+                self.mtx_mode = unsafe { transmute(first_param as u8 & 3) };
             }
 
-            macro_rules! read_from_gx_pipe {
-                () => {
-                    emu.gpu.engine_3d.gx_pipe.read_unchecked()
-                };
-                (
-                    $len: literal,
-                    $iter: expr,
-                    |$elem_ident: ident, $entry_ident: ident| $f: expr
-                ) => {
-                    let mut iter = $iter.into_iter();
-                    let pipe_len = emu.gpu.engine_3d.gx_pipe.len();
-                    if pipe_len >= $len {
-                        for $elem_ident in iter {
-                            let $entry_ident = emu.gpu.engine_3d.gx_pipe.read_unchecked();
-                            $f
-                        }
-                    } else {
-                        for $elem_ident in Iterator::take(&mut iter, pipe_len) {
-                            let $entry_ident = emu.gpu.engine_3d.gx_pipe.read_unchecked();
-                            $f
-                        }
-                        for $elem_ident in iter {
-                            let $entry_ident = emu.gpu.engine_3d.gx_fifo.read_unchecked();
-                            $f
+            0x11 => {
+                // MTX_PUSH
+                match self.mtx_mode {
+                    MatrixMode::Projection => {
+                        if self.proj_stack_pointer {
+                            self.gx_status.set_matrix_stack_overflow(true);
                         }
+                        self.proj_stack = self.cur_proj_mtx;
+                        self.proj_stack_pointer = true;
                     }
-                };
-            }
 
-            let FifoEntry {
-                command,
-                param: first_param,
-            } = unsafe { emu.gpu.engine_3d.gx_pipe.peek_unchecked() };
+                    MatrixMode::Position | MatrixMode::PositionVector => {
+                        if self.pos_vec_stack_pointer >= 31 {
+                            self.gx_status.set_matrix_stack_overflow(true);
+                        }
+                        self.pos_vec_stack[(self.pos_vec_stack_pointer & 31) as usize] =
+                            self.cur_pos_vec_mtxs;
+                        self.pos_vec_stack_pointer = (self.pos_vec_stack_pointer + 1).min(63);
+                    }
 
-            if command == 0 {
-                unsafe {
-                    read_from_gx_pipe!();
+                    MatrixMode::Texture => {
+                        self.tex_stack = self.cur_tex_mtx;
+                    }
                 }
-                emu.gpu.engine_3d.refill_gx_pipe(&mut emu.arm9, 0);
-                continue;
             }
 
-            let params = emu.gpu.engine_3d.params_for_command(command);
-
-            if emu.gpu.engine_3d.gx_pipe.len() + emu.gpu.engine_3d.gx_fifo.len() < params as usize {
-                break;
-            }
+            0x12 => {
+                // MTX_POP
+                match self.mtx_mode {
+                    MatrixMode::Projection => {
+                        self.proj_stack_pointer = false;
+                        self.cur_proj_mtx = self.proj_stack;
+                        self.clip_mtx_needs_recalculation = true;
+                    }
 
-            emu.gpu.engine_3d.gx_status.set_busy(true);
-            let prev_gx_pipe_len = emu.gpu.engine_3d.gx_pipe.len();
+                    MatrixMode::Position | MatrixMode::PositionVector => {
+                        self.pos_vec_stack_pointer =
+                            (self.pos_vec_stack_pointer as i8 - ((first_param as i8) << 2 >> 2))
+                                .clamp(0, 63) as u8;
+                        if self.pos_vec_stack_pointer >= 31 {
+                            self.gx_status.set_matrix_stack_overflow(true);
+                        }
+                        self.cur_pos_vec_mtxs =
+                            self.pos_vec_stack[(self.pos_vec_stack_pointer & 31) as usize];
+                        self.clip_mtx_needs_recalculation = true;
+                    }
 
-            unsafe {
-                read_from_gx_pipe!();
+                    MatrixMode::Texture => {
+                        self.cur_tex_mtx = self.tex_stack;
+                    }
+                }
             }
 
-            macro_rules! dequeue_mtx_stack_cmd {
-                () => {
-                    emu.gpu.engine_3d.queued_mtx_stack_cmds -= 1;
-                    if emu.gpu.engine_3d.queued_mtx_stack_cmds == 0 {
-                        emu.gpu.engine_3d.gx_status.set_matrix_stack_busy(false);
+            0x13 => {
+                // MTX_STORE
+                match self.mtx_mode {
+                    MatrixMode::Projection => {
+                        self.proj_stack = self.cur_proj_mtx;
                     }
-                };
-            }
 
-            macro_rules! dequeue_test_cmd_entries {
-                ($num: expr) => {
-                    emu.gpu.engine_3d.queued_test_cmd_entries -= $num;
-                    if emu.gpu.engine_3d.queued_test_cmd_entries == 0 {
-                        emu.gpu.engine_3d.gx_status.set_test_busy(false);
+                    MatrixMode::Position | MatrixMode::PositionVector => {
+                        let addr = first_param as u8 & 31;
+                        if addr == 31 {
+                            self.gx_status.set_matrix_stack_overflow(true);
+                        }
+                        self.pos_vec_stack[addr as usize] = self.cur_pos_vec_mtxs;
                     }
-                };
-            }
 
-            #[allow(clippy::match_same_arms)]
-            match command {
-                0x10 => {
-                    // MTX_MODE
-                    emu.gpu.engine_3d.mtx_mode = unsafe { transmute(first_param as u8 & 3) };
+                    MatrixMode::Texture => {
+                        self.tex_stack = self.cur_tex_mtx;
+                    }
                 }
+            }
 
-                0x11 => {
-                    // MTX_PUSH
-                    match emu.gpu.engine_3d.mtx_mode {
-                        MatrixMode::Projection => {
-                            if emu.gpu.engine_3d.proj_stack_pointer {
-                                emu.gpu.engine_3d.gx_status.set_matrix_stack_overflow(true);
-                            }
-                            emu.gpu.engine_3d.proj_stack = emu.gpu.engine_3d.cur_proj_mtx;
-                            emu.gpu.engine_3d.proj_stack_pointer = true;
-                        }
+            0x14 => {
+                // MTX_RESTORE
+                match self.mtx_mode {
+                    MatrixMode::Projection => {
+                        self.cur_proj_mtx = self.proj_stack;
+                        self.clip_mtx_needs_recalculation = true;
+                    }
 
-                        MatrixMode::Position | MatrixMode::PositionVector => {
-                            if emu.gpu.engine_3d.pos_vec_stack_pointer >= 31 {
-                                emu.gpu.engine_3d.gx_status.set_matrix_stack_overflow(true);
-                            }
-                            emu.gpu.engine_3d.pos_vec_stack
-                                [(emu.gpu.engine_3d.pos_vec_stack_pointer & 31) as usize] =
-                                emu.gpu.engine_3d.cur_pos_vec_mtxs;
-                            emu.gpu.engine_3d.pos_vec_stack_pointer =
-                                (emu.gpu.engine_3d.pos_vec_stack_pointer + 1).min(63);
+                    MatrixMode::Position | MatrixMode::PositionVector => {
+                        let addr = first_param as u8 & 31;
+                        if addr == 31 {
+                            self.gx_status.set_matrix_stack_overflow(true);
                         }
+                        self.cur_pos_vec_mtxs = self.pos_vec_stack[addr as usize];
+                        self.clip_mtx_needs_recalculation = true;
+                    }
 
-                        MatrixMode::Texture => {
-                            emu.gpu.engine_3d.tex_stack = emu.gpu.engine_3d.cur_tex_mtx;
-                        }
+                    MatrixMode::Texture => {
+                        self.cur_tex_mtx = self.tex_stack;
                     }
-                    dequeue_mtx_stack_cmd!();
                 }
+            }
 
-                0x12 => {
-                    // MTX_POP
-                    match emu.gpu.engine_3d.mtx_mode {
-                        MatrixMode::Projection => {
-                            emu.gpu.engine_3d.proj_stack_pointer = false;
-                            emu.gpu.engine_3d.cur_proj_mtx = emu.gpu.engine_3d.proj_stack;
-                            emu.gpu.engine_3d.clip_mtx_needs_recalculation = true;
-                        }
+            0x15 => {
+                // MTX_IDENTITY
+                match self.mtx_mode {
+                    MatrixMode::Projection => {
+                        self.cur_proj_mtx = Matrix::identity();
+                        self.clip_mtx_needs_recalculation = true;
+                    }
 
-                        MatrixMode::Position | MatrixMode::PositionVector => {
-                            emu.gpu.engine_3d.pos_vec_stack_pointer =
-                                (emu.gpu.engine_3d.pos_vec_stack_pointer as i8
-                                    - ((first_param as i8) << 2 >> 2))
-                                    .clamp(0, 63) as u8;
-                            if emu.gpu.engine_3d.pos_vec_stack_pointer >= 31 {
-                                emu.gpu.engine_3d.gx_status.set_matrix_stack_overflow(true);
-                            }
-                            emu.gpu.engine_3d.cur_pos_vec_mtxs = emu.gpu.engine_3d.pos_vec_stack
-                                [(emu.gpu.engine_3d.pos_vec_stack_pointer & 31) as usize];
-                            emu.gpu.engine_3d.clip_mtx_needs_recalculation = true;
-                        }
+                    MatrixMode::Position => {
+                        self.cur_pos_vec_mtxs[0] = Matrix::identity();
+                        self.clip_mtx_needs_recalculation = true;
+                    }
 
-                        MatrixMode::Texture => {
-                            emu.gpu.engine_3d.cur_tex_mtx = emu.gpu.engine_3d.tex_stack;
-                        }
+                    MatrixMode::PositionVector => {
+                        self.cur_pos_vec_mtxs[0] = Matrix::identity();
+                        self.cur_pos_vec_mtxs[1] = Matrix::identity();
+                        self.clip_mtx_needs_recalculation = true;
                     }
-                    dequeue_mtx_stack_cmd!();
+
+                    MatrixMode::Texture => self.cur_tex_mtx = Matrix::identity(),
                 }
+            }
 
-                0x13 => {
-                    // MTX_STORE
-                    match emu.gpu.engine_3d.mtx_mode {
-                        MatrixMode::Projection => {
-                            emu.gpu.engine_3d.proj_stack = emu.gpu.engine_3d.cur_proj_mtx;
-                        }
+            0x16 => {
+                // MTX_LOAD_4x4
+                let mut contents = MatrixBuffer([0; 16]);
+                contents.0[0] = first_param as i32;
+                for elem in &mut contents.0[1..] {
+                    *elem = read_param(self) as i32;
+                }
+                self.load_matrix(Matrix::new(contents));
+            }
 
-                        MatrixMode::Position | MatrixMode::PositionVector => {
-                            let addr = first_param as u8 & 31;
-                            if addr == 31 {
-                                emu.gpu.engine_3d.gx_status.set_matrix_stack_overflow(true);
-                            }
-                            emu.gpu.engine_3d.pos_vec_stack[addr as usize] =
-                                emu.gpu.engine_3d.cur_pos_vec_mtxs;
-                        }
+            0x17 => {
+                // MTX_LOAD_4x3
+                let mut contents = MatrixBuffer([0; 16]);
+                contents.0[0] = first_param as i32;
+                contents.0[15] = 0x1000;
+                for i in [1, 2, 4, 5, 6, 8, 9, 10, 12, 13, 14] {
+                    contents.0[i] = read_param(self) as i32;
+                }
+                self.load_matrix(Matrix::new(contents));
+            }
 
-                        MatrixMode::Texture => {
-                            emu.gpu.engine_3d.tex_stack = emu.gpu.engine_3d.cur_tex_mtx;
-                        }
-                    }
+            0x18 => {
+                // MTX_MULT_4x4
+                let mut contents = MatrixBuffer([0; 16]);
+                contents.0[0] = first_param as i32;
+                for elem in &mut contents.0[1..] {
+                    *elem = read_param(self) as i32;
                 }
 
-                0x14 => {
-                    // MTX_RESTORE
-                    match emu.gpu.engine_3d.mtx_mode {
-                        MatrixMode::Projection => {
-                            emu.gpu.engine_3d.cur_proj_mtx = emu.gpu.engine_3d.proj_stack;
-                            emu.gpu.engine_3d.clip_mtx_needs_recalculation = true;
-                        }
+                match self.mtx_mode {
+                    MatrixMode::Projection => {
+                        self.cur_proj_mtx.mul_left_4x4(contents);
+                        self.clip_mtx_needs_recalculation = true;
+                    }
 
-                        MatrixMode::Position | MatrixMode::PositionVector => {
-                            let addr = first_param as u8 & 31;
-                            if addr == 31 {
-                                emu.gpu.engine_3d.gx_status.set_matrix_stack_overflow(true);
-                            }
-                            emu.gpu.engine_3d.cur_pos_vec_mtxs =
-                                emu.gpu.engine_3d.pos_vec_stack[addr as usize];
-                            emu.gpu.engine_3d.clip_mtx_needs_recalculation = true;
-                        }
+                    MatrixMode::Position => {
+                        self.cur_pos_vec_mtxs[0].mul_left_4x4(contents);
+                        self.clip_mtx_needs_recalculation = true;
+                    }
 
-                        MatrixMode::Texture => {
-                            emu.gpu.engine_3d.cur_tex_mtx = emu.gpu.engine_3d.tex_stack;
-                        }
+                    MatrixMode::PositionVector => {
+                        self.cur_pos_vec_mtxs[0].mul_left_4x4(contents);
+                        self.cur_pos_vec_mtxs[1].mul_left_4x4(contents);
+                        self.clip_mtx_needs_recalculation = true;
                     }
+
+                    MatrixMode::Texture => self.cur_tex_mtx.mul_left_4x4(contents),
                 }
+            }
 
-                0x15 => {
-                    // MTX_IDENTITY
-                    match emu.gpu.engine_3d.mtx_mode {
-                        MatrixMode::Projection => {
-                            emu.gpu.engine_3d.cur_proj_mtx = Matrix::identity();
-                            emu.gpu.engine_3d.clip_mtx_needs_recalculation = true;
-                        }
+            0x19 => {
+                // MTX_MULT_4x3
+                let mut contents = MatrixBuffer([0; 12]);
+                contents.0[0] = first_param as i32;
+                for elem in &mut contents.0[1..] {
+                    *elem = read_param(self) as i32;
+                }
 
-                        MatrixMode::Position => {
-                            emu.gpu.engine_3d.cur_pos_vec_mtxs[0] = Matrix::identity();
-                            emu.gpu.engine_3d.clip_mtx_needs_recalculation = true;
-                        }
+                match self.mtx_mode {
+                    MatrixMode::Projection => {
+                        self.cur_proj_mtx.mul_left_4x3(contents);
+                        self.clip_mtx_needs_recalculation = true;
+                    }
 
-                        MatrixMode::PositionVector => {
-                            emu.gpu.engine_3d.cur_pos_vec_mtxs[0] = Matrix::identity();
-                            emu.gpu.engine_3d.cur_pos_vec_mtxs[1] = Matrix::identity();
-                            emu.gpu.engine_3d.clip_mtx_needs_recalculation = true;
-                        }
+                    MatrixMode::Position => {
+                        self.cur_pos_vec_mtxs[0].mul_left_4x3(contents);
+                        self.clip_mtx_needs_recalculation = true;
+                    }
 
-                        MatrixMode::Texture => emu.gpu.engine_3d.cur_tex_mtx = Matrix::identity(),
+                    MatrixMode::PositionVector => {
+                        self.cur_pos_vec_mtxs[0].mul_left_4x3(contents);
+                        self.cur_pos_vec_mtxs[1].mul_left_4x3(contents);
+                        self.clip_mtx_needs_recalculation = true;
                     }
+
+                    MatrixMode::Texture => self.cur_tex_mtx.mul_left_4x3(contents),
                 }
+            }
 
-                0x16 => {
-                    // MTX_LOAD_4x4
-                    let mut contents = MatrixBuffer([0; 16]);
-                    contents.0[0] = first_param as i32;
-                    unsafe {
-                        read_from_gx_pipe!(15, &mut contents.0[1..], |elem, entry| *elem =
-                            entry.param as i32);
-                    }
-                    emu.gpu.engine_3d.load_matrix(Matrix::new(contents));
+            0x1A => {
+                // MTX_MULT_3x3
+                let mut contents = MatrixBuffer([0; 9]);
+                contents.0[0] = first_param as i32;
+                for elem in &mut contents.0[1..] {
+                    *elem = read_param(self) as i32;
                 }
 
-                0x17 => {
-                    // MTX_LOAD_4x3
-                    let mut contents = MatrixBuffer([0; 16]);
-                    contents.0[0] = first_param as i32;
-                    contents.0[15] = 0x1000;
-                    unsafe {
-                        read_from_gx_pipe!(
-                            11,
-                            [1, 2, 4, 5, 6, 8, 9, 10, 12, 13, 14],
-                            |i, entry| contents.0[i] = entry.param as i32
-                        );
+                match self.mtx_mode {
+                    MatrixMode::Projection => {
+                        self.cur_proj_mtx.mul_left_3x3(contents);
+                        self.clip_mtx_needs_recalculation = true;
                     }
-                    emu.gpu.engine_3d.load_matrix(Matrix::new(contents));
-                }
 
-                0x18 => {
-                    // MTX_MULT_4x4
-                    let mut contents = MatrixBuffer([0; 16]);
-                    contents.0[0] = first_param as i32;
-                    unsafe {
-                        read_from_gx_pipe!(15, &mut contents.0[1..], |elem, entry| *elem =
-                            entry.param as i32);
+                    MatrixMode::Position => {
+                        self.cur_pos_vec_mtxs[0].mul_left_3x3(contents);
+                        self.clip_mtx_needs_recalculation = true;
                     }
 
-                    match emu.gpu.engine_3d.mtx_mode {
-                        MatrixMode::Projection => {
-                            emu.gpu.engine_3d.cur_proj_mtx.mul_left_4x4(contents);
-                            emu.gpu.engine_3d.clip_mtx_needs_recalculation = true;
-                        }
+                    MatrixMode::PositionVector => {
+                        self.cur_pos_vec_mtxs[0].mul_left_3x3(contents);
+                        self.cur_pos_vec_mtxs[1].mul_left_3x3(contents);
+                        self.clip_mtx_needs_recalculation = true;
+                    }
 
-                        MatrixMode::Position => {
-                            emu.gpu.engine_3d.cur_pos_vec_mtxs[0].mul_left_4x4(contents);
-                            emu.gpu.engine_3d.clip_mtx_needs_recalculation = true;
-                        }
+                    MatrixMode::Texture => self.cur_tex_mtx.mul_left_3x3(contents),
+                }
+            }
 
-                        MatrixMode::PositionVector => {
-                            emu.gpu.engine_3d.cur_pos_vec_mtxs[0].mul_left_4x4(contents);
-                            emu.gpu.engine_3d.cur_pos_vec_mtxs[1].mul_left_4x4(contents);
-                            emu.gpu.engine_3d.clip_mtx_needs_recalculation = true;
-                        }
+            0x1B => {
+                // MTX_SCALE
+                let mut contents = [first_param as i32, 0, 0];
+                for elem in &mut contents[1..] {
+                    *elem = read_param(self) as i32;
+                }
 
-                        MatrixMode::Texture => emu.gpu.engine_3d.cur_tex_mtx.mul_left_4x4(contents),
+                match self.mtx_mode {
+                    MatrixMode::Projection => {
+                        self.cur_proj_mtx.scale(contents);
+                        self.clip_mtx_needs_recalculation = true;
                     }
-                }
 
-                0x19 => {
-                    // MTX_MULT_4x3
-                    let mut contents = MatrixBuffer([0; 12]);
-                    contents.0[0] = first_param as i32;
-                    unsafe {
-                        read_from_gx_pipe!(11, &mut contents.0[1..], |elem, entry| *elem =
-                            entry.param as i32);
+                    MatrixMode::Position | MatrixMode::PositionVector => {
+                        self.cur_pos_vec_mtxs[0].scale(contents);
+                        self.clip_mtx_needs_recalculation = true;
                     }
 
-                    match emu.gpu.engine_3d.mtx_mode {
-                        MatrixMode::Projection => {
-                            emu.gpu.engine_3d.cur_proj_mtx.mul_left_4x3(contents);
-                            emu.gpu.engine_3d.clip_mtx_needs_recalculation = true;
-                        }
+                    MatrixMode::Texture => self.cur_tex_mtx.scale(contents),
+                }
+            }
+            0x1C => {
+                // MTX_TRANS
+                let mut contents = [first_param as i32, 0, 0];
+                for elem in &mut contents[1..] {
+                    *elem = read_param(self) as i32;
+                }
 
-                        MatrixMode::Position => {
-                            emu.gpu.engine_3d.cur_pos_vec_mtxs[0].mul_left_4x3(contents);
-                            emu.gpu.engine_3d.clip_mtx_needs_recalculation = true;
-                        }
+                match self.mtx_mode {
+                    MatrixMode::Projection => {
+                        self.cur_proj_mtx.translate(contents);
+                        self.clip_mtx_needs_recalculation = true;
+                    }
 
-                        MatrixMode::PositionVector => {
-                            emu.gpu.engine_3d.cur_pos_vec_mtxs[0].mul_left_4x3(contents);
-                            emu.gpu.engine_3d.cur_pos_vec_mtxs[1].mul_left_4x3(contents);
-                            emu.gpu.engine_3d.clip_mtx_needs_recalculation = true;
-                        }
+                    MatrixMode::Position => {
+                        self.cur_pos_vec_mtxs[0].translate(contents);
+                        self.clip_mtx_needs_recalculation = true;
+                    }
 
-                        MatrixMode::Texture => emu.gpu.engine_3d.cur_tex_mtx.mul_left_4x3(contents),
+                    MatrixMode::PositionVector => {
+                        self.cur_pos_vec_mtxs[0].translate(contents);
+                        self.cur_pos_vec_mtxs[1].translate(contents);
+                        self.clip_mtx_needs_recalculation = true;
                     }
+
+                    MatrixMode::Texture => self.cur_tex_mtx.translate(contents),
                 }
+            }
 
-                0x1A => {
-                    // MTX_MULT_3x3
-                    let mut contents = MatrixBuffer([0; 9]);
-                    contents.0[0] = first_param as i32;
-                    unsafe {
-                        read_from_gx_pipe!(8, &mut contents.0[1..], |elem, entry| *elem =
-                            entry.param as i32);
-                    }
+            0x20 => {
+                // COLOR
+                self.vert_color = rgb5_to_rgb6(decode_rgb5(first_param as u16, 0));
+            }
 
-                    match emu.gpu.engine_3d.mtx_mode {
-                        MatrixMode::Projection => {
-                            emu.gpu.engine_3d.cur_proj_mtx.mul_left_3x3(contents);
-                            emu.gpu.engine_3d.clip_mtx_needs_recalculation = true;
-                        }
+            0x21 => {
+                // NORMAL
+                self.vert_normal = [
+                    (first_param as i16) << 6 >> 6,
+                    (first_param >> 4) as i16 >> 6,
+                    (first_param >> 14) as i16 >> 6,
+                ];
+
+                if self.tex_params.coord_transform_mode() == 2 {
+                    let [u, v, ..] = self
+                        .cur_tex_mtx
+                        .mul_left_vec3_zero::<i16, i16, 21>(self.vert_normal)
+                        .to_array();
+                    self.transformed_tex_coords = self.tex_coords + TexCoords::from_array([u, v]);
+                }
 
-                        MatrixMode::Position => {
-                            emu.gpu.engine_3d.cur_pos_vec_mtxs[0].mul_left_3x3(contents);
-                            emu.gpu.engine_3d.clip_mtx_needs_recalculation = true;
-                        }
+                self.apply_lighting();
+            }
 
-                        MatrixMode::PositionVector => {
-                            emu.gpu.engine_3d.cur_pos_vec_mtxs[0].mul_left_3x3(contents);
-                            emu.gpu.engine_3d.cur_pos_vec_mtxs[1].mul_left_3x3(contents);
-                            emu.gpu.engine_3d.clip_mtx_needs_recalculation = true;
-                        }
+            0x22 => {
+                // TEXCOORD
+                self.tex_coords =
+                    TexCoords::from_array([first_param as i16, (first_param >> 16) as i16]);
 
-                        MatrixMode::Texture => emu.gpu.engine_3d.cur_tex_mtx.mul_left_3x3(contents),
+                match self.tex_params.coord_transform_mode() {
+                    0 => {
+                        self.transformed_tex_coords = self.tex_coords;
                     }
+                    1 => {
+                        let [u, v, ..] = self
+                            .cur_tex_mtx
+                            .mul_left_vec2_one_one::<i16, i16>(self.tex_coords)
+                            .to_array();
+                        self.transformed_tex_coords = TexCoords::from_array([u, v]);
+                    }
+                    _ => {}
                 }
+            }
 
-                0x1B => {
-                    // MTX_SCALE
-                    let mut contents = [first_param as i32, 0, 0];
-                    unsafe {
-                        read_from_gx_pipe!(2, &mut contents[1..], |elem, entry| *elem =
-                            entry.param as i32);
-                    }
+            0x23 => {
+                // VTX_16
+                let second_param = read_param(self);
+                self.add_vert([
+                    first_param as i16,
+                    (first_param >> 16) as i16,
+                    second_param as i16,
+                ]);
+            }
 
-                    match emu.gpu.engine_3d.mtx_mode {
-                        MatrixMode::Projection => {
-                            emu.gpu.engine_3d.cur_proj_mtx.scale(contents);
-                            emu.gpu.engine_3d.clip_mtx_needs_recalculation = true;
-                        }
+            0x24 => {
+                // VTX_10
+                self.add_vert([
+                    (first_param as i16) << 6,
+                    ((first_param >> 10) as i16) << 6,
+                    ((first_param >> 20) as i16) << 6,
+                ]);
+            }
+            0x25 => {
+                // VTX_XY
+                self.add_vert([
+                    first_param as i16,
+                    (first_param >> 16) as i16,
+                    self.last_vtx_coords[2],
+                ]);
+            }
+            0x26 => {
+                // VTX_XZ
+                self.add_vert([
+                    first_param as i16,
+                    self.last_vtx_coords[1],
+                    (first_param >> 16) as i16,
+                ]);
+            }
 
-                        MatrixMode::Position | MatrixMode::PositionVector => {
-                            emu.gpu.engine_3d.cur_pos_vec_mtxs[0].scale(contents);
-                            emu.gpu.engine_3d.clip_mtx_needs_recalculation = true;
-                        }
+            0x27 => {
+                // VTX_YZ
+                self.add_vert([
+                    self.last_vtx_coords[0],
+                    first_param as i16,
+                    (first_param >> 16) as i16,
+                ]);
+            }
 
-                        MatrixMode::Texture => emu.gpu.engine_3d.cur_tex_mtx.scale(contents),
-                    }
-                }
-                0x1C => {
-                    // MTX_TRANS
-                    let mut contents = [first_param as i32, 0, 0];
-                    unsafe {
-                        read_from_gx_pipe!(2, &mut contents[1..], |elem, entry| *elem =
-                            entry.param as i32);
-                    }
+            0x28 => {
+                // VTX_DIFF
+                self.add_vert([
+                    self.last_vtx_coords[0].wrapping_add((first_param as i16) << 6 >> 6),
+                    self.last_vtx_coords[1].wrapping_add((first_param >> 4) as i16 >> 6),
+                    self.last_vtx_coords[2].wrapping_add((first_param >> 14) as i16 >> 6),
+                ]);
+            }
 
-                    match emu.gpu.engine_3d.mtx_mode {
-                        MatrixMode::Projection => {
-                            emu.gpu.engine_3d.cur_proj_mtx.translate(contents);
-                            emu.gpu.engine_3d.clip_mtx_needs_recalculation = true;
-                        }
+            0x29 => {
+                // POLYGON_ATTR
+                self.next_poly_attrs = PolygonAttrs(first_param);
+            }
 
-                        MatrixMode::Position => {
-                            emu.gpu.engine_3d.cur_pos_vec_mtxs[0].translate(contents);
-                            emu.gpu.engine_3d.clip_mtx_needs_recalculation = true;
-                        }
+            0x2A => {
+                // TEXIMAGE_PARAM
+                self.tex_params = TextureParams(first_param);
+            }
 
-                        MatrixMode::PositionVector => {
-                            emu.gpu.engine_3d.cur_pos_vec_mtxs[0].translate(contents);
-                            emu.gpu.engine_3d.cur_pos_vec_mtxs[1].translate(contents);
-                            emu.gpu.engine_3d.clip_mtx_needs_recalculation = true;
-                        }
+            0x2B => {
+                // PLTT_BASE
+                self.tex_palette_base = first_param as u16 & 0x1FFF;
+            }
 
-                        MatrixMode::Texture => emu.gpu.engine_3d.cur_tex_mtx.translate(contents),
-                    }
+            0x30 => {
+                // DIF_AMB
+                let diffuse_color = decode_rgb5(first_param as u16, 0);
+                self.diffuse_color = diffuse_color.cast();
+                self.ambient_color = decode_rgb5((first_param >> 16) as u16, 0).cast();
+                if first_param & 1 << 15 != 0 {
+                    self.vert_color = rgb5_to_rgb6(diffuse_color);
                 }
+            }
 
-                0x20 => {
-                    // COLOR
-                    emu.gpu.engine_3d.vert_color = rgb5_to_rgb6(decode_rgb5(first_param as u16, 0));
-                }
+            0x31 => {
+                // SPE_EMI
+                self.specular_color = decode_rgb5(first_param as u16, 0).cast();
+                self.emission_color = decode_rgb5((first_param >> 16) as u16, 0).cast();
+                self.shininess_table_enabled = first_param & 1 << 15 != 0;
+            }
 
-                0x21 => {
-                    // NORMAL
-                    emu.gpu.engine_3d.vert_normal = [
+            0x32 => {
+                // LIGHT_VECTOR
+                let transformed = self.cur_pos_vec_mtxs[1]
+                    .mul_left_vec3_zero::<i16, i32, 12>([
                         (first_param as i16) << 6 >> 6,
                         (first_param >> 4) as i16 >> 6,
                         (first_param >> 14) as i16 >> 6,
-                    ];
+                    ])
+                    .to_array();
+                let light = &mut self.lights[(first_param >> 30) as usize];
+                light.direction = [transformed[0], transformed[1], transformed[2]];
+                light.half_vec = [
+                    transformed[0] >> 1,
+                    transformed[1] >> 1,
+                    (transformed[2] - 0x200) >> 1,
+                ];
+            }
 
-                    if emu.gpu.engine_3d.tex_params.coord_transform_mode() == 2 {
-                        let [u, v, ..] = emu
-                            .gpu
-                            .engine_3d
-                            .cur_tex_mtx
-                            .mul_left_vec3_zero::<i16, i16, 21>(emu.gpu.engine_3d.vert_normal)
-                            .to_array();
-                        emu.gpu.engine_3d.transformed_tex_coords =
-                            emu.gpu.engine_3d.tex_coords + TexCoords::from_array([u, v]);
-                    }
+            0x33 => {
+                // LIGHT_COLOR
+                self.lights[(first_param >> 30) as usize].color =
+                    decode_rgb5(first_param as u16, 0).cast();
+            }
 
-                    emu.gpu.engine_3d.apply_lighting();
+            0x34 => {
+                // SHININESS
+                self.shininess_table[0] = first_param as u8;
+                self.shininess_table[1] = (first_param >> 8) as u8;
+                self.shininess_table[2] = (first_param >> 16) as u8;
+                self.shininess_table[3] = (first_param >> 24) as u8;
+                for i in (4..128).step_by(4) {
+                    let param = read_param(self);
+                    self.shininess_table[i] = param as u8;
+                    self.shininess_table[i + 1] = (param >> 8) as u8;
+                    self.shininess_table[i + 2] = (param >> 16) as u8;
+                    self.shininess_table[i + 3] = (param >> 24) as u8;
                 }
+            }
 
-                0x22 => {
-                    // TEXCOORD
-                    emu.gpu.engine_3d.tex_coords =
-                        TexCoords::from_array([first_param as i16, (first_param >> 16) as i16]);
-
-                    match emu.gpu.engine_3d.tex_params.coord_transform_mode() {
-                        0 => {
-                            emu.gpu.engine_3d.transformed_tex_coords = emu.gpu.engine_3d.tex_coords;
-                        }
-                        1 => {
-                            let [u, v, ..] = emu
-                                .gpu
-                                .engine_3d
-                                .cur_tex_mtx
-                                .mul_left_vec2_one_one::<i16, i16>(emu.gpu.engine_3d.tex_coords)
-                                .to_array();
-                            emu.gpu.engine_3d.transformed_tex_coords =
-                                TexCoords::from_array([u, v]);
-                        }
-                        _ => {}
-                    }
-                }
+            0x40 => {
+                // BEGIN_VTXS
+                self.cur_poly_attrs = self.next_poly_attrs;
+                self.cur_prim_type = unsafe { transmute(first_param as u8 & 3) };
+                self.cur_prim_vert_index = PrimVertIndex::new(0);
+                self.cur_prim_max_verts = match self.cur_prim_type {
+                    PrimitiveType::Triangles | PrimitiveType::TriangleStrip => PrimMaxVerts::new(3),
+                    PrimitiveType::Quads | PrimitiveType::QuadStrip => PrimMaxVerts::new(4),
+                };
+                self.cur_strip_prim_is_odd = false;
+                self.connect_to_last_strip_prim = false;
+            }
 
-                0x23 => {
-                    // VTX_16
-                    let second_param = unsafe { read_from_gx_pipe!() }.param;
-                    emu.gpu.engine_3d.add_vert([
-                        first_param as i16,
-                        (first_param >> 16) as i16,
-                        second_param as i16,
-                    ]);
-                }
+            0x41 => {
+                // END_VTXS
+                // Should do nothing according to GBATEK
+            }
 
-                0x24 => {
-                    // VTX_10
-                    emu.gpu.engine_3d.add_vert([
-                        (first_param as i16) << 6,
-                        ((first_param >> 10) as i16) << 6,
-                        ((first_param >> 20) as i16) << 6,
-                    ]);
-                }
-                0x25 => {
-                    // VTX_XY
-                    emu.gpu.engine_3d.add_vert([
-                        first_param as i16,
-                        (first_param >> 16) as i16,
-                        emu.gpu.engine_3d.last_vtx_coords[2],
-                    ]);
-                }
-                0x26 => {
-                    // VTX_XZ
-                    emu.gpu.engine_3d.add_vert([
-                        first_param as i16,
-                        emu.gpu.engine_3d.last_vtx_coords[1],
-                        (first_param >> 16) as i16,
-                    ]);
-                }
+            0x50 => {
+                // SWAP_BUFFERS
+                self.swap_buffers_attrs = SwapBuffersAttrs(first_param as u8);
+                return true;
+            }
 
-                0x27 => {
-                    // VTX_YZ
-                    emu.gpu.engine_3d.add_vert([
-                        emu.gpu.engine_3d.last_vtx_coords[0],
-                        first_param as i16,
-                        (first_param >> 16) as i16,
-                    ]);
-                }
+            0x60 => {
+                // VIEWPORT
 
-                0x28 => {
-                    // VTX_DIFF
-                    emu.gpu.engine_3d.add_vert([
-                        emu.gpu.engine_3d.last_vtx_coords[0]
-                            .wrapping_add((first_param as i16) << 6 >> 6),
-                        emu.gpu.engine_3d.last_vtx_coords[1]
-                            .wrapping_add((first_param >> 4) as i16 >> 6),
-                        emu.gpu.engine_3d.last_vtx_coords[2]
-                            .wrapping_add((first_param >> 14) as i16 >> 6),
-                    ]);
-                }
+                let x0 = first_param & 0xFF;
+                let y0_unmasked = first_param >> 8;
+                let x1 = first_param >> 16 & 0xFF;
+                let y1 = first_param >> 24;
 
-                0x29 => {
-                    // POLYGON_ATTR
-                    emu.gpu.engine_3d.next_poly_attrs = PolygonAttrs(first_param);
-                }
+                self.viewport_origin = u32x2::from_array([x0, 191_u32.wrapping_sub(y1) & 0xFF]);
+                self.viewport_size = u32x2::from_array([
+                    x1.wrapping_sub(x0).wrapping_add(1) & 0x1FF,
+                    y1.wrapping_sub(y0_unmasked).wrapping_add(1) & 0xFF,
+                ])
+                .cast();
+            }
 
-                0x2A => {
-                    // TEXIMAGE_PARAM
-                    emu.gpu.engine_3d.tex_params = TextureParams(first_param);
-                }
+            0x70 => {
+                // BOX_TEST
+                let second_param = read_param(self);
+                let third_param = read_param(self);
+                let result = self.box_test(
+                    [
+                        first_param as i16,
+                        (first_param >> 16) as i16,
+                        second_param as i16,
+                    ],
+                    [
+                        (second_param >> 16) as i16,
+                        third_param as i16,
+                        (third_param >> 16) as i16,
+                    ],
+                );
+                self.gx_status.set_box_test_result(result);
+            }
 
-                0x2B => {
-                    // PLTT_BASE
-                    emu.gpu.engine_3d.tex_palette_base = first_param as u16 & 0x1FFF;
-                }
+            0x71 => {
+                // POS_TEST
+                let second_param = read_param(self);
+                self.pos_test([
+                    first_param as i16,
+                    (first_param >> 16) as i16,
+                    second_param as i16,
+                ]);
+            }
 
-                0x30 => {
-                    // DIF_AMB
-                    let diffuse_color = decode_rgb5(first_param as u16, 0);
-                    emu.gpu.engine_3d.diffuse_color = diffuse_color.cast();
-                    emu.gpu.engine_3d.ambient_color =
-                        decode_rgb5((first_param >> 16) as u16, 0).cast();
-                    if first_param & 1 << 15 != 0 {
-                        emu.gpu.engine_3d.vert_color = rgb5_to_rgb6(diffuse_color);
-                    }
-                }
+            0x72 => {
+                // VEC_TEST
+                self.vec_test([
+                    (first_param as i16) << 6 >> 6,
+                    (first_param >> 4) as i16 >> 6,
+                    (first_param >> 14) as i16 >> 6,
+                ]);
+            }
 
-                0x31 => {
-                    // SPE_EMI
-                    emu.gpu.engine_3d.specular_color = decode_rgb5(first_param as u16, 0).cast();
-                    emu.gpu.engine_3d.emission_color =
-                        decode_rgb5((first_param >> 16) as u16, 0).cast();
-                    emu.gpu.engine_3d.shininess_table_enabled = first_param & 1 << 15 != 0;
-                }
+            _ => {}
+        }
+        false
+    }
 
-                0x32 => {
-                    // LIGHT_VECTOR
-                    let transformed = emu.gpu.engine_3d.cur_pos_vec_mtxs[1]
-                        .mul_left_vec3_zero::<i16, i32, 12>([
-                            (first_param as i16) << 6 >> 6,
-                            (first_param >> 4) as i16 >> 6,
-                            (first_param >> 14) as i16 >> 6,
-                        ])
-                        .to_array();
-                    let light = &mut emu.gpu.engine_3d.lights[(first_param >> 30) as usize];
-                    light.direction = [transformed[0], transformed[1], transformed[2]];
-                    light.half_vec = [
-                        transformed[0] >> 1,
-                        transformed[1] >> 1,
-                        (transformed[2] - 0x200) >> 1,
-                    ];
-                }
+    pub(crate) fn process_next_command(emu: &mut Emu<impl cpu::Engine>) {
+        loop {
+            if emu.gpu.engine_3d.gx_pipe.is_empty() {
+                break;
+            }
 
-                0x33 => {
-                    // LIGHT_COLOR
-                    emu.gpu.engine_3d.lights[(first_param >> 30) as usize].color =
-                        decode_rgb5(first_param as u16, 0).cast();
-                }
+            let FifoEntry {
+                command,
+                param: first_param,
+            } = unsafe { emu.gpu.engine_3d.gx_pipe.peek_unchecked() };
 
-                0x34 => {
-                    // SHININESS
-                    emu.gpu.engine_3d.shininess_table[0] = first_param as u8;
-                    emu.gpu.engine_3d.shininess_table[1] = (first_param >> 8) as u8;
-                    emu.gpu.engine_3d.shininess_table[2] = (first_param >> 16) as u8;
-                    emu.gpu.engine_3d.shininess_table[3] = (first_param >> 24) as u8;
-                    unsafe {
-                        read_from_gx_pipe!(31, (4..128).step_by(4), |i, entry| {
-                            emu.gpu.engine_3d.shininess_table[i] = entry.param as u8;
-                            emu.gpu.engine_3d.shininess_table[i + 1] = (entry.param >> 8) as u8;
-                            emu.gpu.engine_3d.shininess_table[i + 2] = (entry.param >> 16) as u8;
-                            emu.gpu.engine_3d.shininess_table[i + 3] = (entry.param >> 24) as u8;
-                        });
-                    }
+            if command == 0 {
+                unsafe {
+                    emu.gpu.engine_3d.gx_pipe.read_unchecked();
                 }
+                emu.gpu.engine_3d.refill_gx_pipe(&mut emu.arm9, 0);
+                continue;
+            }
 
-                0x40 => {
-                    // BEGIN_VTXS
-                    emu.gpu.engine_3d.cur_poly_attrs = emu.gpu.engine_3d.next_poly_attrs;
-                    emu.gpu.engine_3d.cur_prim_type = unsafe { transmute(first_param as u8 & 3) };
-                    emu.gpu.engine_3d.cur_prim_vert_index = PrimVertIndex::new(0);
-                    emu.gpu.engine_3d.cur_prim_max_verts = match emu.gpu.engine_3d.cur_prim_type {
-                        PrimitiveType::Triangles | PrimitiveType::TriangleStrip => {
-                            PrimMaxVerts::new(3)
-                        }
-                        PrimitiveType::Quads | PrimitiveType::QuadStrip => PrimMaxVerts::new(4),
-                    };
-                    emu.gpu.engine_3d.cur_strip_prim_is_odd = false;
-                    emu.gpu.engine_3d.connect_to_last_strip_prim = false;
-                }
+            let params = emu.gpu.engine_3d.params_for_command(command);
 
-                0x41 => {
-                    // END_VTXS
-                    // Should do nothing according to GBATEK
-                }
+            if emu.gpu.engine_3d.gx_pipe.len() + emu.gpu.engine_3d.gx_fifo.len() < params as usize {
+                break;
+            }
 
-                0x50 => {
-                    // SWAP_BUFFERS
-                    emu.gpu.engine_3d.swap_buffers_attrs = SwapBuffersAttrs(first_param as u8);
-                    // Gets unlocked by the GPU when VBlank starts
-                    emu.gpu.engine_3d.command_finish_time.0 = RawTimestamp::MAX;
-                    return;
-                }
+            emu.gpu.engine_3d.gx_status.set_busy(true);
+            let prev_gx_pipe_len = emu.gpu.engine_3d.gx_pipe.len();
 
-                0x60 => {
-                    // VIEWPORT
+            unsafe {
+                emu.gpu.engine_3d.gx_pipe.read_unchecked();
+            }
 
-                    let x0 = first_param & 0xFF;
-                    let y0_unmasked = first_param >> 8;
-                    let x1 = first_param >> 16 & 0xFF;
-                    let y1 = first_param >> 24;
+            let cycles = emu.gpu.engine_3d.command_cycles(command);
 
-                    emu.gpu.engine_3d.viewport_origin =
-                        u32x2::from_array([x0, 191_u32.wrapping_sub(y1) & 0xFF]);
-                    emu.gpu.engine_3d.viewport_size = u32x2::from_array([
-                        x1.wrapping_sub(x0).wrapping_add(1) & 0x1FF,
-                        y1.wrapping_sub(y0_unmasked).wrapping_add(1) & 0xFF,
-                    ])
-                    .cast();
-                }
+            emu.gpu
+                .engine_3d
+                .recorder
+                .record_command(command, first_param);
+            let is_swap_buffers =
+                emu.gpu
+                    .engine_3d
+                    .execute_command(command, first_param, |engine_3d| {
+                        // The total amount of entries in the GX pipe and FIFO was checked above
+                        let param = unsafe { engine_3d.read_param_unchecked() };
+                        engine_3d.recorder.record_command(command, param);
+                        param
+                    });
 
-                0x70 => {
-                    // TODO: BOX_TEST
-                    emu.gpu.engine_3d.gx_status.set_box_test_result(true);
-                    for _ in 1..3 {
-                        unsafe {
-                            read_from_gx_pipe!();
-                        }
+            match command {
+                0x11 | 0x12 => {
+                    emu.gpu.engine_3d.queued_mtx_stack_cmds -= 1;
+                    if emu.gpu.engine_3d.queued_mtx_stack_cmds == 0 {
+                        emu.gpu.engine_3d.gx_status.set_matrix_stack_busy(false);
                     }
-                    dequeue_test_cmd_entries!(3);
                 }
-
-                0x71 => {
-                    // TODO: POS_TEST
-                    for _ in 1..2 {
-                        unsafe {
-                            read_from_gx_pipe!();
-                        }
+                0x70..=0x72 => {
+                    emu.gpu.engine_3d.queued_test_cmd_entries -= params as u16;
+                    if emu.gpu.engine_3d.queued_test_cmd_entries == 0 {
+                        emu.gpu.engine_3d.gx_status.set_test_busy(false);
                     }
-                    dequeue_test_cmd_entries!(2);
                 }
-
-                0x72 => {
-                    // TODO: VEC_TEST
-                    dequeue_test_cmd_entries!(1);
-                }
-
                 _ => {}
             }
 
+            if is_swap_buffers {
+                emu.gpu.engine_3d.record_swap_buffers_executed();
+                // Gets unlocked by the GPU when VBlank starts
+                emu.gpu.engine_3d.command_finish_time.0 = RawTimestamp::MAX;
+                return;
+            }
+
             emu.gpu.engine_3d.refill_gx_pipe(
                 &mut emu.arm9,
                 (prev_gx_pipe_len ^ params.max(1) as usize) & 1,
             );
 
             emu.gpu.engine_3d.command_finish_time.0 =
-                emu::Timestamp::from(arm9::Timestamp(emu.arm9.schedule.cur_time().0 + 1)).0 + 10;
+                emu::Timestamp::from(arm9::Timestamp(emu.arm9.schedule.cur_time().0 + 1)).0
+                    + cycles as RawTimestamp;
             emu.gpu.engine_3d.gx_fifo_stalled &= emu.gpu.engine_3d.gx_fifo.len() > 256;
             if emu.gpu.engine_3d.gx_fifo_stalled() {
                 emu.schedule.schedule_event(
@@ -1881,3 +2349,133 @@ impl Engine3d {
         emu.gpu.engine_3d.command_finish_time.0 = 0;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A detached engine with identity projection and position/vector matrices, so that clip
+    // coordinates match the input ones with W = 1.0
+    fn engine_3d() -> Engine3d {
+        let mut engine_3d = Engine3d::new_detached(
+            Box::new(ReplayRendererTx),
+            #[cfg(feature = "log")]
+            slog::Logger::root(slog::Discard, slog::o!()),
+        );
+        engine_3d.cur_proj_mtx = Matrix::identity();
+        engine_3d.cur_pos_vec_mtxs = [Matrix::identity(); 2];
+        engine_3d.clip_mtx_needs_recalculation = true;
+        engine_3d
+    }
+
+    fn run(engine_3d: &mut Engine3d, command: u8, params: &[u32]) {
+        let mut params = params.iter().copied();
+        let first_param = params.next().unwrap();
+        assert!(!engine_3d.execute_command(command, first_param, |_| params.next().unwrap()));
+        assert_eq!(params.next(), None);
+    }
+
+    fn pack(low: i16, high: i16) -> u32 {
+        low as u16 as u32 | (high as u16 as u32) << 16
+    }
+
+    fn box_test(engine_3d: &mut Engine3d, pos: [i16; 3], size: [i16; 3]) -> bool {
+        run(
+            engine_3d,
+            0x70,
+            &[
+                pack(pos[0], pos[1]),
+                pack(pos[2], size[0]),
+                pack(size[1], size[2]),
+            ],
+        );
+        engine_3d.gx_status.box_test_result()
+    }
+
+    fn pos_test(engine_3d: &mut Engine3d, coords: [i16; 3]) -> [i32; 4] {
+        run(
+            engine_3d,
+            0x71,
+            &[pack(coords[0], coords[1]), coords[2] as u16 as u32],
+        );
+        engine_3d.pos_test_result()
+    }
+
+    fn vec_test(engine_3d: &mut Engine3d, vec: [i16; 3]) -> [i16; 3] {
+        let param = vec.iter().enumerate().fold(0, |acc, (i, &coord)| {
+            acc | (coord as u32 & 0x3FF) << (i * 10)
+        });
+        run(engine_3d, 0x72, &[param]);
+        engine_3d.vec_test_result()
+    }
+
+    #[test]
+    fn box_test_results() {
+        let mut engine_3d = engine_3d();
+        // Inside the view volume
+        assert!(box_test(&mut engine_3d, [-0x800; 3], [0x1000; 3]));
+        // Outside of it, with all corners outside of the same plane
+        assert!(!box_test(&mut engine_3d, [0x2000, 0, 0], [0x800; 3]));
+        assert!(!box_test(&mut engine_3d, [0, -0x3000, 0], [0x800; 3]));
+        assert!(!box_test(&mut engine_3d, [0, 0, 0x1001], [0x800; 3]));
+        // Touching the near plane
+        assert!(box_test(
+            &mut engine_3d,
+            [0, 0, -0x2000],
+            [0x800, 0x800, 0x1000]
+        ));
+        // Crossing the view volume with all corners outside of it, but on opposite sides
+        assert!(box_test(
+            &mut engine_3d,
+            [-0x4000, -0x100, -0x100],
+            [0x7FFF, 0x200, 0x200]
+        ));
+        assert!(engine_3d.gx_status.box_test_result());
+        // Moved out of the view volume by the position matrix
+        engine_3d.cur_pos_vec_mtxs[0].translate([0x2000, 0, 0]);
+        engine_3d.clip_mtx_needs_recalculation = true;
+        assert!(!box_test(&mut engine_3d, [-0x800; 3], [0x1000; 3]));
+        assert!(!engine_3d.gx_status.box_test_result());
+    }
+
+    #[test]
+    fn pos_test_results() {
+        let mut engine_3d = engine_3d();
+        assert_eq!(
+            pos_test(&mut engine_3d, [0x1000, -0x800, 0x400]),
+            [0x1000, -0x800, 0x400, 0x1000]
+        );
+        engine_3d.cur_pos_vec_mtxs[0].translate([0x2000, 0, -0x1000]);
+        engine_3d.cur_proj_mtx.scale([0x2000, 0x1000, 0x1000]);
+        engine_3d.clip_mtx_needs_recalculation = true;
+        assert_eq!(
+            pos_test(&mut engine_3d, [0x1000, -0x800, 0x400]),
+            [0x6000, -0x800, -0xC00, 0x1000]
+        );
+        // The tested position becomes the last vertex's, used by relative vertex commands
+        assert_eq!(engine_3d.last_vtx_coords, [0x1000, -0x800, 0x400]);
+    }
+
+    #[test]
+    fn vec_test_results() {
+        let mut engine_3d = engine_3d();
+        // 1.0.9 vectors get converted to 4.12
+        assert_eq!(
+            vec_test(&mut engine_3d, [0x100, -0x200, 0x1FF]),
+            [0x800, -0x1000, 0xFF8]
+        );
+        // Only the vector matrix is used, with no translation
+        engine_3d.cur_pos_vec_mtxs[0].scale([0x3000; 3]);
+        engine_3d.cur_pos_vec_mtxs[1].translate([0x1000; 3]);
+        assert_eq!(
+            vec_test(&mut engine_3d, [0x100, -0x200, 0x1FF]),
+            [0x800, -0x1000, 0xFF8]
+        );
+        // Bits 12-15 of the result are copied from its sign, even if it overflows
+        engine_3d.cur_pos_vec_mtxs[1].scale([0x2000, 0x1000, -0x1000]);
+        assert_eq!(
+            vec_test(&mut engine_3d, [0x1FF, 0x100, 0x100]),
+            [-0x10, 0x800, -0x800]
+        );
+    }
+}
@@ -0,0 +1,283 @@
+use super::{GeometryState, Polygon, RendererTx, RenderingState, ScreenVertex};
+use crate::utils::{Bytes, ReadSavestate, WriteSavestate};
+use core::mem::replace;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecordedCommand {
+    pub command: u8,
+    pub param: u32,
+}
+
+// Identifies recording files, followed by the format version, which should be bumped whenever the
+// layout of any of the stored fields changes
+const MAGIC: u32 = u32::from_le_bytes(*b"DSGX");
+const VERSION: u32 = 2;
+
+// Everything the 3D renderer receives to render a single frame, along with all GX commands executed
+// for it (from the one after the previous SWAP_BUFFERS command up to its own SWAP_BUFFERS command,
+// included) and the geometry engine's state before the first one, so that the commands can be
+// replayed through `Engine3d::new_replay` to regenerate the vertex and polygon RAM contents
+pub struct Recording {
+    pub geometry: Box<GeometryState>,
+    pub commands: Vec<RecordedCommand>,
+    pub vert_ram: Vec<ScreenVertex>,
+    pub poly_ram: Vec<Polygon>,
+    pub state: RenderingState,
+    pub texture: Box<Bytes<0x8_0000>>,
+    pub tex_pal: Box<Bytes<0x1_8000>>,
+}
+
+impl Recording {
+    pub fn store<S: WriteSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
+        save.start_field(b"magic")?;
+        save.store_raw(MAGIC);
+        save.store_raw(VERSION);
+
+        save.start_field(b"geometry")?;
+        save.store(&mut *self.geometry)?;
+
+        save.start_field(b"commands")?;
+        save.store_raw(self.commands.len() as u32);
+        for command in &self.commands {
+            save.store_raw(command.command);
+            save.store_raw(command.param);
+        }
+
+        save.start_field(b"vert_ram")?;
+        save.store_raw(self.vert_ram.len() as u32);
+        for vert in &mut self.vert_ram {
+            save.store(vert)?;
+        }
+
+        save.start_field(b"poly_ram")?;
+        save.store_raw(self.poly_ram.len() as u32);
+        for poly in &mut self.poly_ram {
+            save.store(poly)?;
+        }
+
+        save.start_field(b"state")?;
+        save.store(&mut self.state)?;
+
+        save.start_field(b"texture")?;
+        save.store(&mut *self.texture)?;
+        save.start_field(b"tex_pal")?;
+        save.store(&mut *self.tex_pal)
+    }
+
+    pub fn load<S: ReadSavestate>(save: &mut S) -> Result<Self, S::Error> {
+        save.start_field(b"magic")?;
+        if save.load_raw::<u32>()? != MAGIC || save.load_raw::<u32>()? != VERSION {
+            return Err(S::invalid_enum());
+        }
+
+        save.start_field(b"geometry")?;
+        let geometry = Box::new(save.load()?);
+
+        save.start_field(b"commands")?;
+        let commands_len = save.load_raw::<u32>()?;
+        let mut commands = Vec::with_capacity(commands_len as usize);
+        for _ in 0..commands_len {
+            commands.push(RecordedCommand {
+                command: save.load_raw()?,
+                param: save.load_raw()?,
+            });
+        }
+
+        save.start_field(b"vert_ram")?;
+        let vert_ram_len = save.load_raw::<u32>()?;
+        if vert_ram_len > 6144 {
+            return Err(S::invalid_enum());
+        }
+        let mut vert_ram = Vec::with_capacity(vert_ram_len as usize);
+        for _ in 0..vert_ram_len {
+            vert_ram.push(save.load()?);
+        }
+
+        save.start_field(b"poly_ram")?;
+        let poly_ram_len = save.load_raw::<u32>()?;
+        if poly_ram_len > 2048 {
+            return Err(S::invalid_enum());
+        }
+        let mut poly_ram = Vec::with_capacity(poly_ram_len as usize);
+        for _ in 0..poly_ram_len {
+            poly_ram.push(save.load()?);
+        }
+
+        save.start_field(b"state")?;
+        let state = save.load()?;
+
+        let mut texture: Box<Bytes<0x8_0000>> = unsafe { Box::new_zeroed().assume_init() };
+        let mut tex_pal: Box<Bytes<0x1_8000>> = unsafe { Box::new_zeroed().assume_init() };
+        save.start_field(b"texture")?;
+        save.load_into(&mut *texture)?;
+        save.start_field(b"tex_pal")?;
+        save.load_into(&mut *tex_pal)?;
+
+        Ok(Recording {
+            geometry,
+            commands,
+            vert_ram,
+            poly_ram,
+            state,
+            texture,
+            tex_pal,
+        })
+    }
+}
+
+enum Stage {
+    Idle,
+    // Waiting for the SWAP_BUFFERS command for the current frame to be executed, so that the
+    // recording starts at a frame boundary
+    Requested,
+    RecordingCommands {
+        geometry: Box<GeometryState>,
+        commands: Vec<RecordedCommand>,
+    },
+    // All commands have been executed, waiting for the buffers to be swapped
+    WaitingForSwap {
+        geometry: Box<GeometryState>,
+        commands: Vec<RecordedCommand>,
+    },
+    WaitingForRendering {
+        geometry: Box<GeometryState>,
+        commands: Vec<RecordedCommand>,
+        vert_ram: Vec<ScreenVertex>,
+        poly_ram: Vec<Polygon>,
+        state: RenderingState,
+    },
+    Finished(Box<Recording>),
+}
+
+pub(super) struct Recorder {
+    stage: Stage,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Recorder { stage: Stage::Idle }
+    }
+
+    pub fn start(&mut self) {
+        self.stage = Stage::Requested;
+    }
+
+    pub fn take(&mut self) -> Option<Box<Recording>> {
+        if !matches!(self.stage, Stage::Finished(_)) {
+            return None;
+        }
+        match replace(&mut self.stage, Stage::Idle) {
+            Stage::Finished(recording) => Some(recording),
+            _ => unreachable!(),
+        }
+    }
+
+    #[inline]
+    pub fn is_waiting_for_frame_start(&self) -> bool {
+        matches!(self.stage, Stage::Requested)
+    }
+
+    pub fn start_frame(&mut self, geometry: Box<GeometryState>) {
+        self.stage = Stage::RecordingCommands {
+            geometry,
+            commands: Vec::new(),
+        };
+    }
+
+    // Records a single GX FIFO entry (a command along with one of its parameters, or 0 for
+    // commands without any) as it gets executed
+    #[inline]
+    pub fn record_command(&mut self, command: u8, param: u32) {
+        if let Stage::RecordingCommands { commands, .. } = &mut self.stage {
+            commands.push(RecordedCommand { command, param });
+        }
+    }
+
+    pub fn end_frame(&mut self) {
+        if !matches!(self.stage, Stage::RecordingCommands { .. }) {
+            return;
+        }
+        if let Stage::RecordingCommands { geometry, commands } =
+            replace(&mut self.stage, Stage::Idle)
+        {
+            self.stage = Stage::WaitingForSwap { geometry, commands };
+        }
+    }
+
+    #[inline]
+    pub fn swap_buffers(
+        &mut self,
+        vert_ram: &[ScreenVertex],
+        poly_ram: &[Polygon],
+        state: &RenderingState,
+    ) {
+        if !matches!(self.stage, Stage::WaitingForSwap { .. }) {
+            return;
+        }
+        if let Stage::WaitingForSwap { geometry, commands } = replace(&mut self.stage, Stage::Idle)
+        {
+            self.stage = Stage::WaitingForRendering {
+                geometry,
+                commands,
+                vert_ram: vert_ram.to_vec(),
+                poly_ram: poly_ram.to_vec(),
+                state: state.clone(),
+            };
+        }
+    }
+
+    #[inline]
+    pub fn start_rendering(&mut self, texture: &Bytes<0x8_0000>, tex_pal: &Bytes<0x1_8000>) {
+        if !matches!(self.stage, Stage::WaitingForRendering { .. }) {
+            return;
+        }
+        if let Stage::WaitingForRendering {
+            geometry,
+            commands,
+            vert_ram,
+            poly_ram,
+            state,
+        } = replace(&mut self.stage, Stage::Idle)
+        {
+            let mut recording = Box::new(Recording {
+                geometry,
+                commands,
+                vert_ram,
+                poly_ram,
+                state,
+                texture: unsafe { Box::new_zeroed().assume_init() },
+                tex_pal: unsafe { Box::new_zeroed().assume_init() },
+            });
+            recording.texture.copy_from_slice(&texture[..]);
+            recording.tex_pal.copy_from_slice(&tex_pal[..]);
+            self.stage = Stage::Finished(recording);
+        }
+    }
+}
+
+// Used by geometry engines created to replay recordings, which never send anything to a renderer
+pub(super) struct ReplayRendererTx;
+
+impl RendererTx for ReplayRendererTx {
+    fn set_capture_enabled(&mut self, _capture_enabled: bool) {}
+
+    fn swap_buffers(
+        &mut self,
+        _vert_ram: &[ScreenVertex],
+        _poly_ram: &[Polygon],
+        _state: &RenderingState,
+    ) {
+    }
+
+    fn repeat_last_frame(&mut self, _state: &RenderingState) {}
+
+    fn start_rendering(
+        &mut self,
+        _texture: &Bytes<0x8_0000>,
+        _tex_pal: &Bytes<0x1_8000>,
+        _state: &RenderingState,
+    ) {
+    }
+
+    fn skip_rendering(&mut self) {}
+}
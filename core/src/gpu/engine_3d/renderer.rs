@@ -26,6 +26,11 @@ pub trait SoftRendererRx {
     fn start_frame(&mut self);
     fn read_scanline(&mut self) -> &Scanline<u32>;
     fn skip_scanline(&mut self);
+
+    // Renderers outputting at a higher resolution also return the `1 << resolution_scale_shift`
+    // lines the scanline was downscaled from, each `256 << resolution_scale_shift` pixels wide
+    fn resolution_scale_shift(&self) -> u8;
+    fn read_hi_res_scanline(&mut self) -> (&Scanline<u32>, &[u32]);
 }
 
 pub trait AccelRendererRx {
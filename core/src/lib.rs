@@ -12,7 +12,8 @@
     const_trait_impl,
     const_convert,
     const_for,
-    new_uninit
+    new_uninit,
+    once_cell
 )]
 #![warn(clippy::pedantic)]
 #![allow(
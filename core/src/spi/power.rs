@@ -1,8 +1,13 @@
 use crate::{
     cpu::{arm7, Schedule as _},
-    emu,
+    emu::{self, Timestamp},
     utils::Savestate,
 };
+use core::num::NonZeroU32;
+
+pub const BATTERY_LEVEL_MAX: u64 = 1 << 40;
+// The power management device reports the battery as low below roughly 10% of its charge
+const BATTERY_LOW_THRESHOLD: u64 = BATTERY_LEVEL_MAX / 10;
 
 proc_bitfield::bitfield! {
     #[derive(Clone, Copy, PartialEq, Eq, Savestate)]
@@ -55,6 +60,12 @@ proc_bitfield::bitfield! {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PowerLedColor {
+    Green,
+    Red,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Savestate)]
 pub enum DsLiteBacklightLevel {
     Low,
@@ -74,7 +85,16 @@ pub struct Power {
     control: Control,
     sound_level: SoundLevel,
     power_led_state: PowerLedState,
-    pub battery_low: bool,
+    battery_level: u64,
+    battery_low: bool,
+    battery_update_time: Timestamp,
+    // How long a full battery lasts / takes to charge, in emulated seconds (`None` to keep the
+    // level constant)
+    #[savestate(skip)]
+    pub battery_drain_time: Option<NonZeroU32>,
+    #[savestate(skip)]
+    pub battery_charge_time: Option<NonZeroU32>,
+    external_power: bool,
     mic_amplifier_enabled: bool,
     mic_amplifier_gain_control: MicAmplifierGainControl,
     mic_amplifier_gain: u8,
@@ -101,7 +121,12 @@ impl Power {
                 SoundLevel::Low
             },
             power_led_state: PowerLedState::Normal,
+            battery_level: BATTERY_LEVEL_MAX,
             battery_low: false,
+            battery_update_time: Timestamp(0),
+            battery_drain_time: None,
+            battery_charge_time: None,
+            external_power: false,
             mic_amplifier_enabled: false,
             mic_amplifier_gain_control: MicAmplifierGainControl(0),
             mic_amplifier_gain: 0,
@@ -245,6 +270,76 @@ impl Power {
         }
     }
 
+    #[inline]
+    pub fn battery_level(&self) -> u64 {
+        self.battery_level
+    }
+
+    #[inline]
+    pub fn set_battery_level(&mut self, value: u64) {
+        self.battery_level = value.min(BATTERY_LEVEL_MAX);
+        self.battery_low = self.battery_level < BATTERY_LOW_THRESHOLD;
+    }
+
+    #[inline]
+    pub fn battery_low(&self) -> bool {
+        self.battery_low
+    }
+
+    #[inline]
+    pub fn external_power(&self) -> bool {
+        self.external_power
+    }
+
+    #[inline]
+    pub fn set_external_power(&mut self, value: bool) {
+        self.external_power = value;
+        if self.is_ds_lite {
+            self.ds_lite_backlight_control.set_external_power(value);
+            self.update_ds_lite_backlight_level();
+        }
+    }
+
+    #[inline]
+    pub fn charging(&self) -> bool {
+        self.external_power && self.battery_level < BATTERY_LEVEL_MAX
+    }
+
+    // The power LED is driven by hardware and turns red when the battery is low, independently
+    // from the blinking state requested by software
+    #[inline]
+    pub fn power_led_color(&self) -> PowerLedColor {
+        if self.battery_low {
+            PowerLedColor::Red
+        } else {
+            PowerLedColor::Green
+        }
+    }
+
+    pub(crate) fn update_battery(
+        &mut self,
+        time: Timestamp,
+        arm7_schedule: &mut arm7::Schedule,
+        emu_schedule: &mut emu::Schedule,
+    ) {
+        let elapsed = time.0.saturating_sub(self.battery_update_time.0);
+        self.battery_update_time = time;
+        // BATTERY_LEVEL_MAX / (seconds * 2^25 cycles/s) per elapsed cycle
+        if self.external_power {
+            if let Some(charge_time) = self.battery_charge_time {
+                let charged = (elapsed << 15) / u64::from(charge_time.get());
+                self.set_battery_level(self.battery_level.saturating_add(charged));
+            }
+        } else if let Some(drain_time) = self.battery_drain_time {
+            let drained = (elapsed << 15) / u64::from(drain_time.get());
+            let prev_level = self.battery_level;
+            self.set_battery_level(self.battery_level.saturating_sub(drained));
+            if prev_level != 0 && self.battery_level == 0 {
+                self.request_shutdown(arm7_schedule, emu_schedule);
+            }
+        }
+    }
+
     #[inline]
     pub fn mic_amplifier_enabled(&self) -> bool {
         self.mic_amplifier_enabled
@@ -293,6 +388,7 @@ impl Power {
             });
             self.mic_amplifier_enabled = true;
         }
+        self.mic_amplifier_gain = value;
         true
     }
 
@@ -323,12 +419,6 @@ impl Power {
         self.update_ds_lite_backlight_level();
     }
 
-    #[inline]
-    pub fn set_ds_lite_external_power(&mut self, value: bool) {
-        self.ds_lite_backlight_control.set_external_power(value);
-        self.update_ds_lite_backlight_level();
-    }
-
     #[inline]
     pub fn ds_lite_backlight_level(&self) -> DsLiteBacklightLevel {
         self.ds_lite_backlight_level
@@ -1,7 +1,9 @@
-use super::Power;
+pub mod mic;
+
+use super::{firmware, Power};
 use crate::{
     emu::{input, Timestamp},
-    utils::Savestate,
+    utils::{ByteSlice, Savestate},
 };
 
 proc_bitfield::bitfield! {
@@ -15,6 +17,84 @@ proc_bitfield::bitfield! {
     }
 }
 
+// Touch screen calibration data, as stored in the firmware user settings. Touch positions are
+// converted to ADC values through the same linear mapping games and the firmware use to convert
+// them back, so software calibration (including the firmware's own calibration screen) lines up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Calibration {
+    pub adc_points: [[u16; 2]; 2],
+    pub screen_points: [[u8; 2]; 2],
+}
+
+impl Calibration {
+    pub const IDENTITY: Self = Calibration {
+        adc_points: [[0, 0], [0xFF0, 0xBF0]],
+        screen_points: [[0, 0], [0xFF, 0xBF]],
+    };
+
+    pub fn from_user_settings(user_settings: ByteSlice) -> Self {
+        Calibration {
+            adc_points: [
+                [
+                    user_settings.read_le::<u16>(0x58) & 0xFFF,
+                    user_settings.read_le::<u16>(0x5A) & 0xFFF,
+                ],
+                [
+                    user_settings.read_le::<u16>(0x5E) & 0xFFF,
+                    user_settings.read_le::<u16>(0x60) & 0xFFF,
+                ],
+            ],
+            screen_points: [
+                [user_settings[0x5C], user_settings[0x5D]],
+                [user_settings[0x62], user_settings[0x63]],
+            ],
+        }
+    }
+
+    pub fn from_firmware(firmware: ByteSlice) -> Option<Self> {
+        if firmware.len() < 0x22 {
+            return None;
+        }
+        let user_settings_offset = (firmware.read_le::<u16>(0x20) as usize) << 3;
+        if user_settings_offset + 0x200 > firmware.len() {
+            return None;
+        }
+        Some(Self::from_user_settings(firmware::newest_user_settings(
+            &firmware,
+        )))
+    }
+
+    /// Converts a screen position in 1/16 pixel units to raw ADC values; degenerate calibration
+    /// axes fall back to an identity mapping.
+    #[must_use]
+    pub fn screen_to_adc(&self, pos: [u16; 2]) -> [u16; 2] {
+        let mut result = pos;
+        for (i, result) in result.iter_mut().enumerate() {
+            let adc_start = i32::from(self.adc_points[0][i]);
+            let adc_delta = i32::from(self.adc_points[1][i]) - adc_start;
+            let screen_start = i32::from(self.screen_points[0][i]) << 4;
+            let screen_delta = (i32::from(self.screen_points[1][i]) << 4) - screen_start;
+            if adc_delta != 0 && screen_delta != 0 {
+                *result = (adc_start
+                    + (i32::from(pos[i]) - screen_start) * adc_delta / screen_delta)
+                    .clamp(0, 0xFFF) as u16;
+            }
+        }
+        result
+    }
+}
+
+// Touch resistance model (as described in the TSC2046 datasheet):
+//     R_touch = R_x_plate * (X / 4096) * (Z2 / Z1 - 1)
+// Pressure is mapped linearly to the touch resistance, with harder presses lowering it.
+const X_PLATE_RESISTANCE: u32 = 400;
+const MIN_TOUCH_RESISTANCE: u32 = 100;
+const MAX_TOUCH_RESISTANCE: u32 = 2000;
+const Z2_TOUCHING: u32 = 0xC00;
+
+pub const DEFAULT_PRESSURE: u16 = 0x800;
+pub const DEFAULT_TEMPERATURE: i16 = 25;
+
 pub const MIC_SAMPLES_PER_FRAME: usize = (6 * 355 * 263 + 128) / 128;
 
 pub trait MicBackend {
@@ -22,6 +102,23 @@ pub trait MicBackend {
     fn read_frame_samples(&mut self, offset: usize, samples: &mut [i16]);
 }
 
+// GBATEK (DS Power Management Device, register 3) lists the mic amplifier gain settings as 20x,
+// 40x, 80x and 160x, i.e. linear amplification factors where each step doubles the amplitude of
+// the signal reaching the ADC. Backend samples are taken to be the signal as amplified by the
+// lowest (20x) setting, and are scaled relative to it, saturating at the ADC's input range. With
+// the amplifier disabled (register 2), no signal reaches the ADC, which then reads the bias
+// (center) voltage.
+// UNVERIFIED: GBATEK doesn't describe what happens past the ADC's range or with the amplifier
+// disabled, so the saturation and the bias reading are assumptions; none of this has been checked
+// against hardware or a test ROM.
+fn apply_mic_gain(sample: i16, power: &Power) -> i16 {
+    if !power.mic_amplifier_enabled() {
+        return 0;
+    }
+    (i32::from(sample) << power.mic_amplifier_gain_control().gain_shift()).clamp(-0x8000, 0x7FFF)
+        as i16
+}
+
 pub struct MicData {
     pub backend: Box<dyn MicBackend>,
     read_in_current_frame: bool,
@@ -55,6 +152,8 @@ pub struct Tsc {
     data_out: u16,
     x_pos: u16,
     y_pos: u16,
+    pressure: u16,
+    temperature: i16,
 }
 
 impl Tsc {
@@ -74,7 +173,9 @@ impl Tsc {
             cur_control_byte: ControlByte(0),
             data_out: 0,
             x_pos: 0,
-            y_pos: 0,
+            y_pos: 0xFFF,
+            pressure: DEFAULT_PRESSURE,
+            temperature: DEFAULT_TEMPERATURE,
         }
     }
 
@@ -108,6 +209,50 @@ impl Tsc {
         self.y_pos = 0xFFF;
     }
 
+    #[inline]
+    pub fn pressure(&self) -> u16 {
+        self.pressure
+    }
+
+    #[inline]
+    pub fn set_pressure(&mut self, value: u16) {
+        self.pressure = value.min(0xFFF);
+    }
+
+    /// Returns the die temperature reported through the temperature channels, in degrees Celsius.
+    #[inline]
+    pub fn temperature(&self) -> i16 {
+        self.temperature
+    }
+
+    #[inline]
+    pub fn set_temperature(&mut self, value: i16) {
+        self.temperature = value.clamp(-40, 85);
+    }
+
+    fn z_positions(&self) -> (u16, u16) {
+        if !self.pen_down {
+            return (0, 0xFFF);
+        }
+        let touch_resistance = MAX_TOUCH_RESISTANCE
+            - (MAX_TOUCH_RESISTANCE - MIN_TOUCH_RESISTANCE) * u32::from(self.pressure) / 0xFFF;
+        // Resistance of the X plate portion between the touch point and X-, scaled by 4096
+        let x_resistance = u64::from(X_PLATE_RESISTANCE * u32::from(self.x_pos.max(1)));
+        let z1 = u64::from(Z2_TOUCHING) * x_resistance
+            / (x_resistance + u64::from(touch_resistance) * 4096);
+        (z1 as u16, Z2_TOUCHING as u16)
+    }
+
+    // The TSC's temperature diodes drop ~600 mV at 25 °C, with a -2.1 mV/°C slope for TEMP0, and
+    // TEMP1 - TEMP0 = T(K) * 4096 / 8568 (as used by GBATEK's temperature formula), assuming a
+    // 3.3 V reference.
+    fn temperature_values(&self) -> (u16, u16) {
+        let temp0_uv = 600_000 - 2100 * (i32::from(self.temperature) - 25);
+        let temp0 = temp0_uv * 4096 / 3_300_000;
+        let temp1 = temp0 + (i32::from(self.temperature) + 273) * 4096 / 8568;
+        (temp0.clamp(0, 0xFFF) as u16, temp1.clamp(0, 0xFFF) as u16)
+    }
+
     #[inline]
     pub fn pen_down(&self) -> bool {
         self.pen_down
@@ -132,7 +277,7 @@ impl Tsc {
         &mut self,
         value: ControlByte,
         time: Timestamp,
-        _power: &Power,
+        power: &Power,
         input_status: &mut input::Status,
     ) -> u16 {
         if value.power_down_mode() & 1 == 0 {
@@ -145,18 +290,13 @@ impl Tsc {
         let result = match value.channel() {
             0 => {
                 #[cfg(feature = "log")]
-                if value.single_ended_mode() {
-                    slog::debug!(
-                        self.logger,
-                        "Reading from unimplemented channel 0 (temperature 0)"
-                    );
-                } else {
+                if !value.single_ended_mode() {
                     slog::warn!(
                         self.logger,
                         "Reading from channel 0 (temperature 0) in differential mode"
                     );
                 }
-                0xFFF
+                self.temperature_values().0
             }
             1 => self.y_pos,
             2 => {
@@ -167,24 +307,11 @@ impl Tsc {
                         "Reading from channel 2 (battery voltage) in differential mode"
                     );
                 }
-                0xFFF
-            }
-            3 => {
-                #[cfg(feature = "log")]
-                slog::debug!(
-                    self.logger,
-                    "Reading from unimplemented channel 3 (Z1-position)"
-                );
-                0xFFF
-            }
-            4 => {
-                #[cfg(feature = "log")]
-                slog::debug!(
-                    self.logger,
-                    "Reading from unimplemented channel 4 (Z2-position)"
-                );
-                0xFFF
+                // VBAT is connected to ground
+                0
             }
+            3 => self.z_positions().0,
+            4 => self.z_positions().1,
             5 => self.x_pos,
             6 => {
                 if value.single_ended_mode() {
@@ -199,18 +326,7 @@ impl Tsc {
                     } else {
                         0
                     };
-                    // TODO: How to apply the gain value? It can be assumed to be a value in decibel
-                    //       (20 dB, 40 dB, 80 dB, 160 dB resulting in a 10x, 100x, 10^4x and
-                    //       10^8x increase in amplitude), but the baseline amplitude should be
-                    //       known.
-                    // if power.mic_amplifier_enabled() {
-                    //     sample = (sample as i32)
-                    //         .saturating_mul(
-                    //             [10, 100, 10_000, 100_000_000]
-                    //                 [power.mic_amplifier_gain_control().gain_shift() as usize],
-                    //         )
-                    //         .clamp(-0x8000, 0x7FFF) as i16;
-                    // }
+                    let sample = apply_mic_gain(sample, power);
                     (sample as u16).wrapping_add(0x8000) >> 4
                 } else {
                     if !self.is_ds_lite {
@@ -225,18 +341,13 @@ impl Tsc {
             }
             _ => {
                 #[cfg(feature = "log")]
-                if value.single_ended_mode() {
-                    slog::debug!(
-                        self.logger,
-                        "Reading from unimplemented channel 7 (temperature 1)"
-                    );
-                } else {
+                if !value.single_ended_mode() {
                     slog::warn!(
                         self.logger,
                         "Reading from channel 7 (temperature 1) in differential mode"
                     );
                 }
-                0xFFF
+                self.temperature_values().1
             }
         };
         (if value.res_8_bit() {
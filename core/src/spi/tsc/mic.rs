@@ -0,0 +1,549 @@
+use super::MicBackend;
+use core::fmt;
+use std::error::Error;
+
+const CYCLES_PER_FRAME: u64 = 6 * 355 * 263;
+const CYCLES_PER_SAMPLE: u64 = 128;
+
+// One mic sample can be read every 128 cycles (33.554432 MHz / 262.144 kHz)
+pub const SAMPLE_RATE: u32 = (1 << 25) / CYCLES_PER_SAMPLE as u32;
+
+// Tracks the absolute index of the first sample of the current frame, so that generated samples
+// only depend on emulated time (and repeated reads of the same frame return the same data).
+#[derive(Clone, Copy, Default)]
+struct Position {
+    frame_start_time: u64,
+    started: bool,
+}
+
+impl Position {
+    fn start_frame(&mut self) {
+        if self.started {
+            self.frame_start_time += CYCLES_PER_FRAME;
+        } else {
+            self.started = true;
+        }
+    }
+
+    fn sample_index(&self, offset: usize) -> u64 {
+        self.frame_start_time / CYCLES_PER_SAMPLE + offset as u64
+    }
+}
+
+const fn ms_to_samples(ms: u32) -> u64 {
+    ms as u64 * SAMPLE_RATE as u64 / 1000
+}
+
+// SplitMix64 finalizer, used as a stateless noise source indexed by sample number
+fn noise(seed: u64, index: u64) -> i16 {
+    let mut x = seed.wrapping_add(index.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    x = (x ^ x >> 30).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ x >> 27).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (x ^ x >> 31) as i16
+}
+
+fn scale(sample: i16, amplitude: u16) -> i16 {
+    ((sample as i32 * amplitude as i32) >> 15) as i16
+}
+
+pub struct Silence;
+
+impl MicBackend for Silence {
+    fn start_frame(&mut self) {}
+
+    fn read_frame_samples(&mut self, _offset: usize, samples: &mut [i16]) {
+        samples.fill(0);
+    }
+}
+
+pub struct WhiteNoise {
+    position: Position,
+    seed: u64,
+    amplitude: u16,
+}
+
+impl WhiteNoise {
+    pub fn new(seed: u64, amplitude: u16) -> Self {
+        WhiteNoise {
+            position: Position::default(),
+            seed,
+            amplitude: amplitude.min(0x8000),
+        }
+    }
+}
+
+impl MicBackend for WhiteNoise {
+    fn start_frame(&mut self) {
+        self.position.start_frame();
+    }
+
+    fn read_frame_samples(&mut self, offset: usize, samples: &mut [i16]) {
+        let start_index = self.position.sample_index(offset);
+        for (sample, index) in samples.iter_mut().zip(start_index..) {
+            *sample = scale(noise(self.seed, index), self.amplitude);
+        }
+    }
+}
+
+// Periodic bursts of loud noise with short linear fades, which is what most games' "blowing"
+// detection (usually based on the peak or average amplitude over a few frames) looks for.
+pub struct Blow {
+    position: Position,
+    seed: u64,
+    amplitude: u16,
+    burst_len: u64,
+    fade_len: u64,
+    interval: u64,
+}
+
+impl Blow {
+    pub fn new(seed: u64, amplitude: u16, burst_ms: u32, interval_ms: u32) -> Self {
+        let burst_len = ms_to_samples(burst_ms).max(1);
+        Blow {
+            position: Position::default(),
+            seed,
+            amplitude: amplitude.min(0x8000),
+            burst_len,
+            fade_len: ms_to_samples(10).min(burst_len / 2).max(1),
+            interval: ms_to_samples(interval_ms).max(burst_len),
+        }
+    }
+
+    fn envelope(&self, index: u64) -> u64 {
+        let pos = index % self.interval;
+        if pos >= self.burst_len {
+            0
+        } else {
+            let edge_dist = pos.min(self.burst_len - 1 - pos);
+            (edge_dist.min(self.fade_len) << 15) / self.fade_len
+        }
+    }
+}
+
+impl MicBackend for Blow {
+    fn start_frame(&mut self) {
+        self.position.start_frame();
+    }
+
+    fn read_frame_samples(&mut self, offset: usize, samples: &mut [i16]) {
+        let start_index = self.position.sample_index(offset);
+        for (sample, index) in samples.iter_mut().zip(start_index..) {
+            let amplitude = (self.amplitude as u64 * self.envelope(index)) >> 15;
+            *sample = scale(noise(self.seed, index), amplitude as u16);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WavError {
+    NotRiffWave,
+    MissingFmtChunk,
+    MissingDataChunk,
+    InvalidFmtChunk,
+    UnsupportedFormat {
+        format_tag: u16,
+        bits_per_sample: u16,
+    },
+}
+
+impl Error for WavError {}
+
+impl fmt::Display for WavError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WavError::NotRiffWave => f.write_str("not a RIFF WAVE file"),
+            WavError::MissingFmtChunk => f.write_str("missing fmt chunk"),
+            WavError::MissingDataChunk => f.write_str("missing data chunk"),
+            WavError::InvalidFmtChunk => f.write_str("invalid fmt chunk"),
+            WavError::UnsupportedFormat {
+                format_tag,
+                bits_per_sample,
+            } => write!(
+                f,
+                "unsupported sample format {format_tag:#06X} with {bits_per_sample} bits per sample"
+            ),
+        }
+    }
+}
+
+struct WavFormat {
+    format_tag: u16,
+    channels: u16,
+    sample_rate: u32,
+    block_align: u16,
+    bits_per_sample: u16,
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+fn parse_fmt_chunk(chunk: &[u8]) -> Result<WavFormat, WavError> {
+    if chunk.len() < 16 {
+        return Err(WavError::InvalidFmtChunk);
+    }
+    let mut format_tag = read_u16(chunk, 0);
+    // WAVE_FORMAT_EXTENSIBLE stores the actual format tag at the start of the sub-format GUID
+    if format_tag == 0xFFFE {
+        if chunk.len() < 26 {
+            return Err(WavError::InvalidFmtChunk);
+        }
+        format_tag = read_u16(chunk, 24);
+    }
+    let format = WavFormat {
+        format_tag,
+        channels: read_u16(chunk, 2),
+        sample_rate: read_u32(chunk, 4),
+        block_align: read_u16(chunk, 12),
+        bits_per_sample: read_u16(chunk, 14),
+    };
+    if format.channels == 0
+        || format.sample_rate == 0
+        || (format.block_align as u32)
+            < format.channels as u32 * (format.bits_per_sample as u32 / 8)
+    {
+        return Err(WavError::InvalidFmtChunk);
+    }
+    Ok(format)
+}
+
+fn decode_sample(format: &WavFormat, bytes: &[u8]) -> Option<i32> {
+    Some(match (format.format_tag, format.bits_per_sample) {
+        (1, 8) => (bytes[0] as i32 - 0x80) << 8,
+        (1, 16) => i16::from_le_bytes([bytes[0], bytes[1]]) as i32,
+        (1, 24) => i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 16,
+        (1, 32) => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) >> 16,
+        (3, 32) => {
+            let value = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            (value * 32768.0).clamp(-32768.0, 32767.0) as i32
+        }
+        (3, 64) => {
+            let value = f64::from_le_bytes([
+                bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+            ]);
+            (value * 32768.0).clamp(-32768.0, 32767.0) as i32
+        }
+        _ => return None,
+    })
+}
+
+pub struct Wav {
+    position: Position,
+    samples: Box<[i16]>,
+    sample_rate: u32,
+    looping: bool,
+}
+
+impl Wav {
+    pub fn new(data: &[u8], looping: bool) -> Result<Self, WavError> {
+        if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+            return Err(WavError::NotRiffWave);
+        }
+
+        let mut format = None;
+        let mut sample_data = None;
+        let mut chunk_start = 12;
+        while chunk_start + 8 <= data.len() {
+            let id = &data[chunk_start..chunk_start + 4];
+            let len = read_u32(data, chunk_start + 4) as usize;
+            let contents_start = chunk_start + 8;
+            let contents_end = contents_start.saturating_add(len).min(data.len());
+            let contents = &data[contents_start..contents_end];
+            match id {
+                b"fmt " => format = Some(parse_fmt_chunk(contents)?),
+                b"data" => sample_data = Some(contents),
+                _ => {}
+            }
+            // Chunks are padded to an even length
+            chunk_start = contents_start.saturating_add(len).saturating_add(len & 1);
+        }
+
+        let format = format.ok_or(WavError::MissingFmtChunk)?;
+        let sample_data = sample_data.ok_or(WavError::MissingDataChunk)?;
+
+        let unsupported_format = WavError::UnsupportedFormat {
+            format_tag: format.format_tag,
+            bits_per_sample: format.bits_per_sample,
+        };
+        let bytes_per_sample = format.bits_per_sample as usize / 8;
+        if format.bits_per_sample % 8 != 0 || bytes_per_sample == 0 {
+            return Err(unsupported_format);
+        }
+
+        let mut samples = Vec::with_capacity(sample_data.len() / format.block_align as usize);
+        for frame in sample_data.chunks_exact(format.block_align as usize) {
+            let mut sum = 0;
+            for channel in
+                frame[..format.channels as usize * bytes_per_sample].chunks_exact(bytes_per_sample)
+            {
+                sum += decode_sample(&format, channel).ok_or(unsupported_format)?;
+            }
+            samples.push((sum / format.channels as i32) as i16);
+        }
+
+        Ok(Wav {
+            position: Position::default(),
+            samples: samples.into_boxed_slice(),
+            sample_rate: format.sample_rate,
+            looping,
+        })
+    }
+
+    #[inline]
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    #[inline]
+    pub fn looping(&self) -> bool {
+        self.looping
+    }
+
+    fn source_sample(&self, index: u64) -> i32 {
+        let len = self.samples.len() as u64;
+        let index = if self.looping {
+            index % len
+        } else if index < len {
+            index
+        } else {
+            return 0;
+        };
+        self.samples[index as usize] as i32
+    }
+
+    fn sample_at(&self, index: u64) -> i16 {
+        if self.samples.is_empty() {
+            return 0;
+        }
+        // 16.16 fixed-point position in the source sample stream, linearly interpolated
+        let pos = ((index as u128 * self.sample_rate as u128) << 16) / SAMPLE_RATE as u128;
+        let int_pos = (pos >> 16) as u64;
+        let fract = (pos & 0xFFFF) as i32;
+        let a = self.source_sample(int_pos);
+        let b = self.source_sample(int_pos + 1);
+        (a + (((b - a) * fract) >> 16)) as i16
+    }
+}
+
+impl MicBackend for Wav {
+    fn start_frame(&mut self) {
+        self.position.start_frame();
+    }
+
+    fn read_frame_samples(&mut self, offset: usize, samples: &mut [i16]) {
+        let start_index = self.position.sample_index(offset);
+        for (sample, index) in samples.iter_mut().zip(start_index..) {
+            *sample = self.sample_at(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fmt_chunk(
+        format_tag: u16,
+        channels: u16,
+        sample_rate: u32,
+        bits_per_sample: u16,
+    ) -> Vec<u8> {
+        let block_align = channels * (bits_per_sample / 8);
+        let mut chunk = Vec::new();
+        chunk.extend_from_slice(&format_tag.to_le_bytes());
+        chunk.extend_from_slice(&channels.to_le_bytes());
+        chunk.extend_from_slice(&sample_rate.to_le_bytes());
+        chunk.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        chunk.extend_from_slice(&block_align.to_le_bytes());
+        chunk.extend_from_slice(&bits_per_sample.to_le_bytes());
+        chunk
+    }
+
+    fn chunk(id: &[u8; 4], contents: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(contents);
+        if contents.len() & 1 != 0 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn wav_file(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = b"RIFF\0\0\0\0WAVE".to_vec();
+        for chunk in chunks {
+            data.extend_from_slice(chunk);
+        }
+        let riff_len = data.len() as u32 - 8;
+        data[4..8].copy_from_slice(&riff_len.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn parses_pcm16_stereo_downmixing_to_mono() {
+        let samples: Vec<u8> = [1000_i16, 3000, -2000, -4000]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        let wav = Wav::new(
+            &wav_file(&[
+                chunk(b"fmt ", &fmt_chunk(1, 2, 44100, 16)),
+                chunk(b"data", &samples),
+            ]),
+            false,
+        )
+        .unwrap();
+        assert_eq!(wav.sample_rate(), 44100);
+        assert_eq!(&*wav.samples, &[2000, -3000]);
+    }
+
+    #[test]
+    fn skips_unknown_and_odd_length_chunks() {
+        let wav = Wav::new(
+            &wav_file(&[
+                chunk(b"LIST", b"abc"),
+                chunk(b"fmt ", &fmt_chunk(1, 1, SAMPLE_RATE, 8)),
+                chunk(b"data", &[0x80, 0xFF, 0x00]),
+            ]),
+            true,
+        )
+        .unwrap();
+        assert_eq!(&*wav.samples, &[0, 0x7F00, -0x8000]);
+        assert!(wav.looping());
+    }
+
+    #[test]
+    fn decodes_float_and_extensible_formats() {
+        let mut extensible = fmt_chunk(0xFFFE, 1, 48000, 32);
+        extensible.extend_from_slice(&[22, 0, 32, 0, 4, 0, 0, 0]);
+        extensible.extend_from_slice(&3_u16.to_le_bytes());
+        extensible.extend_from_slice(&[0; 14]);
+        let samples: Vec<u8> = [0.5_f32, -2.0]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        let wav = Wav::new(
+            &wav_file(&[chunk(b"fmt ", &extensible), chunk(b"data", &samples)]),
+            false,
+        )
+        .unwrap();
+        assert_eq!(&*wav.samples, &[0x4000, -0x8000]);
+    }
+
+    #[test]
+    fn truncated_data_chunk_drops_partial_frames() {
+        let mut data = wav_file(&[
+            chunk(b"fmt ", &fmt_chunk(1, 1, 22050, 16)),
+            chunk(b"data", &[0x34, 0x12, 0x78, 0x56]),
+        ]);
+        data.truncate(data.len() - 1);
+        let wav = Wav::new(&data, false).unwrap();
+        assert_eq!(&*wav.samples, &[0x1234]);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        assert_eq!(
+            Wav::new(b"RIFF\0\0\0\0AVI ", false).err(),
+            Some(WavError::NotRiffWave)
+        );
+        assert_eq!(Wav::new(b"RIFF", false).err(), Some(WavError::NotRiffWave));
+        assert_eq!(
+            Wav::new(&wav_file(&[chunk(b"data", &[0; 4])]), false).err(),
+            Some(WavError::MissingFmtChunk)
+        );
+        assert_eq!(
+            Wav::new(
+                &wav_file(&[chunk(b"fmt ", &fmt_chunk(1, 1, 8000, 16))]),
+                false
+            )
+            .err(),
+            Some(WavError::MissingDataChunk)
+        );
+        assert_eq!(
+            Wav::new(&wav_file(&[chunk(b"fmt ", &[1, 0, 1, 0])]), false).err(),
+            Some(WavError::InvalidFmtChunk)
+        );
+        assert_eq!(
+            Wav::new(
+                &wav_file(&[
+                    chunk(b"fmt ", &fmt_chunk(1, 0, 8000, 16)),
+                    chunk(b"data", &[])
+                ]),
+                false
+            )
+            .err(),
+            Some(WavError::InvalidFmtChunk)
+        );
+        assert_eq!(
+            Wav::new(
+                &wav_file(&[
+                    chunk(b"fmt ", &fmt_chunk(2, 1, 8000, 16)),
+                    chunk(b"data", &[0; 2])
+                ]),
+                false
+            )
+            .err(),
+            Some(WavError::UnsupportedFormat {
+                format_tag: 2,
+                bits_per_sample: 16,
+            })
+        );
+        assert_eq!(
+            Wav::new(
+                &wav_file(&[
+                    chunk(b"fmt ", &fmt_chunk(1, 1, 8000, 12)),
+                    chunk(b"data", &[0; 2])
+                ]),
+                false
+            )
+            .err(),
+            Some(WavError::UnsupportedFormat {
+                format_tag: 1,
+                bits_per_sample: 12,
+            })
+        );
+    }
+
+    #[test]
+    fn resamples_and_stops_or_loops_at_the_end() {
+        let samples: Vec<u8> = [0_i16, 0x1000]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        let data = wav_file(&[
+            chunk(b"fmt ", &fmt_chunk(1, 1, SAMPLE_RATE / 2, 16)),
+            chunk(b"data", &samples),
+        ]);
+
+        let mut wav = Wav::new(&data, false).unwrap();
+        wav.start_frame();
+        let mut output = [0; 6];
+        wav.read_frame_samples(0, &mut output);
+        assert_eq!(output, [0, 0x800, 0x1000, 0x800, 0, 0]);
+
+        let mut wav = Wav::new(&data, true).unwrap();
+        wav.start_frame();
+        wav.read_frame_samples(0, &mut output);
+        assert_eq!(output, [0, 0x800, 0x1000, 0x800, 0, 0x800]);
+    }
+}
@@ -2,15 +2,37 @@ mod cpal;
 pub use self::cpal::*;
 
 use super::{InterpMethod, SYS_CLOCK_RATE};
-use dust_core::spi::tsc::{MicBackend, MIC_SAMPLES_PER_FRAME};
+use dust_core::spi::tsc::{
+    mic::{self, WavError},
+    MicBackend, MIC_SAMPLES_PER_FRAME,
+};
+use serde::{Deserialize, Serialize};
 use std::{
     cell::UnsafeCell,
+    fmt, fs, io,
+    path::Path,
     sync::atomic::{AtomicUsize, Ordering},
     sync::Arc,
 };
 
 pub const OUTPUT_SAMPLE_RATE: u32 = SYS_CLOCK_RATE / 128;
 
+const SYNTH_SEED: u64 = 0x4D49_4353_5953_4E54;
+const WHITE_NOISE_AMPLITUDE: u16 = 0x2000;
+const BLOW_AMPLITUDE: u16 = 0x6000;
+const BLOW_BURST_MS: u32 = 1000;
+const BLOW_INTERVAL_MS: u32 = 3000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Source {
+    Device,
+    Wav,
+    Silence,
+    WhiteNoise,
+    Blow,
+}
+
 #[repr(C)]
 struct Buffer {
     write_pos: AtomicUsize,
@@ -99,3 +121,62 @@ impl Channel {
         })
     }
 }
+
+pub enum BackendError {
+    NoDevice,
+    MissingWavPath,
+    Io(io::Error),
+    Wav(WavError),
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendError::NoDevice => f.write_str("No default audio input device available"),
+            BackendError::MissingWavPath => f.write_str("No WAV file path specified"),
+            BackendError::Io(err) => write!(f, "Couldn't read WAV file: {err}"),
+            BackendError::Wav(err) => write!(f, "Couldn't load WAV file: {err}"),
+        }
+    }
+}
+
+pub struct Backend {
+    pub input_stream: Option<InputStream>,
+    pub mic_backend: Box<dyn MicBackend + Send>,
+}
+
+impl Backend {
+    pub fn new(
+        source: Source,
+        interp_method: InterpMethod,
+        wav_path: Option<&Path>,
+        wav_looping: bool,
+    ) -> Result<Self, BackendError> {
+        let mic_backend: Box<dyn MicBackend + Send> = match source {
+            Source::Device => {
+                let channel = Channel::new(interp_method).ok_or(BackendError::NoDevice)?;
+                return Ok(Backend {
+                    input_stream: Some(channel.input_stream),
+                    mic_backend: Box::new(channel.rx),
+                });
+            }
+            Source::Wav => {
+                let data = fs::read(wav_path.ok_or(BackendError::MissingWavPath)?)
+                    .map_err(BackendError::Io)?;
+                Box::new(mic::Wav::new(&data, wav_looping).map_err(BackendError::Wav)?)
+            }
+            Source::Silence => Box::new(mic::Silence),
+            Source::WhiteNoise => Box::new(mic::WhiteNoise::new(SYNTH_SEED, WHITE_NOISE_AMPLITUDE)),
+            Source::Blow => Box::new(mic::Blow::new(
+                SYNTH_SEED,
+                BLOW_AMPLITUDE,
+                BLOW_BURST_MS,
+                BLOW_INTERVAL_MS,
+            )),
+        };
+        Ok(Backend {
+            input_stream: None,
+            mic_backend,
+        })
+    }
+}
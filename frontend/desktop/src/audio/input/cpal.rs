@@ -100,7 +100,7 @@ impl InputData {
             self.interp
                 .push_input_sample([input_sample / self.channels as f64]);
             while fract < 1.0 {
-                let [result] = self.interp.get_output_sample(fract);
+                let [result] = self.interp.get_output_sample(fract, self.sample_rate_ratio);
                 self.tx
                     .write_sample((result * 32768.0).clamp(-32768.0, 32767.0) as i16);
                 fract += self.sample_rate_ratio;
@@ -1,10 +1,13 @@
+use dust_core::audio::sinc;
 use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
 
 #[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum InterpMethod {
     Nearest,
     Cubic,
+    Sinc,
 }
 
 impl InterpMethod {
@@ -17,6 +20,9 @@ impl InterpMethod {
             InterpMethod::Cubic => Box::new(Cubic {
                 hist: [[0.0; CHANNELS]; 4],
             }),
+            InterpMethod::Sinc => Box::new(Sinc {
+                hist: [[0.0; CHANNELS]; SINC_MAX_TAPS],
+            }),
         }
     }
 }
@@ -24,7 +30,8 @@ impl InterpMethod {
 pub trait Interp<const CHANNELS: usize>: Send {
     fn push_input_sample(&mut self, sample: [f64; CHANNELS]);
     fn copy_last_input_sample(&mut self);
-    fn get_output_sample(&self, fract: f64) -> [f64; CHANNELS];
+    // `ratio` is the input sample rate divided by the output's
+    fn get_output_sample(&self, fract: f64, ratio: f64) -> [f64; CHANNELS];
 }
 
 struct Nearest<const CHANNELS: usize> {
@@ -36,7 +43,7 @@ impl<const CHANNELS: usize> Interp<CHANNELS> for Nearest<CHANNELS> {
         self.last_sample = sample;
     }
     fn copy_last_input_sample(&mut self) {}
-    fn get_output_sample(&self, _fract: f64) -> [f64; CHANNELS] {
+    fn get_output_sample(&self, _fract: f64, _ratio: f64) -> [f64; CHANNELS] {
         self.last_sample
     }
 }
@@ -53,7 +60,7 @@ impl<const CHANNELS: usize> Interp<CHANNELS> for Cubic<CHANNELS> {
     fn copy_last_input_sample(&mut self) {
         self.hist.copy_within(1..4, 0);
     }
-    fn get_output_sample(&self, fract: f64) -> [f64; CHANNELS] {
+    fn get_output_sample(&self, fract: f64, _ratio: f64) -> [f64; CHANNELS] {
         let mut result = [0.0; CHANNELS];
         for (i, result) in result.iter_mut().enumerate() {
             let a = self.hist[3][i] - self.hist[2][i] - self.hist[0][i] + self.hist[1][i];
@@ -65,3 +72,37 @@ impl<const CHANNELS: usize> Interp<CHANNELS> for Cubic<CHANNELS> {
         result
     }
 }
+
+const SINC_BASE_TAPS: usize = 16;
+const SINC_MAX_TAPS: usize = sinc::max_taps(SINC_BASE_TAPS);
+const SINC_PHASES: usize = 512;
+
+static SINC_TABLES: LazyLock<sinc::Tables> =
+    LazyLock::new(|| sinc::Tables::new(SINC_BASE_TAPS, SINC_PHASES));
+
+struct Sinc<const CHANNELS: usize> {
+    hist: [[f64; CHANNELS]; SINC_MAX_TAPS],
+}
+
+impl<const CHANNELS: usize> Interp<CHANNELS> for Sinc<CHANNELS> {
+    fn push_input_sample(&mut self, sample: [f64; CHANNELS]) {
+        self.hist.copy_within(1.., 0);
+        self.hist[SINC_MAX_TAPS - 1] = sample;
+    }
+    fn copy_last_input_sample(&mut self) {
+        self.hist.copy_within(1.., 0);
+    }
+    fn get_output_sample(&self, fract: f64, ratio: f64) -> [f64; CHANNELS] {
+        // Interpolate between the two samples at the center of the last `taps` samples of the
+        // history buffer, using a kernel whose width depends on the downsampling ratio
+        let table = SINC_TABLES.get(ratio);
+        let hist = &self.hist[SINC_MAX_TAPS - table.taps()..];
+        let mut result = [0.0; CHANNELS];
+        for (coeff, sample) in table.coeffs(fract).zip(hist) {
+            for (result, sample) in result.iter_mut().zip(sample) {
+                *result += coeff * sample;
+            }
+        }
+        result.map(|sample| sample.clamp(-1.0, 1.0))
+    }
+}
@@ -1,5 +1,7 @@
 mod cpal;
 pub use self::cpal::*;
+mod time_stretch;
+use time_stretch::TimeStretcher;
 
 use super::{InterpMethod, SYS_CLOCK_RATE};
 use dust_core::audio::OutputSample;
@@ -9,7 +11,9 @@ use parking_lot::RwLock;
 #[cfg(feature = "xq-audio")]
 use std::num::NonZeroU32;
 use std::{
+    cell::Cell,
     marker::PhantomData,
+    rc::Rc,
     sync::atomic::{AtomicUsize, Ordering},
     sync::Arc,
     thread::{self, Thread},
@@ -75,11 +79,14 @@ pub struct Sender {
     buffer: Arc<Buffer>,
     write_pos: usize,
     sync: bool,
+    time_stretcher: Option<TimeStretcher>,
     _not_send: PhantomData<*const ()>,
 }
 
 impl Sender {
-    pub fn new(data: &SenderData, sync: bool) -> Self {
+    // `speed` is the current emulation speed relative to real time, used for time-stretching when
+    // not syncing to audio
+    pub fn new(data: &SenderData, sync: bool, speed: Option<Rc<Cell<f64>>>) -> Self {
         #[cfg(feature = "xq-audio")]
         let buffer = Arc::clone(&data.buffer_ptr.read());
         #[cfg(not(feature = "xq-audio"))]
@@ -91,6 +98,11 @@ impl Sender {
             write_pos: buffer.write_pos.load(Ordering::Relaxed),
             buffer,
             sync,
+            time_stretcher: if sync {
+                None
+            } else {
+                speed.map(TimeStretcher::new)
+            },
             _not_send: PhantomData,
         }
     }
@@ -98,6 +110,9 @@ impl Sender {
 
 impl dust_core::audio::Backend for Sender {
     fn handle_sample_chunk(&mut self, samples: &mut Vec<[OutputSample; 2]>) {
+        if let Some(time_stretcher) = &mut self.time_stretcher {
+            time_stretcher.process(samples);
+        }
         while !samples.is_empty() {
             #[cfg(not(feature = "xq-audio"))]
             let buffer_mask = BUFFER_BASE_CAPACITY - 1;
@@ -169,7 +169,7 @@ impl OutputData {
                         self.rx.finish_reading();
                         return;
                     }
-                    let result = self.interp.get_output_sample(fract);
+                    let result = self.interp.get_output_sample(fract, sample_rate_ratio);
                     data[output_i] = T::from(&(result[0] as f32 * volume));
                     data[output_i + 1] = T::from(&(result[1] as f32 * volume));
                     fract += sample_rate_ratio;
@@ -0,0 +1,143 @@
+use dust_core::audio::OutputSample;
+use std::{cell::Cell, f32::consts::PI, rc::Rc};
+
+// WSOLA (waveform similarity overlap-add) parameters, in input samples; at the default sample rate,
+// this amounts to ~31 ms segments and a search range of ~7.8 ms in either direction
+const SEGMENT_LEN: usize = 1024;
+const HOP_LEN: usize = SEGMENT_LEN / 2;
+const SEEK_RADIUS: usize = 256;
+// Only every Nth sample and offset gets checked while searching, to keep the cost down
+const SEEK_STEP: usize = 2;
+
+const MIN_SPEED: f64 = 0.25;
+const MAX_SPEED: f64 = 4.0;
+
+#[cfg(not(feature = "xq-audio"))]
+fn sample_to_f32(sample: [OutputSample; 2]) -> [f32; 2] {
+    sample.map(|sample| (sample as f32 - 512.0) * (1.0 / 512.0))
+}
+
+#[cfg(not(feature = "xq-audio"))]
+fn f32_to_sample(sample: [f32; 2]) -> [OutputSample; 2] {
+    sample.map(|sample| (sample * 512.0 + 512.0).round().clamp(0.0, 1023.0) as OutputSample)
+}
+
+#[cfg(feature = "xq-audio")]
+fn sample_to_f32(sample: [OutputSample; 2]) -> [f32; 2] {
+    sample
+}
+
+#[cfg(feature = "xq-audio")]
+fn f32_to_sample(sample: [f32; 2]) -> [OutputSample; 2] {
+    sample
+}
+
+// Changes the tempo of the audio stream by `speed` (the current emulation speed relative to real
+// time) without affecting its pitch
+pub struct TimeStretcher {
+    speed: Rc<Cell<f64>>,
+    window: Box<[f32; SEGMENT_LEN]>,
+    input: Vec<[f32; 2]>,
+    // Ideal position of the next segment, relative to the start of `input`
+    next_pos: f64,
+    prev_segment_pos: Option<usize>,
+    overlap: Box<[[f32; 2]; HOP_LEN]>,
+}
+
+impl TimeStretcher {
+    pub fn new(speed: Rc<Cell<f64>>) -> Self {
+        let mut window = Box::new([0.0; SEGMENT_LEN]);
+        // Periodic Hann window, so that overlapping halves sum to 1
+        for (i, value) in window.iter_mut().enumerate() {
+            *value = 0.5 - 0.5 * (2.0 * PI * i as f32 / SEGMENT_LEN as f32).cos();
+        }
+        TimeStretcher {
+            speed,
+            window,
+            input: Vec::new(),
+            next_pos: SEEK_RADIUS as f64,
+            prev_segment_pos: None,
+            overlap: Box::new([[0.0; 2]; HOP_LEN]),
+        }
+    }
+
+    fn find_best_segment_pos(&self, ideal_pos: usize) -> usize {
+        let Some(prev_segment_pos) = self.prev_segment_pos else {
+            return ideal_pos;
+        };
+        // Look for the segment most similar to the natural continuation of the previous one, using
+        // normalized cross-correlation over the part that will get overlapped
+        let target = &self.input[prev_segment_pos + HOP_LEN..prev_segment_pos + HOP_LEN * 2];
+        let mut best_pos = ideal_pos;
+        let mut best_score = f32::NEG_INFINITY;
+        for pos in (ideal_pos - SEEK_RADIUS..=ideal_pos + SEEK_RADIUS).step_by(SEEK_STEP) {
+            let candidate = &self.input[pos..pos + HOP_LEN];
+            let mut corr = 0.0;
+            let mut energy = 0.0;
+            for (a, b) in target
+                .iter()
+                .zip(candidate)
+                .step_by(SEEK_STEP)
+                .map(|(a, b)| (a[0] + a[1], b[0] + b[1]))
+            {
+                corr += a * b;
+                energy += b * b;
+            }
+            let score = corr / (energy + 1e-6).sqrt();
+            if score > best_score {
+                best_score = score;
+                best_pos = pos;
+            }
+        }
+        best_pos
+    }
+
+    #[allow(clippy::ptr_arg)]
+    pub fn process(&mut self, samples: &mut Vec<[OutputSample; 2]>) {
+        self.input.extend(samples.drain(..).map(sample_to_f32));
+        let speed = self.speed.get().clamp(MIN_SPEED, MAX_SPEED);
+
+        loop {
+            let ideal_pos = self.next_pos as usize;
+            let required_len = (ideal_pos + SEEK_RADIUS + SEGMENT_LEN).max(
+                self.prev_segment_pos
+                    .map_or(0, |prev_segment_pos| prev_segment_pos + HOP_LEN * 2),
+            );
+            if self.input.len() < required_len {
+                break;
+            }
+
+            let pos = self.find_best_segment_pos(ideal_pos);
+            let segment = &self.input[pos..pos + SEGMENT_LEN];
+            let (first_half, second_half) = segment.split_at(HOP_LEN);
+            let (first_window_half, second_window_half) = self.window.split_at(HOP_LEN);
+            for ((new, factor), overlap) in first_half
+                .iter()
+                .zip(first_window_half)
+                .zip(self.overlap.iter())
+            {
+                samples.push(f32_to_sample([
+                    overlap[0] + new[0] * factor,
+                    overlap[1] + new[1] * factor,
+                ]));
+            }
+            for ((sample, factor), overlap) in second_half
+                .iter()
+                .zip(second_window_half)
+                .zip(self.overlap.iter_mut())
+            {
+                *overlap = [sample[0] * factor, sample[1] * factor];
+            }
+            self.prev_segment_pos = Some(pos);
+            self.next_pos += HOP_LEN as f64 * speed;
+
+            // Drop input samples that can't be used anymore
+            let discard_len = pos.min(self.next_pos as usize - SEEK_RADIUS);
+            if discard_len != 0 {
+                self.input.drain(..discard_len);
+                self.next_pos -= discard_len as f64;
+                self.prev_segment_pos = Some(pos - discard_len);
+            }
+        }
+    }
+}
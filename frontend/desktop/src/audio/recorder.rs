@@ -0,0 +1,452 @@
+use dust_core::audio::{Backend, OutputSample};
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+    rc::Rc,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Wav,
+    Flac,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("flac") => Format::Flac,
+            _ => Format::Wav,
+        }
+    }
+}
+
+#[cfg(not(feature = "xq-audio"))]
+fn sample_to_i16(sample: OutputSample) -> i16 {
+    // 10-bit unsigned samples, centered around 0x200
+    (sample as i16 - 0x200) << 6
+}
+
+#[cfg(feature = "xq-audio")]
+fn sample_to_i16(sample: OutputSample) -> i16 {
+    (sample * 32768.0).clamp(-32768.0, 32767.0) as i16
+}
+
+const FLAC_BLOCK_SIZE: usize = 4096;
+
+fn flac_crc8(bytes: &[u8]) -> u8 {
+    let mut crc = 0_u8;
+    for &byte in bytes {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                crc << 1 ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn flac_crc16(bytes: &[u8]) -> u16 {
+    let mut crc = 0_u16;
+    for &byte in bytes {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                crc << 1 ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+// FLAC's UTF-8-like variable length coding for frame numbers
+fn push_flac_utf8(buffer: &mut Vec<u8>, value: u32) {
+    if value < 0x80 {
+        buffer.push(value as u8);
+        return;
+    }
+    let mut continuation_bytes = 1;
+    while value >= 1 << (6 - continuation_bytes + 6 * continuation_bytes) {
+        continuation_bytes += 1;
+    }
+    let lead_mask = !(0xFF_u8 >> (continuation_bytes + 1));
+    buffer.push(lead_mask | (value >> (6 * continuation_bytes)) as u8);
+    for i in (0..continuation_bytes).rev() {
+        buffer.push(0x80 | (value >> (6 * i) & 0x3F) as u8);
+    }
+}
+
+pub struct Recorder {
+    writer: BufWriter<File>,
+    format: Format,
+    sample_rate: u32,
+    channels: u16,
+    frames_written: u64,
+    // Interleaved samples for the FLAC frame currently being built
+    flac_block: Vec<i16>,
+    flac_frame: Vec<u8>,
+    flac_frame_number: u32,
+}
+
+impl Recorder {
+    pub fn new(path: &Path, format: Format, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        assert!((1..=8).contains(&channels));
+        let mut recorder = Recorder {
+            writer: BufWriter::new(File::create(path)?),
+            format,
+            sample_rate,
+            channels,
+            frames_written: 0,
+            flac_block: Vec::with_capacity(FLAC_BLOCK_SIZE * channels as usize),
+            flac_frame: Vec::new(),
+            flac_frame_number: 0,
+        };
+        match format {
+            Format::Wav => recorder.write_wav_header()?,
+            Format::Flac => recorder.write_flac_header()?,
+        }
+        Ok(recorder)
+    }
+
+    fn write_wav_header(&mut self) -> io::Result<()> {
+        let frame_len = self.channels as u32 * 2;
+        let data_len = (self.frames_written * frame_len as u64).min(u32::MAX as u64 - 36) as u32;
+        let w = &mut self.writer;
+        w.write_all(b"RIFF")?;
+        w.write_all(&(36 + data_len).to_le_bytes())?;
+        w.write_all(b"WAVEfmt ")?;
+        w.write_all(&16_u32.to_le_bytes())?;
+        w.write_all(&1_u16.to_le_bytes())?;
+        w.write_all(&self.channels.to_le_bytes())?;
+        w.write_all(&self.sample_rate.to_le_bytes())?;
+        w.write_all(&(self.sample_rate * frame_len).to_le_bytes())?;
+        w.write_all(&(frame_len as u16).to_le_bytes())?;
+        w.write_all(&16_u16.to_le_bytes())?;
+        w.write_all(b"data")?;
+        w.write_all(&data_len.to_le_bytes())
+    }
+
+    fn write_flac_header(&mut self) -> io::Result<()> {
+        let w = &mut self.writer;
+        w.write_all(b"fLaC")?;
+        // Last metadata block, STREAMINFO, 34 bytes long
+        w.write_all(&[0x80, 0, 0, 34])?;
+        w.write_all(&(FLAC_BLOCK_SIZE as u16).to_be_bytes())?;
+        w.write_all(&(FLAC_BLOCK_SIZE as u16).to_be_bytes())?;
+        // Unknown min/max frame sizes
+        w.write_all(&[0; 6])?;
+        // Sample rate (20 bits), channels - 1 (3 bits), bits per sample - 1 (5 bits), total
+        // samples (36 bits)
+        w.write_all(
+            &((self.sample_rate as u64) << 44
+                | (self.channels as u64 - 1) << 41
+                | 15 << 36
+                | (self.frames_written & 0xF_FFFF_FFFF))
+                .to_be_bytes(),
+        )?;
+        // Unknown MD5 signature
+        w.write_all(&[0; 16])
+    }
+
+    fn write_flac_frame(&mut self) -> io::Result<()> {
+        let channels = self.channels as usize;
+        let frame = &mut self.flac_frame;
+        frame.clear();
+        // Sync code, fixed block size, block size stored as a 16-bit value at the end of the
+        // header, sample rate taken from STREAMINFO, independent channels, 16 bits per sample
+        frame.extend_from_slice(&[0xFF, 0xF8, 0x70, (channels as u8 - 1) << 4 | 0x08]);
+        push_flac_utf8(frame, self.flac_frame_number);
+        frame.extend_from_slice(&((self.flac_block.len() / channels) as u16 - 1).to_be_bytes());
+        let crc = flac_crc8(frame);
+        frame.push(crc);
+        for channel in 0..channels {
+            // Verbatim subframe
+            frame.push(0x02);
+            for sample in self.flac_block.iter().skip(channel).step_by(channels) {
+                frame.extend_from_slice(&sample.to_be_bytes());
+            }
+        }
+        let crc = flac_crc16(frame);
+        frame.extend_from_slice(&crc.to_be_bytes());
+        self.writer.write_all(frame)?;
+        self.flac_block.clear();
+        self.flac_frame_number += 1;
+        Ok(())
+    }
+
+    fn write_frame(&mut self, samples: &[i16]) -> io::Result<()> {
+        match self.format {
+            Format::Wav => {
+                for sample in samples {
+                    self.writer.write_all(&sample.to_le_bytes())?;
+                }
+            }
+            Format::Flac => {
+                self.flac_block.extend_from_slice(samples);
+                if self.flac_block.len() == FLAC_BLOCK_SIZE * self.channels as usize {
+                    self.write_flac_frame()?;
+                }
+            }
+        }
+        self.frames_written += 1;
+        Ok(())
+    }
+
+    pub fn write_samples(&mut self, samples: &[[OutputSample; 2]]) -> io::Result<()> {
+        debug_assert_eq!(self.channels, 2);
+        for sample in samples {
+            self.write_frame(&sample.map(sample_to_i16))?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "channel-audio-capture")]
+    pub fn write_mono_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        debug_assert_eq!(self.channels, 1);
+        for sample in samples {
+            self.write_frame(&[*sample])?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        if !self.flac_block.is_empty() {
+            self.write_flac_frame()?;
+        }
+        // Rewrite the header now that the total length is known
+        self.writer.seek(SeekFrom::Start(0))?;
+        match self.format {
+            Format::Wav => self.write_wav_header()?,
+            Format::Flac => self.write_flac_header()?,
+        }
+        self.writer.flush()
+    }
+}
+
+// Writes each of the 16 sound channels' raw output to a separate mono WAV file
+#[cfg(feature = "channel-audio-capture")]
+pub struct MultitrackRecorder {
+    recorders: Vec<Recorder>,
+}
+
+#[cfg(feature = "channel-audio-capture")]
+impl MultitrackRecorder {
+    pub fn new(dir_path: &Path) -> io::Result<Self> {
+        let recorders = (0..16)
+            .map(|i| {
+                Recorder::new(
+                    &dir_path.join(format!("channel_{i:02}.wav")),
+                    Format::Wav,
+                    super::output::DEFAULT_INPUT_SAMPLE_RATE,
+                    1,
+                )
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(MultitrackRecorder { recorders })
+    }
+
+    pub fn write_samples(&mut self, buffers: &[Vec<i16>; 16]) -> io::Result<()> {
+        for (recorder, buffer) in self.recorders.iter_mut().zip(buffers) {
+            recorder.write_mono_samples(buffer)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        for recorder in self.recorders {
+            recorder.finish()?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct Recording {
+    recorder: Option<Recorder>,
+    error: Option<io::Error>,
+}
+
+impl Recording {
+    pub fn start(&mut self, path: &Path, format: Format, sample_rate: u32) -> io::Result<()> {
+        self.stop()?;
+        self.recorder = Some(Recorder::new(path, format, sample_rate, 2)?);
+        Ok(())
+    }
+
+    pub fn stop(&mut self) -> io::Result<()> {
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(feature = "xq-audio")]
+    #[inline]
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.recorder.as_ref().map(|recorder| recorder.sample_rate)
+    }
+
+    #[inline]
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    fn write_samples(&mut self, samples: &[[OutputSample; 2]]) {
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(err) = recorder.write_samples(samples) {
+                self.recorder = None;
+                self.error = Some(err);
+            }
+        }
+    }
+}
+
+pub type SharedRecording = Rc<RefCell<Recording>>;
+
+// Tees the emulator's output samples into the current recording (if any) before passing them on to
+// the actual backend
+pub struct RecordingBackend {
+    inner: Box<dyn Backend>,
+    recording: SharedRecording,
+}
+
+impl RecordingBackend {
+    pub fn new(inner: Box<dyn Backend>, recording: SharedRecording) -> Self {
+        RecordingBackend { inner, recording }
+    }
+}
+
+impl Backend for RecordingBackend {
+    fn handle_sample_chunk(&mut self, samples: &mut Vec<[OutputSample; 2]>) {
+        self.recording.borrow_mut().write_samples(samples);
+        self.inner.handle_sample_chunk(samples);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::PathBuf};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("dust-recorder-test-{}-{name}", std::process::id()))
+    }
+
+    fn record(name: &str, format: Format, channels: u16, frames: &[&[i16]]) -> Vec<u8> {
+        let path = temp_path(name);
+        let mut recorder = Recorder::new(&path, format, 44100, channels).unwrap();
+        for frame in frames {
+            recorder.write_frame(frame).unwrap();
+        }
+        recorder.finish().unwrap();
+        let contents = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        contents
+    }
+
+    #[test]
+    fn crcs_match_check_values() {
+        assert_eq!(flac_crc8(b"123456789"), 0xF4);
+        assert_eq!(flac_crc16(b"123456789"), 0xFEE8);
+    }
+
+    #[test]
+    fn flac_utf8_coding() {
+        for (value, expected) in [
+            (0, &[0x00][..]),
+            (0x7F, &[0x7F][..]),
+            (0x80, &[0xC2, 0x80][..]),
+            (0x7FF, &[0xDF, 0xBF][..]),
+            (0x800, &[0xE0, 0xA0, 0x80][..]),
+            (0xFFFF, &[0xEF, 0xBF, 0xBF][..]),
+            (0x1_0000, &[0xF0, 0x90, 0x80, 0x80][..]),
+        ] {
+            let mut buffer = Vec::new();
+            push_flac_utf8(&mut buffer, value);
+            assert_eq!(buffer, expected, "value {value:#X}");
+        }
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(Format::from_path(Path::new("a.FLAC")), Format::Flac);
+        assert_eq!(Format::from_path(Path::new("a.wav")), Format::Wav);
+        assert_eq!(Format::from_path(Path::new("a")), Format::Wav);
+    }
+
+    #[test]
+    fn wav_header_and_data() {
+        let contents = record("wav", Format::Wav, 2, &[&[1, -1], &[0x1234, -0x8000]]);
+        let mut expected = Vec::new();
+        expected.extend_from_slice(b"RIFF");
+        expected.extend_from_slice(&44_u32.to_le_bytes());
+        expected.extend_from_slice(b"WAVEfmt ");
+        expected.extend_from_slice(&16_u32.to_le_bytes());
+        expected.extend_from_slice(&1_u16.to_le_bytes());
+        expected.extend_from_slice(&2_u16.to_le_bytes());
+        expected.extend_from_slice(&44100_u32.to_le_bytes());
+        expected.extend_from_slice(&(44100_u32 * 4).to_le_bytes());
+        expected.extend_from_slice(&4_u16.to_le_bytes());
+        expected.extend_from_slice(&16_u16.to_le_bytes());
+        expected.extend_from_slice(b"data");
+        expected.extend_from_slice(&8_u32.to_le_bytes());
+        expected.extend_from_slice(&[0x01, 0x00, 0xFF, 0xFF, 0x34, 0x12, 0x00, 0x80]);
+        assert_eq!(contents, expected);
+    }
+
+    #[test]
+    fn flac_stream_info_and_frames() {
+        let samples = (0..FLAC_BLOCK_SIZE as i16 + 3).collect::<Vec<_>>();
+        let frames = samples.chunks(1).collect::<Vec<_>>();
+        let contents = record("flac", Format::Flac, 1, &frames);
+
+        assert_eq!(&contents[..8], b"fLaC\x80\0\0\x22");
+        assert_eq!(&contents[8..12], &[0x10, 0x00, 0x10, 0x00]);
+        assert_eq!(
+            u64::from_be_bytes(contents[18..26].try_into().unwrap()),
+            44100 << 44 | 15 << 36 | samples.len() as u64
+        );
+
+        let mut frame_start = 42;
+        for (frame_number, block) in samples.chunks(FLAC_BLOCK_SIZE).enumerate() {
+            let frame_len = 8 + 1 + block.len() * 2 + 2;
+            let frame = &contents[frame_start..frame_start + frame_len];
+            let block_size = (block.len() as u16 - 1).to_be_bytes();
+            assert_eq!(
+                &frame[..8],
+                &[
+                    0xFF,
+                    0xF8,
+                    0x70,
+                    0x08,
+                    frame_number as u8,
+                    block_size[0],
+                    block_size[1],
+                    flac_crc8(&frame[..7]),
+                ]
+            );
+            assert_eq!(frame[8], 0x02);
+            for (bytes, sample) in frame[9..frame_len - 2].chunks(2).zip(block) {
+                assert_eq!(i16::from_be_bytes([bytes[0], bytes[1]]), *sample);
+            }
+            assert_eq!(
+                u16::from_be_bytes([frame[frame_len - 2], frame[frame_len - 1]]),
+                flac_crc16(&frame[..frame_len - 2])
+            );
+            frame_start += frame_len;
+        }
+        assert_eq!(frame_start, contents.len());
+    }
+
+    #[test]
+    fn creating_in_missing_directory_fails() {
+        let path = temp_path("missing").join("recording.wav");
+        assert!(Recorder::new(&path, Format::Wav, 44100, 2).is_err());
+    }
+}
@@ -0,0 +1,133 @@
+use crate::audio;
+use std::{env, ffi::OsString, fmt, path::PathBuf, process};
+
+static USAGE: &str = "\
+Usage: dust-desktop [OPTIONS] [ROM]
+
+Options:
+    --mic <SOURCE>        Override the microphone input source (device, wav, silence, white-noise,
+                          blow)
+    --mic-wav <PATH>      Feed the microphone from the given WAV file (implies --mic wav)
+    --mic-wav-once        Play the microphone WAV file once instead of looping it
+    --record-audio <PATH> Record the emulator's audio output to the given WAV or FLAC file (chosen
+                          by extension)
+    -h, --help            Print this help message";
+
+pub struct MicArgs {
+    pub source: audio::input::Source,
+    pub wav_path: Option<PathBuf>,
+    pub wav_looping: Option<bool>,
+}
+
+#[derive(Default)]
+pub struct Args {
+    pub rom_path: Option<PathBuf>,
+    pub mic: Option<MicArgs>,
+    pub record_audio: Option<PathBuf>,
+}
+
+pub enum Error {
+    MissingValue(&'static str),
+    InvalidValue(&'static str, OsString),
+    UnknownOption(OsString),
+    UnexpectedArg(OsString),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MissingValue(option) => write!(f, "Missing value for `{option}`"),
+            Error::InvalidValue(option, value) => {
+                write!(
+                    f,
+                    "Invalid value for `{option}`: `{}`",
+                    value.to_string_lossy()
+                )
+            }
+            Error::UnknownOption(option) => {
+                write!(f, "Unknown option `{}`", option.to_string_lossy())
+            }
+            Error::UnexpectedArg(arg) => {
+                write!(f, "Unexpected argument `{}`", arg.to_string_lossy())
+            }
+        }
+    }
+}
+
+fn parse_mic_source(value: &str) -> Option<audio::input::Source> {
+    Some(match value {
+        "device" => audio::input::Source::Device,
+        "wav" => audio::input::Source::Wav,
+        "silence" => audio::input::Source::Silence,
+        "white-noise" => audio::input::Source::WhiteNoise,
+        "blow" => audio::input::Source::Blow,
+        _ => return None,
+    })
+}
+
+impl Args {
+    fn mic_mut(&mut self) -> &mut MicArgs {
+        self.mic.get_or_insert(MicArgs {
+            source: audio::input::Source::Device,
+            wav_path: None,
+            wav_looping: None,
+        })
+    }
+
+    pub fn parse() -> Result<Self, Error> {
+        let mut result = Args::default();
+        let mut args = env::args_os().skip(1);
+
+        macro_rules! value {
+            ($option: literal) => {
+                args.next().ok_or(Error::MissingValue($option))?
+            };
+        }
+
+        while let Some(arg) = args.next() {
+            match arg.to_str() {
+                Some("-h" | "--help") => {
+                    println!("{USAGE}");
+                    process::exit(0);
+                }
+
+                Some("--mic") => {
+                    let value = value!("--mic");
+                    let source = value
+                        .to_str()
+                        .and_then(parse_mic_source)
+                        .ok_or_else(|| Error::InvalidValue("--mic", value.clone()))?;
+                    result.mic_mut().source = source;
+                }
+
+                Some("--mic-wav") => {
+                    let path = PathBuf::from(value!("--mic-wav"));
+                    let mic = result.mic_mut();
+                    mic.source = audio::input::Source::Wav;
+                    mic.wav_path = Some(path);
+                }
+
+                Some("--mic-wav-once") => {
+                    result.mic_mut().wav_looping = Some(false);
+                }
+
+                Some("--record-audio") => {
+                    result.record_audio = Some(PathBuf::from(value!("--record-audio")));
+                }
+
+                Some(option) if option.starts_with('-') => {
+                    return Err(Error::UnknownOption(arg));
+                }
+
+                _ => {
+                    if result.rom_path.is_some() {
+                        return Err(Error::UnexpectedArg(arg));
+                    }
+                    result.rom_path = Some(PathBuf::from(arg));
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}
@@ -351,6 +351,36 @@ pub enum Renderer3dKind {
     Wgpu,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScreenLayout {
+    Vertical,
+    Horizontal,
+    SingleScreen,
+    LargeTop,
+    Hybrid,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScreenFilter {
+    Nearest,
+    Bilinear,
+    Scale2x,
+    Scale4x,
+    Xbr2x,
+    Xbr4x,
+    LcdGrid,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScreenColorCorrection {
+    None,
+    Ds,
+    DsLite,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum TitleBarMode {
@@ -410,6 +440,16 @@ def_config! {
                 resolve resolve_option, set set_option,
             screen_rot: u16 = 0, None,
                 resolve resolve_option, set set_option,
+            screen_layout: ScreenLayout = ScreenLayout::Vertical, None,
+                resolve resolve_option, set set_option,
+            screen_swap: bool = false, None,
+                resolve resolve_option, set set_option,
+            screen_gap: u16 = 0, None,
+                resolve resolve_option, set set_option,
+            screen_filter: ScreenFilter = ScreenFilter::Bilinear, None,
+                resolve resolve_option, set set_option,
+            screen_color_correction: ScreenColorCorrection = ScreenColorCorrection::None, None,
+                resolve resolve_option, set set_option,
             sys_paths: ResolvedSysPaths, GlobalSysPaths, GameSysPaths, ()
                 = Default::default(), GameSysPaths::default(),
                 resolve ResolvedSysPaths::resolve, set set_unreachable,
@@ -436,6 +476,8 @@ def_config! {
             logging_kind: LoggingKind = LoggingKind::Imgui,
             save_dir_path: HomePathBuf = HomePathBuf(data_base().join("saves")),
             savestate_dir_path: HomePathBuf = HomePathBuf(data_base().join("states")),
+            texture_pack_dir_path: HomePathBuf = HomePathBuf(data_base().join("textures")),
+            audio_input_wav_path: Option<HomePathBuf> = None,
         }
         overridable {
             full_window_screen: bool = true, None,
@@ -450,6 +492,8 @@ def_config! {
                 resolve resolve_option, set set_option,
             sync_to_audio: bool = true, None,
                 resolve resolve_option, set set_option,
+            audio_time_stretching: bool = true, None,
+                resolve resolve_option, set set_option,
             audio_volume: f32 = 1.0, None,
                 resolve resolve_option, set set_option,
             audio_sample_chunk_size: u16 = 512, None,
@@ -462,6 +506,10 @@ def_config! {
             audio_input_interp_method: audio::InterpMethod
                 = audio::InterpMethod::Nearest, None,
                 resolve resolve_option, set set_option,
+            audio_input_source: audio::input::Source = audio::input::Source::Device, None,
+                resolve resolve_option, set set_option,
+            audio_input_wav_looping: bool = true, None,
+                resolve resolve_option, set set_option,
             audio_custom_sample_rate: Option<NonZeroU32>, u32 = 0, None,
                 resolve resolve_opt_nonzero_u32, set set_opt_nonzero_u32,
             audio_channel_interp_method: AudioChannelInterpMethod = AudioChannelInterpMethod::Nearest, None,
@@ -470,12 +518,24 @@ def_config! {
                 resolve resolve_option, set set_option,
             rtc_time_offset_seconds: i64 = 0, None,
                 resolve resolve_option, set set_option,
+            battery_drain_time_mins: Option<NonZeroU32>, u32 = 0, None,
+                resolve resolve_opt_nonzero_u32, set set_opt_nonzero_u32,
+            battery_charge_time_mins: Option<NonZeroU32>, u32 = 0, None,
+                resolve resolve_opt_nonzero_u32, set set_opt_nonzero_u32,
+            battery_external_power: bool = false, None,
+                resolve resolve_option, set set_option,
+            touch_pressure: f32 = 0.5, None,
+                resolve resolve_option, set set_option,
             renderer_2d_kind: Renderer2dKind = Renderer2dKind::SoftLockstepScanlines, None,
                 resolve resolve_option, set set_option,
             renderer_3d_kind: Renderer3dKind = Renderer3dKind::Soft, None,
                 resolve resolve_option, set set_option,
             resolution_scale_shift: u8 = 0, None,
                 resolve resolve_option, set set_option,
+            dump_textures: bool = false, None,
+                resolve resolve_option, set set_option,
+            load_texture_packs: bool = false, None,
+                resolve resolve_option, set set_option,
         }
         game {
             save_path_config: Option<saves::PathConfig> = Some(Default::default()),
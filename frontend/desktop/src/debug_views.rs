@@ -14,6 +14,8 @@ mod bg_maps_2d;
 use bg_maps_2d::BgMaps2d;
 mod audio_channels;
 use audio_channels::AudioChannels;
+mod audio_output;
+use audio_output::AudioOutput;
 
 use super::ui::window::Window;
 use ahash::AHashMap as HashMap;
@@ -586,4 +588,5 @@ declare_structs!(
     instanceable palettes_2d, Palettes2d, TogglePalettes2d, UpdatePalettes2d, Palettes2dCustom;
     instanceable bg_maps_2d, BgMaps2d, ToggleBgMaps2d, UpdateBgMaps2d;
     instanceable audio_channels, AudioChannels, ToggleAudioChannels, UpdateAudioChannels;
+    instanceable audio_output, AudioOutput, ToggleAudioOutput, UpdateAudioOutput;
 );
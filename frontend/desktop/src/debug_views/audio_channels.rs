@@ -1,5 +1,8 @@
 use super::{
-    common::regs::{bitfield, BitfieldCommand},
+    common::{
+        regs::{bitfield, BitfieldCommand},
+        RingBuffer,
+    },
     FrameDataSlot, InstanceableView, Messages, View,
 };
 use crate::ui::window::Window;
@@ -10,61 +13,6 @@ use dust_core::{
 };
 use imgui::{PlotLines, SliderFlags, StyleVar, TableFlags, Ui};
 use realfft::{num_complex::Complex, RealFftPlanner as FftPlanner};
-use std::cmp::Ordering;
-
-#[derive(Clone)]
-struct RingBuffer<T: Copy> {
-    buffer: Vec<T>,
-    start: usize,
-}
-
-impl<T: Copy> RingBuffer<T> {
-    pub fn new(len: usize, fill_value: T) -> Self {
-        RingBuffer {
-            buffer: vec![fill_value; len],
-            start: 0,
-        }
-    }
-
-    pub fn fill(&mut self, value: T) {
-        self.buffer.fill(value);
-    }
-
-    pub fn extend(&mut self, iter: impl IntoIterator<Item = T>) {
-        for elem in iter {
-            self.buffer[self.start] = elem;
-            self.start += 1;
-            if self.start == self.buffer.len() {
-                self.start = 0;
-            }
-        }
-    }
-
-    pub fn resize(&mut self, new_len: usize, value: T) {
-        let prev_len = self.buffer.len();
-        match new_len.cmp(&prev_len) {
-            Ordering::Less => {
-                let prev_start = self.start;
-                self.start = (self.start + prev_len - new_len) % new_len;
-                if new_len < prev_start {
-                    self.buffer.copy_within(prev_start - new_len..prev_start, 0);
-                } else {
-                    self.buffer
-                        .copy_within(prev_len - (new_len - prev_start)..prev_len, self.start);
-                }
-                self.buffer.truncate(new_len);
-                self.buffer.shrink_to_fit();
-            }
-            Ordering::Greater => {
-                let new_range = self.start..self.start + (new_len - prev_len);
-                self.buffer.resize(new_len, value);
-                self.buffer.copy_within(self.start..prev_len, new_range.end);
-                self.buffer[new_range].fill(value);
-            }
-            Ordering::Equal => {}
-        }
-    }
-}
 
 pub struct ChannelData {
     channel: Option<ChannelIndex>,
@@ -220,17 +168,7 @@ impl View for AudioChannels {
             .fft_planner
             .plan_fft_forward(self.samples_to_show as usize);
         self.fft_input_buf.clear();
-        self.fft_input_buf.reserve(self.samples_to_show as usize);
-        {
-            let mut i = self.samples.start;
-            for _ in 0..self.samples.buffer.len() {
-                if i >= self.samples.buffer.len() {
-                    i = 0;
-                }
-                self.fft_input_buf.push(self.samples.buffer[i]);
-                i += 1;
-            }
-        }
+        self.fft_input_buf.extend(self.samples.iter());
         self.fft_output_buf.resize(
             self.samples_to_show as usize / 2 + 1,
             Complex { re: 0.0, im: 0.0 },
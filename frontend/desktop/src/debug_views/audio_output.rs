@@ -0,0 +1,479 @@
+use super::{common::RingBuffer, FrameDataSlot, InstanceableView, Messages, View};
+use crate::{
+    audio::{output::DEFAULT_INPUT_SAMPLE_RATE, SYS_CLOCK_RATE},
+    ui::{utils::combo_value, window::Window},
+};
+use dust_core::{audio::OutputSample, cpu, emu::Emu};
+use imgui::{Image, PlotLines, SliderFlags, TextureId, Ui};
+use realfft::{num_complex::Complex, RealFftPlanner as FftPlanner};
+use std::{f32::consts::PI, slice};
+
+const SPECTROGRAM_WIDTH: usize = 512;
+const SPECTROGRAM_HEIGHT: usize = 256;
+const MIN_DB: f32 = -96.0;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Output,
+    CaptureUnit(u8),
+}
+
+impl Source {
+    fn is_stereo(self) -> bool {
+        self == Source::Output
+    }
+}
+
+#[cfg(not(feature = "xq-audio"))]
+fn output_sample_to_f32(sample: OutputSample) -> f32 {
+    // 10-bit unsigned samples, centered around 0x200
+    (sample as f32 - 512.0) * (1.0 / 512.0)
+}
+
+#[cfg(feature = "xq-audio")]
+fn output_sample_to_f32(sample: OutputSample) -> f32 {
+    sample
+}
+
+// Goes from black through blue, red and yellow as the value increases
+fn heat_map_color(value: f32) -> u32 {
+    let value = value.clamp(0.0, 1.0) * 3.0;
+    let [r, g, b] = [
+        (value - 1.0).clamp(0.0, 1.0),
+        (value - 2.0).clamp(0.0, 1.0),
+        if value < 1.0 {
+            value
+        } else {
+            (2.0 - value).max(0.0)
+        },
+    ]
+    .map(|v| (v * 255.0) as u32);
+    0xFF00_0000 | b << 16 | g << 8 | r
+}
+
+pub struct OutputData {
+    source: Option<Source>,
+    sample_rate: f32,
+    samples: Vec<[f32; 2]>,
+}
+
+impl Default for OutputData {
+    fn default() -> Self {
+        OutputData {
+            source: None,
+            sample_rate: DEFAULT_INPUT_SAMPLE_RATE as f32,
+            samples: Vec::new(),
+        }
+    }
+}
+
+struct Spectrum {
+    fft_planner: FftPlanner<f32>,
+    window: Vec<f32>,
+    input_buf: Vec<f32>,
+    output_buf: Vec<Complex<f32>>,
+}
+
+impl Spectrum {
+    fn new() -> Self {
+        Spectrum {
+            fft_planner: FftPlanner::new(),
+            window: Vec::new(),
+            input_buf: Vec::new(),
+            output_buf: Vec::new(),
+        }
+    }
+
+    // Samples get a Hann window applied before the FFT, otherwise spectral leakage would easily
+    // hide the low-level aliasing images this is meant to show
+    fn compute(&mut self, samples: impl Iterator<Item = f32>, len: usize, output: &mut Vec<f32>) {
+        if self.window.len() != len {
+            self.window.clear();
+            self.window
+                .extend((0..len).map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / len as f32).cos()));
+        }
+        self.input_buf.clear();
+        self.input_buf.extend(
+            samples
+                .zip(&self.window)
+                .map(|(sample, window)| sample * window),
+        );
+        self.output_buf
+            .resize(len / 2 + 1, Complex { re: 0.0, im: 0.0 });
+        let fft = self.fft_planner.plan_fft_forward(len);
+        fft.process(&mut self.input_buf, &mut self.output_buf)
+            .expect("couldn't process FFT");
+
+        // A full-scale sine wave has a magnitude of len / 4 once windowed, which maps to 0 dBFS
+        let scale = 4.0 / len as f32;
+        output.clear();
+        output.extend(
+            self.output_buf
+                .iter()
+                .map(|v| (20.0 * (v.norm() * scale).max(1e-6).log10()).max(MIN_DB)),
+        );
+    }
+}
+
+pub struct AudioOutput {
+    cur_source: Source,
+    data_source: Option<Source>,
+    sample_rate: f32,
+    samples_to_show: u32,
+    samples: [RingBuffer<f32>; 2],
+    spectrum: Spectrum,
+    spectrum_db: [Vec<f32>; 2],
+    spectrogram_channel: usize,
+    spectrogram_fft_len: usize,
+    spectrogram_samples: RingBuffer<f32>,
+    spectrogram_new_samples: usize,
+    spectrogram_db: Vec<f32>,
+    spectrogram_pixels: Box<[u32]>,
+    tex_id: TextureId,
+}
+
+impl AudioOutput {
+    fn clear(&mut self) {
+        for samples in &mut self.samples {
+            samples.fill(0.0);
+        }
+        self.spectrogram_samples.fill(0.0);
+        self.spectrogram_new_samples = 0;
+        self.spectrogram_pixels.fill(0xFF00_0000);
+    }
+
+    fn push_spectrogram_column(&mut self) {
+        self.spectrum.compute(
+            self.spectrogram_samples.iter(),
+            self.spectrogram_fft_len,
+            &mut self.spectrogram_db,
+        );
+        let bins = self.spectrogram_db.len();
+        for (y, row) in self
+            .spectrogram_pixels
+            .chunks_exact_mut(SPECTROGRAM_WIDTH)
+            .enumerate()
+        {
+            // Higher frequencies are at the top, and each row shows the loudest bin it covers
+            let row_i = SPECTROGRAM_HEIGHT - 1 - y;
+            let start = row_i * bins / SPECTROGRAM_HEIGHT;
+            let end = ((row_i + 1) * bins / SPECTROGRAM_HEIGHT).max(start + 1);
+            let db = self.spectrogram_db[start..end]
+                .iter()
+                .copied()
+                .fold(MIN_DB, f32::max);
+            row.copy_within(1.., 0);
+            row[SPECTROGRAM_WIDTH - 1] = heat_map_color((db - MIN_DB) / -MIN_DB);
+        }
+    }
+}
+
+impl View for AudioOutput {
+    const NAME: &'static str = "Audio output";
+
+    type FrameData = OutputData;
+    type EmuState = Source;
+
+    fn new(window: &mut Window) -> Self {
+        const DEFAULT_SAMPLES: u32 = 512 * 8;
+        const DEFAULT_FFT_LEN: usize = 1024;
+        let tex_id = window.imgui.gfx.create_and_add_owned_texture(
+            Some("Spectrogram".into()),
+            imgui_wgpu::TextureDescriptor {
+                width: SPECTROGRAM_WIDTH as u32,
+                height: SPECTROGRAM_HEIGHT as u32,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                ..Default::default()
+            },
+            imgui_wgpu::SamplerDescriptor {
+                min_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            },
+        );
+        AudioOutput {
+            cur_source: Source::Output,
+            data_source: None,
+            sample_rate: DEFAULT_INPUT_SAMPLE_RATE as f32,
+            samples_to_show: DEFAULT_SAMPLES,
+            samples: [(); 2].map(|_| RingBuffer::new(DEFAULT_SAMPLES as usize, 0.0)),
+            spectrum: Spectrum::new(),
+            spectrum_db: [Vec::new(), Vec::new()],
+            spectrogram_channel: 0,
+            spectrogram_fft_len: DEFAULT_FFT_LEN,
+            spectrogram_samples: RingBuffer::new(DEFAULT_FFT_LEN, 0.0),
+            spectrogram_new_samples: 0,
+            spectrogram_db: Vec::new(),
+            spectrogram_pixels: vec![0xFF00_0000; SPECTROGRAM_WIDTH * SPECTROGRAM_HEIGHT]
+                .into_boxed_slice(),
+            tex_id,
+        }
+    }
+
+    fn destroy(self, window: &mut Window) {
+        window.imgui.gfx.remove_texture(self.tex_id);
+    }
+
+    fn emu_state(&self) -> Self::EmuState {
+        self.cur_source
+    }
+
+    fn handle_emu_state_changed<E: cpu::Engine>(
+        prev_source: Option<&Self::EmuState>,
+        new_source: Option<&Self::EmuState>,
+        emu: &mut Emu<E>,
+    ) {
+        let data = &mut emu.audio.channel_audio_capture_data;
+        match prev_source {
+            Some(Source::Output) => data.output_enabled = false,
+            Some(Source::CaptureUnit(i)) => data.capture_unit_mask &= !(1 << i),
+            None => {}
+        }
+        match new_source {
+            Some(Source::Output) => data.output_enabled = true,
+            Some(Source::CaptureUnit(i)) => data.capture_unit_mask |= 1 << i,
+            None => {}
+        }
+    }
+
+    fn prepare_frame_data<'a, E: cpu::Engine, S: FrameDataSlot<'a, Self::FrameData>>(
+        source: &Self::EmuState,
+        emu: &mut Emu<E>,
+        frame_data: S,
+    ) {
+        let frame_data = frame_data.get_or_insert_with(Default::default);
+        frame_data.source = Some(*source);
+        frame_data.samples.clear();
+        let data = &emu.audio.channel_audio_capture_data;
+        match *source {
+            Source::Output => {
+                #[cfg(feature = "xq-audio")]
+                let sample_rate = emu
+                    .audio
+                    .custom_sample_rate()
+                    .map_or(DEFAULT_INPUT_SAMPLE_RATE, |sample_rate| sample_rate.get());
+                #[cfg(not(feature = "xq-audio"))]
+                let sample_rate = DEFAULT_INPUT_SAMPLE_RATE;
+                frame_data.sample_rate = sample_rate as f32;
+                frame_data.samples.extend(
+                    data.output_buffer
+                        .iter()
+                        .map(|sample| sample.map(output_sample_to_f32)),
+                );
+            }
+            Source::CaptureUnit(i) => {
+                // The capture timer runs at half the system clock rate, like the channel timers
+                let timer_reload = emu.audio.capture[i as usize].timer_reload;
+                frame_data.sample_rate =
+                    (SYS_CLOCK_RATE >> 1) as f32 / (0x1_0000 - timer_reload as u32) as f32;
+                frame_data.samples.extend(
+                    data.capture_unit_buffers[i as usize]
+                        .iter()
+                        .map(|sample| [*sample as f32 / 32768.0; 2]),
+                );
+            }
+        }
+    }
+
+    fn clear_frame_data(&mut self) {
+        self.data_source = None;
+        self.clear();
+    }
+
+    fn update_from_frame_data(&mut self, frame_data: &Self::FrameData, _window: &mut Window) {
+        if frame_data.source != self.data_source {
+            self.clear();
+        }
+        self.data_source = frame_data.source;
+        self.sample_rate = frame_data.sample_rate;
+        for sample in &frame_data.samples {
+            for (samples, sample) in self.samples.iter_mut().zip(sample) {
+                samples.extend([*sample]);
+            }
+            self.spectrogram_samples
+                .extend([sample[self.spectrogram_channel]]);
+            self.spectrogram_new_samples += 1;
+            // Consecutive columns overlap by half of their samples
+            if self.spectrogram_new_samples >= self.spectrogram_fft_len / 2 {
+                self.spectrogram_new_samples = 0;
+                self.push_spectrogram_column();
+            }
+        }
+    }
+
+    fn customize_window<'ui, 'a, T: AsRef<str>>(
+        &mut self,
+        _ui: &imgui::Ui,
+        window: imgui::Window<'ui, 'a, T>,
+    ) -> imgui::Window<'ui, 'a, T> {
+        window
+    }
+
+    fn draw(
+        &mut self,
+        ui: &Ui,
+        window: &mut Window,
+        _emu_running: bool,
+        _messages: impl Messages<Self>,
+    ) -> Option<Self::EmuState> {
+        static POSSIBLE_SOURCES: [Source; 3] = [
+            Source::Output,
+            Source::CaptureUnit(0),
+            Source::CaptureUnit(1),
+        ];
+
+        let item_spacing = style!(ui, item_spacing);
+
+        let controls_width = 0.5 * (ui.content_region_avail()[0] - item_spacing[0]);
+
+        ui.set_next_item_width(controls_width);
+        let source_updated = combo_value(
+            ui,
+            "##source",
+            &mut self.cur_source,
+            &POSSIBLE_SOURCES,
+            |source| match source {
+                Source::Output => "Mixed output".into(),
+                Source::CaptureUnit(i) => format!("Capture unit {i}").into(),
+            },
+        );
+
+        let new_state = if source_updated {
+            self.clear();
+            if !self.cur_source.is_stereo() {
+                self.spectrogram_channel = 0;
+            }
+            Some(self.cur_source)
+        } else {
+            None
+        };
+
+        ui.same_line();
+        ui.set_next_item_width(controls_width);
+        if ui
+            .slider_config("##visible_samples", 512, 256 * 1024)
+            .flags(SliderFlags::LOGARITHMIC)
+            .display_format("Last %d samples")
+            .build(&mut self.samples_to_show)
+        {
+            self.samples_to_show &= !1;
+            for samples in &mut self.samples {
+                samples.resize(self.samples_to_show as usize, 0.0);
+            }
+        }
+
+        if self.data_source != Some(self.cur_source) {
+            return new_state;
+        }
+
+        ui.text(format!(
+            "Sample rate: {:.1} Hz, showing 0 - {:.1} Hz",
+            self.sample_rate,
+            self.sample_rate * 0.5
+        ));
+
+        let channels = if self.cur_source.is_stereo() { 2 } else { 1 };
+        let graph_width = ui.content_region_avail()[0];
+        let channel_name = |i: usize| {
+            if channels == 1 {
+                ""
+            } else if i == 0 {
+                "Left"
+            } else {
+                "Right"
+            }
+        };
+
+        for i in 0..channels {
+            PlotLines::new(ui, &format!("##sample_graph_{i}"), &self.samples[i].buffer)
+                .graph_size([graph_width, 96.0])
+                .scale_min(-1.0)
+                .scale_max(1.0)
+                .values_offset(self.samples[i].start)
+                .overlay_text(channel_name(i))
+                .build();
+        }
+
+        for i in 0..channels {
+            self.spectrum.compute(
+                self.samples[i].iter(),
+                self.samples_to_show as usize,
+                &mut self.spectrum_db[i],
+            );
+            PlotLines::new(ui, &format!("##frequency_graph_{i}"), &self.spectrum_db[i])
+                .graph_size([graph_width, 96.0])
+                .scale_min(MIN_DB)
+                .scale_max(0.0)
+                .overlay_text(channel_name(i))
+                .build();
+        }
+
+        ui.separator();
+
+        static POSSIBLE_FFT_LENS: [usize; 5] = [256, 512, 1024, 2048, 4096];
+        ui.set_next_item_width(controls_width);
+        if combo_value(
+            ui,
+            "##spectrogram_fft_len",
+            &mut self.spectrogram_fft_len,
+            &POSSIBLE_FFT_LENS,
+            |len| format!("{len}-sample FFT").into(),
+        ) {
+            self.spectrogram_samples
+                .resize(self.spectrogram_fft_len, 0.0);
+            self.spectrogram_new_samples = 0;
+        }
+        if channels == 2 {
+            static POSSIBLE_CHANNELS: [usize; 2] = [0, 1];
+            ui.same_line();
+            ui.set_next_item_width(controls_width);
+            if combo_value(
+                ui,
+                "##spectrogram_channel",
+                &mut self.spectrogram_channel,
+                &POSSIBLE_CHANNELS,
+                |i| channel_name(*i).into(),
+            ) {
+                self.clear();
+            }
+        }
+
+        window
+            .imgui
+            .gfx
+            .texture(self.tex_id)
+            .unwrap_owned_ref()
+            .set_data(
+                window.gfx().device(),
+                window.gfx().queue(),
+                unsafe {
+                    slice::from_raw_parts(
+                        self.spectrogram_pixels.as_ptr() as *const u8,
+                        SPECTROGRAM_WIDTH * SPECTROGRAM_HEIGHT * 4,
+                    )
+                },
+                Default::default(),
+            );
+
+        let image_width = ui.content_region_avail()[0];
+        Image::new(
+            self.tex_id,
+            [
+                image_width,
+                image_width * (SPECTROGRAM_HEIGHT as f32 / SPECTROGRAM_WIDTH as f32),
+            ],
+        )
+        .build(ui);
+
+        new_state
+    }
+}
+
+impl InstanceableView for AudioOutput {
+    fn finish_preparing_frame_data<E: cpu::Engine>(emu: &mut Emu<E>) {
+        let data = &mut emu.audio.channel_audio_capture_data;
+        data.output_buffer.clear();
+        for buffer in &mut data.capture_unit_buffers {
+            buffer.clear();
+        }
+    }
+}
@@ -11,6 +11,8 @@ mod range_inclusive;
 pub use range_inclusive::RangeInclusive;
 pub mod disasm;
 pub mod regs;
+mod ring_buffer;
+pub use ring_buffer::RingBuffer;
 mod scrollbar;
 use scrollbar::Scrollbar;
 mod y_pos;
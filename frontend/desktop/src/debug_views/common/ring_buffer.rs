@@ -0,0 +1,62 @@
+use std::cmp::Ordering;
+
+#[derive(Clone)]
+pub struct RingBuffer<T: Copy> {
+    pub buffer: Vec<T>,
+    pub start: usize,
+}
+
+impl<T: Copy> RingBuffer<T> {
+    pub fn new(len: usize, fill_value: T) -> Self {
+        RingBuffer {
+            buffer: vec![fill_value; len],
+            start: 0,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        self.buffer[self.start..]
+            .iter()
+            .chain(&self.buffer[..self.start])
+            .copied()
+    }
+
+    pub fn fill(&mut self, value: T) {
+        self.buffer.fill(value);
+    }
+
+    pub fn extend(&mut self, iter: impl IntoIterator<Item = T>) {
+        for elem in iter {
+            self.buffer[self.start] = elem;
+            self.start += 1;
+            if self.start == self.buffer.len() {
+                self.start = 0;
+            }
+        }
+    }
+
+    pub fn resize(&mut self, new_len: usize, value: T) {
+        let prev_len = self.buffer.len();
+        match new_len.cmp(&prev_len) {
+            Ordering::Less => {
+                let prev_start = self.start;
+                self.start = (self.start + prev_len - new_len) % new_len;
+                if new_len < prev_start {
+                    self.buffer.copy_within(prev_start - new_len..prev_start, 0);
+                } else {
+                    self.buffer
+                        .copy_within(prev_len - (new_len - prev_start)..prev_len, self.start);
+                }
+                self.buffer.truncate(new_len);
+                self.buffer.shrink_to_fit();
+            }
+            Ordering::Greater => {
+                let new_range = self.start..self.start + (new_len - prev_len);
+                self.buffer.resize(new_len, value);
+                self.buffer.copy_within(self.start..prev_len, new_range.end);
+                self.buffer[new_range].fill(value);
+            }
+            Ordering::Equal => {}
+        }
+    }
+}
@@ -25,13 +25,14 @@ use dust_core::{
 use emu_utils::triple_buffer;
 #[cfg(feature = "gdb-server")]
 use std::net::SocketAddr;
-#[cfg(feature = "xq-audio")]
-use std::num::NonZeroU32;
 use std::{
+    cell::Cell,
     fs::{self, File},
     hint,
     io::{self, Read},
+    num::NonZeroU32,
     path::PathBuf,
+    rc::Rc,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
@@ -79,6 +80,13 @@ pub enum Message {
 
     UpdateRtcTimeOffsetSeconds(i64),
 
+    UpdateBatteryDrainTimeMins(Option<NonZeroU32>),
+    UpdateBatteryChargeTimeMins(Option<NonZeroU32>),
+    UpdateBatteryExternalPower(bool),
+    SetBatteryLevel(f32),
+
+    UpdateTouchPressure(f32),
+
     UpdateRenderers {
         renderer_2d_is_accel: bool,
         renderer_2d: Box<dyn engine_2d::Renderer + Send>,
@@ -89,13 +97,28 @@ pub enum Message {
     UpdatePausedFramerateLimit(f32),
 
     UpdateSyncToAudio(bool),
+    UpdateAudioTimeStretching(bool),
     UpdateAudioSampleChunkSize(u16),
     #[cfg(feature = "xq-audio")]
     UpdateAudioCustomSampleRate(Option<NonZeroU32>),
     #[cfg(feature = "xq-audio")]
     UpdateAudioChannelInterpMethod(AudioChannelInterpMethod),
 
-    ToggleAudioInput(Option<audio::input::Receiver>),
+    UpdateAudioInput(Option<Box<dyn spi::tsc::MicBackend + Send>>),
+
+    StartAudioRecording(PathBuf, audio::recorder::Format),
+    StopAudioRecording,
+
+    Record3dFrame(PathBuf),
+
+    UpdateAudioChannelMuteMask(u16),
+    UpdateAudioChannelSoloMask(u16),
+    #[cfg(feature = "channel-audio-capture")]
+    StartMultitrackAudioExport(PathBuf),
+    #[cfg(feature = "channel-audio-capture")]
+    StopMultitrackAudioExport,
+
+    UpdateLidClosed(bool),
 
     #[cfg(feature = "log")]
     UpdateLogger(slog::Logger),
@@ -109,6 +132,16 @@ pub enum Notification {
     RtcTimeOffsetSecondsUpdated(i64),
     SavestateCreated(String, Savestate),
     SavestateFailed(String),
+    AudioRecordingStarted,
+    AudioRecordingStopped,
+    AudioRecordingFailed(String),
+    Frame3dRecordingFailed(String),
+    #[cfg(feature = "channel-audio-capture")]
+    MultitrackAudioExportStarted,
+    #[cfg(feature = "channel-audio-capture")]
+    MultitrackAudioExportStopped,
+    #[cfg(feature = "channel-audio-capture")]
+    MultitrackAudioExportFailed(String),
 }
 
 pub struct DsSlot {
@@ -307,21 +340,30 @@ pub struct LaunchData {
     pub to_ui: crossbeam_channel::Sender<Notification>,
 
     pub audio_tx_data: Option<audio::output::SenderData>,
-    pub mic_rx: Option<audio::input::Receiver>,
+    pub mic_backend: Option<Box<dyn spi::tsc::MicBackend + Send>>,
     pub frame_tx: triple_buffer::Sender<FrameData>,
 
     pub framerate_ratio_limit: Option<f32>,
     pub paused_framerate_limit: f32,
 
     pub sync_to_audio: bool,
+    pub audio_time_stretching: bool,
     pub audio_sample_chunk_size: u16,
     #[cfg(feature = "xq-audio")]
     pub audio_custom_sample_rate: Option<NonZeroU32>,
     #[cfg(feature = "xq-audio")]
     pub audio_channel_interp_method: AudioChannelInterpMethod,
+    pub audio_channel_mute_mask: u16,
+    pub audio_channel_solo_mask: u16,
 
     pub rtc_time_offset_seconds: i64,
 
+    pub battery_drain_time_mins: Option<NonZeroU32>,
+    pub battery_charge_time_mins: Option<NonZeroU32>,
+    pub battery_external_power: bool,
+
+    pub touch_pressure: f32,
+
     pub renderer_2d_is_accel: bool,
     pub renderer_2d: Box<dyn engine_2d::Renderer + Send>,
     pub renderer_3d_tx: Box<dyn engine_3d::RendererTx + Send>,
@@ -330,6 +372,14 @@ pub struct LaunchData {
     pub logger: slog::Logger,
 }
 
+fn mins_to_secs(mins: Option<NonZeroU32>) -> Option<NonZeroU32> {
+    mins.and_then(|mins| mins.checked_mul(NonZeroU32::new(60).unwrap()))
+}
+
+fn touch_pressure_to_raw(pressure: f32) -> u16 {
+    (pressure.clamp(0.0, 1.0) * 4095.0).round() as u16
+}
+
 #[allow(clippy::too_many_arguments)]
 pub(super) fn run(
     LaunchData {
@@ -347,21 +397,30 @@ pub(super) fn run(
         to_ui,
 
         audio_tx_data,
-        mic_rx,
+        mic_backend,
         mut frame_tx,
 
         framerate_ratio_limit,
         paused_framerate_limit,
 
         mut sync_to_audio,
+        mut audio_time_stretching,
         audio_sample_chunk_size,
         #[cfg(feature = "xq-audio")]
         audio_custom_sample_rate,
         #[cfg(feature = "xq-audio")]
         audio_channel_interp_method,
+        audio_channel_mute_mask,
+        audio_channel_solo_mask,
 
         mut rtc_time_offset_seconds,
 
+        battery_drain_time_mins,
+        battery_charge_time_mins,
+        battery_external_power,
+
+        touch_pressure,
+
         mut renderer_2d_is_accel,
         renderer_2d,
         renderer_3d_tx,
@@ -378,6 +437,32 @@ pub(super) fn run(
         };
     }
 
+    // Emulation speed relative to real time, smoothed out over a few frames
+    let emu_speed = Rc::new(Cell::new(1.0));
+    macro_rules! audio_output_backend {
+        ($data: expr) => {
+            Box::new(audio::output::Sender::new(
+                $data,
+                sync_to_audio,
+                if audio_time_stretching {
+                    Some(Rc::clone(&emu_speed))
+                } else {
+                    None
+                },
+            ))
+        };
+    }
+
+    let audio_recording = audio::recorder::SharedRecording::default();
+    macro_rules! audio_backend {
+        ($backend: expr) => {
+            Box::new(audio::recorder::RecordingBackend::new(
+                $backend,
+                Rc::clone(&audio_recording),
+            ))
+        };
+    }
+
     let (ds_slot_rom, ds_slot_spi) = setup_ds_slot(
         ds_slot,
         &sys_files.arm7_bios,
@@ -400,11 +485,11 @@ pub(super) fn run(
         .expect("Couldn't build firmware"),
         ds_slot_rom,
         ds_slot_spi,
-        match &audio_tx_data {
-            Some(data) => Box::new(audio::output::Sender::new(data, sync_to_audio)),
+        audio_backend!(match &audio_tx_data {
+            Some(data) => audio_output_backend!(data),
             None => Box::new(DummyAudioBackend),
-        },
-        mic_rx.map(|mic_rx| Box::new(mic_rx) as Box<dyn spi::tsc::MicBackend>),
+        }),
+        mic_backend.map(|backend| backend as Box<dyn spi::tsc::MicBackend>),
         Box::new(rtc::Backend::new(rtc_time_offset_seconds)),
         renderer_2d,
         renderer_3d_tx,
@@ -424,8 +509,14 @@ pub(super) fn run(
         emu_builder.audio_custom_sample_rate = audio_custom_sample_rate;
         emu_builder.audio_channel_interp_method = audio_channel_interp_method;
     }
+    emu_builder.battery_drain_time = mins_to_secs(battery_drain_time_mins);
+    emu_builder.battery_charge_time = mins_to_secs(battery_charge_time_mins);
+    emu_builder.external_power = battery_external_power;
 
     let mut emu = emu_builder.build(Interpreter).unwrap();
+    emu.audio.channel_mute_mask = audio_channel_mute_mask;
+    emu.audio.channel_solo_mask = audio_channel_solo_mask;
+    emu.set_touch_pressure(touch_pressure_to_raw(touch_pressure));
 
     const FRAME_BASE_INTERVAL: Duration = Duration::from_nanos(1_000_000_000 / 60);
     let mut frame_interval = framerate_ratio_limit.map(|value| FRAME_BASE_INTERVAL.div_f32(value));
@@ -437,12 +528,34 @@ pub(super) fn run(
     let mut last_fps_calc_time = last_frame_time;
     let mut fps = 0.0;
 
+    const SPEED_SMOOTHING_FACTOR: f64 = 0.1;
+    let mut last_speed_calc_time = last_frame_time;
+
     let mut save_interval = Duration::from_secs_f32(save_interval_ms);
     let mut last_save_flush_time = last_frame_time;
 
     #[cfg(feature = "debug-views")]
     let mut debug_views = debug_views::EmuState::new();
 
+    #[cfg(feature = "channel-audio-capture")]
+    let mut multitrack_recorder: Option<audio::recorder::MultitrackRecorder> = None;
+
+    #[cfg(feature = "channel-audio-capture")]
+    macro_rules! stop_multitrack_audio_export {
+        ($result: ident => $notif: expr) => {
+            if let Some(recorder) = multitrack_recorder.take() {
+                emu.audio.channel_audio_capture_data.export_mask = 0;
+                for buffer in &mut emu.audio.channel_audio_capture_data.buffers {
+                    buffer.clear();
+                }
+                let $result = recorder.finish();
+                $notif;
+            }
+        };
+    }
+
+    let mut frame_3d_recording_path: Option<PathBuf> = None;
+
     #[cfg(feature = "gdb-server")]
     let mut gdb_server = None;
     #[cfg(feature = "gdb-server")]
@@ -603,6 +716,29 @@ pub(super) fn run(
                         .set_time_offset_seconds(value);
                 }
 
+                Message::UpdateBatteryDrainTimeMins(value) => {
+                    emu.spi.power.battery_drain_time = mins_to_secs(value);
+                }
+
+                Message::UpdateBatteryChargeTimeMins(value) => {
+                    emu.spi.power.battery_charge_time = mins_to_secs(value);
+                }
+
+                Message::UpdateBatteryExternalPower(value) => {
+                    emu.spi.power.set_external_power(value);
+                }
+
+                Message::SetBatteryLevel(value) => {
+                    emu.spi.power.set_battery_level(
+                        (value.clamp(0.0, 1.0) as f64 * spi::power::BATTERY_LEVEL_MAX as f64)
+                            as u64,
+                    );
+                }
+
+                Message::UpdateTouchPressure(value) => {
+                    emu.set_touch_pressure(touch_pressure_to_raw(value));
+                }
+
                 Message::UpdateRenderers {
                     renderer_2d_is_accel: new_renderer_2d_is_accel,
                     renderer_2d,
@@ -624,8 +760,14 @@ pub(super) fn run(
                 Message::UpdateSyncToAudio(value) => {
                     sync_to_audio = value;
                     if let Some(data) = &audio_tx_data {
-                        emu.audio.backend =
-                            Box::new(audio::output::Sender::new(data, sync_to_audio));
+                        emu.audio.backend = audio_backend!(audio_output_backend!(data));
+                    }
+                }
+
+                Message::UpdateAudioTimeStretching(value) => {
+                    audio_time_stretching = value;
+                    if let Some(data) = &audio_tx_data {
+                        emu.audio.backend = audio_backend!(audio_output_backend!(data));
                     }
                 }
 
@@ -636,6 +778,19 @@ pub(super) fn run(
                 #[cfg(feature = "xq-audio")]
                 Message::UpdateAudioCustomSampleRate(value) => {
                     Audio::set_custom_sample_rate(&mut emu, value);
+                    // Recordings can't change their sample rate midway through
+                    let sample_rate =
+                        value.map_or(audio::output::DEFAULT_INPUT_SAMPLE_RATE, NonZeroU32::get);
+                    let mut recording = audio_recording.borrow_mut();
+                    if recording
+                        .sample_rate()
+                        .map_or(false, |prev| prev != sample_rate)
+                    {
+                        match recording.stop() {
+                            Ok(()) => notif!(Notification::AudioRecordingStopped),
+                            Err(err) => notif!(Notification::AudioRecordingFailed(err.to_string())),
+                        }
+                    }
                 }
 
                 #[cfg(feature = "xq-audio")]
@@ -643,9 +798,74 @@ pub(super) fn run(
                     emu.audio.set_channel_interp_method(value);
                 }
 
-                Message::ToggleAudioInput(mic_rx) => {
-                    emu.spi.tsc.mic_data =
-                        mic_rx.map(|mic_rx| spi::tsc::MicData::new(Box::new(mic_rx)));
+                Message::UpdateLidClosed(value) => {
+                    emu.set_lid_closed(value);
+                }
+
+                Message::UpdateAudioInput(mic_backend) => {
+                    emu.spi.tsc.mic_data = mic_backend.map(|backend| {
+                        spi::tsc::MicData::new(backend as Box<dyn spi::tsc::MicBackend>)
+                    });
+                }
+
+                Message::StartAudioRecording(path, format) => {
+                    #[cfg(feature = "xq-audio")]
+                    let sample_rate = emu
+                        .audio
+                        .custom_sample_rate()
+                        .map_or(audio::output::DEFAULT_INPUT_SAMPLE_RATE, NonZeroU32::get);
+                    #[cfg(not(feature = "xq-audio"))]
+                    let sample_rate = audio::output::DEFAULT_INPUT_SAMPLE_RATE;
+                    match audio_recording
+                        .borrow_mut()
+                        .start(&path, format, sample_rate)
+                    {
+                        Ok(()) => notif!(Notification::AudioRecordingStarted),
+                        Err(err) => notif!(Notification::AudioRecordingFailed(err.to_string())),
+                    }
+                }
+
+                Message::StopAudioRecording => match audio_recording.borrow_mut().stop() {
+                    Ok(()) => notif!(Notification::AudioRecordingStopped),
+                    Err(err) => notif!(Notification::AudioRecordingFailed(err.to_string())),
+                },
+
+                Message::Record3dFrame(path) => {
+                    emu.gpu.engine_3d.start_recording();
+                    frame_3d_recording_path = Some(path);
+                }
+
+                Message::UpdateAudioChannelMuteMask(value) => {
+                    emu.audio.channel_mute_mask = value;
+                }
+
+                Message::UpdateAudioChannelSoloMask(value) => {
+                    emu.audio.channel_solo_mask = value;
+                }
+
+                #[cfg(feature = "channel-audio-capture")]
+                Message::StartMultitrackAudioExport(dir_path) => {
+                    stop_multitrack_audio_export!(_result => ());
+                    match audio::recorder::MultitrackRecorder::new(&dir_path) {
+                        Ok(recorder) => {
+                            multitrack_recorder = Some(recorder);
+                            emu.audio.channel_audio_capture_data.export_mask = 0xFFFF;
+                            notif!(Notification::MultitrackAudioExportStarted);
+                        }
+                        Err(err) => {
+                            notif!(Notification::MultitrackAudioExportFailed(err.to_string()));
+                        }
+                    }
+                }
+
+                #[cfg(feature = "channel-audio-capture")]
+                Message::StopMultitrackAudioExport => {
+                    stop_multitrack_audio_export!(result => match result {
+                        Ok(()) => notif!(Notification::MultitrackAudioExportStopped),
+                        Err(err) => {
+                            notif!(Notification::MultitrackAudioExportFailed(err.to_string()))
+                        }
+                    });
                 }
 
                 #[cfg(feature = "log")]
@@ -693,6 +913,16 @@ pub(super) fn run(
             let audio_custom_sample_rate = emu.audio.custom_sample_rate();
             #[cfg(feature = "xq-audio")]
             let audio_channel_interp_method = emu.audio.channel_interp_method();
+            // Resetting the console doesn't recharge its battery
+            let battery_level = emu.spi.power.battery_level();
+            let battery_drain_time = emu.spi.power.battery_drain_time;
+            let battery_charge_time = emu.spi.power.battery_charge_time;
+            let battery_external_power = emu.spi.power.external_power();
+            let touch_pressure = emu.spi.tsc.pressure();
+            let audio_channel_mute_mask = emu.audio.channel_mute_mask;
+            let audio_channel_solo_mask = emu.audio.channel_solo_mask;
+            #[cfg(feature = "channel-audio-capture")]
+            let channel_audio_export_mask = emu.audio.channel_audio_capture_data.export_mask;
 
             let (renderer_2d, renderer_3d_tx) = emu.gpu.into_renderers();
 
@@ -729,8 +959,19 @@ pub(super) fn run(
                 emu_builder.audio_custom_sample_rate = audio_custom_sample_rate;
                 emu_builder.audio_channel_interp_method = audio_channel_interp_method;
             }
+            emu_builder.battery_level = battery_level;
+            emu_builder.battery_drain_time = battery_drain_time;
+            emu_builder.battery_charge_time = battery_charge_time;
+            emu_builder.external_power = battery_external_power;
 
             emu = emu_builder.build(Interpreter).unwrap();
+            emu.audio.channel_mute_mask = audio_channel_mute_mask;
+            emu.audio.channel_solo_mask = audio_channel_solo_mask;
+            emu.set_touch_pressure(touch_pressure);
+            #[cfg(feature = "channel-audio-capture")]
+            {
+                emu.audio.channel_audio_capture_data.export_mask = channel_audio_export_mask;
+            }
             #[cfg(feature = "gdb-server")]
             if let Some(server) = &mut gdb_server {
                 server.attach(&mut emu);
@@ -787,11 +1028,37 @@ pub(super) fn run(
             frame
                 .fb
                 .copy_from_slice(&emu.gpu.renderer_2d().framebuffer()[..]);
+            if let Some((resolution_scale_shift, hi_res_fb)) =
+                emu.gpu.renderer_2d().hi_res_framebuffer()
+            {
+                frame.resolution_scale_shift = resolution_scale_shift;
+                frame.hi_res_fb.clear();
+                frame.hi_res_fb.extend_from_slice(hi_res_fb);
+            } else {
+                frame.resolution_scale_shift = 0;
+            }
+        }
+
+        #[cfg(feature = "channel-audio-capture")]
+        if let Some(recorder) = &mut multitrack_recorder {
+            if let Err(err) = recorder.write_samples(&emu.audio.channel_audio_capture_data.buffers)
+            {
+                stop_multitrack_audio_export!(_result => ());
+                notif!(Notification::MultitrackAudioExportFailed(err.to_string()));
+            }
         }
 
         #[cfg(feature = "debug-views")]
         debug_views.prepare_frame_data(&mut emu, &mut frame.debug);
 
+        // The debug views only clear the capture buffers while they're using them
+        #[cfg(feature = "channel-audio-capture")]
+        if multitrack_recorder.is_some() {
+            for buffer in &mut emu.audio.channel_audio_capture_data.buffers {
+                buffer.clear();
+            }
+        }
+
         frames_since_last_fps_calc += 1;
         let now = Instant::now();
         let elapsed = now - last_fps_calc_time;
@@ -802,8 +1069,47 @@ pub(super) fn run(
         }
         frame.fps = fps;
 
+        if playing {
+            let frame_time = (now - last_speed_calc_time).as_secs_f64().max(1e-6);
+            let cur_speed = FRAME_BASE_INTERVAL.as_secs_f64() / frame_time;
+            emu_speed.set(emu_speed.get() + (cur_speed - emu_speed.get()) * SPEED_SMOOTHING_FACTOR);
+        }
+        last_speed_calc_time = now;
+        frame.battery_level =
+            (emu.spi.power.battery_level() as f64 / spi::power::BATTERY_LEVEL_MAX as f64) as f32;
+        frame.battery_charging = emu.spi.power.charging();
+        let power_control = emu.spi.power.control();
+        frame.backlights_enabled = [
+            power_control.upper_backlight_enabled(),
+            power_control.lower_backlight_enabled(),
+        ];
+        frame.power_led_state = emu.spi.power.power_led_state();
+        frame.power_led_color = emu.spi.power.power_led_color();
+
         frame_tx.finish();
 
+        if let Some(err) = audio_recording.borrow_mut().take_error() {
+            notif!(Notification::AudioRecordingFailed(err.to_string()));
+        }
+
+        if frame_3d_recording_path.is_some() {
+            if let Some(mut recording) = emu.gpu.engine_3d.take_recording() {
+                let path = frame_3d_recording_path.take().unwrap();
+                let mut contents = Vec::new();
+                let result = if recording
+                    .store(&mut PersistentWriteSavestate::new(&mut contents))
+                    .is_ok()
+                {
+                    fs::write(path, &contents).map_err(|err| err.to_string())
+                } else {
+                    Err("couldn't serialize recording".to_string())
+                };
+                if let Err(err) = result {
+                    notif!(Notification::Frame3dRecordingFailed(err));
+                }
+            }
+        }
+
         let now = Instant::now();
         if now - last_save_flush_time >= save_interval {
             last_save_flush_time = now;
@@ -849,5 +1155,18 @@ pub(super) fn run(
 
     save!();
 
+    #[cfg(feature = "channel-audio-capture")]
+    stop_multitrack_audio_export!(_result => {
+        #[cfg(feature = "log")]
+        if let Err(err) = _result {
+            slog::error!(logger, "Couldn't finish multitrack audio export: {err}");
+        }
+    });
+
+    if let Err(_err) = audio_recording.borrow_mut().stop() {
+        #[cfg(feature = "log")]
+        slog::error!(logger, "Couldn't finish audio recording: {_err}");
+    }
+
     frame_tx
 }
@@ -1,11 +1,21 @@
 #[cfg(feature = "debug-views")]
 use crate::debug_views;
-use dust_core::gpu::Framebuffer;
+use dust_core::{
+    gpu::Framebuffer,
+    spi::power::{PowerLedColor, PowerLedState},
+};
 
 #[repr(C)]
 pub struct FrameData {
     pub fb: Box<Framebuffer>,
+    pub resolution_scale_shift: u8,
+    pub hi_res_fb: Vec<u32>,
     pub fps: f32,
+    pub battery_level: f32,
+    pub battery_charging: bool,
+    pub backlights_enabled: [bool; 2],
+    pub power_led_state: PowerLedState,
+    pub power_led_color: PowerLedColor,
     #[cfg(feature = "debug-views")]
     pub debug: debug_views::FrameData,
 }
@@ -14,7 +24,14 @@ impl Default for FrameData {
     fn default() -> Self {
         FrameData {
             fb: unsafe { Box::new_zeroed().assume_init() },
+            resolution_scale_shift: 0,
+            hi_res_fb: Vec::new(),
             fps: 0.0,
+            battery_level: 1.0,
+            battery_charging: false,
+            backlights_enabled: [true; 2],
+            power_led_state: PowerLedState::Normal,
+            power_led_color: PowerLedColor::Green,
             #[cfg(feature = "debug-views")]
             debug: debug_views::FrameData::new(),
         }
@@ -14,6 +14,8 @@ pub enum Action {
     ToggleFramerateLimit,
     ToggleSyncToAudio,
     ToggleFullWindowScreen,
+    ToggleLid,
+    SwapScreens,
 }
 
 pub type PressedKey = (Option<VirtualKeyCode>, ScanCode);
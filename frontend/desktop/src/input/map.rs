@@ -39,6 +39,8 @@ static ACTION_IDENTS: &[(Action, &str)] = &[
     ),
     (Action::ToggleSyncToAudio, "toggle-sync-to-audio"),
     (Action::ToggleFramerateLimit, "toggle-framerate-limit"),
+    (Action::ToggleLid, "toggle-lid"),
+    (Action::SwapScreens, "swap-screens"),
 ];
 
 #[derive(Clone)]
@@ -93,6 +95,8 @@ fn default_hotkey_map() -> HashMap<Action, Option<Trigger>> {
         (Action::ToggleFullWindowScreen, None),
         (Action::ToggleSyncToAudio, None),
         (Action::ToggleFramerateLimit, None),
+        (Action::ToggleLid, None),
+        (Action::SwapScreens, None),
     ]
     .into_iter()
     .collect()
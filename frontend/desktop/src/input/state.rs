@@ -46,7 +46,6 @@ impl State {
 
     pub fn set_touchscreen_bounds_from_points(
         &mut self,
-        center: [f32; 2],
         points: &[[f32; 2]; 4],
         rot: f32,
         scale_factor: f64,
@@ -57,17 +56,18 @@ impl State {
             (x * x + y * y).sqrt()
         }
 
-        let center = center.map(|v| v as f64 * scale_factor);
+        let center = [0, 1].map(|i| (points[0][i] + points[2][i]) as f64 * 0.5 * scale_factor);
         let size = [
             distance(points[0], points[1]) as f64 * scale_factor,
-            (distance(points[1], points[2]) * 0.5) as f64 * scale_factor,
+            distance(points[1], points[2]) as f64 * scale_factor,
         ];
-        self.set_touchscreen_bounds(
-            center.into(),
-            (center[0], center[1] + size[1] * 0.5).into(),
-            size.into(),
-            rot as f64,
-        );
+        self.set_touchscreen_bounds(center.into(), center.into(), size.into(), rot as f64);
+    }
+
+    pub fn clear_touchscreen_bounds(&mut self) {
+        self.touchscreen_size = Default::default();
+        self.touchscreen_half_size = Default::default();
+        self.touch_pos = None;
     }
 
     pub fn set_touchscreen_bounds(
@@ -27,6 +27,7 @@ mod utils;
 mod config;
 
 mod audio;
+mod cli;
 #[cfg(feature = "debug-views")]
 mod debug_views;
 mod ds_slot_rom;
@@ -6,6 +6,10 @@ mod save_slot_editor;
 use save_slot_editor::Editor as SaveSlotEditor;
 mod savestate_editor;
 use savestate_editor::Editor as SavestateEditor;
+mod screen_filter;
+mod screen_layout;
+mod sdat_browser;
+use sdat_browser::Browser as SdatBrowser;
 
 #[cfg(feature = "log")]
 mod log;
@@ -15,7 +19,7 @@ pub mod window;
 #[cfg(feature = "debug-views")]
 use crate::debug_views;
 use crate::{
-    audio,
+    audio, cli,
     config::{self, Launch, Renderer2dKind, Renderer3dKind, TitleBarMode},
     emu, game_db, input,
     utils::{config_base, Lazy},
@@ -24,6 +28,10 @@ use crate::{
 use dust_core::{
     ds_slot::rom::Contents,
     gpu::{engine_2d, engine_3d, Framebuffer, SCREEN_HEIGHT, SCREEN_WIDTH},
+    spi::{
+        power::{PowerLedColor, PowerLedState},
+        tsc::MicBackend,
+    },
 };
 use emu_utils::triple_buffer;
 #[cfg(feature = "log")]
@@ -31,14 +39,13 @@ use log::Log;
 use rfd::FileDialog;
 #[cfg(feature = "gdb-server")]
 use std::net::SocketAddr;
-#[cfg(feature = "xq-audio")]
-use std::num::NonZeroU32;
 #[cfg(feature = "discord-presence")]
 use std::time::SystemTime;
 use std::{
-    env,
     fmt::Write,
-    fs, io, panic,
+    fs, io, mem,
+    num::NonZeroU32,
+    panic,
     path::{Path, PathBuf},
     slice,
     sync::{
@@ -63,7 +70,7 @@ enum Renderer2dData {
 }
 
 enum Renderer3dData {
-    Soft,
+    Soft(emu::soft_renderer_3d::FrontendChannels),
     Wgpu(dust_wgpu_3d::threaded::FrontendChannels),
 }
 
@@ -71,6 +78,7 @@ struct EmuState {
     playing: bool,
     title: String,
     game_loaded: bool,
+    game_code: Option<u32>,
     save_path_update: Option<emu::SavePathUpdate>,
     #[cfg(feature = "gdb-server")]
     gdb_server_addr: Option<SocketAddr>,
@@ -82,6 +90,10 @@ struct EmuState {
     to_emu: crossbeam_channel::Sender<emu::Message>,
 
     mic_input_stream: Option<audio::input::InputStream>,
+    lid_closed: bool,
+    audio_recording: bool,
+    #[cfg(feature = "channel-audio-capture")]
+    multitrack_audio_export: bool,
 
     renderer_2d: Renderer2dData,
     renderer_3d: Renderer3dData,
@@ -93,6 +105,34 @@ impl EmuState {
             .send(msg)
             .expect("couldn't send message to emulation thread");
     }
+
+    fn update_texture_pack_config(&self, config: &config::Config) {
+        let Renderer3dData::Wgpu(channels) = &self.renderer_3d else {
+            return;
+        };
+        // Texture packs are stored per game, in a directory named after its game code
+        let game_dir = self.game_code.map(|game_code| {
+            let dir_name = game_code
+                .to_le_bytes()
+                .iter()
+                .map(|&c| {
+                    if c.is_ascii_alphanumeric() {
+                        c as char
+                    } else {
+                        '_'
+                    }
+                })
+                .collect::<String>();
+            config!(config, &texture_pack_dir_path).0.join(dir_name)
+        });
+        channels.set_texture_pack_config(dust_wgpu_3d::TexturePackConfig {
+            dump_dir: game_dir
+                .as_ref()
+                .filter(|_| config!(config, dump_textures))
+                .map(|game_dir| game_dir.join("dump")),
+            replacement_dir: game_dir.filter(|_| config!(config, load_texture_packs)),
+        });
+    }
 }
 
 struct Config {
@@ -193,11 +233,18 @@ pub struct UiState {
     game_db: Lazy<Option<game_db::Database>>,
 
     emu: Option<EmuState>,
+    rom_path: Option<PathBuf>,
 
     fb_texture: FbTexture,
+    screen_filter: screen_filter::Chain,
     frame_tx: Option<triple_buffer::Sender<FrameData>>,
     frame_rx: triple_buffer::Receiver<FrameData>,
     fps_fixed: Option<u64>,
+    battery_level: f32,
+    battery_charging: bool,
+    backlights_enabled: [bool; 2],
+    power_led_state: PowerLedState,
+    power_led_color: PowerLedColor,
 
     show_menu_bar: bool,
     screen_focused: bool,
@@ -208,8 +255,13 @@ pub struct UiState {
 
     save_slot_editor: SaveSlotEditor,
     savestate_editor: SavestateEditor,
+    sdat_browser: Option<SdatBrowser>,
 
     audio_channel: Option<audio::output::Channel>,
+    mic_override: Option<cli::MicArgs>,
+    record_audio_path: Option<PathBuf>,
+    audio_channel_mute_mask: u16,
+    audio_channel_solo_mask: u16,
 
     #[cfg(target_os = "windows")]
     icon_update: Option<Option<[u32; 32 * 32]>>,
@@ -238,9 +290,36 @@ impl UiState {
 
     fn reset(&mut self) {
         if let Some(emu) = &mut self.emu {
+            emu.lid_closed = false;
             emu.send_message(emu::Message::Reset);
         }
     }
+
+    fn start_audio_recording(&mut self, path: PathBuf) {
+        if let Some(emu) = &self.emu {
+            let format = audio::recorder::Format::from_path(&path);
+            emu.send_message(emu::Message::StartAudioRecording(path, format));
+        }
+    }
+
+    fn stop_audio_recording(&mut self) {
+        if let Some(emu) = &self.emu {
+            emu.send_message(emu::Message::StopAudioRecording);
+        }
+    }
+
+    fn record_3d_frame(&mut self, path: PathBuf) {
+        if let Some(emu) = &self.emu {
+            emu.send_message(emu::Message::Record3dFrame(path));
+        }
+    }
+
+    fn toggle_lid(&mut self) {
+        if let Some(emu) = &mut self.emu {
+            emu.lid_closed = !emu.lid_closed;
+            emu.send_message(emu::Message::UpdateLidClosed(emu.lid_closed));
+        }
+    }
 }
 
 bitflags::bitflags! {
@@ -302,6 +381,7 @@ impl UiState {
                     window,
                 );
                 config.game_path = game_config.path;
+                self.rom_path = Some(path.to_path_buf());
             }
 
             Err(errors) => {
@@ -364,11 +444,14 @@ impl UiState {
                 Renderer2dKind::WgpuLockstepScanlines => {
                     let (tx_3d, rx_3d_2d_data, renderer_3d_data) = match renderer_3d_kind {
                         Renderer3dKind::Soft => {
-                            let (tx_3d, rx_3d) = emu::soft_renderer_3d::init();
+                            // The accelerated 2D renderer only composites native-resolution
+                            // software 3D output
+                            let (tx_3d, rx_3d, renderer_3d_channels) =
+                                emu::soft_renderer_3d::init(0);
                             (
                                 Box::new(tx_3d) as Box<dyn engine_3d::RendererTx + Send>,
                                 dust_wgpu_2d::Renderer3dRx::Soft(Box::new(rx_3d)),
-                                Renderer3dData::Soft,
+                                Renderer3dData::Soft(renderer_3d_channels),
                             )
                         }
 
@@ -399,7 +482,7 @@ impl UiState {
                             resolution_scale_shift,
                             rx_3d_2d_data,
                         );
-                    fb_texture.set_view(window, color_output_view);
+                    fb_texture.set_view(color_output_view, resolution_scale_shift);
 
                     (
                         Box::new(renderer_2d) as Box<dyn engine_2d::Renderer + Send>,
@@ -410,7 +493,8 @@ impl UiState {
                 }
 
                 _ => {
-                    let (tx_3d, rx_3d) = emu::soft_renderer_3d::init();
+                    let (tx_3d, rx_3d, renderer_3d_channels) =
+                        emu::soft_renderer_3d::init(resolution_scale_shift);
 
                     let (renderer_2d, renderer_2d_data) = match renderer_2d_kind {
                         Renderer2dKind::SoftSync => {
@@ -440,7 +524,7 @@ impl UiState {
                         renderer_2d,
                         Box::new(tx_3d) as Box<dyn engine_3d::RendererTx + Send>,
                         renderer_2d_data,
-                        Renderer3dData::Soft,
+                        Renderer3dData::Soft(renderer_3d_channels),
                     )
                 }
             }
@@ -483,6 +567,8 @@ impl UiState {
         #[cfg(feature = "log")]
         let logger = self.log.logger().clone();
 
+        let mut ds_slot_game_code = None;
+
         #[allow(unused_mut, clippy::bind_instead_of_map)]
         let ds_slot = ds_slot_rom.and_then(|mut rom| {
             #[cfg(target_os = "windows")]
@@ -496,6 +582,7 @@ impl UiState {
             }
 
             let game_code = rom.game_code();
+            ds_slot_game_code = Some(game_code);
 
             let save_type = self
                 .game_db
@@ -557,17 +644,8 @@ impl UiState {
             .as_ref()
             .map(|audio_channel| audio_channel.tx_data.clone());
 
-        let (mic_input_stream, mic_rx) = if config!(config.config, audio_input_enabled) {
-            if let Some(channel) =
-                audio::input::Channel::new(config!(config.config, audio_input_interp_method))
-            {
-                (Some(channel.input_stream), Some(channel.rx))
-            } else {
-                (None, None)
-            }
-        } else {
-            (None, None)
-        };
+        let (mic_input_stream, mic_backend) =
+            create_mic_backend(&config.config, self.mic_override.as_ref());
 
         let (to_emu, from_ui) = crossbeam_channel::unbounded::<emu::Message>();
         let (to_ui, from_emu) = crossbeam_channel::unbounded::<emu::Notification>();
@@ -597,7 +675,7 @@ impl UiState {
             to_ui,
 
             audio_tx_data,
-            mic_rx,
+            mic_backend,
             frame_tx,
 
             framerate_ratio_limit: {
@@ -607,14 +685,23 @@ impl UiState {
             paused_framerate_limit: config!(config.config, paused_framerate_limit),
 
             sync_to_audio: config!(config.config, sync_to_audio),
+            audio_time_stretching: config!(config.config, audio_time_stretching),
             audio_sample_chunk_size: config!(config.config, audio_sample_chunk_size),
             #[cfg(feature = "xq-audio")]
             audio_custom_sample_rate: config!(config.config, audio_custom_sample_rate),
             #[cfg(feature = "xq-audio")]
             audio_channel_interp_method: config!(config.config, audio_channel_interp_method),
+            audio_channel_mute_mask: self.audio_channel_mute_mask,
+            audio_channel_solo_mask: self.audio_channel_solo_mask,
 
             rtc_time_offset_seconds: config!(config.config, rtc_time_offset_seconds),
 
+            battery_drain_time_mins: config!(config.config, battery_drain_time_mins),
+            battery_charge_time_mins: config!(config.config, battery_charge_time_mins),
+            battery_external_power: config!(config.config, battery_external_power),
+
+            touch_pressure: config!(config.config, touch_pressure),
+
             renderer_2d_is_accel,
             renderer_2d,
             renderer_3d_tx,
@@ -635,6 +722,7 @@ impl UiState {
             playing,
             title,
             game_loaded,
+            game_code: ds_slot_game_code,
             save_path_update: None,
             #[cfg(feature = "gdb-server")]
             gdb_server_addr: None,
@@ -646,10 +734,23 @@ impl UiState {
             to_emu,
 
             mic_input_stream,
+            lid_closed: false,
+            audio_recording: false,
+            #[cfg(feature = "channel-audio-capture")]
+            multitrack_audio_export: false,
 
             renderer_2d: renderer_2d_data,
             renderer_3d: renderer_3d_data,
         });
+        self.emu
+            .as_ref()
+            .unwrap()
+            .update_texture_pack_config(&config.config);
+
+        // Only the first game launched gets recorded when requested from the command line
+        if let Some(path) = self.record_audio_path.take() {
+            self.start_audio_recording(path);
+        }
     }
 
     fn stop_emu(&mut self, config: &mut Config) {
@@ -671,6 +772,7 @@ impl UiState {
 
     fn stop(&mut self, config: &mut Config, window: &mut window::Window) {
         self.stop_emu(config);
+        self.rom_path = None;
 
         self.savestate_editor
             .update_game(window, &config.config, None);
@@ -710,6 +812,7 @@ impl UiState {
 
         self.fb_texture.set_owned(window);
         self.fb_texture.clear(window);
+        self.backlights_enabled = [true; 2];
     }
 
     fn playing(&self) -> bool {
@@ -779,111 +882,229 @@ impl UiState {
 }
 
 struct FbTexture {
-    id: imgui::TextureId,
-    is_view: bool,
+    owned_texture: Option<wgpu::Texture>,
+    view: wgpu::TextureView,
+    resolution_scale_shift: u8,
+    changed: bool,
 }
 
 impl FbTexture {
-    fn create_owned(window: &window::Window) -> imgui::TextureId {
-        window.imgui.gfx.create_and_add_owned_texture(
-            Some("Framebuffer".into()),
-            imgui_wgpu::TextureDescriptor {
-                width: SCREEN_WIDTH as u32,
-                height: SCREEN_HEIGHT as u32 * 2,
+    fn create_owned(window: &window::Window, resolution_scale_shift: u8) -> wgpu::Texture {
+        window
+            .gfx()
+            .device()
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("Framebuffer"),
+                size: wgpu::Extent3d {
+                    width: (SCREEN_WIDTH as u32) << resolution_scale_shift,
+                    height: (SCREEN_HEIGHT as u32 * 2) << resolution_scale_shift,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
                 format: wgpu::TextureFormat::Rgba8Unorm,
-                ..Default::default()
-            },
-            imgui_wgpu::SamplerDescriptor {
-                min_filter: wgpu::FilterMode::Linear,
-                ..Default::default()
-            },
-        )
-    }
-
-    fn create_view(window: &window::Window, view: wgpu::TextureView) -> imgui::TextureId {
-        window.imgui.gfx.create_and_add_texture_view(
-            Some("Framebuffer".into()),
-            view,
-            imgui_wgpu::SamplerDescriptor {
-                min_filter: wgpu::FilterMode::Linear,
-                ..Default::default()
-            },
-        )
+                usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+            })
     }
 
     fn new(window: &mut window::Window) -> Self {
+        let texture = Self::create_owned(window, 0);
         let result = FbTexture {
-            id: Self::create_owned(window),
-            is_view: false,
+            view: texture.create_view(&Default::default()),
+            owned_texture: Some(texture),
+            resolution_scale_shift: 0,
+            changed: false,
         };
         result.clear(window);
         result
     }
 
+    fn is_view(&self) -> bool {
+        self.owned_texture.is_none()
+    }
+
+    fn set_owned_texture(&mut self, window: &window::Window, resolution_scale_shift: u8) {
+        let texture = Self::create_owned(window, resolution_scale_shift);
+        self.view = texture.create_view(&Default::default());
+        self.owned_texture = Some(texture);
+        self.resolution_scale_shift = resolution_scale_shift;
+        self.changed = true;
+    }
+
     fn set_owned(&mut self, window: &window::Window) {
-        if !self.is_view {
+        if !self.is_view() {
             return;
         }
-        window.imgui.gfx.remove_texture(self.id);
-        self.id = Self::create_owned(window);
-        self.is_view = false;
+        self.set_owned_texture(window, self.resolution_scale_shift);
     }
 
-    fn set_view(&mut self, window: &window::Window, view: wgpu::TextureView) {
-        if self.is_view {
-            window
-                .imgui
-                .gfx
-                .texture_mut(self.id)
-                .unwrap_view_mut()
-                .set_texture_view(view);
-        } else {
-            window.imgui.gfx.remove_texture(self.id);
-            self.id = Self::create_view(window, view);
-            self.is_view = true;
+    fn set_owned_resolution_scale_shift(&mut self, window: &window::Window, value: u8) {
+        if self.is_view() || value == self.resolution_scale_shift {
+            return;
         }
+        self.set_owned_texture(window, value);
+    }
+
+    fn set_view(&mut self, view: wgpu::TextureView, resolution_scale_shift: u8) {
+        self.owned_texture = None;
+        self.view = view;
+        self.resolution_scale_shift = resolution_scale_shift;
+        self.changed = true;
+    }
+
+    fn view(&self) -> &wgpu::TextureView {
+        &self.view
     }
 
-    fn id(&self) -> imgui::TextureId {
-        self.id
+    fn size(&self) -> [u32; 2] {
+        [
+            (SCREEN_WIDTH as u32) << self.resolution_scale_shift,
+            (SCREEN_HEIGHT as u32 * 2) << self.resolution_scale_shift,
+        ]
+    }
+
+    fn take_changed(&mut self) -> bool {
+        mem::replace(&mut self.changed, false)
+    }
+
+    fn write_data(&self, window: &window::Window, data: &[u8]) {
+        let texture = match &self.owned_texture {
+            Some(texture) => texture,
+            None => return,
+        };
+        let [width, height] = self.size();
+        window.gfx().queue().write_texture(
+            texture.as_image_copy(),
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(width << 2),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
     }
 
     fn clear(&self, window: &window::Window) {
-        let mut data =
-            unsafe { Box::<[u8; SCREEN_WIDTH * SCREEN_HEIGHT * 8]>::new_zeroed().assume_init() };
-        for i in (3..data.len()).step_by(4) {
-            data[i] = 0xFF;
+        let data = vec![
+            0xFF00_0000_u32;
+            (SCREEN_WIDTH * SCREEN_HEIGHT * 2) << (2 * self.resolution_scale_shift)
+        ];
+        self.write_data(window, unsafe {
+            slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * 4)
+        });
+    }
+
+    fn set_data(&mut self, window: &window::Window, data: &Framebuffer) {
+        self.set_owned_resolution_scale_shift(window, 0);
+        self.write_data(window, unsafe {
+            slice::from_raw_parts(
+                data.as_ptr() as *const u8,
+                2 * 4 * SCREEN_WIDTH * SCREEN_HEIGHT,
+            )
+        });
+    }
+
+    fn set_hi_res_data(
+        &mut self,
+        window: &window::Window,
+        resolution_scale_shift: u8,
+        data: &[u32],
+    ) {
+        self.set_owned_resolution_scale_shift(window, resolution_scale_shift);
+        self.write_data(window, unsafe {
+            slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * 4)
+        });
+    }
+}
+
+fn draw_screens(
+    draw_list: &imgui::DrawListMut,
+    texture_id: imgui::TextureId,
+    layout: &screen_layout::Layout,
+    layout_points: &[[f32; 2]; 4],
+    backlights_enabled: [bool; 2],
+) {
+    for (screen, points) in layout.screen_quads(layout_points) {
+        // Screens with their backlight turned off (i.e. while the lid is closed) show up black,
+        // whichever 2D renderer produced the framebuffer
+        let backlight_enabled = match screen {
+            screen_layout::Screen::Top => backlights_enabled[0],
+            screen_layout::Screen::Bottom => backlights_enabled[1],
+        };
+        if !backlight_enabled {
+            draw_list
+                .add_polyline(points.to_vec(), [0.0, 0.0, 0.0, 1.0])
+                .filled(true)
+                .build();
+            continue;
         }
-        window
-            .imgui
-            .gfx
-            .texture(self.id)
-            .unwrap_owned_ref()
-            .set_data(
-                window.gfx().device(),
-                window.gfx().queue(),
-                &data[..],
-                imgui_wgpu::TextureSetRange::default(),
-            );
+        let uvs = screen.uvs();
+        draw_list
+            .add_image_quad(texture_id, points[0], points[1], points[2], points[3])
+            .uv(uvs[0], uvs[1], uvs[2], uvs[3])
+            .build();
     }
+}
 
-    fn set_data(&self, window: &window::Window, data: &Framebuffer) {
-        window
-            .imgui
-            .gfx
-            .texture(self.id)
-            .unwrap_owned_ref()
-            .set_data(
-                window.gfx().device(),
-                window.gfx().queue(),
-                unsafe {
-                    slice::from_raw_parts(
-                        data.as_ptr() as *const u8,
-                        2 * 4 * SCREEN_WIDTH * SCREEN_HEIGHT,
-                    )
-                },
-                imgui_wgpu::TextureSetRange::default(),
-            );
+fn update_touchscreen_bounds(
+    input: &mut input::State,
+    layout: &screen_layout::Layout,
+    layout_points: &[[f32; 2]; 4],
+    rot: f32,
+    scale_factor: f64,
+) {
+    match layout.touchscreen_quad(layout_points) {
+        Some(points) => input.set_touchscreen_bounds_from_points(&points, rot, scale_factor),
+        None => input.clear_touchscreen_bounds(),
+    }
+}
+
+fn create_mic_backend(
+    config: &config::Config,
+    mic_override: Option<&cli::MicArgs>,
+) -> (
+    Option<audio::input::InputStream>,
+    Option<Box<dyn MicBackend + Send>>,
+) {
+    let result = if let Some(mic_override) = mic_override {
+        audio::input::Backend::new(
+            mic_override.source,
+            config!(config, audio_input_interp_method),
+            mic_override.wav_path.as_deref().or_else(|| {
+                config!(config, &audio_input_wav_path)
+                    .as_ref()
+                    .map(|p| &*p.0)
+            }),
+            mic_override
+                .wav_looping
+                .unwrap_or_else(|| config!(config, audio_input_wav_looping)),
+        )
+    } else if config!(config, audio_input_enabled) {
+        audio::input::Backend::new(
+            config!(config, audio_input_source),
+            config!(config, audio_input_interp_method),
+            config!(config, &audio_input_wav_path)
+                .as_ref()
+                .map(|p| &*p.0),
+            config!(config, audio_input_wav_looping),
+        )
+    } else {
+        return (None, None);
+    };
+    match result {
+        Ok(backend) => (backend.input_stream, Some(backend.mic_backend)),
+        // Keep the previous behavior of silently disabling input when no device is present
+        Err(audio::input::BackendError::NoDevice) => (None, None),
+        Err(err) => {
+            config_error!("Couldn't set up microphone input: {err}");
+            (None, None)
+        }
     }
 }
 
@@ -897,6 +1118,14 @@ pub fn main() {
         panic_hook(info);
     }));
 
+    let args = match cli::Args::parse() {
+        Ok(args) => args,
+        Err(err) => {
+            error!("Invalid arguments", "{err}");
+            cli::Args::default()
+        }
+    };
+
     let mut config = Config::new();
 
     #[cfg(feature = "log")]
@@ -934,16 +1163,30 @@ pub fn main() {
     ]);
 
     let fb_texture = FbTexture::new(&mut window_builder.window);
+    let screen_filter = screen_filter::Chain::new(
+        &window_builder.window,
+        fb_texture.view(),
+        fb_texture.size(),
+        config!(config.config, screen_filter),
+        config!(config.config, screen_color_correction),
+    );
 
     let mut state = UiState {
         game_db: Lazy::new(),
 
         emu: None,
+        rom_path: None,
 
         fb_texture,
+        screen_filter,
         frame_tx: Some(frame_tx),
         frame_rx,
         fps_fixed: None,
+        battery_level: 1.0,
+        battery_charging: false,
+        backlights_enabled: [true; 2],
+        power_led_state: PowerLedState::Normal,
+        power_led_color: PowerLedColor::Green,
 
         show_menu_bar: true,
         screen_focused: true,
@@ -954,8 +1197,13 @@ pub fn main() {
 
         save_slot_editor: SaveSlotEditor::new(),
         savestate_editor: SavestateEditor::new(),
+        sdat_browser: None,
 
         audio_channel,
+        mic_override: args.mic,
+        record_audio_path: args.record_audio,
+        audio_channel_mute_mask: 0,
+        audio_channel_solo_mask: 0,
 
         #[cfg(target_os = "windows")]
         icon_update: None,
@@ -979,12 +1227,8 @@ pub fn main() {
         discord_presence.stop();
     }
 
-    if let Some(rom_path) = env::args_os().nth(1) {
-        state.load_from_rom_path(
-            Path::new(&rom_path),
-            &mut config,
-            &mut window_builder.window,
-        );
+    if let Some(rom_path) = &args.rom_path {
+        state.load_from_rom_path(rom_path, &mut config, &mut window_builder.window);
     }
 
     window_builder.run(
@@ -1035,6 +1279,8 @@ pub fn main() {
                     input::Action::ToggleFullWindowScreen => {
                         toggle_config!(config.config, full_window_screen)
                     }
+                    input::Action::ToggleLid => state.toggle_lid(),
+                    input::Action::SwapScreens => toggle_config!(config.config, screen_swap),
                 }
             }
 
@@ -1112,10 +1358,37 @@ pub fn main() {
                         emu.send_message(emu::Message::UpdateRtcTimeOffsetSeconds(value));
                     }
 
+                    if let Some(value) =
+                        config_changed_value!(config.config, battery_drain_time_mins)
+                    {
+                        emu.send_message(emu::Message::UpdateBatteryDrainTimeMins(value));
+                    }
+
+                    if let Some(value) =
+                        config_changed_value!(config.config, battery_charge_time_mins)
+                    {
+                        emu.send_message(emu::Message::UpdateBatteryChargeTimeMins(value));
+                    }
+
+                    if let Some(value) =
+                        config_changed_value!(config.config, battery_external_power)
+                    {
+                        emu.send_message(emu::Message::UpdateBatteryExternalPower(value));
+                    }
+
+                    if let Some(value) = config_changed_value!(config.config, touch_pressure) {
+                        emu.send_message(emu::Message::UpdateTouchPressure(value));
+                    }
+
                     if let Some(value) = config_changed_value!(config.config, sync_to_audio) {
                         emu.send_message(emu::Message::UpdateSyncToAudio(value));
                     }
 
+                    if let Some(value) = config_changed_value!(config.config, audio_time_stretching)
+                    {
+                        emu.send_message(emu::Message::UpdateAudioTimeStretching(value));
+                    }
+
                     if let Some(value) =
                         config_changed_value!(config.config, audio_sample_chunk_size)
                     {
@@ -1147,23 +1420,20 @@ pub fn main() {
                         }
                     }
 
-                    if let Some(value) = config_changed_value!(config.config, audio_input_enabled) {
-                        'change: {
-                            let (mic_input_stream, mic_rx) = if value {
-                                if let Some(channel) = audio::input::Channel::new(config!(
-                                    config.config,
-                                    audio_input_interp_method
-                                )) {
-                                    (Some(channel.input_stream), Some(channel.rx))
-                                } else {
-                                    break 'change;
-                                }
-                            } else {
-                                (None, None)
-                            };
-                            emu.mic_input_stream = mic_input_stream;
-                            emu.send_message(emu::Message::ToggleAudioInput(mic_rx));
-                        }
+                    if config_changed!(
+                        config.config,
+                        audio_input_enabled
+                            | audio_input_source
+                            | audio_input_wav_path
+                            | audio_input_wav_looping
+                    ) {
+                        // Any explicit change to the input settings takes precedence over the
+                        // command-line override
+                        state.mic_override = None;
+                        let (mic_input_stream, mic_backend) =
+                            create_mic_backend(&config.config, None);
+                        emu.mic_input_stream = mic_input_stream;
+                        emu.send_message(emu::Message::UpdateAudioInput(mic_backend));
                     }
 
                     if config_changed!(config.config, renderer_2d_kind | renderer_3d_kind) {
@@ -1181,6 +1451,7 @@ pub fn main() {
 
                         emu.renderer_2d = renderer_2d_data;
                         emu.renderer_3d = renderer_3d_data;
+                        emu.update_texture_pack_config(&config.config);
 
                         emu.send_message(emu::Message::UpdateRenderers {
                             renderer_2d_is_accel,
@@ -1199,12 +1470,23 @@ pub fn main() {
                             }
                         }
                         match &emu.renderer_3d {
-                            Renderer3dData::Soft => {}
+                            Renderer3dData::Soft(channels) => {
+                                if matches!(emu.renderer_2d, Renderer2dData::Soft) {
+                                    channels.set_resolution_scale_shift(value);
+                                }
+                            }
                             Renderer3dData::Wgpu(channels) => {
                                 channels.set_resolution_scale_shift(value);
                             }
                         }
                     }
+
+                    if config_changed!(
+                        config.config,
+                        dump_textures | load_texture_packs | texture_pack_dir_path
+                    ) {
+                        emu.update_texture_pack_config(&config.config);
+                    }
                 }
 
                 if let Some(channel) = state.audio_channel.as_mut() {
@@ -1269,6 +1551,42 @@ pub fn main() {
                             emu::Notification::SavestateFailed(name) => {
                                 state.savestate_editor.savestate_failed(name);
                             }
+
+                            emu::Notification::AudioRecordingStarted => {
+                                emu.audio_recording = true;
+                            }
+
+                            emu::Notification::AudioRecordingStopped => {
+                                emu.audio_recording = false;
+                            }
+
+                            emu::Notification::AudioRecordingFailed(err) => {
+                                emu.audio_recording = false;
+                                error!("Audio recording error", "Couldn't record audio: {err}");
+                            }
+
+                            emu::Notification::Frame3dRecordingFailed(err) => {
+                                error!("3D recording error", "Couldn't record 3D frame: {err}");
+                            }
+
+                            #[cfg(feature = "channel-audio-capture")]
+                            emu::Notification::MultitrackAudioExportStarted => {
+                                emu.multitrack_audio_export = true;
+                            }
+
+                            #[cfg(feature = "channel-audio-capture")]
+                            emu::Notification::MultitrackAudioExportStopped => {
+                                emu.multitrack_audio_export = false;
+                            }
+
+                            #[cfg(feature = "channel-audio-capture")]
+                            emu::Notification::MultitrackAudioExportFailed(err) => {
+                                emu.multitrack_audio_export = false;
+                                error!(
+                                    "Audio export error",
+                                    "Couldn't export audio channels: {err}"
+                                );
+                            }
                         }
                     }
                 }
@@ -1282,14 +1600,28 @@ pub fn main() {
                     .debug_views
                     .update_from_frame_data(&frame.debug, window);
 
-                if !state.fb_texture.is_view {
-                    state.fb_texture.set_data(window, &frame.fb);
+                if !state.fb_texture.is_view() {
+                    if frame.resolution_scale_shift == 0 {
+                        state.fb_texture.set_data(window, &frame.fb);
+                    } else {
+                        state.fb_texture.set_hi_res_data(
+                            window,
+                            frame.resolution_scale_shift,
+                            &frame.hi_res_fb,
+                        );
+                    }
                 }
 
                 let fps_fixed = (frame.fps * 10.0).round() as u64;
                 if Some(fps_fixed) != state.fps_fixed {
                     state.fps_fixed = Some(fps_fixed);
                 }
+
+                state.battery_level = frame.battery_level;
+                state.battery_charging = frame.battery_charging;
+                state.backlights_enabled = frame.backlights_enabled;
+                state.power_led_state = frame.power_led_state;
+                state.power_led_color = frame.power_led_color;
             }
 
             // Draw menu bar
@@ -1364,6 +1696,166 @@ pub fn main() {
                             state.load_firmware(config, window);
                         }
 
+                        let lid_closed = state.emu.as_ref().map_or(false, |emu| emu.lid_closed);
+                        if ui
+                            .menu_item_config("Close lid")
+                            .selected(lid_closed)
+                            .enabled(state.emu.is_some())
+                            .build()
+                        {
+                            state.toggle_lid();
+                        }
+
+                        ui.enabled(state.emu.is_some(), || {
+                            ui.menu("Battery", || {
+                                let mut level = state.battery_level * 100.0;
+                                ui.set_next_item_width(
+                                    ui.calc_text_size("000.00%")[0] * 5.0
+                                        + style!(ui, frame_padding)[0] * 2.0,
+                                );
+                                if ui
+                                    .slider_config("##battery_level", 0.0, 100.0)
+                                    .flags(imgui::SliderFlags::ALWAYS_CLAMP)
+                                    .display_format(if state.battery_charging {
+                                        "%.02f%% (charging)"
+                                    } else {
+                                        "%.02f%%"
+                                    })
+                                    .build(&mut level)
+                                {
+                                    if let Some(emu) = &state.emu {
+                                        emu.send_message(emu::Message::SetBatteryLevel(
+                                            level / 100.0,
+                                        ));
+                                    }
+                                }
+
+                                let mut external_power =
+                                    config!(config.config, battery_external_power);
+                                if ui
+                                    .menu_item_config("External power")
+                                    .build_with_ref(&mut external_power)
+                                {
+                                    set_config!(
+                                        config.config,
+                                        battery_external_power,
+                                        external_power
+                                    );
+                                }
+                            });
+                        });
+
+                        let audio_recording =
+                            state.emu.as_ref().map_or(false, |emu| emu.audio_recording);
+                        if audio_recording {
+                            if ui.menu_item("Stop audio recording") {
+                                state.stop_audio_recording();
+                            }
+                        } else if ui
+                            .menu_item_config("Start audio recording...")
+                            .enabled(state.emu.is_some())
+                            .build()
+                        {
+                            if let Some(path) = FileDialog::new()
+                                .add_filter("WAV file", &["wav"])
+                                .add_filter("FLAC file", &["flac"])
+                                .save_file()
+                            {
+                                state.start_audio_recording(path);
+                            }
+                        }
+
+                        if ui
+                            .menu_item_config("Record 3D frame...")
+                            .enabled(state.emu.is_some())
+                            .build()
+                        {
+                            if let Some(path) = FileDialog::new()
+                                .add_filter("3D frame recording", &["gxframe"])
+                                .save_file()
+                            {
+                                state.record_3d_frame(path);
+                            }
+                        }
+
+                        if ui.menu_item("Sound archives...") && state.sdat_browser.is_none() {
+                            state.sdat_browser = Some(SdatBrowser::new(state.rom_path.clone()));
+                        }
+
+                        ui.menu("Audio channels", || {
+                            let prev_mute_mask = state.audio_channel_mute_mask;
+                            let prev_solo_mask = state.audio_channel_solo_mask;
+                            for i in 0..16 {
+                                let _id = ui.push_id_usize(i);
+                                ui.align_text_to_frame_padding();
+                                ui.text(format!("Channel {i:>2}"));
+                                ui.same_line();
+                                ui.checkbox_flags(
+                                    "Mute",
+                                    &mut state.audio_channel_mute_mask,
+                                    1 << i,
+                                );
+                                ui.same_line();
+                                ui.checkbox_flags(
+                                    "Solo",
+                                    &mut state.audio_channel_solo_mask,
+                                    1 << i,
+                                );
+                            }
+                            if let Some(emu) = &state.emu {
+                                if state.audio_channel_mute_mask != prev_mute_mask {
+                                    emu.send_message(emu::Message::UpdateAudioChannelMuteMask(
+                                        state.audio_channel_mute_mask,
+                                    ));
+                                }
+                                if state.audio_channel_solo_mask != prev_solo_mask {
+                                    emu.send_message(emu::Message::UpdateAudioChannelSoloMask(
+                                        state.audio_channel_solo_mask,
+                                    ));
+                                }
+                            }
+
+                            ui.separator();
+
+                            if ui.menu_item("Reset") {
+                                state.audio_channel_mute_mask = 0;
+                                state.audio_channel_solo_mask = 0;
+                                if let Some(emu) = &state.emu {
+                                    emu.send_message(emu::Message::UpdateAudioChannelMuteMask(0));
+                                    emu.send_message(emu::Message::UpdateAudioChannelSoloMask(0));
+                                }
+                            }
+
+                            #[cfg(feature = "channel-audio-capture")]
+                            {
+                                let multitrack_audio_export = state
+                                    .emu
+                                    .as_ref()
+                                    .map_or(false, |emu| emu.multitrack_audio_export);
+                                if multitrack_audio_export {
+                                    if ui.menu_item("Stop exporting channels") {
+                                        if let Some(emu) = &state.emu {
+                                            emu.send_message(
+                                                emu::Message::StopMultitrackAudioExport,
+                                            );
+                                        }
+                                    }
+                                } else if ui
+                                    .menu_item_config("Export channels to WAV...")
+                                    .enabled(state.emu.is_some())
+                                    .build()
+                                {
+                                    if let Some(dir_path) = FileDialog::new().pick_folder() {
+                                        if let Some(emu) = &state.emu {
+                                            emu.send_message(
+                                                emu::Message::StartMultitrackAudioExport(dir_path),
+                                            );
+                                        }
+                                    }
+                                }
+                            }
+                        });
+
                         ui.separator();
 
                         state
@@ -1563,6 +2055,48 @@ pub fn main() {
                     #[allow(unused)]
                     let mut right_title_limit = ui.window_size()[0];
 
+                    if state.emu.is_some() {
+                        let orig_cursor_pos = ui.cursor_pos();
+                        let item_spacing = style!(ui, item_spacing)[0];
+                        let line_height = ui.text_line_height();
+                        let radius = (line_height * 0.3).round();
+                        let width = radius * 2.0 + item_spacing * 2.0;
+                        right_title_limit = ui.content_region_max()[0] - width;
+                        ui.set_cursor_pos([right_title_limit, orig_cursor_pos[1]]);
+                        ui.separator();
+
+                        // The blinking rates aren't documented, these are only meant to make the
+                        // two speeds distinguishable
+                        let lit = match state.power_led_state {
+                            PowerLedState::Normal => true,
+                            PowerLedState::Blinking => ui.time() % 1.0 < 0.5,
+                            PowerLedState::BlinkingFast => ui.time() % 0.25 < 0.125,
+                        };
+                        let mut color = match state.power_led_color {
+                            PowerLedColor::Green => [0.2, 0.9, 0.3, 1.0],
+                            PowerLedColor::Red => [0.95, 0.2, 0.2, 1.0],
+                        };
+                        if !lit {
+                            color[3] = 0.25;
+                        }
+                        let window_pos = ui.window_pos();
+                        let center = [
+                            window_pos[0] + right_title_limit + item_spacing + radius,
+                            window_pos[1] + orig_cursor_pos[1] + line_height * 0.5,
+                        ];
+                        ui.get_window_draw_list()
+                            .add_circle(center, radius, color)
+                            .filled(true)
+                            .build();
+                        if ui.is_mouse_hovering_rect(
+                            [center[0] - radius, center[1] - radius],
+                            [center[0] + radius, center[1] + radius],
+                        ) {
+                            ui.tooltip_text("Power LED");
+                        }
+                        ui.set_cursor_pos(orig_cursor_pos);
+                    }
+
                     #[cfg(feature = "gdb-server")]
                     if let Some(emu) = &state.emu {
                         if emu.shared_state.gdb_server_active.load(Ordering::Relaxed) {
@@ -1571,7 +2105,8 @@ pub fn main() {
                                 let text = format!("GDB: {server_addr}");
                                 let width =
                                     ui.calc_text_size(&text)[0] + style!(ui, item_spacing)[0];
-                                right_title_limit = ui.content_region_max()[0] - width;
+                                right_title_limit =
+                                    right_title_limit.min(ui.content_region_max()[0]) - width;
                                 ui.set_cursor_pos([right_title_limit, ui.cursor_pos()[1]]);
                                 ui.separator();
                                 ui.text(&text);
@@ -1644,24 +2179,51 @@ pub fn main() {
                 }
             }
 
+            // Draw sound archive browser
+            if let Some(browser) = &mut state.sdat_browser {
+                let mut opened = true;
+                browser.draw(ui, &config.config, state.rom_path.as_deref(), &mut opened);
+                if !opened {
+                    state.sdat_browser = None;
+                }
+            }
+
             // Draw screen
             if let Some(emu) = &mut state.emu {
                 match &emu.renderer_2d {
                     Renderer2dData::Soft => {}
                     Renderer2dData::Wgpu(channels) => {
                         if let Some(color_output_view) = channels.new_color_output_view() {
-                            state.fb_texture.set_view(window, color_output_view);
+                            state.fb_texture.set_view(
+                                color_output_view,
+                                config!(config.config, resolution_scale_shift),
+                            );
                         }
                     }
                 }
             }
 
+            let fb_texture_changed = state.fb_texture.take_changed();
+            state.screen_filter.update(
+                window,
+                state.fb_texture.view(),
+                state.fb_texture.size(),
+                fb_texture_changed,
+                config!(config.config, screen_filter),
+                config!(config.config, screen_color_correction),
+            );
+
             let window_size = window.window().inner_size();
             let screen_integer_scale = config!(config.config, screen_integer_scale);
             let screen_rot = (config!(config.config, screen_rot) as f32).to_radians();
+            let screen_layout = screen_layout::Layout::new(
+                config!(config.config, screen_layout),
+                config!(config.config, screen_swap),
+                config!(config.config, screen_gap) as f32,
+            );
             if config!(config.config, full_window_screen) {
-                let (center, points) = scale_to_fit_rotated(
-                    [SCREEN_WIDTH as f32, (2 * SCREEN_HEIGHT) as f32],
+                let (_, points) = scale_to_fit_rotated(
+                    screen_layout.size(),
                     screen_integer_scale,
                     screen_rot,
                     [
@@ -1669,19 +2231,18 @@ pub fn main() {
                         (window_size.height as f64 / window.scale_factor()) as f32,
                     ],
                 );
-                ui.get_background_draw_list()
-                    .add_image_quad(
-                        state.fb_texture.id(),
-                        points[0],
-                        points[1],
-                        points[2],
-                        points[3],
-                    )
-                    .build();
+                draw_screens(
+                    &ui.get_background_draw_list(),
+                    state.screen_filter.id(),
+                    &screen_layout,
+                    &points,
+                    state.backlights_enabled,
+                );
                 state.screen_focused =
                     !ui.is_window_focused_with_flags(imgui::WindowFocusedFlags::ANY_WINDOW);
-                state.input.set_touchscreen_bounds_from_points(
-                    center,
+                update_touchscreen_bounds(
+                    &mut state.input,
+                    &screen_layout,
                     &points,
                     screen_rot,
                     window.scale_factor(),
@@ -1691,11 +2252,12 @@ pub fn main() {
                 let title_bar_height = style!(ui, frame_padding)[1] * 2.0 + ui.current_font_size();
                 const DEFAULT_SCALE: f32 = 2.0;
                 state.screen_focused = false;
+                let layout_size = screen_layout.size();
                 ui.window("Screen")
                     .size(
                         [
-                            SCREEN_WIDTH as f32 * DEFAULT_SCALE,
-                            (SCREEN_HEIGHT * 2) as f32 * DEFAULT_SCALE + title_bar_height,
+                            layout_size[0] * DEFAULT_SCALE,
+                            layout_size[1] * DEFAULT_SCALE + title_bar_height,
                         ],
                         imgui::Condition::FirstUseEver,
                     )
@@ -1708,8 +2270,8 @@ pub fn main() {
                     )
                     .position_pivot([0.5; 2])
                     .build(|| {
-                        let (center, points) = scale_to_fit_rotated(
-                            [SCREEN_WIDTH as f32, (2 * SCREEN_HEIGHT) as f32],
+                        let (_, points) = scale_to_fit_rotated(
+                            layout_size,
                             screen_integer_scale,
                             screen_rot,
                             ui.content_region_avail(),
@@ -1732,18 +2294,17 @@ pub fn main() {
                         ];
                         let abs_points =
                             points.map(|[x, y]| [x + upper_left[0], y + upper_left[1]]);
-                        ui.get_window_draw_list()
-                            .add_image_quad(
-                                state.fb_texture.id(),
-                                abs_points[0],
-                                abs_points[1],
-                                abs_points[2],
-                                abs_points[3],
-                            )
-                            .build();
+                        draw_screens(
+                            &ui.get_window_draw_list(),
+                            state.screen_filter.id(),
+                            &screen_layout,
+                            &abs_points,
+                            state.backlights_enabled,
+                        );
                         state.screen_focused = ui.is_window_focused();
-                        state.input.set_touchscreen_bounds_from_points(
-                            [center[0] + upper_left[0], center[1] + upper_left[1]],
+                        update_touchscreen_bounds(
+                            &mut state.input,
+                            &screen_layout,
                             &abs_points,
                             screen_rot,
                             window.scale_factor(),
@@ -1781,7 +2342,7 @@ pub fn main() {
                 fs::write(&path.0, &buf).expect("couldn't save imgui configuration");
             }
         },
-        move |_, _, frame, encoder, _| {
+        move |_, (_, state), frame, encoder, _| {
             encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: None,
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
@@ -1794,6 +2355,7 @@ pub fn main() {
                 })],
                 depth_stencil_attachment: None,
             });
+            state.screen_filter.render(encoder);
             window::ControlFlow::Continue
         },
         move |window, (mut config, mut state)| {
@@ -118,7 +118,8 @@ use crate::config::LoggingKind;
 use crate::{
     audio,
     config::{
-        self, saves, ModelConfig, Renderer2dKind, Renderer3dKind, Setting as _, TitleBarMode,
+        self, saves, ModelConfig, Renderer2dKind, Renderer3dKind, ScreenColorCorrection,
+        ScreenFilter, ScreenLayout, Setting as _, TitleBarMode,
     },
     ui::utils::combo_value,
     utils::HomePathBuf,
@@ -130,7 +131,6 @@ use input_map::Editor as InputMapEditor;
 use rfd::FileDialog;
 use setting::Setting;
 use std::borrow::Cow;
-#[cfg(feature = "xq-audio")]
 use std::num::NonZeroU32;
 
 struct SettingsData {
@@ -153,6 +153,9 @@ macro_rules! opt_home_path {
             |config, value| set_config!(config, $id, value),
         )
     };
+    (nonoverridable $id: ident, $filter_name: expr, $extensions: expr) => {
+        opt_home_path!(nonoverridable $id).with_file_filter($filter_name, $extensions)
+    };
 }
 
 #[allow(unused_macros)]
@@ -189,7 +192,6 @@ macro_rules! scalar {
     };
 }
 
-#[cfg(feature = "xq-audio")]
 macro_rules! opt_nonzero_u32_slider {
     (overridable $id: ident, $default: expr, $min: expr, $max: expr, $display_format: expr) => {
         (
@@ -504,6 +506,11 @@ struct UiSettings {
     full_window_screen: setting::Overridable<setting::Bool>,
     screen_integer_scale: setting::NonOverridable<setting::Bool>,
     screen_rot: setting::Overridable<setting::Slider<u16>>,
+    screen_layout: setting::Overridable<setting::Combo<ScreenLayout>>,
+    screen_swap: setting::Overridable<setting::Bool>,
+    screen_gap: setting::Overridable<setting::Slider<u16>>,
+    screen_filter: setting::Overridable<setting::Combo<ScreenFilter>>,
+    screen_color_correction: setting::Overridable<setting::Combo<ScreenColorCorrection>>,
 }
 
 impl UiSettings {
@@ -535,6 +542,68 @@ impl UiSettings {
                 bool
             ),
             screen_rot: overridable!("Screen rotation", screen_rot, slider, 0, 359, "%d°"),
+            screen_layout: overridable!(
+                "Screen layout",
+                screen_layout,
+                combo,
+                &[
+                    ScreenLayout::Vertical,
+                    ScreenLayout::Horizontal,
+                    ScreenLayout::SingleScreen,
+                    ScreenLayout::LargeTop,
+                    ScreenLayout::Hybrid,
+                ],
+                |layout| match layout {
+                    ScreenLayout::Vertical => "Vertical",
+                    ScreenLayout::Horizontal => "Horizontal",
+                    ScreenLayout::SingleScreen => "Single screen",
+                    ScreenLayout::LargeTop => "Large top, small bottom",
+                    ScreenLayout::Hybrid => "Hybrid",
+                }
+                .into()
+            ),
+            screen_swap: overridable!("Swap screens", screen_swap, bool),
+            screen_gap: overridable!("Screen gap", screen_gap, slider, 0, 192, "%d px"),
+            screen_filter: overridable!(
+                "Screen filter",
+                screen_filter,
+                combo,
+                &[
+                    ScreenFilter::Nearest,
+                    ScreenFilter::Bilinear,
+                    ScreenFilter::Scale2x,
+                    ScreenFilter::Scale4x,
+                    ScreenFilter::Xbr2x,
+                    ScreenFilter::Xbr4x,
+                    ScreenFilter::LcdGrid,
+                ],
+                |filter| match filter {
+                    ScreenFilter::Nearest => "Nearest",
+                    ScreenFilter::Bilinear => "Bilinear",
+                    ScreenFilter::Scale2x => "Scale2x",
+                    ScreenFilter::Scale4x => "Scale4x",
+                    ScreenFilter::Xbr2x => "xBR 2x",
+                    ScreenFilter::Xbr4x => "xBR 4x",
+                    ScreenFilter::LcdGrid => "LCD grid",
+                }
+                .into()
+            ),
+            screen_color_correction: overridable!(
+                "Screen color correction",
+                screen_color_correction,
+                combo,
+                &[
+                    ScreenColorCorrection::None,
+                    ScreenColorCorrection::Ds,
+                    ScreenColorCorrection::DsLite,
+                ],
+                |color_correction| match color_correction {
+                    ScreenColorCorrection::None => "None",
+                    ScreenColorCorrection::Ds => "DS",
+                    ScreenColorCorrection::DsLite => "DS Lite",
+                }
+                .into()
+            ),
         }
     }
 }
@@ -542,6 +611,7 @@ impl UiSettings {
 struct AudioSettings {
     volume: setting::Overridable<setting::Slider<f32>>,
     sample_chunk_size: setting::Overridable<setting::Scalar<u16>>,
+    time_stretching: setting::Overridable<setting::Bool>,
     #[cfg(feature = "xq-audio")]
     custom_sample_rate: setting::Overridable<setting::OptNonZeroU32Slider>,
     #[cfg(feature = "xq-audio")]
@@ -549,6 +619,9 @@ struct AudioSettings {
     output_interp_method: setting::Overridable<setting::Combo<audio::InterpMethod>>,
     input_enabled: setting::Overridable<setting::Bool>,
     input_interp_method: setting::Overridable<setting::Combo<audio::InterpMethod>>,
+    input_source: setting::Overridable<setting::Combo<audio::input::Source>>,
+    input_wav_path: setting::NonOverridable<setting::OptHomePath>,
+    input_wav_looping: setting::Overridable<setting::Bool>,
 }
 
 impl AudioSettings {
@@ -561,6 +634,7 @@ impl AudioSettings {
                 scalar,
                 Some(128)
             ),
+            time_stretching: overridable!("Time stretching", audio_time_stretching, bool),
             #[cfg(feature = "xq-audio")]
             custom_sample_rate: overridable!(
                 "Custom sample rate",
@@ -584,11 +658,13 @@ impl AudioSettings {
                 &[
                     AudioChannelInterpMethod::Nearest,
                     AudioChannelInterpMethod::Cubic,
+                    AudioChannelInterpMethod::Sinc,
                 ],
                 |interp_method| {
                     match interp_method {
                         AudioChannelInterpMethod::Nearest => "Nearest",
                         AudioChannelInterpMethod::Cubic => "Cubic",
+                        AudioChannelInterpMethod::Sinc => "Windowed sinc",
                     }
                     .into()
                 }
@@ -597,11 +673,16 @@ impl AudioSettings {
                 "Interpolation method",
                 audio_output_interp_method,
                 combo,
-                &[audio::InterpMethod::Nearest, audio::InterpMethod::Cubic],
+                &[
+                    audio::InterpMethod::Nearest,
+                    audio::InterpMethod::Cubic,
+                    audio::InterpMethod::Sinc,
+                ],
                 |interp_method| {
                     match interp_method {
                         audio::InterpMethod::Nearest => "Nearest",
                         audio::InterpMethod::Cubic => "Cubic",
+                        audio::InterpMethod::Sinc => "Windowed sinc",
                     }
                     .into()
                 }
@@ -611,15 +692,50 @@ impl AudioSettings {
                 "Interpolation method",
                 audio_input_interp_method,
                 combo,
-                &[audio::InterpMethod::Nearest, audio::InterpMethod::Cubic],
+                &[
+                    audio::InterpMethod::Nearest,
+                    audio::InterpMethod::Cubic,
+                    audio::InterpMethod::Sinc,
+                ],
                 |interp_method| {
                     match interp_method {
                         audio::InterpMethod::Nearest => "Nearest",
                         audio::InterpMethod::Cubic => "Cubic",
+                        audio::InterpMethod::Sinc => "Windowed sinc",
+                    }
+                    .into()
+                }
+            ),
+            input_source: overridable!(
+                "Source",
+                audio_input_source,
+                combo,
+                &[
+                    audio::input::Source::Device,
+                    audio::input::Source::Wav,
+                    audio::input::Source::Silence,
+                    audio::input::Source::WhiteNoise,
+                    audio::input::Source::Blow,
+                ],
+                |source| {
+                    match source {
+                        audio::input::Source::Device => "Device",
+                        audio::input::Source::Wav => "WAV file",
+                        audio::input::Source::Silence => "Silence",
+                        audio::input::Source::WhiteNoise => "White noise",
+                        audio::input::Source::Blow => "Blow",
                     }
                     .into()
                 }
             ),
+            input_wav_path: nonoverridable!(
+                "WAV file path",
+                audio_input_wav_path,
+                opt_home_path,
+                "WAV file",
+                &["wav"]
+            ),
+            input_wav_looping: overridable!("Loop WAV file", audio_input_wav_looping, bool),
         }
     }
 }
@@ -671,9 +787,16 @@ struct EmulationSettings {
     model: setting::Overridable<setting::Combo<ModelConfig>>,
     ds_slot_rom_in_memory_max_size: setting::Overridable<setting::Scalar<u32>>,
     rtc_time_offset_seconds: setting::Overridable<setting::Scalar<i64>>,
+    battery_drain_time_mins: setting::Overridable<setting::OptNonZeroU32Slider>,
+    battery_charge_time_mins: setting::Overridable<setting::OptNonZeroU32Slider>,
+    battery_external_power: setting::Overridable<setting::Bool>,
+    touch_pressure: setting::Overridable<setting::Slider<f32>>,
     renderer_2d_kind: setting::Overridable<setting::Combo<Renderer2dKind>>,
     renderer_3d_kind: setting::Overridable<setting::Combo<Renderer3dKind>>,
     resolution_scale_shift: setting::Overridable<setting::StringFormatSlider<u8>>,
+    dump_textures: setting::Overridable<setting::Bool>,
+    load_texture_packs: setting::Overridable<setting::Bool>,
+    texture_pack_dir_path: setting::NonOverridable<setting::HomePath>,
 }
 
 impl EmulationSettings {
@@ -734,6 +857,34 @@ impl EmulationSettings {
                 scalar,
                 Some(1)
             ),
+            battery_drain_time_mins: overridable!(
+                "Battery drain time",
+                battery_drain_time_mins,
+                opt_nonzero_u32_slider,
+                NonZeroU32::new(600).unwrap(),
+                1,
+                1440,
+                "%d min"
+            ),
+            battery_charge_time_mins: overridable!(
+                "Battery charge time",
+                battery_charge_time_mins,
+                opt_nonzero_u32_slider,
+                NonZeroU32::new(180).unwrap(),
+                1,
+                1440,
+                "%d min"
+            ),
+            battery_external_power: overridable!("External power", battery_external_power, bool),
+            touch_pressure: overridable!(
+                "Touch pressure",
+                touch_pressure,
+                slider,
+                0.0,
+                100.0,
+                "%.0f%%",
+                100.0
+            ),
             renderer_2d_kind: overridable!(
                 "2D renderer kind",
                 renderer_2d_kind,
@@ -770,6 +921,13 @@ impl EmulationSettings {
                 3,
                 |value| format!("{}x", 1 << value)
             ),
+            dump_textures: overridable!("Dump textures", dump_textures, bool),
+            load_texture_packs: overridable!("Load texture packs", load_texture_packs, bool),
+            texture_pack_dir_path: nonoverridable!(
+                "Texture pack directory path",
+                texture_pack_dir_path,
+                home_path
+            ),
         }
     }
 }
@@ -1349,6 +1507,11 @@ impl Editor {
                                 // full_window_screen
                                 // screen_integer_scale
                                 // screen_rot
+                                // screen_layout
+                                // screen_swap
+                                // screen_gap
+                                // screen_filter
+                                // screen_color_correction
 
                                 draw!(
                                     "general",
@@ -1358,7 +1521,12 @@ impl Editor {
                                         title_bar_mode,
                                         full_window_screen,
                                         screen_integer_scale,
-                                        screen_rot
+                                        screen_rot,
+                                        screen_layout,
+                                        screen_swap,
+                                        screen_gap,
+                                        screen_filter,
+                                        screen_color_correction
                                     ]
                                 );
                             }
@@ -1366,11 +1534,16 @@ impl Editor {
                             Section::Audio => {
                                 // audio_volume
                                 // audio_sample_chunk_size
+                                // audio_time_stretching
                                 // audio_custom_sample_rate
                                 // audio_channel_interp_method
                                 // audio_interp_method
 
-                                draw!("general", audio, [volume, sample_chunk_size]);
+                                draw!(
+                                    "general",
+                                    audio,
+                                    [volume, sample_chunk_size, time_stretching]
+                                );
 
                                 #[cfg(feature = "xq-audio")]
                                 {
@@ -1392,7 +1565,13 @@ impl Editor {
                                 draw!(
                                     "frontend_interp",
                                     audio,
-                                    [input_enabled, input_interp_method]
+                                    [
+                                        input_enabled,
+                                        input_source,
+                                        input_interp_method,
+                                        input_wav_path,
+                                        input_wav_looping
+                                    ]
                                 );
                             }
 
@@ -1435,9 +1614,16 @@ impl Editor {
                                 // model
                                 // ds_slot_rom_in_memory_max_size
                                 // rtc_time_offset_seconds
+                                // battery_drain_time_mins
+                                // battery_charge_time_mins
+                                // battery_external_power
+                                // touch_pressure
                                 // renderer_2d_kind
                                 // renderer_3d_kind
                                 // resolution_scale_shift
+                                // dump_textures
+                                // load_texture_packs
+                                // texture_pack_dir_path
 
                                 draw!(
                                     "general",
@@ -1452,9 +1638,16 @@ impl Editor {
                                         model,
                                         ds_slot_rom_in_memory_max_size,
                                         rtc_time_offset_seconds,
+                                        battery_drain_time_mins,
+                                        battery_charge_time_mins,
+                                        battery_external_power,
+                                        touch_pressure,
                                         renderer_2d_kind,
                                         renderer_3d_kind,
-                                        resolution_scale_shift
+                                        resolution_scale_shift,
+                                        dump_textures,
+                                        load_texture_packs,
+                                        texture_pack_dir_path
                                     ]
                                 );
                             }
@@ -94,6 +94,8 @@ static ACTIONS: &[(Action, &str)] = &[
     (Action::ToggleFramerateLimit, "Toggle framerate limit"),
     (Action::ToggleSyncToAudio, "Toggle sync to audio"),
     (Action::ToggleFullWindowScreen, "Toggle full-window screen"),
+    (Action::ToggleLid, "Toggle lid"),
+    (Action::SwapScreens, "Swap screens"),
 ];
 
 impl Editor {
@@ -126,6 +126,8 @@ impl RawSetting for HomePath {
 pub struct OptHomePath {
     pub get: fn(&Config) -> Option<&HomePathBuf>,
     pub set: fn(&mut Config, Option<HomePathBuf>),
+    // If set, the browse button picks a file matching the given filter instead of a folder
+    pub file_filter: Option<(&'static str, &'static [&'static str])>,
     buffer: StdString,
 }
 
@@ -137,9 +139,19 @@ impl OptHomePath {
         OptHomePath {
             get,
             set,
+            file_filter: None,
             buffer: StdString::new(),
         }
     }
+
+    pub const fn with_file_filter(
+        mut self,
+        name: &'static str,
+        extensions: &'static [&'static str],
+    ) -> Self {
+        self.file_filter = Some((name, extensions));
+        self
+    }
 }
 
 impl RawSetting for OptHomePath {
@@ -189,7 +201,12 @@ impl RawSetting for OptHomePath {
             ui.same_line();
 
             if ui.button("\u{f07c}") {
-                if let Some(path) = FileDialog::new().pick_folder() {
+                let path = if let Some((name, extensions)) = self.file_filter {
+                    FileDialog::new().add_filter(name, extensions).pick_file()
+                } else {
+                    FileDialog::new().pick_folder()
+                };
+                if let Some(path) = path {
                     new_value = Some(Some(HomePathBuf(path)));
                 }
             }
@@ -0,0 +1,392 @@
+use super::window::Window;
+use crate::config::{ScreenColorCorrection, ScreenFilter};
+use core::slice;
+use dust_core::gpu::SCREEN_WIDTH;
+use wgpu::util::DeviceExt;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PassKind {
+    Color,
+    Scale2x,
+    Xbr2x,
+    LcdGrid,
+}
+
+struct Pass {
+    kind: PassKind,
+    bg: wgpu::BindGroup,
+    output_texture: wgpu::Texture,
+    output_view: wgpu::TextureView,
+    output_size: [u32; 2],
+}
+
+// Color correction matrices (applied in linear space) and gammas are approximations of the
+// original DS and DS Lite panels' response
+fn color_params(color_correction: ScreenColorCorrection) -> [f32; 16] {
+    match color_correction {
+        ScreenColorCorrection::None => [
+            1.0, 0.0, 0.0, 0.0, //
+            0.0, 1.0, 0.0, 0.0, //
+            0.0, 0.0, 1.0, 0.0, //
+            1.0, 1.0, 1.0, 0.0,
+        ],
+        ScreenColorCorrection::Ds => [
+            0.705, 0.235, -0.075, 0.0, //
+            0.09, 0.585, 0.155, 0.0, //
+            0.1075, 0.1725, 0.72, 0.0, //
+            2.2, 2.2, 0.91, 0.0,
+        ],
+        ScreenColorCorrection::DsLite => [
+            0.86, 0.12, 0.02, 0.0, //
+            0.04, 0.9, 0.06, 0.0, //
+            0.02, 0.08, 0.9, 0.0, //
+            2.2, 2.2, 1.0, 0.0,
+        ],
+    }
+}
+
+fn pass_kinds(filter: ScreenFilter) -> &'static [PassKind] {
+    match filter {
+        ScreenFilter::Nearest | ScreenFilter::Bilinear => &[PassKind::Color],
+        ScreenFilter::Scale2x => &[PassKind::Color, PassKind::Scale2x],
+        ScreenFilter::Scale4x => &[PassKind::Color, PassKind::Scale2x, PassKind::Scale2x],
+        ScreenFilter::Xbr2x => &[PassKind::Color, PassKind::Xbr2x],
+        ScreenFilter::Xbr4x => &[PassKind::Color, PassKind::Xbr2x, PassKind::Xbr2x],
+        ScreenFilter::LcdGrid => &[PassKind::Color, PassKind::LcdGrid],
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    label: &str,
+    src: &str,
+) -> wgpu::RenderPipeline {
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: wgpu::ShaderSource::Wgsl(src.into()),
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+
+        vertex: wgpu::VertexState {
+            module: &shader_module,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleStrip,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+
+        depth_stencil: None,
+
+        multisample: wgpu::MultisampleState::default(),
+
+        fragment: Some(wgpu::FragmentState {
+            module: &shader_module,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+
+        multiview: None,
+    })
+}
+
+fn create_pass(
+    device: &wgpu::Device,
+    bg_layout: &wgpu::BindGroupLayout,
+    kind: PassKind,
+    color_correction: ScreenColorCorrection,
+    input: &wgpu::TextureView,
+    input_size: [u32; 2],
+) -> Pass {
+    let (output_size, params) = match kind {
+        PassKind::Color => (input_size, color_params(color_correction).to_vec()),
+        PassKind::Scale2x | PassKind::Xbr2x => (input_size.map(|v| v << 1), vec![0.0; 4]),
+        PassKind::LcdGrid => {
+            // Make sure every DS pixel covers at least 4x4 output pixels, so that the grid lines
+            // don't take up most of the screen
+            let scale = (4 * SCREEN_WIDTH as u32 / input_size[0]).max(1);
+            let cell_size = input_size[0] * scale / SCREEN_WIDTH as u32;
+            (
+                input_size.map(|v| v * scale),
+                vec![scale as f32, cell_size as f32, 0.75, 0.0],
+            )
+        }
+    };
+
+    let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Screen filter params"),
+        contents: unsafe { slice::from_raw_parts(params.as_ptr() as *const u8, params.len() * 4) },
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Screen filter"),
+        layout: bg_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(input),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: params_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    let output_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Screen filter output"),
+        size: wgpu::Extent3d {
+            width: output_size[0],
+            height: output_size[1],
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+    let output_view = output_texture.create_view(&Default::default());
+
+    Pass {
+        kind,
+        bg,
+        output_texture,
+        output_view,
+        output_size,
+    }
+}
+
+fn create_passes(
+    device: &wgpu::Device,
+    bg_layout: &wgpu::BindGroupLayout,
+    filter: ScreenFilter,
+    color_correction: ScreenColorCorrection,
+    input: &wgpu::TextureView,
+    input_size: [u32; 2],
+) -> Vec<Pass> {
+    let max_size = device.limits().max_texture_dimension_2d;
+    let mut passes: Vec<Pass> = Vec::new();
+    for &kind in pass_kinds(filter) {
+        let (input, input_size) = match passes.last() {
+            Some(prev) => (&prev.output_view, prev.output_size),
+            None => (input, input_size),
+        };
+        // Upscaling already high-resolution output could exceed the maximum texture size
+        if matches!(kind, PassKind::Scale2x | PassKind::Xbr2x) && input_size[1] << 1 > max_size {
+            continue;
+        }
+        let pass = create_pass(device, bg_layout, kind, color_correction, input, input_size);
+        passes.push(pass);
+    }
+    passes
+}
+
+fn add_output_texture(window: &Window, passes: &[Pass], filter: ScreenFilter) -> imgui::TextureId {
+    let filter_mode = if filter == ScreenFilter::Nearest {
+        wgpu::FilterMode::Nearest
+    } else {
+        wgpu::FilterMode::Linear
+    };
+    window.imgui.gfx.create_and_add_texture_view(
+        Some("Screen".into()),
+        passes
+            .last()
+            .unwrap()
+            .output_texture
+            .create_view(&Default::default()),
+        imgui_wgpu::SamplerDescriptor {
+            mag_filter: filter_mode,
+            min_filter: filter_mode,
+            ..Default::default()
+        },
+    )
+}
+
+pub struct Chain {
+    bg_layout: wgpu::BindGroupLayout,
+    color_pipeline: wgpu::RenderPipeline,
+    scale2x_pipeline: wgpu::RenderPipeline,
+    xbr2x_pipeline: wgpu::RenderPipeline,
+    lcd_grid_pipeline: wgpu::RenderPipeline,
+
+    filter: ScreenFilter,
+    color_correction: ScreenColorCorrection,
+    passes: Vec<Pass>,
+    id: imgui::TextureId,
+}
+
+impl Chain {
+    pub fn new(
+        window: &Window,
+        input: &wgpu::TextureView,
+        input_size: [u32; 2],
+        filter: ScreenFilter,
+        color_correction: ScreenColorCorrection,
+    ) -> Self {
+        let device = window.gfx().device();
+
+        let bg_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Screen filter"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Screen filter"),
+            bind_group_layouts: &[&bg_layout],
+            push_constant_ranges: &[],
+        });
+
+        let color_pipeline = create_pipeline(
+            device,
+            &pipeline_layout,
+            "Screen filter color correction",
+            concat!(
+                include_str!("screen_filter/vert.wgsl"),
+                include_str!("screen_filter/color.wgsl"),
+            ),
+        );
+        let scale2x_pipeline = create_pipeline(
+            device,
+            &pipeline_layout,
+            "Screen filter Scale2x",
+            concat!(
+                include_str!("screen_filter/vert.wgsl"),
+                include_str!("screen_filter/scale2x.wgsl"),
+            ),
+        );
+        let xbr2x_pipeline = create_pipeline(
+            device,
+            &pipeline_layout,
+            "Screen filter xBR 2x",
+            concat!(
+                include_str!("screen_filter/vert.wgsl"),
+                include_str!("screen_filter/xbr2x.wgsl"),
+            ),
+        );
+        let lcd_grid_pipeline = create_pipeline(
+            device,
+            &pipeline_layout,
+            "Screen filter LCD grid",
+            concat!(
+                include_str!("screen_filter/vert.wgsl"),
+                include_str!("screen_filter/lcd_grid.wgsl"),
+            ),
+        );
+
+        let passes = create_passes(
+            device,
+            &bg_layout,
+            filter,
+            color_correction,
+            input,
+            input_size,
+        );
+        let id = add_output_texture(window, &passes, filter);
+
+        Chain {
+            bg_layout,
+            color_pipeline,
+            scale2x_pipeline,
+            xbr2x_pipeline,
+            lcd_grid_pipeline,
+
+            filter,
+            color_correction,
+            passes,
+            id,
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        window: &Window,
+        input: &wgpu::TextureView,
+        input_size: [u32; 2],
+        input_changed: bool,
+        filter: ScreenFilter,
+        color_correction: ScreenColorCorrection,
+    ) {
+        if !input_changed && filter == self.filter && color_correction == self.color_correction {
+            return;
+        }
+        self.filter = filter;
+        self.color_correction = color_correction;
+        self.passes = create_passes(
+            window.gfx().device(),
+            &self.bg_layout,
+            filter,
+            color_correction,
+            input,
+            input_size,
+        );
+        window.imgui.gfx.remove_texture(self.id);
+        self.id = add_output_texture(window, &self.passes, filter);
+    }
+
+    pub fn id(&self) -> imgui::TextureId {
+        self.id
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder) {
+        for pass in &self.passes {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Screen filter"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &pass.output_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(match pass.kind {
+                PassKind::Color => &self.color_pipeline,
+                PassKind::Scale2x => &self.scale2x_pipeline,
+                PassKind::Xbr2x => &self.xbr2x_pipeline,
+                PassKind::LcdGrid => &self.lcd_grid_pipeline,
+            });
+            render_pass.set_bind_group(0, &pass.bg, &[]);
+            render_pass.draw(0..4, 0..1);
+        }
+    }
+}
@@ -0,0 +1,25 @@
+
+struct ColorParams {
+    r: vec4<f32>,
+    g: vec4<f32>,
+    b: vec4<f32>,
+    // (input gamma, output gamma, luminance, unused)
+    gamma_lum: vec4<f32>,
+}
+
+@group(0) @binding(0) var t_input: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> params: ColorParams;
+
+@fragment
+fn fs_main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+    let color = pow(
+        textureLoad(t_input, vec2<i32>(pos.xy), 0).rgb,
+        vec3<f32>(params.gamma_lum.x),
+    ) * params.gamma_lum.z;
+    let corrected = clamp(
+        vec3<f32>(dot(params.r.rgb, color), dot(params.g.rgb, color), dot(params.b.rgb, color)),
+        vec3<f32>(0.0),
+        vec3<f32>(1.0),
+    );
+    return vec4<f32>(pow(corrected, vec3<f32>(1.0 / params.gamma_lum.y)), 1.0);
+}
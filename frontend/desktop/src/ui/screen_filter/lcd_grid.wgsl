@@ -0,0 +1,15 @@
+
+@group(0) @binding(0) var t_input: texture_2d<f32>;
+// (input to output scale, output pixels per DS pixel, grid line brightness, unused)
+@group(0) @binding(1) var<uniform> params: vec4<f32>;
+
+@fragment
+fn fs_main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+    let out_coords = vec2<i32>(pos.xy);
+    let color = textureLoad(t_input, out_coords / i32(params.x), 0).rgb;
+    let cell_size = i32(params.y);
+    let sub = out_coords % cell_size;
+    let brightness = select(1.0, params.z, sub.x == cell_size - 1)
+        * select(1.0, params.z, sub.y == cell_size - 1);
+    return vec4<f32>(color * brightness, 1.0);
+}
@@ -0,0 +1,45 @@
+
+@group(0) @binding(0) var t_input: texture_2d<f32>;
+
+fn load_clamped(coords: vec2<i32>, size: vec2<i32>, min_y: i32, max_y: i32) -> vec3<f32> {
+    return textureLoad(
+        t_input,
+        vec2<i32>(clamp(coords.x, 0, size.x - 1), clamp(coords.y, min_y, max_y)),
+        0,
+    ).rgb;
+}
+
+@fragment
+fn fs_main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(t_input));
+    let out_coords = vec2<i32>(pos.xy);
+    let coords = out_coords / 2;
+    let sub = out_coords % 2;
+
+    // Both screens are stacked in the same texture, neighbors should only be taken from the
+    // current one
+    let screen_height = size.y / 2;
+    let min_y = (coords.y / screen_height) * screen_height;
+    let max_y = min_y + screen_height - 1;
+
+    let e = load_clamped(coords, size, min_y, max_y);
+    let b = load_clamped(coords + vec2<i32>(0, -1), size, min_y, max_y);
+    let d = load_clamped(coords + vec2<i32>(-1, 0), size, min_y, max_y);
+    let f = load_clamped(coords + vec2<i32>(1, 0), size, min_y, max_y);
+    let h = load_clamped(coords + vec2<i32>(0, 1), size, min_y, max_y);
+
+    // EPX/Scale2x: use the neighbors on the side of the current output pixel, and the ones
+    // opposite to them
+    let h_neighbor = select(d, f, sub.x == 1);
+    let v_neighbor = select(b, h, sub.y == 1);
+    let h_opposite = select(f, d, sub.x == 1);
+    let v_opposite = select(h, b, sub.y == 1);
+
+    var color = e;
+    if all(h_neighbor == v_neighbor)
+        && any(v_neighbor != h_opposite)
+        && any(h_neighbor != v_opposite) {
+        color = h_neighbor;
+    }
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,11 @@
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    var vert_positions: array<vec2<f32>, 4> = array<vec2<f32>, 4>(
+        vec2(-1.0, 1.0),
+        vec2(1.0, 1.0),
+        vec2(-1.0, -1.0),
+        vec2(1.0, -1.0),
+    );
+
+    return vec4<f32>((*(&vert_positions))[vertex_index], 0.0, 1.0);
+}
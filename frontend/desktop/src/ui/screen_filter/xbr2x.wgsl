@@ -0,0 +1,70 @@
+
+@group(0) @binding(0) var t_input: texture_2d<f32>;
+
+fn load_clamped(coords: vec2<i32>, size: vec2<i32>, min_y: i32, max_y: i32) -> vec3<f32> {
+    return textureLoad(
+        t_input,
+        vec2<i32>(clamp(coords.x, 0, size.x - 1), clamp(coords.y, min_y, max_y)),
+        0,
+    ).rgb;
+}
+
+// Weighted YUV distance, as used by the reference xBR implementation
+fn dist(a: vec3<f32>, b: vec3<f32>) -> f32 {
+    let diff = a - b;
+    let yuv = vec3<f32>(
+        dot(diff, vec3<f32>(0.299, 0.587, 0.114)),
+        dot(diff, vec3<f32>(-0.169, -0.331, 0.499)),
+        dot(diff, vec3<f32>(0.499, -0.418, -0.0813)),
+    );
+    return dot(abs(yuv), vec3<f32>(48.0, 7.0, 6.0));
+}
+
+@fragment
+fn fs_main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(t_input));
+    let out_coords = vec2<i32>(pos.xy);
+    let coords = out_coords / 2;
+    let sub = out_coords % 2;
+
+    // Both screens are stacked in the same texture, neighbors should only be taken from the
+    // current one
+    let screen_height = size.y / 2;
+    let min_y = (coords.y / screen_height) * screen_height;
+    let max_y = min_y + screen_height - 1;
+
+    // The neighborhood is mirrored so that the corner the current output pixel is in is always
+    // the bottom right one:
+    //      A1 B1 C1
+    //   A0 A  B  C  C4
+    //   D0 D  E  F  F4
+    //   G0 G  H  I  I4
+    //      G5 H5 I5
+    // (only the pixels involved in that corner's edge detection are loaded)
+    let dx = vec2<i32>(sub.x * 2 - 1, 0);
+    let dy = vec2<i32>(0, sub.y * 2 - 1);
+    let e = load_clamped(coords, size, min_y, max_y);
+    let b = load_clamped(coords - dy, size, min_y, max_y);
+    let c = load_clamped(coords + dx - dy, size, min_y, max_y);
+    let d = load_clamped(coords - dx, size, min_y, max_y);
+    let f = load_clamped(coords + dx, size, min_y, max_y);
+    let g = load_clamped(coords - dx + dy, size, min_y, max_y);
+    let h = load_clamped(coords + dy, size, min_y, max_y);
+    let i = load_clamped(coords + dx + dy, size, min_y, max_y);
+    let f4 = load_clamped(coords + 2 * dx, size, min_y, max_y);
+    let i4 = load_clamped(coords + 2 * dx + dy, size, min_y, max_y);
+    let h5 = load_clamped(coords + 2 * dy, size, min_y, max_y);
+    let i5 = load_clamped(coords + dx + 2 * dy, size, min_y, max_y);
+
+    // xBR level 1: an edge runs along the F-H diagonal if pixels along it differ less than the
+    // ones across it; the corner is then blended towards the closest of F and H
+    let edge_weight = dist(e, c) + dist(e, g) + dist(i, f4) + dist(i, h5) + 4.0 * dist(h, f);
+    let cross_weight = dist(h, d) + dist(h, i5) + dist(f, i4) + dist(f, b) + 4.0 * dist(e, i);
+
+    var color = e;
+    if edge_weight < cross_weight && any(e != f) && any(e != h) {
+        let new_color = select(h, f, dist(e, f) <= dist(e, h));
+        color = mix(e, new_color, 0.5);
+    }
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,136 @@
+use crate::config::ScreenLayout;
+use dust_core::gpu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Screen {
+    Top,
+    Bottom,
+}
+
+impl Screen {
+    // UVs of the screen's corners inside the framebuffer texture, which has both screens stacked
+    // vertically
+    pub fn uvs(self) -> [[f32; 2]; 4] {
+        let (min_v, max_v) = match self {
+            Screen::Top => (0.0, 0.5),
+            Screen::Bottom => (0.5, 1.0),
+        };
+        [[0.0, min_v], [1.0, min_v], [1.0, max_v], [0.0, max_v]]
+    }
+}
+
+struct Placement {
+    screen: Screen,
+    pos: [f32; 2],
+    scale: f32,
+}
+
+pub struct Layout {
+    size: [f32; 2],
+    placements: Vec<Placement>,
+}
+
+impl Layout {
+    pub fn new(kind: ScreenLayout, swap: bool, gap: f32) -> Self {
+        const WIDTH: f32 = SCREEN_WIDTH as f32;
+        const HEIGHT: f32 = SCREEN_HEIGHT as f32;
+
+        let (primary, secondary) = if swap {
+            (Screen::Bottom, Screen::Top)
+        } else {
+            (Screen::Top, Screen::Bottom)
+        };
+        let placement = |screen, pos, scale| Placement { screen, pos, scale };
+
+        let (size, placements) = match kind {
+            ScreenLayout::Vertical => (
+                [WIDTH, HEIGHT * 2.0 + gap],
+                vec![
+                    placement(primary, [0.0, 0.0], 1.0),
+                    placement(secondary, [0.0, HEIGHT + gap], 1.0),
+                ],
+            ),
+            ScreenLayout::Horizontal => (
+                [WIDTH * 2.0 + gap, HEIGHT],
+                vec![
+                    placement(primary, [0.0, 0.0], 1.0),
+                    placement(secondary, [WIDTH + gap, 0.0], 1.0),
+                ],
+            ),
+            ScreenLayout::SingleScreen => {
+                ([WIDTH, HEIGHT], vec![placement(primary, [0.0, 0.0], 1.0)])
+            }
+            ScreenLayout::LargeTop => (
+                [WIDTH * 3.0 + gap, HEIGHT * 2.0],
+                vec![
+                    placement(primary, [0.0, 0.0], 2.0),
+                    placement(secondary, [WIDTH * 2.0 + gap, HEIGHT], 1.0),
+                ],
+            ),
+            // The primary screen is shown enlarged, next to both screens at their native size
+            ScreenLayout::Hybrid => (
+                [WIDTH * 3.0 + gap, HEIGHT * 2.0 + gap],
+                vec![
+                    placement(primary, [0.0, gap * 0.5], 2.0),
+                    placement(Screen::Top, [WIDTH * 2.0 + gap, 0.0], 1.0),
+                    placement(Screen::Bottom, [WIDTH * 2.0 + gap, HEIGHT + gap], 1.0),
+                ],
+            ),
+        };
+
+        Layout { size, placements }
+    }
+
+    pub fn size(&self) -> [f32; 2] {
+        self.size
+    }
+
+    // Maps a point in the unrotated layout to the quad the whole layout is being drawn to
+    fn map_point(&self, layout_points: &[[f32; 2]; 4], [x, y]: [f32; 2]) -> [f32; 2] {
+        let (x, y) = (x / self.size[0], y / self.size[1]);
+        [0, 1].map(|i| {
+            layout_points[0][i]
+                + (layout_points[1][i] - layout_points[0][i]) * x
+                + (layout_points[3][i] - layout_points[0][i]) * y
+        })
+    }
+
+    fn placement_points(
+        &self,
+        layout_points: &[[f32; 2]; 4],
+        placement: &Placement,
+    ) -> [[f32; 2]; 4] {
+        let [x, y] = placement.pos;
+        let width = SCREEN_WIDTH as f32 * placement.scale;
+        let height = SCREEN_HEIGHT as f32 * placement.scale;
+        [
+            [x, y],
+            [x + width, y],
+            [x + width, y + height],
+            [x, y + height],
+        ]
+        .map(|point| self.map_point(layout_points, point))
+    }
+
+    pub fn screen_quads<'a>(
+        &'a self,
+        layout_points: &'a [[f32; 2]; 4],
+    ) -> impl Iterator<Item = (Screen, [[f32; 2]; 4])> + 'a {
+        self.placements.iter().map(|placement| {
+            (
+                placement.screen,
+                self.placement_points(layout_points, placement),
+            )
+        })
+    }
+
+    // Returns the quad touch input should be mapped to (the largest instance of the bottom
+    // screen), if the bottom screen is visible at all
+    pub fn touchscreen_quad(&self, layout_points: &[[f32; 2]; 4]) -> Option<[[f32; 2]; 4]> {
+        self.placements
+            .iter()
+            .filter(|placement| placement.screen == Screen::Bottom)
+            .max_by(|a, b| a.scale.total_cmp(&b.scale))
+            .map(|placement| self.placement_points(layout_points, placement))
+    }
+}
@@ -0,0 +1,374 @@
+use crate::{
+    audio::{self, recorder},
+    config::Config,
+    DsSlotRom,
+};
+use dust_core::{
+    audio::{
+        sdat::{player::Player, Sdat, Sequence},
+        Backend as _,
+    },
+    ds_slot::rom::{fs, header::Header, Contents},
+    utils::Bytes,
+};
+use imgui::{TableFlags, Ui};
+use rfd::FileDialog;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+const CHUNK_LEN: usize = 0x200;
+
+// Sequences that loop forever get exported with two full loops, then faded out by releasing all
+// notes; the total length is capped in case a sequence never loops back
+const EXPORT_LOOPS: u32 = 2;
+const EXPORT_MAX_LEN: usize = 10 * 60 * audio::output::DEFAULT_INPUT_SAMPLE_RATE as usize;
+const EXPORT_MAX_RELEASE_LEN: usize = 10 * audio::output::DEFAULT_INPUT_SAMPLE_RATE as usize;
+
+struct Archive {
+    path: String,
+    sdat: Sdat,
+}
+
+struct Playback {
+    archive_i: usize,
+    sequence_i: usize,
+    stop: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+    // Dropped after the thread is done, so that it never waits on a stream that isn't running
+    _audio_channel: audio::output::Channel,
+}
+
+impl Drop for Playback {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct Export {
+    path: PathBuf,
+    thread: thread::JoinHandle<io::Result<()>>,
+}
+
+fn sequence_name(sdat: &Sdat, i: usize) -> String {
+    sdat.sequences[i]
+        .as_ref()
+        .and_then(|info| info.name.clone())
+        .unwrap_or_else(|| format!("SSEQ_{i}"))
+}
+
+// Archives that fail to parse are skipped, and their errors returned alongside the valid ones
+fn read_archives(rom_path: &Path) -> Result<(Vec<Archive>, Vec<String>), String> {
+    let mut rom = DsSlotRom::new(rom_path, 0).map_err(|err| err.to_string())?;
+    let mut header_bytes = Bytes::new([0; 0x170]);
+    rom.read_header(&mut header_bytes);
+    let header = Header::new(header_bytes.as_byte_slice()).ok_or("invalid ROM header")?;
+    let files = fs::read_files(&header, &mut rom).map_err(|err| err.to_string())?;
+
+    let mut archives = Vec::new();
+    let mut errors = Vec::new();
+    for file in files {
+        if file.len() < 0x40 {
+            continue;
+        }
+        let mut magic = Bytes::new([0; 4]);
+        rom.read_slice(file.start as usize, magic.as_byte_mut_slice());
+        if &magic[..] != b"SDAT" {
+            continue;
+        }
+        match Sdat::new(file.read(&mut rom).into_boxed_slice()) {
+            Ok(sdat) => archives.push(Archive {
+                path: file.path,
+                sdat,
+            }),
+            Err(err) => errors.push(format!("{}: {err}", file.path)),
+        }
+    }
+    Ok((archives, errors))
+}
+
+fn export_sequence(sequence: Sequence, path: &Path) -> io::Result<()> {
+    let mut recorder = recorder::Recorder::new(
+        path,
+        recorder::Format::from_path(path),
+        audio::output::DEFAULT_INPUT_SAMPLE_RATE,
+        2,
+    )?;
+    let mut player = Player::new(sequence);
+    let mut samples = Vec::with_capacity(CHUNK_LEN);
+    let mut len = 0;
+    let mut release_len = None;
+    while !player.is_finished() && release_len.map_or(true, |len| len < EXPORT_MAX_RELEASE_LEN) {
+        if release_len.is_none() && (player.loop_count() >= EXPORT_LOOPS || len >= EXPORT_MAX_LEN) {
+            player.release();
+            release_len = Some(0);
+        }
+        player.render(&mut samples, CHUNK_LEN);
+        recorder.write_samples(&samples)?;
+        samples.clear();
+        len += CHUNK_LEN;
+        if let Some(release_len) = &mut release_len {
+            *release_len += CHUNK_LEN;
+        }
+    }
+    recorder.finish()
+}
+
+pub(super) struct Browser {
+    rom_path: Option<PathBuf>,
+    archives: Vec<Archive>,
+    error: Option<String>,
+    playback: Option<Playback>,
+    exports: Vec<Export>,
+}
+
+impl Browser {
+    pub fn new(rom_path: Option<PathBuf>) -> Self {
+        let mut browser = Browser {
+            rom_path: None,
+            archives: Vec::new(),
+            error: None,
+            playback: None,
+            exports: Vec::new(),
+        };
+        if let Some(rom_path) = rom_path {
+            browser.open_rom(rom_path);
+        }
+        browser
+    }
+
+    fn open_rom(&mut self, rom_path: PathBuf) {
+        self.playback = None;
+        match read_archives(&rom_path) {
+            Ok((archives, errors)) => {
+                self.error = if !errors.is_empty() {
+                    Some(format!(
+                        "Couldn't read some sound archives:\n{}",
+                        errors.join("\n")
+                    ))
+                } else {
+                    archives
+                        .is_empty()
+                        .then(|| "No sound archives found".to_string())
+                };
+                self.archives = archives;
+            }
+            Err(err) => {
+                self.archives.clear();
+                self.error = Some(err);
+            }
+        }
+        self.rom_path = Some(rom_path);
+    }
+
+    fn play(&mut self, archive_i: usize, sequence_i: usize, config: &Config) {
+        self.playback = None;
+        let sequence = match self.archives[archive_i].sdat.load_sequence(sequence_i) {
+            Ok(sequence) => sequence,
+            Err(err) => {
+                self.error = Some(err.to_string());
+                return;
+            }
+        };
+        let Some(audio_channel) = audio::output::Channel::new(
+            config!(config, audio_output_interp_method),
+            config!(config, audio_volume),
+            #[cfg(feature = "xq-audio")]
+            None,
+        ) else {
+            self.error = Some("Couldn't create audio output stream".to_string());
+            return;
+        };
+        let tx_data = audio_channel.tx_data.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = thread::Builder::new()
+            .name("SDAT player".to_string())
+            .spawn({
+                let stop = Arc::clone(&stop);
+                move || {
+                    let mut sender = audio::output::Sender::new(&tx_data, true, None);
+                    let mut player = Player::new(sequence);
+                    let mut samples = Vec::with_capacity(CHUNK_LEN);
+                    while !stop.load(Ordering::Relaxed) && !player.is_finished() {
+                        player.render(&mut samples, CHUNK_LEN);
+                        sender.handle_sample_chunk(&mut samples);
+                    }
+                }
+            })
+            .expect("couldn't spawn SDAT player thread");
+        self.playback = Some(Playback {
+            archive_i,
+            sequence_i,
+            stop,
+            thread: Some(thread),
+            _audio_channel: audio_channel,
+        });
+    }
+
+    fn export(&mut self, archive_i: usize, sequence_i: usize) {
+        let archive = &self.archives[archive_i];
+        let Some(path) = FileDialog::new()
+            .add_filter("WAV file", &["wav"])
+            .add_filter("FLAC file", &["flac"])
+            .set_file_name(&format!("{}.wav", sequence_name(&archive.sdat, sequence_i)))
+            .save_file()
+        else {
+            return;
+        };
+        let sequence = match archive.sdat.load_sequence(sequence_i) {
+            Ok(sequence) => sequence,
+            Err(err) => {
+                self.error = Some(err.to_string());
+                return;
+            }
+        };
+        let thread = thread::Builder::new()
+            .name("SDAT export".to_string())
+            .spawn({
+                let path = path.clone();
+                move || export_sequence(sequence, &path)
+            })
+            .expect("couldn't spawn SDAT export thread");
+        self.exports.push(Export { path, thread });
+    }
+
+    fn check_exports(&mut self) {
+        let mut i = 0;
+        while i < self.exports.len() {
+            if !self.exports[i].thread.is_finished() {
+                i += 1;
+                continue;
+            }
+            let export = self.exports.swap_remove(i);
+            match export.thread.join() {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => {
+                    error!(
+                        "Sequence export error",
+                        "Couldn't export sequence to {}: {err}",
+                        export.path.display()
+                    );
+                }
+                Err(_) => {
+                    error!(
+                        "Sequence export error",
+                        "Sequence export to {} crashed",
+                        export.path.display()
+                    );
+                }
+            }
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        ui: &Ui,
+        config: &Config,
+        game_rom_path: Option<&Path>,
+        opened: &mut bool,
+    ) {
+        self.check_exports();
+
+        if self.playback.as_ref().map_or(false, |playback| {
+            playback.thread.as_ref().unwrap().is_finished()
+        }) {
+            self.playback = None;
+        }
+
+        ui.window("Sound archives").opened(opened).build(|| {
+            if ui.button("Open ROM...") {
+                if let Some(path) = FileDialog::new()
+                    .add_filter("NDS ROM file", &["nds", "bin"])
+                    .pick_file()
+                {
+                    self.open_rom(path);
+                }
+            }
+            if let Some(game_rom_path) = game_rom_path {
+                ui.same_line();
+                if ui.button("Open current game") {
+                    self.open_rom(game_rom_path.to_path_buf());
+                }
+            }
+            if let Some(rom_path) = &self.rom_path {
+                ui.text(format!("{}", rom_path.display()));
+            }
+            if !self.exports.is_empty() {
+                ui.text_disabled(format!("Exporting {} sequence(s)...", self.exports.len()));
+            }
+            if let Some(error) = &self.error {
+                ui.text_wrapped(error);
+            }
+            ui.separator();
+
+            let mut play = None;
+            let mut stop = false;
+            let mut export = None;
+
+            for (archive_i, archive) in self.archives.iter().enumerate() {
+                let _id = ui.push_id_usize(archive_i);
+                let Some(_node) = ui.tree_node(archive.path.as_str()) else {
+                    continue;
+                };
+                let Some(_table) = ui.begin_table_with_flags(
+                    "sequences",
+                    4,
+                    TableFlags::BORDERS_INNER_V | TableFlags::ROW_BG,
+                ) else {
+                    continue;
+                };
+                for (sequence_i, info) in archive.sdat.sequences.iter().enumerate() {
+                    let Some(info) = info else {
+                        continue;
+                    };
+                    let _id = ui.push_id_usize(sequence_i);
+                    ui.table_next_row();
+                    ui.table_next_column();
+                    ui.text(format!("{sequence_i}"));
+                    ui.table_next_column();
+                    ui.text(sequence_name(&archive.sdat, sequence_i));
+                    ui.table_next_column();
+                    ui.text(format!("Bank {}", info.bank));
+                    ui.table_next_column();
+                    let playing = self.playback.as_ref().map_or(false, |playback| {
+                        playback.archive_i == archive_i && playback.sequence_i == sequence_i
+                    });
+                    if playing {
+                        if ui.small_button("\u{f04d}") {
+                            stop = true;
+                        }
+                    } else if ui.small_button("\u{f04b}") {
+                        play = Some((archive_i, sequence_i));
+                    }
+                    ui.same_line();
+                    if ui.small_button("Export...") {
+                        export = Some((archive_i, sequence_i));
+                    }
+                }
+            }
+
+            if stop {
+                self.playback = None;
+            }
+            if let Some((archive_i, sequence_i)) = play {
+                self.play(archive_i, sequence_i, config);
+            }
+            if let Some((archive_i, sequence_i)) = export {
+                self.export(archive_i, sequence_i);
+            }
+        });
+
+        if !*opened {
+            self.playback = None;
+        }
+    }
+}
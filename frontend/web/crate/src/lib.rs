@@ -161,10 +161,28 @@ impl EmuState {
         }
     }
 
+    pub fn set_resolution_scale_shift(&mut self, value: u8) {
+        renderer_3d::set_resolution_scale_shift(value);
+    }
+
+    // The resolution scale shift of the framebuffer last returned by `run_frame`
+    pub fn frame_resolution_scale_shift(&self) -> u8 {
+        self.emu
+            .as_ref()
+            .unwrap()
+            .gpu
+            .renderer_2d()
+            .hi_res_framebuffer()
+            .map_or(0, |(resolution_scale_shift, _)| resolution_scale_shift)
+    }
+
     pub fn run_frame(&mut self) -> Uint32Array {
         // TODO: Handle an eventual shutdown
         let emu = self.emu.as_mut().unwrap();
         emu.run(true);
+        if let Some((_, hi_res_fb)) = emu.gpu.renderer_2d().hi_res_framebuffer() {
+            return Uint32Array::from(hi_res_fb);
+        }
         Uint32Array::from(unsafe {
             core::slice::from_raw_parts(
                 emu.gpu.renderer_2d().framebuffer().as_ptr() as *const u32,
@@ -5,14 +5,14 @@ use dust_core::{
         engine_3d::{
             Polygon, RendererTx, RenderingState as CoreRenderingState, ScreenVertex, SoftRendererRx,
         },
-        Scanline, SCREEN_HEIGHT,
+        Scanline, SCREEN_HEIGHT, SCREEN_WIDTH,
     },
     utils::Bytes,
 };
-use dust_soft_3d::{Renderer, RenderingData};
+use dust_soft_3d::{Renderer, RenderingData, MAX_RESOLUTION_SCALE_SHIFT};
 use std::{
     cell::UnsafeCell,
-    hint,
+    hint, slice,
     sync::{
         atomic::{AtomicBool, AtomicU8, Ordering},
         OnceLock,
@@ -20,6 +20,9 @@ use std::{
 };
 use wasm_bindgen::prelude::*;
 
+const HI_RES_SCANLINE_BUFFER_LEN: usize =
+    (SCREEN_WIDTH * SCREEN_HEIGHT) << (2 * MAX_RESOLUTION_SCALE_SHIFT);
+
 static SHARED_DATA: OnceLock<SharedData> = OnceLock::new();
 
 macro_rules! shared_data {
@@ -31,7 +34,10 @@ macro_rules! shared_data {
 struct SharedData {
     rendering_data: Box<UnsafeCell<RenderingData>>,
     scanline_buffer: Box<UnsafeCell<[Scanline<u32>; SCREEN_HEIGHT]>>,
+    hi_res_scanline_buffer: Box<UnsafeCell<[u32; HI_RES_SCANLINE_BUFFER_LEN]>>,
     processing_scanline: AtomicU8,
+    resolution_scale_shift: AtomicU8,
+    frame_resolution_scale_shift: AtomicU8,
     stopped: AtomicBool,
 }
 
@@ -74,8 +80,16 @@ impl RendererTx for Tx {
         tex_pal: &Bytes<0x1_8000>,
         state: &CoreRenderingState,
     ) {
+        self.wait_for_frame_end();
         unsafe { &mut *shared_data!().rendering_data.get() }.copy_vram(texture, tex_pal, state);
 
+        // Resolution changes only take effect once the previous frame has been fully read
+        shared_data!().frame_resolution_scale_shift.store(
+            shared_data!()
+                .resolution_scale_shift
+                .load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
         shared_data!()
             .processing_scanline
             .store(u8::MAX, Ordering::Release);
@@ -121,6 +135,32 @@ impl SoftRendererRx for Rx {
     fn skip_scanline(&mut self) {
         self.next_scanline += 1;
     }
+
+    fn resolution_scale_shift(&self) -> u8 {
+        shared_data!()
+            .frame_resolution_scale_shift
+            .load(Ordering::Relaxed)
+    }
+
+    fn read_hi_res_scanline(&mut self) -> (&Scanline<u32>, &[u32]) {
+        self.wait_for_line(self.next_scanline);
+        let len = SCREEN_WIDTH << (2 * self.resolution_scale_shift());
+        let start = self.next_scanline as usize * len;
+        let result = unsafe {
+            (
+                &(&*shared_data!().scanline_buffer.get())[self.next_scanline as usize],
+                &(&*shared_data!().hi_res_scanline_buffer.get())[start..start + len],
+            )
+        };
+        self.next_scanline += 1;
+        result
+    }
+}
+
+pub fn set_resolution_scale_shift(value: u8) {
+    shared_data!()
+        .resolution_scale_shift
+        .store(value.min(MAX_RESOLUTION_SCALE_SHIFT), Ordering::Relaxed);
 }
 
 pub fn init() -> (Tx, Rx) {
@@ -128,7 +168,10 @@ pub fn init() -> (Tx, Rx) {
         SharedData {
             rendering_data: Box::new_zeroed().assume_init(),
             scanline_buffer: Box::new_zeroed().assume_init(),
+            hi_res_scanline_buffer: Box::new_zeroed().assume_init(),
             processing_scanline: AtomicU8::new(SCREEN_HEIGHT as u8),
+            resolution_scale_shift: AtomicU8::new(0),
+            frame_resolution_scale_shift: AtomicU8::new(0),
             stopped: AtomicBool::new(false),
         }
     });
@@ -153,10 +196,25 @@ pub fn run_worker() {
             }
         }
         let rendering_data = unsafe { &*shared_data.rendering_data.get() };
+        raw_renderer.set_resolution_scale_shift(
+            shared_data
+                .frame_resolution_scale_shift
+                .load(Ordering::Relaxed),
+        );
         raw_renderer.start_frame(rendering_data);
+        let hi_res_len = SCREEN_WIDTH << (2 * raw_renderer.resolution_scale_shift());
         for y in 0..192 {
-            let scanline = &mut unsafe { &mut *shared_data.scanline_buffer.get() }[y as usize];
-            raw_renderer.render_line(y, scanline, rendering_data);
+            let (scanline, hi_res_scanlines) = unsafe {
+                (
+                    &mut (&mut *shared_data.scanline_buffer.get())[y as usize],
+                    slice::from_raw_parts_mut(
+                        (shared_data.hi_res_scanline_buffer.get() as *mut u32)
+                            .add(y as usize * hi_res_len),
+                        hi_res_len,
+                    ),
+                )
+            };
+            raw_renderer.render_line(y, scanline, hi_res_scanlines, rendering_data);
             if shared_data
                 .processing_scanline
                 .compare_exchange(y, y + 1, Ordering::Release, Ordering::Relaxed)
@@ -72,6 +72,7 @@ class FpsLimiter {
             {
                 type: EmuToUi.MessageType.RenderFrame,
                 buffer,
+                resolutionScaleShift: emu!.frame_resolution_scale_shift(),
             },
             [buffer.buffer]
         );
@@ -177,6 +178,11 @@ class FpsLimiter {
                 fpsLimiter.limit = message.value ? 60.0 : null;
                 break;
             }
+
+            case UiToEmu.MessageType.UpdateResolutionScaleShift: {
+                emu!.set_resolution_scale_shift(message.value);
+                break;
+            }
         }
     };
 
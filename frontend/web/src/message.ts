@@ -52,6 +52,7 @@ export namespace UiToEmu {
         UpdateInput,
         UpdatePlaying,
         UpdateFramerateLimit,
+        UpdateResolutionScaleShift,
     }
 
     export interface StartMessage {
@@ -85,12 +86,18 @@ export namespace UiToEmu {
         value: boolean;
     }
 
+    export interface UpdateResolutionScaleShiftMessage {
+        type: MessageType.UpdateResolutionScaleShift;
+        value: number;
+    }
+
     export type Message =
         | StartMessage
         | RawMessage
         | LoadSaveMessage
         | UpdateInputMessage
-        | UpdateFlagMessage;
+        | UpdateFlagMessage
+        | UpdateResolutionScaleShiftMessage;
 }
 
 export namespace EmuToUi {
@@ -127,6 +134,7 @@ export namespace EmuToUi {
     export interface RenderFrameMessage {
         type: MessageType.RenderFrame;
         buffer: Uint32Array;
+        resolutionScaleShift: number;
     }
 
     export interface PlayAudioChunkMessage {
@@ -20,6 +20,7 @@ export class Ui {
 
     private limitFramerateCheckbox: HTMLInputElement;
     private touchControlsCheckbox: HTMLInputElement;
+    private resolutionScaleShiftSelect: HTMLSelectElement;
 
     private files: Files;
     private bios7?: Uint8Array;
@@ -29,6 +30,7 @@ export class Ui {
     private gl: WebGLRenderingContext;
     private fbProgram: WebGLProgram;
     private fbCoordsAttrib: number;
+    private fbResolutionScaleShift: number;
 
     private worker: Worker | undefined;
     private rendererWorker: Worker | undefined;
@@ -85,6 +87,9 @@ export class Ui {
         this.touchControlsCheckbox = document.getElementById(
             "toggle-touch-controls"
         ) as HTMLInputElement;
+        this.resolutionScaleShiftSelect = document.getElementById(
+            "resolution-scale-shift"
+        ) as HTMLSelectElement;
 
         this.files = new Files(
             (id, filename, buffer) => {
@@ -133,6 +138,12 @@ export class Ui {
             this.input.touch = this.touchControlsCheckbox.checked;
         });
 
+        this.resolutionScaleShiftSelect.addEventListener("change", (e) => {
+            this.setResolutionScaleShift(
+                parseInt(this.resolutionScaleShiftSelect.value, 10)
+            );
+        });
+
         const gl = this.canvas.getContext("webgl", {
             alpha: false,
             depth: false,
@@ -162,6 +173,7 @@ export class Ui {
             gl.UNSIGNED_BYTE,
             new Uint8Array(256 * 384 * 4)
         );
+        this.fbResolutionScaleShift = 0;
 
         const vertShader = gl.createShader(gl.VERTEX_SHADER)!;
         gl.shaderSource(vertShader, vertShaderSource);
@@ -243,6 +255,7 @@ export class Ui {
         this.resetButton.disabled = false;
         this.limitFramerateCheckbox.disabled = false;
         this.limitFramerateCheckbox.checked = true;
+        this.resolutionScaleShiftSelect.disabled = false;
 
         const romFilenameExtStart = this.nextRomFilename!.lastIndexOf(".");
         this.gameTitle =
@@ -280,6 +293,9 @@ export class Ui {
             },
             [this.nextRomBuffer!.buffer]
         );
+        this.setResolutionScaleShift(
+            parseInt(this.resolutionScaleShiftSelect.value, 10)
+        );
 
         this.files.loadSaveFromStorage(this.gameTitle);
 
@@ -323,17 +339,34 @@ export class Ui {
             }
 
             case EmuToUi.MessageType.RenderFrame: {
-                this.gl.texSubImage2D(
-                    this.gl.TEXTURE_2D,
-                    0,
-                    0,
-                    0,
-                    256,
-                    384,
-                    this.gl.RGBA,
-                    this.gl.UNSIGNED_BYTE,
-                    new Uint8Array(message.buffer.buffer)
-                );
+                const shift = message.resolutionScaleShift;
+                const data = new Uint8Array(message.buffer.buffer);
+                if (shift !== this.fbResolutionScaleShift) {
+                    this.fbResolutionScaleShift = shift;
+                    this.gl.texImage2D(
+                        this.gl.TEXTURE_2D,
+                        0,
+                        this.gl.RGBA,
+                        256 << shift,
+                        384 << shift,
+                        0,
+                        this.gl.RGBA,
+                        this.gl.UNSIGNED_BYTE,
+                        data
+                    );
+                } else {
+                    this.gl.texSubImage2D(
+                        this.gl.TEXTURE_2D,
+                        0,
+                        0,
+                        0,
+                        256 << shift,
+                        384 << shift,
+                        this.gl.RGBA,
+                        this.gl.UNSIGNED_BYTE,
+                        data
+                    );
+                }
                 break;
             }
 
@@ -409,6 +442,7 @@ export class Ui {
         this.stopButton.disabled = true;
         this.resetButton.disabled = true;
         this.limitFramerateCheckbox.disabled = true;
+        this.resolutionScaleShiftSelect.disabled = true;
 
         this.sendMessage({
             type: UiToEmu.MessageType.Stop,
@@ -416,17 +450,18 @@ export class Ui {
         this.worker.onmessage = this.handleClosingWorkerMessage.bind(this);
 
         this.files.unloadRom();
-        this.gl.texSubImage2D(
+        this.gl.texImage2D(
             this.gl.TEXTURE_2D,
             0,
-            0,
-            0,
+            this.gl.RGBA,
             256,
             384,
+            0,
             this.gl.RGBA,
             this.gl.UNSIGNED_BYTE,
             new Uint8Array(256 * 384 * 4)
         );
+        this.fbResolutionScaleShift = 0;
     }
 
     tryStartQueuedWorker() {
@@ -466,6 +501,13 @@ export class Ui {
         });
     }
 
+    setResolutionScaleShift(value: number) {
+        this.sendMessage({
+            type: UiToEmu.MessageType.UpdateResolutionScaleShift,
+            value,
+        });
+    }
+
     loadSave(filename: string, buffer: ArrayBuffer) {
         this.files.storeSaveToStorage(filename, buffer, this.gameTitle!);
         this.saveFilename = filename;
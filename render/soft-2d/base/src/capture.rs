@@ -11,6 +11,7 @@ pub fn run(
     capture_control: CaptureControl,
     bg_obj_scanline: &Scanline<BgObjPixel>,
     scanline_3d: Option<&Scanline<u32>>,
+    main_mem_display_scanline: &Scanline<u16>,
     vram: &mut Vram,
 ) {
     let dst_bank_index = capture_control.dst_bank();
@@ -37,7 +38,7 @@ pub fn run(
 
         let src_b_line = if capture_source != 0 && (factor_b != 0 || capture_source & 2 == 0) {
             if capture_control.src_b_display_fifo() {
-                todo!("Display capture display FIFO source");
+                Some(main_mem_display_scanline.0.as_ptr())
             } else {
                 let src_bank_index = control.a_vram_bank();
                 let src_bank_control = vram.bank_control()[src_bank_index as usize];
@@ -47,3 +47,16 @@ pub fn render_scanline_vram_display<R: Role>(
         scanline_buffer.0.fill(0);
     }
 }
+
+pub fn render_scanline_main_mem_display<R: Role>(
+    scanline_buffer: &mut Scanline<u32>,
+    engine: &Engine2d<R>,
+) {
+    for (pixel, src) in scanline_buffer
+        .0
+        .iter_mut()
+        .zip(&engine.main_mem_display_scanline().0)
+    {
+        *pixel = rgb5_to_rgb6(*src);
+    }
+}
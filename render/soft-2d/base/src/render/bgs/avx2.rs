@@ -1,6 +1,6 @@
 // TODO: Possibly migrate to core::simd when masked loads/stores are supported
 
-use super::common::{read_bg_text_tiles, TextTiles};
+use super::common::{read_bg_text_tiles, scroll_scanline_3d, TextTiles};
 use crate::{rgb5_to_rgb6_64, BgObjPixel, Buffers, RenderingData, Vram};
 use core::{arch::x86_64::*, mem::transmute, simd::u64x4};
 use dust_core::gpu::{
@@ -118,7 +118,11 @@ pub unsafe fn render_scanline_bgs_and_objs<
         if data.bg_priority(BgIndex::new(0)) == priority {
             if R::IS_A && data.control().bg0_3d() {
                 if let Some(scanline_3d) = scanline_3d {
-                    render_scanline_bg_3d(buffers, scanline_3d);
+                    render_scanline_bg_3d(
+                        buffers,
+                        scanline_3d,
+                        data.bg_scroll(BgIndex::new(0))[0],
+                    );
                 }
             } else if BG_MODE != 6 {
                 render_scanline_bg_text(buffers, BgIndex::new(0), vount, data, vram);
@@ -972,8 +976,18 @@ pub unsafe fn render_scanline_bg_extended<
 }
 
 #[target_feature(enable = "sse4.1,sse4.2,avx,avx2")]
-pub unsafe fn render_scanline_bg_3d<B: Buffers>(buffers: &mut B, scanline_3d: &Scanline<u32>) {
-    // TODO: 3D layer scrolling
+pub unsafe fn render_scanline_bg_3d<B: Buffers>(
+    buffers: &mut B,
+    scanline_3d: &Scanline<u32>,
+    x_scroll: u16,
+) {
+    let scrolled_scanline_3d;
+    let scanline_3d = if x_scroll & 0x1FF == 0 {
+        scanline_3d
+    } else {
+        scrolled_scanline_3d = scroll_scanline_3d(scanline_3d, x_scroll);
+        &scrolled_scanline_3d
+    };
 
     let zero = _mm256_setzero_si256();
 
@@ -3,7 +3,10 @@ use core::{
     mem::{self, MaybeUninit},
     ptr,
 };
-use dust_core::gpu::engine_2d::{BgControl, Control, Role};
+use dust_core::gpu::{
+    engine_2d::{BgControl, Control, Role},
+    Scanline, SCREEN_WIDTH,
+};
 
 #[repr(align(64))]
 pub struct TextTiles([MaybeUninit<u16>; 64]);
@@ -74,3 +77,18 @@ where
         }
     }
 }
+
+// The 3D layer is scrolled by BG0's 9-bit X offset, with the 256 pixels past its right edge
+// being transparent before wrapping back around
+#[inline]
+pub fn scroll_scanline_3d(scanline_3d: &Scanline<u32>, x_scroll: u16) -> Scanline<u32> {
+    let mut result = Scanline([0; SCREEN_WIDTH]);
+    let x_scroll = x_scroll as usize & 0x1FF;
+    if x_scroll < SCREEN_WIDTH {
+        result.0[..SCREEN_WIDTH - x_scroll].copy_from_slice(&scanline_3d.0[x_scroll..]);
+    } else {
+        let start = 0x200 - x_scroll;
+        result.0[start..].copy_from_slice(&scanline_3d.0[..SCREEN_WIDTH - start]);
+    }
+    result
+}
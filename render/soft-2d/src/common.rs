@@ -1,3 +1,5 @@
+pub mod hi_res;
+
 pub use dust_soft_2d_base::*;
 
 use core::marker::PhantomData;
@@ -0,0 +1,175 @@
+use super::{BgObjPixel, Buffers, RenderingData};
+use dust_core::gpu::{engine_2d::BgIndex, Scanline, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// Fully opaque 3D pixels, used to mark every pixel the 3D layer could show up in, so that it can
+// be patched in later at a higher resolution
+pub static OPAQUE_SCANLINE_3D: Scanline<u32> = Scanline([0x1F << 18; SCREEN_WIDTH]);
+
+#[inline]
+fn patch_pixel_3d(pixel: BgObjPixel, pixel_3d: u32) -> BgObjPixel {
+    const COLOR_MASK: u64 = 0x7F_FFFF;
+    let is_transparent = pixel_3d >> 18 == 0;
+    BgObjPixel(if pixel.is_3d() {
+        if is_transparent {
+            pixel.0 >> 32
+        } else {
+            pixel.0 & !COLOR_MASK | pixel_3d as u64
+        }
+    } else if pixel.bot_is_3d() {
+        if is_transparent {
+            pixel.0 & 0xFFFF_FFFF
+        } else {
+            pixel.0 & !(COLOR_MASK << 32) | (pixel_3d as u64) << 32
+        }
+    } else {
+        pixel.0
+    })
+}
+
+pub fn patch_scanline_3d(
+    bg_obj_scanline: &mut Scanline<BgObjPixel>,
+    scanline_3d: &Scanline<u32>,
+    x_scroll: u16,
+) {
+    for (i, pixel) in bg_obj_scanline.0.iter_mut().enumerate() {
+        if pixel.is_3d() || pixel.bot_is_3d() {
+            // Only pixels inside the 3D layer get marked, so the scrolled position is always in
+            // bounds
+            *pixel = patch_pixel_3d(*pixel, scanline_3d.0[(i + x_scroll as usize) & 0x1FF]);
+        }
+    }
+}
+
+pub struct HiResFramebuffer {
+    resolution_scale_shift: u8,
+    data: Vec<u32>,
+}
+
+impl HiResFramebuffer {
+    pub fn new() -> Self {
+        HiResFramebuffer {
+            resolution_scale_shift: 0,
+            data: Vec::new(),
+        }
+    }
+
+    pub fn resolution_scale_shift(&self) -> u8 {
+        self.resolution_scale_shift
+    }
+
+    pub fn set_resolution_scale_shift(&mut self, value: u8) {
+        if value == self.resolution_scale_shift {
+            return;
+        }
+        self.resolution_scale_shift = value;
+        self.data = vec![
+            0;
+            if value == 0 {
+                0
+            } else {
+                (SCREEN_WIDTH * SCREEN_HEIGHT * 2) << (2 * value)
+            }
+        ];
+    }
+
+    pub fn get(&self) -> Option<(u8, &[u32])> {
+        if self.resolution_scale_shift == 0 {
+            None
+        } else {
+            Some((self.resolution_scale_shift, &self.data))
+        }
+    }
+
+    fn lines_mut(&mut self, is_on_lower_screen: bool, line: u8) -> &mut [u32] {
+        let len = SCREEN_WIDTH << (2 * self.resolution_scale_shift);
+        let start = (is_on_lower_screen as usize * SCREEN_HEIGHT + line as usize) * len;
+        &mut self.data[start..start + len]
+    }
+
+    pub fn upscale_scanline(
+        &mut self,
+        is_on_lower_screen: bool,
+        line: u8,
+        scanline_buffer: &Scanline<u32>,
+    ) {
+        let resolution_scale_shift = self.resolution_scale_shift;
+        if resolution_scale_shift == 0 {
+            return;
+        }
+        let width = SCREEN_WIDTH << resolution_scale_shift;
+        let (first_line, other_lines) =
+            self.lines_mut(is_on_lower_screen, line).split_at_mut(width);
+        for (x, pixel) in first_line.iter_mut().enumerate() {
+            *pixel = scanline_buffer.0[x >> resolution_scale_shift];
+        }
+        for other_line in other_lines.chunks_exact_mut(width) {
+            other_line.copy_from_slice(first_line);
+        }
+    }
+
+    // Composites the upscaled 3D output with the rest of the scanline, and applies color effects and
+    // master brightness to the result; `buffers` needs to hold the scanline's BGs and OBJs before
+    // color effects are applied, with the 3D layer rendered from `OPAQUE_SCANLINE_3D`. Color
+    // effects are applied in `SCREEN_WIDTH`-pixel chunks using `scratch_buffers`.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn render_scanline_3d<B: Buffers, D: RenderingData>(
+        &mut self,
+        is_on_lower_screen: bool,
+        line: u8,
+        buffers: &B,
+        scratch_buffers: &mut B,
+        hi_res_scanline_3d: &[u32],
+        apply_color_effects: unsafe fn(&mut B, &D),
+        apply_brightness: unsafe fn(&mut Scanline<u32>, &D),
+        data: &D,
+    ) {
+        let resolution_scale_shift = self.resolution_scale_shift;
+        let width = SCREEN_WIDTH << resolution_scale_shift;
+        let x_scroll =
+            (data.bg_scroll(BgIndex::new(0))[0] as usize & 0x1FF) << resolution_scale_shift;
+        let x_mask = (0x200 << resolution_scale_shift) - 1;
+
+        let bg_obj_scanline = buffers.bg_obj_scanline();
+        let window = buffers.window();
+        let mut output = Scanline([0; SCREEN_WIDTH]);
+
+        for (hi_res_line, hi_res_line_3d) in self
+            .lines_mut(is_on_lower_screen, line)
+            .chunks_exact_mut(width)
+            .zip(hi_res_scanline_3d.chunks_exact(width))
+        {
+            for (chunk_i, chunk) in hi_res_line.chunks_exact_mut(SCREEN_WIDTH).enumerate() {
+                let scratch_bg_obj_scanline = scratch_buffers.bg_obj_scanline();
+                let scratch_window = scratch_buffers.window();
+                for i in 0..SCREEN_WIDTH {
+                    let x = chunk_i * SCREEN_WIDTH + i;
+                    let pixel = bg_obj_scanline.0[x >> resolution_scale_shift];
+                    scratch_window.0[i] = window.0[x >> resolution_scale_shift];
+                    scratch_bg_obj_scanline.0[i] = if pixel.is_3d() || pixel.bot_is_3d() {
+                        patch_pixel_3d(pixel, hi_res_line_3d[(x + x_scroll) & x_mask])
+                    } else {
+                        pixel
+                    };
+                }
+
+                apply_color_effects(scratch_buffers, data);
+
+                for (dst, src) in output
+                    .0
+                    .iter_mut()
+                    .zip(scratch_buffers.bg_obj_scanline().0.iter())
+                {
+                    *dst = src.0 as u32;
+                }
+                apply_brightness(&mut output, data);
+                chunk.copy_from_slice(&output.0);
+            }
+        }
+    }
+}
+
+impl Default for HiResFramebuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
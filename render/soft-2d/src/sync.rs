@@ -2,6 +2,7 @@ mod impls;
 
 use crate::common::{
     self, capture,
+    hi_res::{self, HiResFramebuffer},
     render::{self, objs::prerender_objs},
     rgb5_to_rgb6_64, BgObjPixel, ObjPixel, WindowPixel,
 };
@@ -27,7 +28,9 @@ pub struct Renderer {
     fns: (FnPtrs<EngineA>, FnPtrs<EngineB>),
     renderer_3d_rx: Box<dyn engine_3d::SoftRendererRx>,
     buffers: [Buffers; 2],
+    hi_res_buffers: Buffers,
     framebuffer: Box<[[Scanline<u32>; SCREEN_HEIGHT]; 2]>,
+    hi_res_framebuffer: HiResFramebuffer,
 }
 
 unsafe impl Send for Renderer {}
@@ -49,7 +52,9 @@ impl Renderer {
             fns: (FnPtrs::new(), FnPtrs::new()),
             renderer_3d_rx,
             buffers: [buffers!(), buffers!()],
+            hi_res_buffers: buffers!(),
             framebuffer: unsafe { Box::new_zeroed().assume_init() },
+            hi_res_framebuffer: HiResFramebuffer::new(),
         }
     }
 
@@ -84,6 +89,11 @@ impl Renderer {
             // TODO: Display capture interaction?
 
             scanline_buffer.0.fill(0xFFFF_FFFF);
+            self.hi_res_framebuffer.upscale_scanline(
+                engine.is_on_lower_screen(),
+                line,
+                scanline_buffer,
+            );
             return;
         }
 
@@ -98,13 +108,27 @@ impl Renderer {
                 && engine.capture_enabled_in_frame()
                 && !engine.capture_control().src_a_3d_only());
 
+        let resolution_scale_shift = self.hi_res_framebuffer.resolution_scale_shift();
+        let mut hi_res_scanline_3d: &[u32] = &[];
+        let mut composite_hi_res_3d = false;
+
         let scanline_3d = if R::IS_A && engine.engine_3d_enabled_in_frame() {
             let enabled_in_bg_obj = engine.bgs[0].priority() != 4 && engine.control().bg0_3d();
             if (engine.capture_enabled_in_frame()
                 && (engine.capture_control().src_a_3d_only() || enabled_in_bg_obj))
                 || (display_mode == 1 && enabled_in_bg_obj)
             {
-                Some(self.renderer_3d_rx.read_scanline())
+                if resolution_scale_shift == 0 {
+                    Some(self.renderer_3d_rx.read_scanline())
+                } else {
+                    let (scanline_3d, hi_res_scanlines_3d) =
+                        self.renderer_3d_rx.read_hi_res_scanline();
+                    hi_res_scanline_3d = hi_res_scanlines_3d;
+                    composite_hi_res_3d = display_mode == 1
+                        && enabled_in_bg_obj
+                        && hi_res_scanline_3d.len() == SCREEN_WIDTH << (2 * resolution_scale_shift);
+                    Some(scanline_3d)
+                }
             } else {
                 self.renderer_3d_rx.skip_scanline();
                 None
@@ -156,21 +180,41 @@ impl Renderer {
                 .0
                 .fill(BgObjPixel(backdrop | backdrop << 32));
 
+            let apply_color_effects =
+                fns.apply_color_effects[engine.color_effects_control().color_effect() as usize];
             unsafe {
                 fns.render_scanline_bgs_and_objs[engine.control().bg_mode() as usize](
                     buffers,
                     vcount,
                     engine,
                     vram,
-                    scanline_3d,
-                );
-                fns.apply_color_effects[engine.color_effects_control().color_effect() as usize](
-                    buffers, engine,
+                    if composite_hi_res_3d {
+                        Some(&hi_res::OPAQUE_SCANLINE_3D)
+                    } else {
+                        scanline_3d
+                    },
                 );
+                if let (true, Some(scanline_3d)) = (composite_hi_res_3d, scanline_3d) {
+                    self.hi_res_framebuffer.render_scanline_3d(
+                        engine.is_on_lower_screen(),
+                        line,
+                        &*buffers,
+                        &mut self.hi_res_buffers,
+                        hi_res_scanline_3d,
+                        apply_color_effects,
+                        fns.apply_brightness,
+                        engine,
+                    );
+                    hi_res::patch_scanline_3d(
+                        buffers.bg_obj_scanline.get_mut(),
+                        scanline_3d,
+                        engine.bgs[0].scroll[0],
+                    );
+                }
+                apply_color_effects(buffers, engine);
             }
         }
 
-        #[allow(clippy::match_same_arms)]
         match display_mode {
             0 => {
                 scanline_buffer.0.fill(0xFFFF_FFFF);
@@ -191,7 +235,7 @@ impl Renderer {
             }
 
             _ => {
-                // TODO: Main memory display mode
+                render::render_scanline_main_mem_display(scanline_buffer, engine);
             }
         }
 
@@ -199,6 +243,14 @@ impl Renderer {
             (fns.apply_brightness)(scanline_buffer, engine);
         }
 
+        if !composite_hi_res_3d {
+            self.hi_res_framebuffer.upscale_scanline(
+                engine.is_on_lower_screen(),
+                line,
+                scanline_buffer,
+            );
+        }
+
         if render_bg_obj_line && line < (SCREEN_HEIGHT - 1) as u8 {
             prerender_objs::<R, _, _, _>(buffers, line + 1, engine, vram);
         }
@@ -210,6 +262,7 @@ impl Renderer {
                 engine.capture_control(),
                 buffers.bg_obj_scanline.get_mut(),
                 scanline_3d,
+                engine.main_mem_display_scanline(),
                 vram,
             )
         }
@@ -229,6 +282,10 @@ impl RendererTrait for Renderer {
         unsafe { &*(self.framebuffer.as_ptr() as *const () as *const Framebuffer) }
     }
 
+    fn hi_res_framebuffer(&self) -> Option<(u8, &[u32])> {
+        self.hi_res_framebuffer.get()
+    }
+
     fn start_prerendering_objs(
         &mut self,
         engines: (&mut Engine2d<EngineA>, &mut Engine2d<EngineB>),
@@ -245,8 +302,12 @@ impl RendererTrait for Renderer {
         engines: (&mut Engine2d<EngineA>, &mut Engine2d<EngineB>),
         _vram: &mut Vram,
     ) {
-        if line == 0 && engines.0.engine_3d_enabled_in_frame() {
-            self.renderer_3d_rx.start_frame();
+        if line == 0 {
+            self.hi_res_framebuffer
+                .set_resolution_scale_shift(self.renderer_3d_rx.resolution_scale_shift());
+            if engines.0.engine_3d_enabled_in_frame() {
+                self.renderer_3d_rx.start_frame();
+            }
         }
     }
 
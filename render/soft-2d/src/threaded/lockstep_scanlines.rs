@@ -2,6 +2,7 @@ mod impls;
 
 use crate::common::{
     self, capture,
+    hi_res::{self, HiResFramebuffer},
     render::{self, objs::prerender_objs},
     rgb5_to_rgb6_64, BgObjPixel, ObjPixel, WindowPixel,
 };
@@ -48,6 +49,7 @@ struct SharedData {
     capture_scanlines: UnsafeCell<(Scanline<BgObjPixel>, Scanline<u32>)>,
 
     framebuffer: UnsafeCell<Box<[[Scanline<u32>; SCREEN_HEIGHT]; 2]>>,
+    hi_res_framebuffer: UnsafeCell<HiResFramebuffer>,
 }
 
 unsafe impl Sync for SharedData {}
@@ -212,6 +214,7 @@ impl Renderer {
                 )),
 
                 framebuffer: UnsafeCell::new(Box::new_zeroed().assume_init()),
+                hi_res_framebuffer: UnsafeCell::new(HiResFramebuffer::new()),
             }
         });
 
@@ -221,6 +224,7 @@ impl Renderer {
             fns: (FnPtrs::new(), FnPtrs::new()),
             renderer_3d_rx,
             buffers: [buffers!(), buffers!()],
+            hi_res_buffers: buffers!(),
         };
 
         Renderer {
@@ -379,6 +383,11 @@ impl RendererTrait for Renderer {
         }
     }
 
+    fn hi_res_framebuffer(&self) -> Option<(u8, &[u32])> {
+        self.wait_for_scanline_finish();
+        unsafe { &*self.shared_data.hi_res_framebuffer.get() }.get()
+    }
+
     fn start_prerendering_objs(
         &mut self,
         engines: (&mut Engine2d<EngineA>, &mut Engine2d<EngineB>),
@@ -417,7 +426,6 @@ impl RendererTrait for Renderer {
             {
                 self.flush_vram_updates::<EngineA>(vram);
             }
-            #[allow(clippy::match_same_arms)]
             match display_mode {
                 2 => render::render_scanline_vram_display(
                     unsafe {
@@ -429,9 +437,14 @@ impl RendererTrait for Renderer {
                     engines.0,
                     vram,
                 ),
-                3 => {
-                    // TODO: Main memory display mode
-                }
+                3 => render::render_scanline_main_mem_display(
+                    unsafe {
+                        (&mut *self.shared_data.framebuffer.get())
+                            [engines.0.is_on_lower_screen() as usize]
+                            .get_unchecked_mut(line as usize)
+                    },
+                    engines.0,
+                ),
                 _ => {}
             }
         }
@@ -484,6 +497,7 @@ impl RendererTrait for Renderer {
                     .0
                     .engine_3d_enabled_in_frame()
                     .then_some(scanline_3d),
+                engines.0.main_mem_display_scanline(),
                 vram,
             )
         }
@@ -516,6 +530,7 @@ struct ThreadData {
     fns: (FnPtrs<EngineA>, FnPtrs<EngineB>),
     renderer_3d_rx: Box<dyn engine_3d::SoftRendererRx + Send + 'static>,
     buffers: [Buffers; 2],
+    hi_res_buffers: Buffers,
 }
 
 impl ThreadData {
@@ -536,6 +551,7 @@ impl ThreadData {
         let buffers = &mut self.buffers[!R::IS_A as usize];
 
         let render_obj_line = if self.cur_scanline >= 0 {
+            let hi_res_framebuffer = unsafe { &mut *self.shared_data.hi_res_framebuffer.get() };
             let scanline_buffer = unsafe {
                 (&mut *self.shared_data.framebuffer.get())[data.is_on_lower_screen as usize]
                     .get_unchecked_mut(self.cur_scanline as usize)
@@ -559,14 +575,34 @@ impl ThreadData {
                     self.renderer_3d_rx.skip_scanline();
                 }
                 scanline_buffer.0.fill(0xFFFF_FFFF);
+                hi_res_framebuffer.upscale_scanline(
+                    data.is_on_lower_screen,
+                    self.cur_scanline as u8,
+                    scanline_buffer,
+                );
             } else {
+                let resolution_scale_shift = hi_res_framebuffer.resolution_scale_shift();
+                let mut hi_res_scanline_3d: &[u32] = &[];
+                let mut composite_hi_res_3d = false;
+
                 let scanline_3d = if R::IS_A && data.engine_3d_enabled_in_frame {
                     let enabled_in_bg_obj = data.bgs[0].priority != 4 && data.control.bg0_3d();
                     if (data.capture_enabled_in_frame
                         && (data.capture_control.src_a_3d_only() || enabled_in_bg_obj))
                         || (display_mode == 1 && enabled_in_bg_obj)
                     {
-                        Some(self.renderer_3d_rx.read_scanline())
+                        if resolution_scale_shift == 0 {
+                            Some(self.renderer_3d_rx.read_scanline())
+                        } else {
+                            let (scanline_3d, hi_res_scanlines_3d) =
+                                self.renderer_3d_rx.read_hi_res_scanline();
+                            hi_res_scanline_3d = hi_res_scanlines_3d;
+                            composite_hi_res_3d = display_mode == 1
+                                && enabled_in_bg_obj
+                                && hi_res_scanline_3d.len()
+                                    == SCREEN_WIDTH << (2 * resolution_scale_shift);
+                            Some(scanline_3d)
+                        }
                     } else {
                         self.renderer_3d_rx.skip_scanline();
                         None
@@ -618,17 +654,38 @@ impl ThreadData {
                         .0
                         .fill(BgObjPixel(backdrop | backdrop << 32));
 
+                    let apply_color_effects =
+                        fns.apply_color_effects[data.color_effects_control.color_effect() as usize];
                     unsafe {
                         fns.render_scanline_bgs_and_objs[data.control.bg_mode() as usize](
                             buffers,
                             vcount,
                             data,
                             vram,
-                            scanline_3d,
-                        );
-                        fns.apply_color_effects[data.color_effects_control.color_effect() as usize](
-                            buffers, data,
+                            if composite_hi_res_3d {
+                                Some(&hi_res::OPAQUE_SCANLINE_3D)
+                            } else {
+                                scanline_3d
+                            },
                         );
+                        if let (true, Some(scanline_3d)) = (composite_hi_res_3d, scanline_3d) {
+                            hi_res_framebuffer.render_scanline_3d(
+                                data.is_on_lower_screen,
+                                self.cur_scanline as u8,
+                                &*buffers,
+                                &mut self.hi_res_buffers,
+                                hi_res_scanline_3d,
+                                apply_color_effects,
+                                fns.apply_brightness,
+                                data,
+                            );
+                            hi_res::patch_scanline_3d(
+                                buffers.bg_obj_scanline.get_mut(),
+                                scanline_3d,
+                                data.bgs[0].scroll[0],
+                            );
+                        }
+                        apply_color_effects(buffers, data);
                     }
                 }
 
@@ -654,6 +711,14 @@ impl ThreadData {
                     (fns.apply_brightness)(scanline_buffer, data);
                 }
 
+                if !composite_hi_res_3d {
+                    hi_res_framebuffer.upscale_scanline(
+                        data.is_on_lower_screen,
+                        self.cur_scanline as u8,
+                        scanline_buffer,
+                    );
+                }
+
                 if R::IS_A
                     && data.capture_enabled_in_frame
                     && self.cur_scanline < data.capture_height as i16
@@ -696,10 +761,13 @@ impl ThreadData {
                 continue;
             }
 
-            if self.cur_scanline == 0
-                && unsafe { &*self.shared_data.rendering_data.get() }[0].engine_3d_enabled_in_frame
-            {
-                self.renderer_3d_rx.start_frame();
+            if self.cur_scanline == 0 {
+                unsafe { &mut *self.shared_data.hi_res_framebuffer.get() }
+                    .set_resolution_scale_shift(self.renderer_3d_rx.resolution_scale_shift());
+                if unsafe { &*self.shared_data.rendering_data.get() }[0].engine_3d_enabled_in_frame
+                {
+                    self.renderer_3d_rx.start_frame();
+                }
             }
 
             let vcount = self.shared_data.vcount.load(Ordering::Acquire);
@@ -2,6 +2,8 @@
 
 mod data;
 pub use data::RenderingData;
+#[cfg(feature = "threaded")]
+pub mod threaded;
 mod utils;
 
 use core::simd::SimdOrd;
@@ -9,13 +11,16 @@ use dust_core::gpu::{
     engine_3d::{
         Color, InterpColor, PolyAddr, PolyVertIndex, PolygonAttrs, TexCoords, TextureParams,
     },
-    Scanline,
+    Scanline, SCREEN_HEIGHT, SCREEN_WIDTH,
 };
 use utils::{
-    dec_poly_vert_index, decode_rgb5, expand_depth, inc_poly_vert_index, rgb5_to_rgb6, Edge,
-    InterpLineData,
+    dec_poly_vert_index, decode_rgb5, expand_depth, fog_density, inc_poly_vert_index, rgb5_to_rgb6,
+    scaled_coords, Edge, InterpLineData,
 };
 
+pub const MAX_RESOLUTION_SCALE_SHIFT: u8 = 2;
+const MAX_WIDTH: usize = SCREEN_WIDTH << MAX_RESOLUTION_SCALE_SHIFT;
+
 type DepthTestFn = fn(u32, u32, PixelAttrs) -> bool;
 type ProcessPixelFn = fn(&RenderingData, &RenderingPolygon, TexCoords, InterpColor) -> InterpColor;
 
@@ -26,9 +31,9 @@ struct RenderingPolygon {
     is_shadow: bool,
     tex_params: TextureParams,
     tex_palette_base: u16,
-    top_y: u8,
-    bot_y: u8,
-    height: u8,
+    top_y: u16,
+    bot_y: u16,
+    height: u16,
     edges: [Edge; 2],
     l_vert_i: PolyVertIndex,
     r_vert_i: PolyVertIndex,
@@ -49,6 +54,8 @@ proc_bitfield::bitfield! {
         pub right_edge: bool @ 2,
         pub left_edge: bool @ 3,
 
+        pub coverage: u8 @ 8..=12,
+
         pub translucent: bool @ 13,
         pub back_facing: bool @ 14,
 
@@ -63,17 +70,37 @@ proc_bitfield::bitfield! {
 
 impl PixelAttrs {
     #[inline]
-    fn from_opaque_poly_attrs(poly: &RenderingPolygon) -> Self {
-        PixelAttrs(poly.attrs.0 & 0x3F00_8000).with_back_facing(!poly.is_front_facing)
+    fn from_opaque_poly_attrs(
+        poly: &RenderingPolygon,
+        prev: PixelAttrs,
+        edge_mask: u8,
+        coverage: u8,
+    ) -> Self {
+        PixelAttrs(poly.attrs.0 & 0x3F00_8000 | prev.0 & 0x8000_0000)
+            .with_back_facing(!poly.is_front_facing)
+            .with_edge_mask(edge_mask)
+            .with_coverage(coverage)
     }
 
     #[inline]
     fn from_translucent_poly_attrs(poly: &RenderingPolygon, opaque: PixelAttrs) -> Self {
-        PixelAttrs(opaque.0 & 0x3F00_8000)
+        // Edge flags and coverage are kept from the opaque pixel below, while the fog flag is only
+        // kept if both the translucent polygon and the pixel below it have fog enabled
+        PixelAttrs(opaque.0 & (0xBF00_1F0F | (poly.attrs.0 & 0x8000)))
             .with_translucent(true)
             .with_back_facing(!poly.is_front_facing)
             .with_translucent_id(poly.id | 0x40)
     }
+
+    #[inline]
+    fn accepts_translucent_poly(self, poly: &RenderingPolygon) -> bool {
+        // Shadow polygons can't be drawn over opaque pixels with the same polygon ID either
+        if poly.is_shadow && !self.translucent() {
+            self.opaque_id() != poly.id
+        } else {
+            self.translucent_id() != poly.id | 0x40
+        }
+    }
 }
 
 fn process_pixel<const FORMAT: u8, const MODE: u8>(
@@ -348,11 +375,39 @@ static PROCESS_PIXEL_TEXTURES_DISABLED: [ProcessPixelFn; 4] = [
     process_pixel::<0, 3>,
 ];
 
+// Sized for the highest supported resolution, only the first `SCREEN_WIDTH <<
+// resolution_scale_shift` pixels are used
+#[derive(Clone, Copy)]
+struct LineBuffers {
+    color: Scanline<Color, MAX_WIDTH>,
+    depth: Scanline<u32, MAX_WIDTH>,
+    attrs: Scanline<PixelAttrs, MAX_WIDTH>,
+
+    // The pixels that were on top before the current ones got drawn, blended with the top ones on
+    // anti-aliased edges
+    back_color: Scanline<Color, MAX_WIDTH>,
+    back_depth: Scanline<u32, MAX_WIDTH>,
+    back_attrs: Scanline<PixelAttrs, MAX_WIDTH>,
+}
+
+impl LineBuffers {
+    #[inline]
+    fn push_back(&mut self, x: usize) {
+        self.back_color.0[x] = self.color.0[x];
+        self.back_depth.0[x] = self.depth.0[x];
+        self.back_attrs.0[x] = self.attrs.0[x];
+    }
+}
+
 pub struct Renderer {
-    color_buffer: Box<Scanline<Color>>,
-    depth_buffer: Box<Scanline<u32>>,
-    attr_buffer: Box<Scanline<PixelAttrs>>,
+    // Lines get rasterized one line ahead of the final pass, as edge marking needs the depth and
+    // attributes of the lines above and below
+    lines: Box<[LineBuffers; 3]>,
+    // The line `finish_line` was last called for plus 1, if the next line can reuse the rasterized
+    // lines in the ring
+    next_y: u16,
     polys: Vec<RenderingPolygon>,
+    resolution_scale_shift: u8,
 }
 
 fn depth_test_equal_w(a: u32, b: u32, _: PixelAttrs) -> bool {
@@ -386,15 +441,42 @@ fn depth_test_less_back_facing(a: u32, b: u32, _: PixelAttrs) -> bool {
 impl Renderer {
     pub fn new() -> Self {
         Renderer {
-            color_buffer: Box::new(Scanline([Color::splat(0); 256])),
-            depth_buffer: unsafe { Box::new_zeroed().assume_init() },
-            attr_buffer: unsafe { Box::new_zeroed().assume_init() },
+            lines: unsafe { Box::new_zeroed().assume_init() },
+            next_y: u16::MAX,
             polys: Vec::with_capacity(2048),
+            resolution_scale_shift: 0,
         }
     }
 
+    pub fn resolution_scale_shift(&self) -> u8 {
+        self.resolution_scale_shift
+    }
+
+    // Should only be called between frames, before `start_frame`
+    pub fn set_resolution_scale_shift(&mut self, value: u8) {
+        self.resolution_scale_shift = value.min(MAX_RESOLUTION_SCALE_SHIFT);
+    }
+
+    fn width(&self) -> usize {
+        SCREEN_WIDTH << self.resolution_scale_shift
+    }
+
+    fn height(&self) -> usize {
+        SCREEN_HEIGHT << self.resolution_scale_shift
+    }
+
+    // Starts a frame with the polygons that were set up by `other`'s last `start_frame` call,
+    // without redoing the setup; `other` must not have rendered any lines since then
+    pub fn start_frame_from(&mut self, other: &Renderer) {
+        self.resolution_scale_shift = other.resolution_scale_shift;
+        self.next_y = u16::MAX;
+        self.polys.clone_from(&other.polys);
+    }
+
     pub fn start_frame(&mut self, rendering_data: &RenderingData) {
         self.polys.clear();
+        self.next_y = u16::MAX;
+        let resolution_scale_shift = self.resolution_scale_shift;
 
         for poly_addr in 0..rendering_data.poly_ram_level {
             let poly_addr = unsafe { PolyAddr::new_unchecked(poly_addr) };
@@ -419,12 +501,8 @@ impl Renderer {
             let is_shadow = poly.attrs.mode() == 3;
             let process_pixel = {
                 let mode = if is_shadow {
-                    // TODO: Do process shadow/shadow mask polygons
-                    continue;
-                    // if poly.attrs.id() == 0 {
-                    //     // TODO: Shadow mask polygons
-                    // }
-                    // 1
+                    // Shadow polygons get blended with their texture like decal ones
+                    1
                 } else {
                     match poly.attrs.mode() {
                         2 => 2 + rendering_data.control.highlight_shading_enabled() as u8,
@@ -438,31 +516,45 @@ impl Renderer {
                 }
             };
 
-            let top_y = poly.top_y;
-            let bot_y = poly.bot_y;
+            let (top_y, bot_y) = if resolution_scale_shift == 0 {
+                (poly.top_y as u16, poly.bot_y as u16)
+            } else {
+                poly.vertices[..poly.vertices_len.get() as usize]
+                    .iter()
+                    .fold((u16::MAX, 0), |(top_y, bot_y), vert_addr| {
+                        let vert = &rendering_data.vert_ram[vert_addr.get() as usize];
+                        let y = scaled_coords(vert, resolution_scale_shift)[1];
+                        (top_y.min(y), bot_y.max(y))
+                    })
+            };
 
             if top_y == bot_y {
                 let mut top_i = PolyVertIndex::new(0);
                 let mut bot_i = top_i;
                 let mut top_vert_addr = poly.vertices[0];
                 let mut top_vert = &rendering_data.vert_ram[top_vert_addr.get() as usize];
+                let mut top_x = scaled_coords(top_vert, resolution_scale_shift)[0];
                 let mut bot_vert_addr = top_vert_addr;
                 let mut bot_vert = top_vert;
+                let mut bot_x = top_x;
 
                 macro_rules! vert {
                     ($i: expr) => {{
                         let i = $i;
                         let vert_addr = poly.vertices[i.get() as usize];
                         let vert = &rendering_data.vert_ram[vert_addr.get() as usize];
-                        if vert.coords[0] < top_vert.coords[0] {
+                        let x = scaled_coords(vert, resolution_scale_shift)[0];
+                        if x < top_x {
                             top_i = i;
                             top_vert_addr = vert_addr;
                             top_vert = vert;
+                            top_x = x;
                         }
-                        if vert.coords[0] > bot_vert.coords[0] {
+                        if x > bot_x {
                             bot_i = i;
                             bot_vert_addr = vert_addr;
                             bot_vert = vert;
+                            bot_x = x;
                         }
                     }};
                 }
@@ -476,8 +568,8 @@ impl Renderer {
                     is_shadow,
                     tex_params: poly.tex_params,
                     tex_palette_base: poly.tex_palette_base,
-                    top_y: poly.top_y,
-                    bot_y: poly.bot_y,
+                    top_y,
+                    bot_y,
                     height: 1,
                     alpha: poly.attrs.alpha(),
                     id: poly.attrs.id(),
@@ -491,6 +583,7 @@ impl Renderer {
                             top_i,
                             top_vert_addr,
                             top_vert,
+                            resolution_scale_shift,
                         ),
                         Edge::new(
                             poly,
@@ -500,6 +593,7 @@ impl Renderer {
                             bot_i,
                             bot_vert_addr,
                             bot_vert,
+                            resolution_scale_shift,
                         ),
                     ],
                     l_vert_i: top_i,
@@ -517,11 +611,12 @@ impl Renderer {
                         let i = PolyVertIndex::new(i as u8);
                         let vert_addr = poly.vertices[i.get() as usize];
                         let vert = &rendering_data.vert_ram[vert_addr.get() as usize];
-                        if vert.coords[1] as u8 == top_y && top_vert.is_none() {
+                        let y = scaled_coords(vert, resolution_scale_shift)[1];
+                        if y == top_y && top_vert.is_none() {
                             top_i = i;
                             top_vert = Some((vert_addr, vert));
                         }
-                        if vert.coords[1] as u8 == bot_y {
+                        if y == bot_y {
                             bot_i = i;
                         }
                     }
@@ -552,9 +647,9 @@ impl Renderer {
                     is_shadow,
                     tex_params: poly.tex_params,
                     tex_palette_base: poly.tex_palette_base,
-                    top_y: poly.top_y,
-                    bot_y: poly.bot_y,
-                    height: poly.bot_y - poly.top_y,
+                    top_y,
+                    bot_y,
+                    height: bot_y - top_y,
                     alpha: poly.attrs.alpha(),
                     id: poly.attrs.id(),
                     is_front_facing: poly.is_front_facing,
@@ -567,6 +662,7 @@ impl Renderer {
                             other_verts[0].0,
                             other_verts[0].1,
                             other_verts[0].2,
+                            resolution_scale_shift,
                         ),
                         Edge::new(
                             poly,
@@ -576,6 +672,7 @@ impl Renderer {
                             other_verts[1].0,
                             other_verts[1].1,
                             other_verts[1].2,
+                            resolution_scale_shift,
                         ),
                     ],
                     l_vert_i: other_verts[0].0,
@@ -588,58 +685,120 @@ impl Renderer {
         }
     }
 
+    // When rendering at a higher resolution, the `1 << resolution_scale_shift` lines making up
+    // scanline `y` are output to `hi_res_scanlines`, and `scanline` gets their top-left samples.
+    // Lines can be rendered starting from any line, but have to be rendered in increasing order
+    // within a frame; consecutive ones are faster to render as they share rasterized lines.
     pub fn render_line(
         &mut self,
         y: u8,
         scanline: &mut Scanline<u32>,
+        hi_res_scanlines: &mut [u32],
         rendering_data: &RenderingData,
     ) {
-        scanline.0.fill(0);
+        let resolution_scale_shift = self.resolution_scale_shift;
+        if resolution_scale_shift == 0 {
+            self.render_hi_res_line(y as u16, &mut scanline.0, rendering_data);
+            return;
+        }
+
+        let width = self.width();
+        let hi_res_scanlines = &mut hi_res_scanlines[..width << resolution_scale_shift];
+        for (i, hi_res_scanline) in hi_res_scanlines.chunks_exact_mut(width).enumerate() {
+            self.render_hi_res_line(
+                (y as u16) << resolution_scale_shift | i as u16,
+                hi_res_scanline,
+                rendering_data,
+            );
+        }
+        for (x, pixel) in scanline.0.iter_mut().enumerate() {
+            *pixel = hi_res_scanlines[x << resolution_scale_shift];
+        }
+    }
+
+    fn render_hi_res_line(&mut self, y: u16, scanline: &mut [u32], rendering_data: &RenderingData) {
+        if y != self.next_y {
+            if y != 0 {
+                self.rasterize_line(y - 1, rendering_data);
+            }
+            self.rasterize_line(y, rendering_data);
+        }
+        if (y as usize) < self.height() - 1 {
+            self.rasterize_line(y + 1, rendering_data);
+        }
+        self.finish_line(y, scanline, rendering_data);
+        self.next_y = y + 1;
+    }
+
+    fn rasterize_line(&mut self, y: u16, rendering_data: &RenderingData) {
+        let resolution_scale_shift = self.resolution_scale_shift;
+        let width = self.width();
+        let x_max = width as u16 - 1;
+        let line = &mut self.lines[y as usize % 3];
 
         if rendering_data.control.rear_plane_bitmap_enabled() {
-            let line_base = (y.wrapping_add(rendering_data.clear_image_offset[1]) as usize) << 9;
-            let mut x_in_image = rendering_data.clear_image_offset[0];
+            let line_base = (((y >> resolution_scale_shift) as u8)
+                .wrapping_add(rendering_data.clear_image_offset[1])
+                as usize)
+                << 9;
+            let x_in_image = |x: usize| {
+                rendering_data.clear_image_offset[0]
+                    .wrapping_add((x >> resolution_scale_shift) as u8)
+            };
 
             let color_line_base = 0x4_0000 | line_base;
-            for x in 0..256 {
+            for x in 0..width {
                 let raw_color = rendering_data
                     .texture
-                    .read_le(color_line_base | (x_in_image as usize) << 1);
-                self.color_buffer.0[x] = rgb5_to_rgb6(decode_rgb5(
+                    .read_le(color_line_base | (x_in_image(x) as usize) << 1);
+                line.color.0[x] = rgb5_to_rgb6(decode_rgb5(
                     raw_color,
                     if raw_color >> 15 != 0 { 31 } else { 0 },
                 ))
                 .cast();
-                x_in_image = x_in_image.wrapping_add(1);
             }
 
             let depth_line_base = 0x4_0000 | line_base;
             let pixel_attrs = PixelAttrs(0).with_opaque_id(rendering_data.clear_poly_id);
-            for x in 0..256 {
+            for x in 0..width {
                 let raw_depth = rendering_data
                     .texture
-                    .read_le(depth_line_base | (x_in_image as usize) << 1);
-                self.depth_buffer.0[x] = expand_depth(raw_depth);
-                self.attr_buffer.0[x] = pixel_attrs.with_fog_enabled(raw_depth >> 15 != 0);
-                x_in_image = x_in_image.wrapping_add(1);
+                    .read_le(depth_line_base | (x_in_image(x) as usize) << 1);
+                line.depth.0[x] = expand_depth(raw_depth);
+                line.attrs.0[x] = pixel_attrs.with_fog_enabled(raw_depth >> 15 != 0);
             }
         } else {
-            self.color_buffer
-                .0
-                .fill(rgb5_to_rgb6(rendering_data.clear_color.cast()).cast());
-            self.depth_buffer.0.fill(rendering_data.clear_depth);
-            self.attr_buffer.0.fill(
+            line.color.0[..width].fill(rgb5_to_rgb6(rendering_data.clear_color.cast()).cast());
+            line.depth.0[..width].fill(rendering_data.clear_depth);
+            line.attrs.0[..width].fill(
                 PixelAttrs(0)
                     .with_opaque_id(rendering_data.clear_poly_id)
                     .with_fog_enabled(rendering_data.rear_plane_fog_enabled),
             );
         }
 
+        line.back_color.0[..width].copy_from_slice(&line.color.0[..width]);
+        line.back_depth.0[..width].copy_from_slice(&line.depth.0[..width]);
+        line.back_attrs.0[..width].copy_from_slice(&line.attrs.0[..width]);
+
+        // The stencil buffer gets cleared at the start of each group of consecutive shadow mask
+        // polygons, and never by the shadow polygons drawn over it (which instead rely on the
+        // translucent polygon ID check to only shadow each pixel once), matching melonDS
+        let mut prev_is_shadow_mask = false;
+
         for poly in self.polys.iter_mut() {
             if y.wrapping_sub(poly.top_y) >= poly.height {
                 continue;
             }
 
+            let is_shadow_mask = poly.is_shadow && poly.id == 0;
+            if is_shadow_mask && !prev_is_shadow_mask {
+                for attrs in &mut line.attrs.0[..width] {
+                    attrs.set_stencil(false);
+                }
+            }
+            prev_is_shadow_mask = is_shadow_mask;
+
             if poly.top_y != poly.bot_y {
                 let raw_poly = rendering_data.poly_ram[poly.poly_addr.get() as usize];
 
@@ -647,6 +806,7 @@ impl Renderer {
                     ($vert_i: expr, $edge: expr, $increasing: expr) => {{
                         if y >= $edge.b_y() {
                             let mut i = *$vert_i;
+                            let mut start_i = i;
                             let mut start_vert_addr = $edge.b_addr();
                             let mut start_vert =
                                 &rendering_data.vert_ram[start_vert_addr.get() as usize];
@@ -659,22 +819,25 @@ impl Renderer {
                                 let new_end_vert_addr = raw_poly.vertices[i.get() as usize];
                                 let new_end_vert =
                                     &rendering_data.vert_ram[new_end_vert_addr.get() as usize];
-                                let new_b_y = new_end_vert.coords[1] as u8;
+                                let new_b_y =
+                                    scaled_coords(new_end_vert, resolution_scale_shift)[1];
 
                                 if new_b_y > y || i == poly.bot_i {
                                     $edge = Edge::new(
                                         &raw_poly,
-                                        *$vert_i,
+                                        start_i,
                                         start_vert_addr,
                                         start_vert,
                                         i,
                                         new_end_vert_addr,
                                         new_end_vert,
+                                        resolution_scale_shift,
                                     );
                                     *$vert_i = i;
                                     break;
                                 }
 
+                                start_i = i;
                                 start_vert = new_end_vert;
                                 start_vert_addr = new_end_vert_addr;
                             }
@@ -687,7 +850,10 @@ impl Renderer {
             }
 
             let mut edges = [&poly.edges[0], &poly.edges[1]];
-            let mut ranges = [edges[0].line_x_range(y), edges[1].line_x_range(y)];
+            let mut ranges = [
+                edges[0].line_x_range(y, x_max),
+                edges[1].line_x_range(y, x_max),
+            ];
 
             if ranges[1].1 <= ranges[0].0 {
                 edges.swap(0, 1);
@@ -709,7 +875,15 @@ impl Renderer {
                     || edges[1].x_incr() == 0,
             ];
 
-            let edge_mask = (y == poly.top_y) as u8 | (y == poly.bot_y - 1) as u8;
+            let edge_mask = PixelAttrs(0)
+                .with_top_edge(y == poly.top_y)
+                .with_bottom_edge(y == poly.bot_y - 1)
+                .edge_mask();
+            let side_edge_masks = [
+                PixelAttrs(0).with_left_edge(true).edge_mask(),
+                PixelAttrs(0).with_right_edge(true).edge_mask(),
+            ];
+            let antialiasing_enabled = rendering_data.control.antialiasing_enabled();
 
             macro_rules! interp_edge {
                 ($i: expr, $x: expr) => {{
@@ -730,17 +904,47 @@ impl Renderer {
 
             let x_interp = InterpLineData::<false>::new(l_w, r_w);
 
+            if is_shadow_mask {
+                // Shadow mask polygons aren't drawn, and only set the stencil bit for pixels where
+                // they fail the depth test
+                let mut set_stencil = |x: u16| {
+                    let interp = x_interp.set_x(x - x_span_start, x_span_len);
+                    let x = x as usize;
+                    let depth =
+                        interp.depth(l_depth, r_depth, rendering_data.w_buffering) & 0x00FF_FFFF;
+                    if !(poly.depth_test)(depth, line.depth.0[x], line.attrs.0[x]) {
+                        line.attrs.0[x].set_stencil(true);
+                    }
+                };
+                if fill_edges[0] {
+                    for x in ranges[0].0..=ranges[0].1 {
+                        set_stencil(x);
+                    }
+                }
+                if !wireframe || edge_mask != 0 {
+                    for x in ranges[0].1 + 1..ranges[1].0 {
+                        set_stencil(x);
+                    }
+                }
+                if fill_edges[1] {
+                    for x in ranges[1].0..=ranges[1].1 {
+                        set_stencil(x);
+                    }
+                }
+                continue;
+            }
+
             for i in 0..2 {
                 if fill_edges[i] {
                     for x in ranges[i].0..=ranges[i].1 {
-                        if poly.is_shadow && !self.attr_buffer.0[x as usize].stencil() {
+                        if poly.is_shadow && !line.attrs.0[x as usize].stencil() {
                             continue;
                         }
 
                         let interp = x_interp.set_x(x - x_span_start, x_span_len);
                         let x = x as usize;
                         let depth = interp.depth(l_depth, r_depth, rendering_data.w_buffering);
-                        if (poly.depth_test)(depth, self.depth_buffer.0[x], self.attr_buffer.0[x]) {
+                        if (poly.depth_test)(depth, line.depth.0[x], line.attrs.0[x]) {
                             let vert_color = interp.color(l_vert_color, r_vert_color);
                             let uv = interp.uv(l_uv, r_uv);
                             let mut color =
@@ -748,15 +952,25 @@ impl Renderer {
                             let alpha = color[3];
                             if alpha > rendering_data.alpha_test_ref as u16 {
                                 if alpha == 0x1F {
-                                    self.color_buffer.0[x] = color.cast();
-                                    self.depth_buffer.0[x] = depth;
-                                    self.attr_buffer.0[x] =
-                                        PixelAttrs::from_opaque_poly_attrs(poly);
+                                    let coverage = if antialiasing_enabled {
+                                        edges[i].coverage(y, x as u16, ranges[i], i == 1)
+                                    } else {
+                                        0x1F
+                                    };
+                                    line.push_back(x);
+                                    line.color.0[x] = color.cast();
+                                    line.depth.0[x] = depth;
+                                    line.attrs.0[x] = PixelAttrs::from_opaque_poly_attrs(
+                                        poly,
+                                        line.back_attrs.0[x],
+                                        edge_mask | side_edge_masks[i],
+                                        coverage,
+                                    );
                                 } else {
-                                    let prev_attrs = self.attr_buffer.0[x];
-                                    if prev_attrs.translucent_id() != poly.id | 0x40 {
+                                    let prev_attrs = line.attrs.0[x];
+                                    if prev_attrs.accepts_translucent_poly(poly) {
                                         if rendering_data.control.alpha_blending_enabled() {
-                                            let prev_color = self.color_buffer.0[x].cast();
+                                            let prev_color = line.color.0[x].cast();
                                             let prev_alpha = prev_color[3];
                                             if prev_alpha != 0 {
                                                 color = ((color * InterpColor::splat(alpha + 1))
@@ -766,14 +980,13 @@ impl Renderer {
                                                 color[3] = alpha.max(prev_alpha);
                                             }
                                         }
-                                        self.color_buffer.0[x] = color.cast();
+                                        line.color.0[x] = color.cast();
                                         if poly.attrs.update_depth_for_translucent() {
-                                            self.depth_buffer.0[x] = depth;
+                                            line.depth.0[x] = depth;
                                         }
-                                        self.attr_buffer.0[x] =
-                                            PixelAttrs::from_translucent_poly_attrs(
-                                                poly, prev_attrs,
-                                            );
+                                        line.attrs.0[x] = PixelAttrs::from_translucent_poly_attrs(
+                                            poly, prev_attrs,
+                                        );
                                     }
                                 }
                             }
@@ -784,7 +997,7 @@ impl Renderer {
 
             if !wireframe || edge_mask != 0 {
                 for x in ranges[0].1 + 1..ranges[1].0 {
-                    if poly.is_shadow && !self.attr_buffer.0[x as usize].stencil() {
+                    if poly.is_shadow && !line.attrs.0[x as usize].stencil() {
                         continue;
                     }
 
@@ -792,21 +1005,27 @@ impl Renderer {
                     let x = x as usize;
                     let depth =
                         interp.depth(l_depth, r_depth, rendering_data.w_buffering) & 0x00FF_FFFF;
-                    if (poly.depth_test)(depth, self.depth_buffer.0[x], self.attr_buffer.0[x]) {
+                    if (poly.depth_test)(depth, line.depth.0[x], line.attrs.0[x]) {
                         let vert_color = interp.color(l_vert_color, r_vert_color);
                         let uv = interp.uv(l_uv, r_uv);
                         let mut color = (poly.process_pixel)(rendering_data, poly, uv, vert_color);
                         let alpha = color[3];
                         if alpha > rendering_data.alpha_test_ref as u16 {
                             if alpha == 0x1F {
-                                self.color_buffer.0[x] = color.cast();
-                                self.depth_buffer.0[x] = depth;
-                                self.attr_buffer.0[x] = PixelAttrs::from_opaque_poly_attrs(poly);
+                                line.push_back(x);
+                                line.color.0[x] = color.cast();
+                                line.depth.0[x] = depth;
+                                line.attrs.0[x] = PixelAttrs::from_opaque_poly_attrs(
+                                    poly,
+                                    line.back_attrs.0[x],
+                                    edge_mask,
+                                    0x1F,
+                                );
                             } else {
-                                let prev_attrs = self.attr_buffer.0[x];
-                                if prev_attrs.translucent_id() != poly.id | 0x40 {
+                                let prev_attrs = line.attrs.0[x];
+                                if prev_attrs.accepts_translucent_poly(poly) {
                                     if rendering_data.control.alpha_blending_enabled() {
-                                        let prev_color = self.color_buffer.0[x].cast();
+                                        let prev_color = line.color.0[x].cast();
                                         let prev_alpha = prev_color[3];
                                         if prev_alpha != 0 {
                                             color = ((color * InterpColor::splat(alpha + 1))
@@ -815,11 +1034,11 @@ impl Renderer {
                                             color[3] = alpha.max(prev_alpha);
                                         }
                                     }
-                                    self.color_buffer.0[x] = color.cast();
+                                    line.color.0[x] = color.cast();
                                     if poly.attrs.update_depth_for_translucent() {
-                                        self.depth_buffer.0[x] = depth;
+                                        line.depth.0[x] = depth;
                                     }
-                                    self.attr_buffer.0[x] =
+                                    line.attrs.0[x] =
                                         PixelAttrs::from_translucent_poly_attrs(poly, prev_attrs);
                                 }
                             }
@@ -828,10 +1047,131 @@ impl Renderer {
                 }
             }
         }
+    }
+
+    fn finish_line(&mut self, y: u16, scanline: &mut [u32], rendering_data: &RenderingData) {
+        let control = rendering_data.control;
+        let width = self.width();
+        let height = self.height();
+        let line_i = y as usize % 3;
+
+        if control.edge_marking_enabled() {
+            // Pixels outside the screen are treated like the clear plane
+            let clear_id_depth = (rendering_data.clear_poly_id, rendering_data.clear_depth);
+            let id_depth = |line_i: usize, x: usize| {
+                let line = &self.lines[line_i];
+                (line.attrs.0[x].opaque_id(), line.depth.0[x])
+            };
+
+            let mut marked = [false; MAX_WIDTH];
+            let marked = &mut marked[..width];
+            for (x, marked) in marked.iter_mut().enumerate() {
+                if self.lines[line_i].attrs.0[x].edge_mask() == 0 {
+                    continue;
+                }
+                let (id, depth) = id_depth(line_i, x);
+                let neighbors = [
+                    if x == 0 {
+                        clear_id_depth
+                    } else {
+                        id_depth(line_i, x - 1)
+                    },
+                    if x == width - 1 {
+                        clear_id_depth
+                    } else {
+                        id_depth(line_i, x + 1)
+                    },
+                    if y == 0 {
+                        clear_id_depth
+                    } else {
+                        id_depth((line_i + 2) % 3, x)
+                    },
+                    if y as usize == height - 1 {
+                        clear_id_depth
+                    } else {
+                        id_depth((line_i + 1) % 3, x)
+                    },
+                ];
+                *marked = neighbors
+                    .iter()
+                    .any(|&(other_id, other_depth)| other_id != id && depth < other_depth);
+            }
+
+            let line = &mut self.lines[line_i];
+            for (x, _) in marked.iter().enumerate().filter(|(_, marked)| **marked) {
+                let attrs = line.attrs.0[x];
+                let mut color = rgb5_to_rgb6(
+                    rendering_data.edge_colors[attrs.opaque_id() as usize >> 3].cast(),
+                );
+                color[3] = line.color.0[x][3] as u16;
+                line.color.0[x] = color.cast();
+                // Edge-marked pixels get blended halfway with the ones behind them when
+                // anti-aliasing is enabled
+                line.attrs.0[x] = attrs.with_coverage(0x10);
+            }
+        }
+
+        let line = &mut self.lines[line_i];
+
+        if control.fog_enabled() {
+            let fog_color = rgb5_to_rgb6(rendering_data.fog_color.cast());
+            let fog_offset = (rendering_data.fog_offset as u32) << 9;
+            let fog_shift = control.fog_depth_shift();
+            let fog_only_alpha = control.fog_only_alpha();
+
+            let apply_fog = |color: &mut Color, depth: u32| {
+                let density =
+                    fog_density(&rendering_data.fog_densities, fog_offset, fog_shift, depth);
+                let prev_color = color.cast::<u16>();
+                let fogged_color = (fog_color * InterpColor::splat(density)
+                    + prev_color * InterpColor::splat(128 - density))
+                    >> InterpColor::splat(7);
+                *color = if fog_only_alpha {
+                    let mut color = prev_color;
+                    color[3] = fogged_color[3];
+                    color
+                } else {
+                    fogged_color
+                }
+                .cast();
+            };
+
+            for x in 0..width {
+                if line.attrs.0[x].fog_enabled() {
+                    apply_fog(&mut line.color.0[x], line.depth.0[x]);
+                }
+                // The pixels behind are only visible on anti-aliased edges
+                if control.antialiasing_enabled() && line.back_attrs.0[x].fog_enabled() {
+                    apply_fog(&mut line.back_color.0[x], line.back_depth.0[x]);
+                }
+            }
+        }
+
+        if control.antialiasing_enabled() {
+            for x in 0..width {
+                let attrs = line.attrs.0[x];
+                if !(attrs.left_edge() || attrs.right_edge()) || attrs.coverage() == 0x1F {
+                    continue;
+                }
+                let coverage = attrs.coverage() as u16 + 1;
+                let color = line.color.0[x].cast::<u16>();
+                let back_color = line.back_color.0[x].cast::<u16>();
+                let mut blended_color = (color * InterpColor::splat(coverage)
+                    + back_color * InterpColor::splat(32 - coverage))
+                    >> InterpColor::splat(5);
+                // Only alpha gets blended if the pixel behind is fully transparent
+                if back_color[3] == 0 {
+                    let alpha = blended_color[3];
+                    blended_color = color;
+                    blended_color[3] = alpha;
+                }
+                line.color.0[x] = blended_color.cast();
+            }
+        }
 
-        for x in 0..256 {
-            let [r, g, b, a] = self.color_buffer.0[x].to_array();
-            scanline.0[x] = r as u32 | (g as u32) << 6 | (b as u32) << 12 | (a as u32) << 18
+        for (x, pixel) in scanline[..width].iter_mut().enumerate() {
+            let [r, g, b, a] = line.color.0[x].to_array();
+            *pixel = r as u32 | (g as u32) << 6 | (b as u32) << 12 | (a as u32) << 18
         }
     }
 }
@@ -0,0 +1,375 @@
+use crate::{Renderer, RenderingData, MAX_RESOLUTION_SCALE_SHIFT};
+use core::{
+    cell::UnsafeCell,
+    hint, slice,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering},
+};
+use dust_core::{
+    gpu::{
+        engine_3d::{
+            Polygon, RendererTx, RenderingState as CoreRenderingState, ScreenVertex, SoftRendererRx,
+        },
+        Scanline, SCREEN_HEIGHT, SCREEN_WIDTH,
+    },
+    utils::Bytes,
+};
+use std::{sync::Arc, thread};
+
+const HI_RES_SCANLINE_BUFFER_LEN: usize =
+    (SCREEN_WIDTH * SCREEN_HEIGHT) << (2 * MAX_RESOLUTION_SCALE_SHIFT);
+
+// Lines are handed out to the rendering threads in bands; each band costs a couple of extra
+// rasterized lines to fill the edge marking line ring, so they can't be too small
+const BAND_HEIGHT: usize = 16;
+const BANDS: u32 = (SCREEN_HEIGHT / BAND_HEIGHT) as u32;
+
+struct SharedData {
+    rendering_data: Box<UnsafeCell<RenderingData>>,
+    // Holds the polygons set up for the current frame, copied by each thread before it starts
+    // rendering its first band
+    setup_renderer: UnsafeCell<Renderer>,
+    scanline_buffer: Box<UnsafeCell<[Scanline<u32>; SCREEN_HEIGHT]>>,
+    hi_res_scanline_buffer: Box<UnsafeCell<[u32; HI_RES_SCANLINE_BUFFER_LEN]>>,
+    lines_ready: [AtomicBool; SCREEN_HEIGHT],
+    lines_rendered: AtomicU8,
+    frame_requested: AtomicBool,
+    // Frame index in the upper 16 bits, index of the next band to render in the lower 16 bits
+    next_band: AtomicU32,
+    resolution_scale_shift: AtomicU8,
+    frame_resolution_scale_shift: AtomicU8,
+    stopped: AtomicBool,
+}
+
+unsafe impl Sync for SharedData {}
+
+impl SharedData {
+    fn frame_finished(&self) -> bool {
+        self.lines_rendered.load(Ordering::Acquire) == SCREEN_HEIGHT as u8
+    }
+}
+
+pub struct Tx {
+    shared_data: Arc<SharedData>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+impl Tx {
+    fn wait_for_frame_end(&self) {
+        while !self.shared_data.frame_finished() {
+            hint::spin_loop();
+        }
+    }
+}
+
+impl RendererTx for Tx {
+    fn set_capture_enabled(&mut self, _capture_enabled: bool) {}
+
+    fn swap_buffers(
+        &mut self,
+        vert_ram: &[ScreenVertex],
+        poly_ram: &[Polygon],
+        state: &CoreRenderingState,
+    ) {
+        self.wait_for_frame_end();
+        unsafe { &mut *self.shared_data.rendering_data.get() }.prepare(vert_ram, poly_ram, state);
+    }
+
+    fn repeat_last_frame(&mut self, state: &CoreRenderingState) {
+        self.wait_for_frame_end();
+        unsafe { &mut *self.shared_data.rendering_data.get() }.repeat_last_frame(state);
+    }
+
+    fn start_rendering(
+        &mut self,
+        texture: &Bytes<0x8_0000>,
+        tex_pal: &Bytes<0x1_8000>,
+        state: &CoreRenderingState,
+    ) {
+        self.wait_for_frame_end();
+        unsafe { &mut *self.shared_data.rendering_data.get() }.copy_vram(texture, tex_pal, state);
+
+        // Resolution changes only take effect once the previous frame has been fully read
+        self.shared_data.frame_resolution_scale_shift.store(
+            self.shared_data
+                .resolution_scale_shift
+                .load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+        for line_ready in &self.shared_data.lines_ready {
+            line_ready.store(false, Ordering::Relaxed);
+        }
+        self.shared_data.lines_rendered.store(0, Ordering::Relaxed);
+        self.shared_data
+            .frame_requested
+            .store(true, Ordering::Release);
+        self.threads[0].thread().unpark();
+    }
+
+    fn skip_rendering(&mut self) {}
+}
+
+impl Drop for Tx {
+    fn drop(&mut self) {
+        self.shared_data.stopped.store(true, Ordering::Relaxed);
+        for thread in &self.threads {
+            thread.thread().unpark();
+        }
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+        for line_ready in &self.shared_data.lines_ready {
+            line_ready.store(true, Ordering::Relaxed);
+        }
+        self.shared_data
+            .lines_rendered
+            .store(SCREEN_HEIGHT as u8, Ordering::Relaxed);
+    }
+}
+
+#[derive(Clone)]
+pub struct Rx {
+    next_scanline: u8,
+    shared_data: Arc<SharedData>,
+}
+
+impl Rx {
+    fn wait_for_line(&self, line: u8) {
+        while !self.shared_data.lines_ready[line as usize].load(Ordering::Acquire) {
+            hint::spin_loop();
+        }
+    }
+}
+
+impl SoftRendererRx for Rx {
+    fn start_frame(&mut self) {
+        self.next_scanline = 0;
+    }
+
+    fn read_scanline(&mut self) -> &Scanline<u32> {
+        self.wait_for_line(self.next_scanline);
+        let result =
+            unsafe { &(&*self.shared_data.scanline_buffer.get())[self.next_scanline as usize] };
+        self.next_scanline += 1;
+        result
+    }
+
+    fn skip_scanline(&mut self) {
+        self.next_scanline += 1;
+    }
+
+    fn resolution_scale_shift(&self) -> u8 {
+        self.shared_data
+            .frame_resolution_scale_shift
+            .load(Ordering::Relaxed)
+    }
+
+    fn read_hi_res_scanline(&mut self) -> (&Scanline<u32>, &[u32]) {
+        self.wait_for_line(self.next_scanline);
+        let resolution_scale_shift = self.resolution_scale_shift();
+        let len = SCREEN_WIDTH << (2 * resolution_scale_shift);
+        let start = self.next_scanline as usize * len;
+        let result = unsafe {
+            (
+                &(&*self.shared_data.scanline_buffer.get())[self.next_scanline as usize],
+                &(&*self.shared_data.hi_res_scanline_buffer.get())[start..start + len],
+            )
+        };
+        self.next_scanline += 1;
+        result
+    }
+}
+
+pub struct FrontendChannels {
+    shared_data: Arc<SharedData>,
+}
+
+impl FrontendChannels {
+    pub fn set_resolution_scale_shift(&self, value: u8) {
+        self.shared_data
+            .resolution_scale_shift
+            .store(value.min(MAX_RESOLUTION_SCALE_SHIFT), Ordering::Relaxed);
+    }
+}
+
+struct ThreadData {
+    shared_data: Arc<SharedData>,
+    renderer: Renderer,
+    // The first thread sets up the polygons for each frame and then wakes up the others
+    other_threads: Option<Vec<thread::Thread>>,
+    frame_index: u16,
+}
+
+impl ThreadData {
+    fn setup_frame(&mut self) {
+        let shared_data = &*self.shared_data;
+        let rendering_data = unsafe { &*shared_data.rendering_data.get() };
+        let setup_renderer = unsafe { &mut *shared_data.setup_renderer.get() };
+        setup_renderer.set_resolution_scale_shift(
+            shared_data
+                .frame_resolution_scale_shift
+                .load(Ordering::Relaxed),
+        );
+        setup_renderer.start_frame(rendering_data);
+
+        self.frame_index = self.frame_index.wrapping_add(1);
+        shared_data
+            .next_band
+            .store((self.frame_index as u32) << 16, Ordering::Release);
+        if let Some(other_threads) = &self.other_threads {
+            for thread in other_threads {
+                thread.unpark();
+            }
+        }
+    }
+
+    fn next_band(&self) -> Option<u32> {
+        let mut next_band = self.shared_data.next_band.load(Ordering::Acquire);
+        loop {
+            let band = next_band & 0xFFFF;
+            if (next_band >> 16) as u16 != self.frame_index || band >= BANDS {
+                return None;
+            }
+            match self.shared_data.next_band.compare_exchange_weak(
+                next_band,
+                next_band + 1,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return Some(band),
+                Err(value) => next_band = value,
+            }
+        }
+    }
+
+    fn render_bands(&mut self) {
+        let shared_data = &*self.shared_data;
+        let rendering_data = unsafe { &*shared_data.rendering_data.get() };
+        let mut started_frame = false;
+
+        while let Some(band) = self.next_band() {
+            if !started_frame {
+                self.renderer
+                    .start_frame_from(unsafe { &*shared_data.setup_renderer.get() });
+                started_frame = true;
+            }
+
+            let resolution_scale_shift = self.renderer.resolution_scale_shift();
+            let hi_res_len = SCREEN_WIDTH << (2 * resolution_scale_shift);
+            let start_y = band as usize * BAND_HEIGHT;
+            for y in start_y..start_y + BAND_HEIGHT {
+                // Each line is only ever accessed by the thread rendering it until it's marked as
+                // ready
+                let (scanline, hi_res_scanlines) = unsafe {
+                    (
+                        &mut *(shared_data.scanline_buffer.get() as *mut Scanline<u32>).add(y),
+                        slice::from_raw_parts_mut(
+                            (shared_data.hi_res_scanline_buffer.get() as *mut u32)
+                                .add(y * hi_res_len),
+                            hi_res_len,
+                        ),
+                    )
+                };
+                self.renderer
+                    .render_line(y as u8, scanline, hi_res_scanlines, rendering_data);
+                shared_data.lines_ready[y].store(true, Ordering::Release);
+                shared_data.lines_rendered.fetch_add(1, Ordering::Release);
+            }
+        }
+    }
+
+    fn run(mut self) {
+        loop {
+            if self.shared_data.stopped.load(Ordering::Relaxed) {
+                return;
+            }
+
+            if self.other_threads.is_some() {
+                if self
+                    .shared_data
+                    .frame_requested
+                    .compare_exchange(true, false, Ordering::Acquire, Ordering::Acquire)
+                    .is_ok()
+                {
+                    self.setup_frame();
+                    self.render_bands();
+                } else {
+                    thread::park();
+                }
+            } else {
+                let frame_index = (self.shared_data.next_band.load(Ordering::Acquire) >> 16) as u16;
+                if frame_index != self.frame_index {
+                    self.frame_index = frame_index;
+                    self.render_bands();
+                } else {
+                    thread::park();
+                }
+            }
+        }
+    }
+}
+
+pub fn init(resolution_scale_shift: u8, thread_count: usize) -> (Tx, Rx, FrontendChannels) {
+    let resolution_scale_shift = resolution_scale_shift.min(MAX_RESOLUTION_SCALE_SHIFT);
+    let shared_data = Arc::new(unsafe {
+        SharedData {
+            rendering_data: Box::new_zeroed().assume_init(),
+            setup_renderer: UnsafeCell::new(Renderer::new()),
+            scanline_buffer: Box::new_zeroed().assume_init(),
+            hi_res_scanline_buffer: Box::new_zeroed().assume_init(),
+            lines_ready: [(); SCREEN_HEIGHT].map(|_| AtomicBool::new(true)),
+            lines_rendered: AtomicU8::new(SCREEN_HEIGHT as u8),
+            frame_requested: AtomicBool::new(false),
+            next_band: AtomicU32::new(BANDS),
+            resolution_scale_shift: AtomicU8::new(resolution_scale_shift),
+            frame_resolution_scale_shift: AtomicU8::new(resolution_scale_shift),
+            stopped: AtomicBool::new(false),
+        }
+    });
+
+    let thread_count = thread_count.max(1);
+    let mut threads = Vec::with_capacity(thread_count);
+    for i in 1..thread_count {
+        let thread_data = ThreadData {
+            shared_data: Arc::clone(&shared_data),
+            renderer: Renderer::new(),
+            other_threads: None,
+            frame_index: 0,
+        };
+        threads.push(
+            thread::Builder::new()
+                .name(format!("3D rendering {i}"))
+                .spawn(move || thread_data.run())
+                .expect("couldn't spawn 3D rendering thread"),
+        );
+    }
+    let thread_data = ThreadData {
+        shared_data: Arc::clone(&shared_data),
+        renderer: Renderer::new(),
+        other_threads: Some(
+            threads
+                .iter()
+                .map(|thread| thread.thread().clone())
+                .collect(),
+        ),
+        frame_index: 0,
+    };
+    threads.insert(
+        0,
+        thread::Builder::new()
+            .name("3D rendering".to_string())
+            .spawn(move || thread_data.run())
+            .expect("couldn't spawn 3D rendering thread"),
+    );
+
+    (
+        Tx {
+            shared_data: Arc::clone(&shared_data),
+            threads,
+        },
+        Rx {
+            next_scanline: 0,
+            shared_data: Arc::clone(&shared_data),
+        },
+        FrontendChannels { shared_data },
+    )
+}
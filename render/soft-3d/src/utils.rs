@@ -1,8 +1,19 @@
 use core::simd::{i32x2, i64x2, u32x4, u64x4, SimdPartialEq, SimdPartialOrd};
 use dust_core::gpu::engine_3d::{
-    InterpColor, PolyVertIndex, PolyVertsLen, Polygon, ScreenVertex, TexCoords, VertexAddr,
+    InterpColor, PolyVertIndex, PolyVertsLen, Polygon, ScreenCoords, ScreenVertex, TexCoords,
+    VertexAddr,
 };
 
+#[inline]
+pub fn scaled_coords(vert: &ScreenVertex, resolution_scale_shift: u8) -> [u16; 2] {
+    // Hi-res coordinates have 4 fractional bits
+    if resolution_scale_shift == 0 {
+        vert.coords.to_array()
+    } else {
+        (vert.hi_res_coords >> ScreenCoords::splat(4 - resolution_scale_shift as u16)).to_array()
+    }
+}
+
 #[inline]
 pub fn expand_depth(depth: u16) -> u32 {
     let depth = depth as u32;
@@ -27,6 +38,32 @@ pub fn rgb5_to_rgb6(color: InterpColor) -> InterpColor {
     result
 }
 
+#[inline]
+pub fn fog_density(densities: &[u8; 0x20], offset: u32, shift: u8, depth: u32) -> u16 {
+    // The density table is indexed by bits 17 and up of the shifted depth difference, with the
+    // first entry being used for all depths up to the fog offset and the last one for all depths
+    // past the end of the table; bits 0-16 are used to interpolate between adjacent entries
+    let (index, fract) = if depth < offset {
+        (0, 0)
+    } else {
+        let z = ((depth - offset) >> 2) << shift;
+        let index = (z >> 17) as usize;
+        if index >= 0x20 {
+            (0x20, 0)
+        } else {
+            (index, z & 0x1_FFFF)
+        }
+    };
+    let a = densities[index.saturating_sub(1)] as u32;
+    let b = densities[index.min(0x1F)] as u32;
+    let density = (a * (0x2_0000 - fract) + b * fract) >> 17;
+    if density >= 127 {
+        128
+    } else {
+        density as u16
+    }
+}
+
 #[inline]
 pub fn inc_poly_vert_index(i: PolyVertIndex, verts: PolyVertsLen) -> PolyVertIndex {
     let new = i.get() + 1;
@@ -49,12 +86,12 @@ pub fn dec_poly_vert_index(i: PolyVertIndex, verts: PolyVertsLen) -> PolyVertInd
 #[derive(Clone, Copy, Debug)]
 pub struct Edge {
     a_addr: VertexAddr,
-    a_y: u8,
+    a_y: u16,
     a_z: u32,
     a_w: u16,
 
     b_addr: VertexAddr,
-    b_y: u8,
+    b_y: u16,
     b_z: u32,
     b_w: u16,
 
@@ -70,6 +107,7 @@ pub struct Edge {
 }
 
 impl Edge {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         poly: &Polygon,
         a_i: PolyVertIndex,
@@ -78,18 +116,17 @@ impl Edge {
         b_i: PolyVertIndex,
         b_addr: VertexAddr,
         b: &ScreenVertex,
+        resolution_scale_shift: u8,
     ) -> Self {
         // Slope calculation based on https://github.com/StrikerX3/nds-interp
 
         let a_w = poly.w_values[a_i.get() as usize];
         let b_w = poly.w_values[b_i.get() as usize];
 
-        let a_x = a.coords[0];
-        let b_x = b.coords[0];
-        let a_y = a.coords[1] as u8;
-        let b_y = b.coords[1] as u8;
+        let [a_x, a_y] = scaled_coords(a, resolution_scale_shift);
+        let [b_x, b_y] = scaled_coords(b, resolution_scale_shift);
         let x_diff = b_x as i16 - a_x as i16;
-        let y_len = (b_y - a_y) as u16;
+        let y_len = b_y - a_y;
 
         let mut x_ref = (a_x as i32) << 18;
 
@@ -133,7 +170,7 @@ impl Edge {
             is_x_major,
             is_negative,
 
-            interp_ref: if is_x_major { a_x.min(b_x) } else { a_y as u16 },
+            interp_ref: if is_x_major { a_x.min(b_x) } else { a_y },
             interp_len: if is_x_major { x_len } else { y_len },
             interp_data: InterpLineData::new(a_w, b_w),
         }
@@ -155,7 +192,7 @@ impl Edge {
         self.b_addr
     }
 
-    pub fn b_y(&self) -> u8 {
+    pub fn b_y(&self) -> u16 {
         self.b_y
     }
 
@@ -179,25 +216,30 @@ impl Edge {
         self.is_x_major
     }
 
-    pub fn line_x_range(&self, y: u8) -> (u16, u16) {
+    fn line_start_frac_x(&self, y: u16) -> i32 {
         let line_x_disp = self.x_incr * (y - self.a_y) as i32;
-        let start_frac_x = if self.is_negative {
+        if self.is_negative {
             self.x_ref - line_x_disp
         } else {
             self.x_ref + line_x_disp
-        };
-        let start_x = (start_frac_x >> 18).clamp(0, 255) as u16;
+        }
+    }
+
+    pub fn line_x_range(&self, y: u16, x_max: u16) -> (u16, u16) {
+        let x_max = x_max as i32;
+        let start_frac_x = self.line_start_frac_x(y);
+        let start_x = (start_frac_x >> 18).clamp(0, x_max) as u16;
         if self.is_x_major {
             if self.is_negative {
                 (
                     (((start_frac_x + (0x1FF - (start_frac_x & 0x1FF)) - self.x_incr) >> 18) + 1)
-                        .clamp(0, 255) as u16,
+                        .clamp(0, x_max) as u16,
                     start_x,
                 )
             } else {
                 (
                     start_x,
-                    (((((start_frac_x & !0x1FF) + self.x_incr) >> 18) - 1).clamp(0, 255) as u16),
+                    (((((start_frac_x & !0x1FF) + self.x_incr) >> 18) - 1).clamp(0, x_max) as u16),
                 )
             }
         } else {
@@ -205,7 +247,33 @@ impl Edge {
         }
     }
 
-    pub fn edge_interp(&self, y: u8, x: u16) -> InterpData<true> {
+    // Returns how much of the pixel at `x` the polygon covers, from 0 to 0x1F, given the range of
+    // pixels the edge spans on line `y` and the side of the polygon it's on
+    pub fn coverage(&self, y: u16, x: u16, x_range: (u16, u16), is_right: bool) -> u8 {
+        if self.is_x_major {
+            // The edge crosses the whole span diagonally, so coverage increases linearly towards
+            // the inside of the polygon
+            let len = (x_range.1 - x_range.0 + 1) as u32;
+            let pos = (x - x_range.0) as u32;
+            let coverage = (((pos << 1 | 1) << 4) / len).min(0x1F) as u8;
+            if is_right {
+                0x1F - coverage
+            } else {
+                coverage
+            }
+        } else {
+            // The edge crosses a single pixel, covering it up to the fractional part of its X
+            // coordinate
+            let frac_x = (self.line_start_frac_x(y) >> 13 & 0x1F) as u8;
+            if is_right {
+                frac_x
+            } else {
+                0x1F - frac_x
+            }
+        }
+    }
+
+    pub fn edge_interp(&self, y: u16, x: u16) -> InterpData<true> {
         self.interp_data.set_x(
             if self.is_x_major {
                 let rel = x - self.interp_ref;
@@ -215,7 +283,7 @@ impl Edge {
                     rel
                 }
             } else {
-                y as u16 - self.interp_ref
+                y - self.interp_ref
             },
             self.interp_len,
         )
@@ -276,9 +344,10 @@ impl<const EDGE: bool> InterpLineData<EDGE> {
             },
         );
         let p_factor = {
-            let numer = (x as u32 * self.p_w0_numer as u32) << Self::PERSP_PRECISION;
+            // Spans can be up to 1024 pixels long when rendering at higher resolutions
+            let numer = (x as u64 * self.p_w0_numer as u64) << Self::PERSP_PRECISION;
             let denom =
-                x as u32 * self.p_w0_denom as u32 + (len - x) as u32 * self.p_w1_denom as u32;
+                x as u64 * self.p_w0_denom as u64 + (len - x) as u64 * self.p_w1_denom as u64;
             if denom == 0 {
                 // TODO: ???
                 0
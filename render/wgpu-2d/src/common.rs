@@ -42,12 +42,15 @@ impl<R: Role, B: Buffers, D: RenderingData, V: Vram<R>> FnPtrs<R, B, D, V> {
     }
 }
 
+#[repr(C)]
 #[derive(Clone, Copy, Default)]
 pub struct ScanlineFlags {
     pub master_brightness_control: u32,
     pub color_effects_control: u32,
     pub blend_coeffs: u32,
     pub brightness_coeff: u32,
+    pub bg0_x_scroll: u32,
+    _padding: [u32; 3],
 }
 
 impl ScanlineFlags {
@@ -59,6 +62,8 @@ impl ScanlineFlags {
             color_effects_control: 0,
             blend_coeffs: 0,
             brightness_coeff: 0,
+            bg0_x_scroll: 0,
+            _padding: [0; 3],
         }
     }
 
@@ -67,11 +72,13 @@ impl ScanlineFlags {
         color_effects_control: ColorEffectsControl,
         blend_coeffs: (u8, u8),
         brightness_coeff: u8,
+        bg0_x_scroll: u16,
     ) -> Self {
         ScanlineFlags {
             color_effects_control: color_effects_control.0 as u32,
             blend_coeffs: blend_coeffs.0 as u32 | (blend_coeffs.1 as u32) << 16,
             brightness_coeff: brightness_coeff as u32,
+            bg0_x_scroll: (bg0_x_scroll & 0x1FF) as u32,
             ..Self::master_brightness_only(master_brightness_control)
         }
     }
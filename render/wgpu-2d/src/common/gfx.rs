@@ -3,6 +3,7 @@ use dust_core::gpu::{engine_3d, Scanline, SCREEN_HEIGHT, SCREEN_WIDTH};
 use emu_utils::triple_buffer;
 use parking_lot::RwLock;
 use std::{
+    mem,
     num::{NonZeroU32, NonZeroU64},
     slice,
     sync::{
@@ -545,7 +546,7 @@ impl GfxThreadData {
 
         let fb_scanline_flags_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("2D renderer framebuffer scanline flags"),
-            size: (SCREEN_HEIGHT * 2 * 16) as u64,
+            size: (SCREEN_HEIGHT * 2 * mem::size_of::<ScanlineFlags>()) as u64,
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
@@ -751,7 +752,7 @@ impl GfxThreadData {
                     .write_buffer(&self.fb_scanline_flags_buffer, 0, unsafe {
                         slice::from_raw_parts(
                             frame.fb_scanline_flags.as_ptr() as *const u8,
-                            SCREEN_HEIGHT * 2 * 16,
+                            SCREEN_HEIGHT * 2 * mem::size_of::<ScanlineFlags>(),
                         )
                     });
 
@@ -10,6 +10,19 @@ use dust_core::gpu::{
 };
 use dust_soft_2d_base::rgb5_to_rgb6_64;
 
+pub fn render_scanline_main_mem_display<R: Role>(
+    scanline_buffer: &mut Scanline<BgObjPixel>,
+    engine: &Engine2d<R>,
+) {
+    for (pixel, src) in scanline_buffer
+        .0
+        .iter_mut()
+        .zip(&engine.main_mem_display_scanline().0)
+    {
+        *pixel = BgObjPixel(rgb5_to_rgb6_64(*src));
+    }
+}
+
 pub fn render_scanline_vram_display<R: Role>(
     scanline_buffer: &mut Scanline<BgObjPixel>,
     vcount: u8,
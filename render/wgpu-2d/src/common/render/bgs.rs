@@ -3,14 +3,20 @@ pub mod all;
 pub mod avx2;
 
 use crate::common::BgObjPixel;
-use dust_core::gpu::Scanline;
+use dust_core::gpu::{Scanline, SCREEN_WIDTH};
 
 pub fn patch_scanline_bg_3d(
     bg_obj_scanline: &mut Scanline<BgObjPixel>,
     scanline_3d: &Scanline<u32>,
+    x_scroll: u16,
 ) {
     for (i, pixel) in bg_obj_scanline.0.iter_mut().enumerate() {
-        let new_pixel = scanline_3d.0[i];
+        let x = (i + x_scroll as usize) & 0x1FF;
+        let new_pixel = if x < SCREEN_WIDTH {
+            scanline_3d.0[x]
+        } else {
+            0
+        };
         if pixel.is_3d() {
             if new_pixel >> 18 == 0 {
                 pixel.0 >>= 32;
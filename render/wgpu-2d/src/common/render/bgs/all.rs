@@ -114,7 +114,7 @@ pub fn render_scanline_bgs_and_objs<
         if data.bg_priority(BgIndex::new(0)) == priority {
             if R::IS_A && data.control().bg0_3d() {
                 if data.engine_3d_enabled_in_frame() {
-                    render_scanline_bg_3d(buffers);
+                    render_scanline_bg_3d(buffers, data.bg_scroll(BgIndex::new(0))[0]);
                 }
             } else if BG_MODE != 6 {
                 render_scanline_bg_text(buffers, BgIndex::new(0), vcount, data, vram);
@@ -153,16 +153,15 @@ pub fn render_scanline_bgs_and_objs<
     }
 }
 
-pub fn render_scanline_bg_3d<B: Buffers>(buffers: &mut B) {
-    // TODO: 3D layer scrolling
-
+pub fn render_scanline_bg_3d<B: Buffers>(buffers: &mut B, x_scroll: u16) {
     let pixel_attrs = BgObjPixel(0).with_color_effects_mask(1).with_is_3d(true);
 
     let scanline = unsafe { buffers.bg_obj_scanline() };
     let window = unsafe { buffers.window() };
 
     for i in 0..SCREEN_WIDTH {
-        if window.0[i].0 & 1 != 0 {
+        // The 3D layer only covers the first 256 pixels of BG0's 512-pixel wide scrolling area
+        if (i + x_scroll as usize) & 0x1FF < SCREEN_WIDTH && window.0[i].0 & 1 != 0 {
             scanline.0[i].0 = scanline.0[i].0 << 32 | pixel_attrs.0;
         }
     }
@@ -117,7 +117,7 @@ pub unsafe fn render_scanline_bgs_and_objs<
 
         if data.bg_priority(BgIndex::new(0)) == priority {
             if R::IS_A && data.control().bg0_3d() {
-                render_scanline_bg_3d(buffers);
+                render_scanline_bg_3d(buffers, data.bg_scroll(BgIndex::new(0))[0]);
             } else if BG_MODE != 6 {
                 render_scanline_bg_text(buffers, BgIndex::new(0), vount, data, vram);
             }
@@ -157,9 +157,7 @@ pub unsafe fn render_scanline_bgs_and_objs<
 }
 
 #[target_feature(enable = "sse4.1,sse4.2,avx,avx2")]
-pub unsafe fn render_scanline_bg_3d<B: Buffers>(buffers: &mut B) {
-    // TODO: 3D layer scrolling
-
+pub unsafe fn render_scanline_bg_3d<B: Buffers>(buffers: &mut B, x_scroll: u16) {
     let zero = _mm256_setzero_si256();
 
     let pixel_attrs =
@@ -168,14 +166,27 @@ pub unsafe fn render_scanline_bg_3d<B: Buffers>(buffers: &mut B) {
     let scanline = unsafe { buffers.bg_obj_scanline() };
     let window = unsafe { buffers.window() };
 
+    // The 3D layer only covers the first 256 pixels of BG0's 512-pixel wide scrolling area
+    let x_mask = _mm256_set1_epi64x(0x1FF);
+    let x_limit = _mm256_set1_epi64x(SCREEN_WIDTH as i64);
+    let mut x = _mm256_add_epi64(
+        _mm256_set1_epi64x(x_scroll as i64),
+        _mm256_set_epi64x(3, 2, 1, 0),
+    );
+    let x_incr = _mm256_set1_epi64x(4);
+
     for i in (0..SCREEN_WIDTH).step_by(4) {
         let scanline_ptr = scanline.0.as_mut_ptr().add(i);
         let window_ptr = window.0.as_ptr().add(i) as *const u32;
 
-        let modify_mask = _mm256_slli_epi64::<63>(_mm256_cvtepu8_epi64(_mm_set_epi64x(
-            0,
-            window_ptr.read() as i64,
-        )));
+        let modify_mask = _mm256_and_si256(
+            _mm256_slli_epi64::<63>(_mm256_cvtepu8_epi64(_mm_set_epi64x(
+                0,
+                window_ptr.read() as i64,
+            ))),
+            _mm256_cmpgt_epi64(x_limit, _mm256_and_si256(x, x_mask)),
+        );
+        x = _mm256_add_epi64(x, x_incr);
 
         let new_pixels = _mm256_or_si256(
             pixel_attrs,
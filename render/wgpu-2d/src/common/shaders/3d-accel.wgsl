@@ -32,6 +32,8 @@ struct ScanlineFlags {
     color_effects_control: u32,
     blend_coeffs: u32,
     brightness_coeff: u32,
+    // Padded to 32 bytes to match the Rust side, as uniform array strides must be multiples of 16
+    @size(16) bg0_x_scroll: u32,
 }
 
 @group(0) @binding(0) var t_output_2d: texture_2d<u32>;
@@ -60,7 +62,11 @@ fn fs_main(
     let scanline_flags = scanline_flags[screen_index][scanline_index];
     let pixel = textureLoad(t_output_2d, vec2<i32>(uv * vec2<f32>(256.0, 384.0)), 0);
 
-    let uv_3d = fract(uv * vec2<f32>(1.0, 2.0));
+    // The 3D layer is scrolled along with BG0; pixels outside of it are never marked as 3D, so
+    // wrapping within the 3D layer's width is enough here
+    var uv_3d = fract(uv * vec2<f32>(1.0, 2.0));
+    let x_3d = (u32(uv_3d.x * 256.0) + scanline_flags.bg0_x_scroll) & 0xFFu;
+    uv_3d.x = (f32(x_3d) + fract(uv_3d.x * 256.0)) * (1.0 / 256.0);
     let pixel_3d =
         textureLoad(t_output_3d, vec2<i32>(uv_3d * vec2<f32>(textureDimensions(t_output_3d))), 0);
 
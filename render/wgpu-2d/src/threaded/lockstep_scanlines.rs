@@ -410,6 +410,10 @@ impl RendererTrait for Renderer {
         unimplemented!();
     }
 
+    fn hi_res_framebuffer(&self) -> Option<(u8, &[u32])> {
+        None
+    }
+
     fn start_prerendering_objs(
         &mut self,
         engines: (&mut Engine2d<EngineA>, &mut Engine2d<EngineB>),
@@ -448,7 +452,6 @@ impl RendererTrait for Renderer {
             {
                 self.flush_vram_updates::<EngineA>(vram);
             }
-            #[allow(clippy::match_same_arms)]
             match display_mode {
                 2 => render::render_scanline_vram_display(
                     unsafe {
@@ -460,9 +463,14 @@ impl RendererTrait for Renderer {
                     engines.0,
                     vram,
                 ),
-                3 => {
-                    // TODO: Main memory display mode
-                }
+                3 => render::render_scanline_main_mem_display(
+                    unsafe {
+                        (&mut *self.shared_data.framebuffer.get())
+                            [engines.0.is_on_lower_screen() as usize]
+                            .get_unchecked_mut(line as usize)
+                    },
+                    engines.0,
+                ),
                 _ => {}
             }
         }
@@ -515,6 +523,7 @@ impl RendererTrait for Renderer {
                     .0
                     .engine_3d_enabled_in_frame()
                     .then_some(scanline_3d),
+                engines.0.main_mem_display_scanline(),
                 vram,
             )
         }
@@ -723,6 +732,7 @@ impl ThreadData {
                             data.color_effects_control,
                             data.blend_coeffs,
                             data.brightness_coeff,
+                            data.bgs[0].scroll[0],
                         );
                         for i in 0..SCREEN_WIDTH {
                             if !buffers.window.get_mut().0[i].color_effects_enabled() {
@@ -731,7 +741,7 @@ impl ThreadData {
                         }
                     }
 
-                    2 => {
+                    2 | 3 => {
                         *scanline_flags =
                             ScanlineFlags::master_brightness_only(data.master_brightness_control);
                     }
@@ -755,6 +765,7 @@ impl ThreadData {
                             render::bgs::patch_scanline_bg_3d(
                                 buffers.bg_obj_scanline.get_mut(),
                                 scanline_3d,
+                                data.bgs[0].scroll[0],
                             );
                         }
                     }
@@ -11,6 +11,9 @@
 mod data;
 pub use data::{FrameData, GxData, RenderingData};
 mod render;
+mod texture_cache;
+#[cfg(feature = "texture-replacement")]
+pub use texture_cache::TexturePackConfig;
 #[cfg(feature = "threaded")]
 pub mod threaded;
 mod utils;
@@ -24,6 +27,7 @@ use core::{
 };
 use dust_core::gpu::engine_3d::{Color, Polygon, RenderingControl, ScreenVertex, TextureParams};
 use std::sync::Arc;
+use texture_cache::{TextureCache, TextureContents};
 use utils::{color_to_wgpu_f64, decode_rgb5, round_up_to_alignment};
 use wgpu::util::DeviceExt;
 
@@ -33,18 +37,13 @@ proc_bitfield::bitfield! {
         pub texture_mapping_enabled: bool @ 0,
         pub highlight_shading_enabled: bool @ 1,
         pub alpha_blending_enabled: bool @ 3,
-        // pub antialiasing_enabled: bool @ 4,
-
-        // TODO: These may require other special handling
-        // pub edge_marking_enabled: bool @ 5,
-        // pub fog_only_alpha: bool @ 6,
-        // pub fog_enabled: bool @ 7,
+        pub fog_enabled: bool @ 7,
     }
 }
 
 impl From<RenderingControl> for ControlFlags {
     fn from(other: RenderingControl) -> Self {
-        ControlFlags(other.0 as u8 & 0xB)
+        ControlFlags(other.0 as u8 & 0x8B)
     }
 }
 
@@ -95,13 +94,15 @@ proc_bitfield::bitfield! {
         pub mode: u8 @ 3..=4,
         pub is_shadow: bool @ 5,
         pub w_buffering: bool @ 6,
+        pub fog_enabled: bool @ 7,
+        pub back_layer: bool @ 8,
     }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 enum BatchKind {
     ShadowMask {
-        depth_test_equal: bool,
+        pipeline: PipelineKey,
     },
     Opaque {
         pipeline: PipelineKey,
@@ -132,7 +133,11 @@ impl BatchKind {
         let depth_test_equal = poly.attrs.depth_test_equal();
         let is_shadow = mode == 3;
         if is_shadow && id == 0 {
-            BatchKind::ShadowMask { depth_test_equal }
+            BatchKind::ShadowMask {
+                pipeline: PipelineKey(0)
+                    .with_depth_test_equal(depth_test_equal)
+                    .with_w_buffering(w_buffering),
+            }
         } else {
             let texture_mapping_enabled =
                 control.texture_mapping_enabled() && poly.tex_params.format() != 0;
@@ -152,7 +157,8 @@ impl BatchKind {
                     }
                 })
                 .with_is_shadow(is_shadow)
-                .with_w_buffering(w_buffering);
+                .with_w_buffering(w_buffering)
+                .with_fog_enabled(control.fog_enabled() && poly.attrs.fog_enabled());
             let texture = texture_mapping_enabled.then(|| {
                 (
                     TextureKey::new(poly.tex_params, poly.tex_palette_base),
@@ -162,7 +168,10 @@ impl BatchKind {
 
             let alpha = poly.attrs.alpha();
 
-            if poly.is_translucent {
+            // Shadow polygons always go through the translucent pipelines, as their shaders check
+            // the shadow mask; pixels with an alpha of 31 are still drawn as opaque ones by the
+            // pipelines' opaque pass
+            if poly.is_translucent || (is_shadow && alpha != 0) {
                 if poly.attrs.update_depth_for_translucent() {
                     BatchKind::Translucent {
                         pipeline,
@@ -185,10 +194,20 @@ impl BatchKind {
             }
         }
     }
+
+    fn is_shadow(&self) -> bool {
+        match self {
+            BatchKind::ShadowMask { .. } => true,
+            BatchKind::Opaque { pipeline, .. }
+            | BatchKind::Translucent { pipeline, .. }
+            | BatchKind::TranslucentNoDepthUpdate { pipeline, .. }
+            | BatchKind::Wireframe { pipeline, .. } => pipeline.is_shadow(),
+        }
+    }
 }
 
 struct Texture {
-    view: wgpu::TextureView,
+    contents: Arc<TextureContents>,
     texture_region_mask: u8,
     tex_pal_region_mask: u8,
 }
@@ -196,7 +215,8 @@ struct Texture {
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 enum PreparedBatchKind {
     ShadowMask {
-        depth_test_equal: bool,
+        pipeline: PipelineKey,
+        starts_group: bool,
     },
     Opaque {
         pipeline: Option<PipelineKey>,
@@ -226,6 +246,7 @@ enum PreparedBatchKind {
 struct PreparedBatch {
     kind: PreparedBatchKind,
     idxs: u16,
+    starts_render_pass: bool,
 }
 
 #[repr(C)]
@@ -236,6 +257,8 @@ struct Vertex {
     pub uv: [i16; 2],
     pub color: [u16; 4],
     pub id: u32,
+    pub edges: [u8; 4],
+    pub y_range: [u16; 2],
 }
 
 impl Vertex {
@@ -245,6 +268,8 @@ impl Vertex {
         depth: u32,
         w: u16,
         id: u8,
+        edges: [u8; 4],
+        y_range: [u16; 2],
     ) -> Self {
         Vertex {
             coords: raw.hi_res_coords.to_array(),
@@ -254,37 +279,22 @@ impl Vertex {
             uv: raw.uv.to_array(),
             color: raw.color.to_array(),
             id: id as u32,
+            edges,
+            y_range,
         }
     }
 }
 
-fn create_texture(
-    device: &wgpu::Device,
-    queue: &wgpu::Queue,
+// Decodes the texture into `decode_buffer`, returning the masks of the texture and texture palette
+// VRAM regions it was read from
+fn decode_texture(
     texture_key: TextureKey,
     frame: &FrameData,
     decode_buffer: &mut Vec<u32>,
-) -> Texture {
+) -> (u8, u8) {
     let width = 8 << texture_key.width_shift();
-    let height = 8 << texture_key.height_shift();
     let total_shift = texture_key.width_shift() + texture_key.height_shift();
 
-    let size = wgpu::Extent3d {
-        width,
-        height,
-        depth_or_array_layers: 1,
-    };
-
-    let raw = device.create_texture(&wgpu::TextureDescriptor {
-        label: Some("3D renderer texture"),
-        size,
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Rgba8Unorm,
-        usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
-    });
-
     decode_buffer.clear();
 
     let tex_base = (texture_key.vram_offset() as usize) << 3;
@@ -535,26 +545,7 @@ fn create_texture(
         }
     }
 
-    unsafe {
-        queue.write_texture(
-            raw.as_image_copy(),
-            slice::from_raw_parts(decode_buffer.as_ptr() as *const u8, decode_buffer.len() * 4),
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(NonZeroU32::new_unchecked(width << 2)),
-                rows_per_image: None,
-            },
-            size,
-        );
-    }
-
-    let view = raw.create_view(&wgpu::TextureViewDescriptor::default());
-
-    Texture {
-        view,
-        texture_region_mask,
-        tex_pal_region_mask: tex_pal_region_mask & 0x3F,
-    }
+    (texture_region_mask, tex_pal_region_mask & 0x3F)
 }
 
 fn create_sampler(device: &wgpu::Device, sampler_key: SamplerKey) -> wgpu::Sampler {
@@ -582,58 +573,330 @@ fn create_sampler(device: &wgpu::Device, sampler_key: SamplerKey) -> wgpu::Sampl
     })
 }
 
+// Per-pixel attributes read by the final pass: the opaque polygon ID in R and the fog flag in G
+const ATTRS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Uint;
+
 struct OutputAttachments {
+    size: wgpu::Extent3d,
     color: wgpu::Texture,
     color_view: wgpu::TextureView,
+    raw_color_view: wgpu::TextureView,
+    attrs: wgpu::Texture,
+    attrs_view: wgpu::TextureView,
+    // Copies of the attributes and of the stencil buffer taken before drawing each group of shadow
+    // polygons, used to check the shadow mask and the IDs of the opaque pixels below them
+    shadow_attrs: wgpu::Texture,
+    shadow_attrs_view: wgpu::TextureView,
+    shadow_stencil_buffer: wgpu::Buffer,
+    shadow_stencil: wgpu::Texture,
+    shadow_stencil_view: wgpu::TextureView,
+    depth: wgpu::Texture,
     depth_view: wgpu::TextureView,
+    depth_texture_view: wgpu::TextureView,
+    // Closest opaque pixels behind the ones above, only drawn when anti-aliasing is enabled
+    back_color_view: wgpu::TextureView,
+    back_attrs_view: wgpu::TextureView,
+    back_depth_view: wgpu::TextureView,
+    back_depth_texture_view: wgpu::TextureView,
 }
 
 impl OutputAttachments {
     fn new(device: &wgpu::Device, resolution_scale_shift: u8) -> Self {
         let resolution_scale = 1 << resolution_scale_shift;
+        let size = wgpu::Extent3d {
+            width: 256 * resolution_scale,
+            height: 192 * resolution_scale,
+            depth_or_array_layers: 1,
+        };
 
-        let color = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("3D renderer color"),
-            size: wgpu::Extent3d {
-                width: 256 * resolution_scale,
-                height: 192 * resolution_scale,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-        });
+        let create_texture = |label, format, usage| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | usage,
+            })
+        };
+
+        let color = create_texture(
+            "3D renderer color",
+            wgpu::TextureFormat::Rgba8Unorm,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        );
         let color_view = color.create_view(&wgpu::TextureViewDescriptor {
             label: Some("3D renderer color view"),
             ..wgpu::TextureViewDescriptor::default()
         });
 
-        let depth = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("3D renderer depth"),
-            size: wgpu::Extent3d {
-                width: 256 * resolution_scale,
-                height: 192 * resolution_scale,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Depth32FloatStencil8,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        let raw_color = create_texture(
+            "3D renderer raw color",
+            wgpu::TextureFormat::Rgba8Unorm,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+        );
+        let raw_color_view = raw_color.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("3D renderer raw color view"),
+            ..wgpu::TextureViewDescriptor::default()
+        });
+
+        let attrs = create_texture(
+            "3D renderer attributes",
+            ATTRS_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        );
+        let attrs_view = attrs.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("3D renderer attributes view"),
+            ..wgpu::TextureViewDescriptor::default()
+        });
+
+        let shadow_attrs = create_texture(
+            "3D renderer shadow attributes",
+            ATTRS_FORMAT,
+            wgpu::TextureUsages::COPY_DST,
+        );
+        let shadow_attrs_view = shadow_attrs.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("3D renderer shadow attributes view"),
+            ..wgpu::TextureViewDescriptor::default()
+        });
+
+        // The stencil aspect can't be copied to a texture directly, so it goes through a buffer
+        // (each row is already a multiple of 256 bytes long)
+        let shadow_stencil_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("3D renderer shadow stencil buffer"),
+            size: (size.width * size.height) as u64,
+            usage: wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let shadow_stencil = create_texture(
+            "3D renderer shadow stencil",
+            wgpu::TextureFormat::R8Uint,
+            wgpu::TextureUsages::COPY_DST,
+        );
+        let shadow_stencil_view = shadow_stencil.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("3D renderer shadow stencil view"),
+            ..wgpu::TextureViewDescriptor::default()
         });
+
+        let depth = create_texture(
+            "3D renderer depth",
+            wgpu::TextureFormat::Depth32FloatStencil8,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        );
         let depth_view = depth.create_view(&wgpu::TextureViewDescriptor {
             label: Some("3D renderer depth view"),
             ..wgpu::TextureViewDescriptor::default()
         });
+        let depth_texture_view = depth.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("3D renderer depth texture view"),
+            aspect: wgpu::TextureAspect::DepthOnly,
+            ..wgpu::TextureViewDescriptor::default()
+        });
+
+        let back_color_view = create_texture(
+            "3D renderer back color",
+            wgpu::TextureFormat::Rgba8Unorm,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+        )
+        .create_view(&wgpu::TextureViewDescriptor {
+            label: Some("3D renderer back color view"),
+            ..wgpu::TextureViewDescriptor::default()
+        });
+        let back_attrs_view = create_texture(
+            "3D renderer back attributes",
+            ATTRS_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+        )
+        .create_view(&wgpu::TextureViewDescriptor {
+            label: Some("3D renderer back attributes view"),
+            ..wgpu::TextureViewDescriptor::default()
+        });
+        let back_depth = create_texture(
+            "3D renderer back depth",
+            wgpu::TextureFormat::Depth32FloatStencil8,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+        );
+        let back_depth_view = back_depth.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("3D renderer back depth view"),
+            ..wgpu::TextureViewDescriptor::default()
+        });
+        let back_depth_texture_view = back_depth.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("3D renderer back depth texture view"),
+            aspect: wgpu::TextureAspect::DepthOnly,
+            ..wgpu::TextureViewDescriptor::default()
+        });
 
         OutputAttachments {
+            size,
             color,
             color_view,
+            raw_color_view,
+            attrs,
+            attrs_view,
+            shadow_attrs,
+            shadow_attrs_view,
+            shadow_stencil_buffer,
+            shadow_stencil,
+            shadow_stencil_view,
+            depth,
             depth_view,
+            depth_texture_view,
+            back_color_view,
+            back_attrs_view,
+            back_depth_view,
+            back_depth_texture_view,
         }
     }
+
+    fn create_shadow_bg(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("3D renderer shadow bind group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&self.shadow_attrs_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&self.shadow_stencil_view),
+                },
+            ],
+        })
+    }
+
+    fn create_front_depth_bg(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("3D renderer front depth bind group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&self.depth_texture_view),
+            }],
+        })
+    }
+
+    fn copy_shadow_state(&self, command_encoder: &mut wgpu::CommandEncoder) {
+        command_encoder.copy_texture_to_texture(
+            self.attrs.as_image_copy(),
+            self.shadow_attrs.as_image_copy(),
+            self.size,
+        );
+        let layout = wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: NonZeroU32::new(self.size.width),
+            rows_per_image: None,
+        };
+        command_encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.depth,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::StencilOnly,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.shadow_stencil_buffer,
+                layout,
+            },
+            self.size,
+        );
+        command_encoder.copy_buffer_to_texture(
+            wgpu::ImageCopyBuffer {
+                buffer: &self.shadow_stencil_buffer,
+                layout,
+            },
+            self.shadow_stencil.as_image_copy(),
+            self.size,
+        );
+    }
+
+    // Begins a render pass to the front or back layer attachments, clearing them according to the
+    // rendering state if it's specified, or keeping their contents otherwise
+    fn begin_render_pass<'a>(
+        &'a self,
+        command_encoder: &'a mut wgpu::CommandEncoder,
+        clear_state: Option<&RenderingData>,
+        back_layer: bool,
+    ) -> wgpu::RenderPass<'a> {
+        let (color_view, attrs_view, depth_view) = if back_layer {
+            (
+                &self.back_color_view,
+                &self.back_attrs_view,
+                &self.back_depth_view,
+            )
+        } else {
+            (&self.raw_color_view, &self.attrs_view, &self.depth_view)
+        };
+        let color_load = clear_state.map_or(wgpu::LoadOp::Load, |rendering| {
+            wgpu::LoadOp::Clear(if rendering.control.rear_plane_bitmap_enabled() {
+                wgpu::Color::BLACK
+            } else {
+                color_to_wgpu_f64(rendering.clear_color)
+            })
+        });
+        let attrs_load = clear_state.map_or(wgpu::LoadOp::Load, |rendering| {
+            wgpu::LoadOp::Clear(wgpu::Color {
+                r: rendering.clear_poly_id as f64,
+                g: rendering.rear_plane_fog_enabled as u8 as f64,
+                b: 0.0,
+                a: 0.0,
+            })
+        });
+        let depth_load = clear_state.map_or(wgpu::LoadOp::Load, |rendering| {
+            wgpu::LoadOp::Clear(if rendering.control.rear_plane_bitmap_enabled() {
+                0.0
+            } else {
+                rendering.clear_depth as f32 / (1 << 24) as f32
+            })
+        });
+        let stencil_load = if clear_state.is_some() {
+            wgpu::LoadOp::Clear(0)
+        } else {
+            wgpu::LoadOp::Load
+        };
+
+        command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("3D renderer render pass"),
+            color_attachments: &[
+                Some(wgpu::RenderPassColorAttachment {
+                    view: color_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: color_load,
+                        store: true,
+                    },
+                }),
+                Some(wgpu::RenderPassColorAttachment {
+                    view: attrs_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: attrs_load,
+                        store: true,
+                    },
+                }),
+            ],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: depth_load,
+                    store: true,
+                }),
+                stencil_ops: Some(wgpu::Operations {
+                    load: stencil_load,
+                    store: true,
+                }),
+            }),
+        })
+    }
 }
 
 pub struct Renderer {
@@ -659,7 +922,10 @@ pub struct Renderer {
     pipelines: HashMap<PipelineKey, wgpu::RenderPipeline>,
     trans_pipelines: HashMap<PipelineKey, [wgpu::RenderPipeline; 2]>,
     trans_no_depth_update_pipelines: HashMap<PipelineKey, [wgpu::RenderPipeline; 2]>,
+    shadow_mask_pipelines: HashMap<PipelineKey, wgpu::RenderPipeline>,
+    shadow_mask_clear_pipeline: wgpu::RenderPipeline,
     textures: HashMap<TextureKey, Texture>,
+    texture_cache: TextureCache,
     samplers: [Option<wgpu::Sampler>; 0x10],
     texture_bg_layout: wgpu::BindGroupLayout,
     texture_bgs: HashMap<(TextureKey, SamplerKey), wgpu::BindGroup>,
@@ -672,6 +938,17 @@ pub struct Renderer {
     alpha_and_ref_bg: wgpu::BindGroup,
     alpha_and_ref_bg_elem_size: usize,
 
+    shadow_bg_layout: wgpu::BindGroupLayout,
+    shadow_bg: wgpu::BindGroup,
+
+    front_depth_bg_layout: wgpu::BindGroupLayout,
+    front_depth_bg: wgpu::BindGroup,
+
+    final_pass_pipeline: wgpu::RenderPipeline,
+    final_pass_bg_layout: wgpu::BindGroupLayout,
+    final_pass_uniform_buffer: wgpu::Buffer,
+    final_pass_bg: wgpu::BindGroup,
+
     texture_decode_buffer: Vec<u32>,
     batches: Vec<PreparedBatch>,
 }
@@ -691,7 +968,7 @@ impl Renderer {
 
         let vert_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("3D renderer vertices"),
-            size: mem::size_of::<Vertex>() as u64 * 6144,
+            size: mem::size_of::<Vertex>() as u64 * 2048 * (10 - 2) * 3,
             usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::VERTEX,
             mapped_at_creation: false,
         });
@@ -754,6 +1031,16 @@ impl Renderer {
                     ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(16),
+                    },
+                    count: None,
+                },
             ],
         });
 
@@ -833,6 +1120,51 @@ impl Renderer {
             }
         );
 
+        let shadow_bg_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("3D renderer shadow bind group layout"),
+            entries: &[0, 1].map(|binding| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Uint,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }),
+        });
+        let shadow_bg = output_attachments.create_shadow_bg(&device, &shadow_bg_layout);
+
+        let front_depth_bg_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("3D renderer front depth bind group layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                }],
+            });
+        let front_depth_bg =
+            output_attachments.create_front_depth_bg(&device, &front_depth_bg_layout);
+
+        let shadow_mask_clear_pipeline = render::shadow_mask::create_clear_pipeline(&device);
+
+        let final_pass_bg_layout = render::final_pass::create_bg_layout(&device);
+        let final_pass_pipeline =
+            render::final_pass::create_pipeline(&device, &final_pass_bg_layout);
+        let final_pass_uniform_buffer = render::final_pass::create_uniform_buffer(&device);
+        let final_pass_bg = render::final_pass::create_bg(
+            &device,
+            &final_pass_bg_layout,
+            &final_pass_uniform_buffer,
+            &output_attachments,
+        );
+
         Renderer {
             device,
             queue,
@@ -854,8 +1186,11 @@ impl Renderer {
             pipelines: HashMap::default(),
             trans_pipelines: HashMap::default(),
             trans_no_depth_update_pipelines: HashMap::default(),
+            shadow_mask_pipelines: HashMap::default(),
+            shadow_mask_clear_pipeline,
 
             textures: HashMap::default(),
+            texture_cache: TextureCache::new(),
             samplers: [const { None }; 0x10],
             texture_bg_layout,
             texture_bgs: HashMap::default(),
@@ -868,6 +1203,17 @@ impl Renderer {
             alpha_and_ref_bg_layout,
             alpha_and_ref_bg_elem_size,
 
+            shadow_bg_layout,
+            shadow_bg,
+
+            front_depth_bg_layout,
+            front_depth_bg,
+
+            final_pass_pipeline,
+            final_pass_bg_layout,
+            final_pass_uniform_buffer,
+            final_pass_bg,
+
             texture_decode_buffer: Vec::new(),
             batches: Vec::new(),
         }
@@ -894,6 +1240,32 @@ impl Renderer {
         }
         self.resolution_scale_shift = value;
         self.output_attachments = OutputAttachments::new(&self.device, value);
+        self.shadow_bg = self
+            .output_attachments
+            .create_shadow_bg(&self.device, &self.shadow_bg_layout);
+        self.front_depth_bg = self
+            .output_attachments
+            .create_front_depth_bg(&self.device, &self.front_depth_bg_layout);
+        self.final_pass_bg = render::final_pass::create_bg(
+            &self.device,
+            &self.final_pass_bg_layout,
+            &self.final_pass_uniform_buffer,
+            &self.output_attachments,
+        );
+    }
+
+    #[cfg(feature = "texture-replacement")]
+    pub fn set_texture_pack_config(&mut self, config: TexturePackConfig) {
+        if self.texture_cache.set_pack_config(config) {
+            // Textures need to be looked up again, as their replacements could have changed
+            self.textures.clear();
+            self.texture_bgs.clear();
+        }
+    }
+
+    #[inline]
+    pub fn output_texture(&self) -> &wgpu::Texture {
+        &self.output_attachments.color
     }
 
     pub fn create_output_view(&self) -> wgpu::TextureView {
@@ -902,6 +1274,53 @@ impl Renderer {
             .create_view(&Default::default())
     }
 
+    // Draws the opaque batches before `end` to the back layer, which keeps the closest pixels
+    // behind the ones in the front layer
+    fn draw_back_layer(
+        &self,
+        command_encoder: &mut wgpu::CommandEncoder,
+        rendering: &RenderingData,
+        end: usize,
+    ) {
+        let mut render_pass =
+            self.output_attachments
+                .begin_render_pass(command_encoder, Some(rendering), true);
+        render_pass.set_vertex_buffer(0, self.vtx_buffer.slice(..));
+        render_pass.set_index_buffer(self.idx_buffer.slice(..), wgpu::IndexFormat::Uint16);
+
+        let mut cur_idx_base = 0;
+        for batch in &self.batches[..end] {
+            if let PreparedBatchKind::Opaque { pipeline, texture } = batch.kind {
+                if let Some(pipeline) = pipeline {
+                    let pipeline = pipeline.with_back_layer(true);
+                    render_pass.set_pipeline(&self.pipelines[&pipeline]);
+                    if pipeline.mode() >= 2 {
+                        render_pass.set_bind_group(0, &self.toon_bg, &[])
+                    }
+                    render_pass.set_bind_group(
+                        (pipeline.mode() >= 2) as u32 + pipeline.texture_mapping_enabled() as u32,
+                        &self.front_depth_bg,
+                        &[],
+                    );
+                }
+
+                if let Some(Some((texture, bg_index))) = texture {
+                    render_pass.set_bind_group(bg_index as u32, &self.texture_bgs[&texture], &[]);
+                }
+
+                if batch.idxs != 0 {
+                    render_pass.draw_indexed(
+                        cur_idx_base..cur_idx_base + batch.idxs as u32,
+                        0,
+                        0..1,
+                    );
+                }
+            }
+
+            cur_idx_base += batch.idxs as u32;
+        }
+    }
+
     pub fn render_frame(&mut self, frame: &FrameData) -> wgpu::CommandBuffer {
         self.textures.retain(|_, texture| {
             (texture.texture_region_mask & frame.rendering.texture_dirty)
@@ -910,6 +1329,7 @@ impl Renderer {
         });
         self.texture_bgs
             .retain(|(texture, _), _| self.textures.contains_key(texture));
+        self.texture_cache.start_frame();
 
         let mut command_encoder =
             self.device
@@ -917,48 +1337,22 @@ impl Renderer {
                     label: Some("3D renderer command encoder"),
                 });
 
-        let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("3D renderer render pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &self.output_attachments.color_view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(
-                        if frame.rendering.control.rear_plane_bitmap_enabled() {
-                            wgpu::Color::BLACK
-                        } else {
-                            color_to_wgpu_f64(frame.rendering.clear_color)
-                        },
-                    ),
-                    store: true,
-                },
-            })],
-            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: &self.output_attachments.depth_view,
-                depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(
-                        if frame.rendering.control.rear_plane_bitmap_enabled() {
-                            0.0
-                        } else {
-                            frame.rendering.clear_depth as f32 / (1 << 24) as f32
-                        },
-                    ),
-                    store: false,
-                }),
-                stencil_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(0),
-                    store: false,
-                }),
-            }),
-        });
+        let mut render_pass = self.output_attachments.begin_render_pass(
+            &mut command_encoder,
+            Some(&frame.rendering),
+            false,
+        );
 
         if frame.rendering.control.rear_plane_bitmap_enabled() {
             // TODO
         }
 
+        let mut back_layer_pending = false;
+
         let polys = &frame.gx.poly_ram[..frame.gx.poly_ram_level as usize];
         if !polys.is_empty() && frame.rendering.alpha_test_ref < 0x1F {
             let control_flags = ControlFlags::from(frame.rendering.control);
+            let antialiasing_enabled = frame.rendering.control.antialiasing_enabled();
 
             let mut toon_used = false;
 
@@ -975,13 +1369,18 @@ impl Renderer {
                     .entry((texture_key, sampler_key))
                     .or_insert_with(|| {
                         let texture = self.textures.entry(texture_key).or_insert_with(|| {
-                            create_texture(
-                                &self.device,
-                                &self.queue,
-                                texture_key,
-                                frame,
-                                &mut self.texture_decode_buffer,
-                            )
+                            let (texture_region_mask, tex_pal_region_mask) =
+                                decode_texture(texture_key, frame, &mut self.texture_decode_buffer);
+                            Texture {
+                                contents: self.texture_cache.get_or_create(
+                                    &self.device,
+                                    &self.queue,
+                                    texture_key,
+                                    &self.texture_decode_buffer,
+                                ),
+                                texture_region_mask,
+                                tex_pal_region_mask,
+                            }
                         });
                         let sampler = self.samplers[sampler_key.0 as usize]
                             .get_or_insert_with(|| create_sampler(&self.device, sampler_key));
@@ -991,12 +1390,18 @@ impl Renderer {
                             entries: &[
                                 wgpu::BindGroupEntry {
                                     binding: 0,
-                                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                                    resource: wgpu::BindingResource::TextureView(
+                                        &texture.contents.view,
+                                    ),
                                 },
                                 wgpu::BindGroupEntry {
                                     binding: 1,
                                     resource: wgpu::BindingResource::Sampler(sampler),
                                 },
+                                wgpu::BindGroupEntry {
+                                    binding: 2,
+                                    resource: texture.contents.params_buffer.as_entire_binding(),
+                                },
                             ],
                         })
                     });
@@ -1037,11 +1442,22 @@ impl Renderer {
                 }
 
                 match batch_kind {
-                    BatchKind::ShadowMask { depth_test_equal } => {
-                        // TODO: Prepare pipeline (the only possible change is depth_test_equal, so
-                        // it's not checked)
+                    BatchKind::ShadowMask { pipeline } => {
+                        self.shadow_mask_pipelines
+                            .entry(pipeline)
+                            .or_insert_with(|| {
+                                render::shadow_mask::create_pipeline(pipeline, &self.device)
+                            });
 
-                        PreparedBatchKind::ShadowMask { depth_test_equal }
+                        // The shadow mask stencil bits get cleared before each group of
+                        // consecutive shadow mask polygons
+                        PreparedBatchKind::ShadowMask {
+                            pipeline,
+                            starts_group: !matches!(
+                                cur_batch_kind,
+                                Some(BatchKind::ShadowMask { .. })
+                            ),
+                        }
                     }
 
                     BatchKind::Opaque { pipeline, texture } => {
@@ -1052,14 +1468,21 @@ impl Renderer {
                         );
 
                         if pipeline_changed {
-                            self.pipelines.entry(pipeline).or_insert_with(|| {
-                                render::opaque::create_pipeline(
-                                    pipeline,
-                                    &self.device,
-                                    &self.toon_bg_layout,
-                                    &self.texture_bg_layout,
-                                )
-                            });
+                            // The back layer is only needed for anti-aliasing
+                            for pipeline in [pipeline, pipeline.with_back_layer(true)] {
+                                if pipeline.back_layer() && !antialiasing_enabled {
+                                    continue;
+                                }
+                                self.pipelines.entry(pipeline).or_insert_with(|| {
+                                    render::opaque::create_pipeline(
+                                        pipeline,
+                                        &self.device,
+                                        &self.toon_bg_layout,
+                                        &self.texture_bg_layout,
+                                        &self.front_depth_bg_layout,
+                                    )
+                                });
+                            }
                         }
 
                         if texture_changed {
@@ -1105,6 +1528,7 @@ impl Renderer {
                                     &self.alpha_and_ref_bg_layout,
                                     &self.toon_bg_layout,
                                     &self.texture_bg_layout,
+                                    &self.shadow_bg_layout,
                                 )
                             });
                         }
@@ -1157,6 +1581,7 @@ impl Renderer {
                                         &self.alpha_and_ref_bg_layout,
                                         &self.toon_bg_layout,
                                         &self.texture_bg_layout,
+                                        &self.shadow_bg_layout,
                                     )
                                 });
                         }
@@ -1219,15 +1644,29 @@ impl Renderer {
 
             macro_rules! finish_batch {
                 () => {
-                    if let Some((_, prepared_batch_kind)) = &cur_batch {
+                    if let Some((_, prepared_batch_kind, starts_render_pass)) = &cur_batch {
                         self.batches.push(PreparedBatch {
                             kind: *prepared_batch_kind,
                             idxs: (self.idx_buffer_contents.len() - cur_batch_indices_start) as u16,
+                            starts_render_pass: *starts_render_pass,
                         });
                     }
                 };
             }
 
+            // Shadow polygons need to read the shadow mask and the attributes of the pixels below
+            // them, so a new render pass is started before the first batch of each group of
+            // consecutive shadow polygons, once those have been copied; as all state is lost
+            // across passes, that batch is prepared from scratch
+            let is_shadow_poly_batch =
+                |kind: BatchKind| kind.is_shadow() && !matches!(kind, BatchKind::ShadowMask { .. });
+
+            // With anti-aliasing enabled, the back layer needs to be drawn once all opaque
+            // polygons have been, before translucent ones can update the depth buffer, so a new
+            // render pass is started for the first other batch too
+            back_layer_pending = antialiasing_enabled;
+            let mut back_layer_batch_found = false;
+
             for poly in polys {
                 let batch_kind = BatchKind::new(
                     control_flags,
@@ -1237,43 +1676,73 @@ impl Renderer {
                 );
                 if match cur_batch {
                     None => true,
-                    Some((cur_batch_kind, _)) => cur_batch_kind != batch_kind,
+                    Some((cur_batch_kind, ..)) => cur_batch_kind != batch_kind,
                 } {
                     finish_batch!();
+                    let ends_opaque_batches = antialiasing_enabled
+                        && !back_layer_batch_found
+                        && !matches!(
+                            batch_kind,
+                            BatchKind::Opaque { .. } | BatchKind::Wireframe { .. }
+                        );
+                    back_layer_batch_found |= ends_opaque_batches;
+                    let starts_render_pass = ends_opaque_batches
+                        || (is_shadow_poly_batch(batch_kind)
+                            && !cur_batch.map_or(false, |v| is_shadow_poly_batch(v.0)));
                     cur_batch = Some((
                         batch_kind,
-                        prepare_batch(batch_kind, cur_batch.as_ref().map(|v| v.0)),
+                        prepare_batch(
+                            batch_kind,
+                            if starts_render_pass {
+                                None
+                            } else {
+                                cur_batch.as_ref().map(|v| v.0)
+                            },
+                        ),
+                        starts_render_pass,
                     ));
                     cur_batch_indices_start = self.idx_buffer_contents.len();
                 }
 
                 toon_used |= poly.attrs.mode() == 2;
 
-                if poly.vertices_len.get() < 3 || poly.attrs.mode() == 3 {
-                    // TODO: Do process shadow/shadow mask polygons
+                if poly.vertices_len.get() < 3 {
                     continue;
                 }
 
                 let id = poly.attrs.id();
-                let base_idx = self.vtx_buffer_contents.len() as u16;
-                self.vtx_buffer_contents.extend(
-                    poly.vertices[..poly.vertices_len.get() as usize]
-                        .iter()
-                        .enumerate()
-                        .map(|(i, vert_addr)| {
-                            Vertex::new(
-                                &frame.gx.vert_ram[vert_addr.get() as usize],
-                                // self.hi_res_coords_mask,
-                                poly.depth_values[i],
-                                poly.w_values[i],
-                                id,
-                            )
-                        }),
+                let verts_len = poly.vertices_len.get() as usize;
+                let y_range = poly.vertices[..verts_len].iter().fold(
+                    [u16::MAX, 0],
+                    |[min, max], vert_addr| {
+                        let y = frame.gx.vert_ram[vert_addr.get() as usize].hi_res_coords[1];
+                        [min.min(y), max.max(y)]
+                    },
                 );
 
-                for i in base_idx..base_idx + (poly.vertices_len.get() - 1) as u16 {
-                    self.idx_buffer_contents
-                        .extend_from_slice(&[base_idx, i, i + 1]);
+                // Vertices aren't shared between the triangles of a polygon, as their edge
+                // attributes depend on which triangle edges are part of the polygon's outline
+                for i in 1..verts_len - 1 {
+                    let is_outline = [true, i + 2 == verts_len, i == 1];
+                    for (corner, vert_i) in [0, i, i + 1].into_iter().enumerate() {
+                        let mut edges = [0xFF; 4];
+                        for (edge, is_outline) in is_outline.into_iter().enumerate() {
+                            if is_outline && edge != corner {
+                                edges[edge] = 0;
+                            }
+                        }
+                        self.idx_buffer_contents
+                            .push(self.vtx_buffer_contents.len() as u16);
+                        self.vtx_buffer_contents.push(Vertex::new(
+                            &frame.gx.vert_ram[poly.vertices[vert_i].get() as usize],
+                            // self.hi_res_coords_mask,
+                            poly.depth_values[vert_i],
+                            poly.w_values[vert_i],
+                            id,
+                            edges,
+                            y_range,
+                        ));
+                    }
                 }
             }
             finish_batch!();
@@ -1319,9 +1788,51 @@ impl Renderer {
             render_pass.set_index_buffer(self.idx_buffer.slice(..), wgpu::IndexFormat::Uint16);
 
             let mut cur_idx_base = 0;
-            for batch in &self.batches {
+            for (i, batch) in self.batches.iter().enumerate() {
+                if batch.starts_render_pass {
+                    drop(render_pass);
+                    if back_layer_pending {
+                        back_layer_pending = false;
+                        self.draw_back_layer(&mut command_encoder, &frame.rendering, i);
+                    }
+                    if matches!(
+                        batch.kind,
+                        PreparedBatchKind::Translucent { pipeline, .. }
+                            | PreparedBatchKind::TranslucentNoDepthUpdate { pipeline, .. }
+                            if pipeline.is_shadow()
+                    ) {
+                        self.output_attachments
+                            .copy_shadow_state(&mut command_encoder);
+                    }
+                    render_pass = self.output_attachments.begin_render_pass(
+                        &mut command_encoder,
+                        None,
+                        false,
+                    );
+                    render_pass.set_vertex_buffer(0, self.vtx_buffer.slice(..));
+                    render_pass
+                        .set_index_buffer(self.idx_buffer.slice(..), wgpu::IndexFormat::Uint16);
+                }
+
                 match batch.kind {
-                    PreparedBatchKind::ShadowMask { depth_test_equal } => {}
+                    PreparedBatchKind::ShadowMask {
+                        pipeline,
+                        starts_group,
+                    } => {
+                        render_pass.set_stencil_reference(render::SHADOW_STENCIL_BIT);
+                        if starts_group {
+                            render_pass.set_pipeline(&self.shadow_mask_clear_pipeline);
+                            render_pass.draw(0..3, 0..1);
+                        }
+                        if batch.idxs != 0 {
+                            render_pass.set_pipeline(&self.shadow_mask_pipelines[&pipeline]);
+                            render_pass.draw_indexed(
+                                cur_idx_base..cur_idx_base + batch.idxs as u32,
+                                0,
+                                0..1,
+                            );
+                        }
+                    }
 
                     PreparedBatchKind::Opaque { pipeline, texture } => {
                         if let Some(pipeline) = pipeline {
@@ -1359,6 +1870,15 @@ impl Renderer {
                             render_pass.set_bind_group(2, &self.toon_bg, &[])
                         }
 
+                        if pipeline_changed && pipeline.is_shadow() {
+                            render_pass.set_bind_group(
+                                2 + (pipeline.mode() >= 2) as u32
+                                    + pipeline.texture_mapping_enabled() as u32,
+                                &self.shadow_bg,
+                                &[],
+                            );
+                        }
+
                         if let Some(id) = id {
                             render_pass.set_stencil_reference((id | 0x40) as u32);
                             render_pass.set_bind_group(
@@ -1409,6 +1929,15 @@ impl Renderer {
                             render_pass.set_bind_group(2, &self.toon_bg, &[])
                         }
 
+                        if pipeline_changed && pipeline.is_shadow() {
+                            render_pass.set_bind_group(
+                                2 + (pipeline.mode() >= 2) as u32
+                                    + pipeline.texture_mapping_enabled() as u32,
+                                &self.shadow_bg,
+                                &[],
+                            );
+                        }
+
                         if let Some(id) = id {
                             render_pass.set_stencil_reference((id | 0x40) as u32);
                             render_pass.set_bind_group(
@@ -1457,6 +1986,34 @@ impl Renderer {
 
         drop(render_pass);
 
+        if back_layer_pending {
+            self.draw_back_layer(&mut command_encoder, &frame.rendering, self.batches.len());
+        }
+
+        self.queue.write_buffer(
+            &self.final_pass_uniform_buffer,
+            0,
+            render::final_pass::Uniform::new(&frame.rendering, self.resolution_scale_shift)
+                .as_bytes(),
+        );
+
+        let mut final_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("3D renderer final pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.output_attachments.color_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        final_pass.set_pipeline(&self.final_pass_pipeline);
+        final_pass.set_bind_group(0, &self.final_pass_bg, &[]);
+        final_pass.draw(0..3, 0..1);
+        drop(final_pass);
+
         command_encoder.finish()
     }
 }
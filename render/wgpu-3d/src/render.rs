@@ -8,7 +8,9 @@ macro_rules! ifdef {
     };
 }
 
+pub mod final_pass;
 pub mod opaque;
+pub mod shadow_mask;
 pub mod trans;
 pub mod trans_no_depth_update;
 
@@ -46,7 +48,9 @@ impl CommonCode {
                 output.v_color = vec4<f32>(vec3<f32>(v_color.xyz) * vec3<f32>(1.0 / 511.0), 1.0);",
 
             common_frag_inputs: "@location(0) v_color: vec4<f32>,",
-            common_frag_outputs: "@location(0) color: vec4<f32>,",
+            common_frag_outputs: "
+                @location(0) color: vec4<f32>,
+                @location(1) attrs: vec4<u32>,",
         }
     }
 }
@@ -74,6 +78,86 @@ const COMMON_VERT_ATTRIBS: [wgpu::VertexAttribute; 4] = [
     },
 ];
 
+// Computes the edge flags and anti-aliasing coverage of opaque pixels, laid out like in soft-3d's
+// pixel attributes: each triangle's vertices have their own attributes, with one component per
+// triangle edge going from 0 on that edge to 1 on the opposite vertex if it's part of the polygon's
+// outline, or constant otherwise
+struct EdgeCode {
+    edge_vert_inputs: &'static str,
+    edge_vert_outputs: &'static str,
+    edge_set_vert_outputs: &'static str,
+
+    edge_frag_inputs: &'static str,
+    edge_get_attrs: &'static str,
+}
+
+impl EdgeCode {
+    const fn new() -> Self {
+        EdgeCode {
+            edge_vert_inputs: "
+                @location(6) edges: vec4<f32>,
+                @location(7) y_range: vec2<u32>,",
+
+            edge_vert_outputs: "
+                @location(4) @interpolate(linear) edges: vec3<f32>,
+                @location(5) @interpolate(linear) edge_y: f32,
+                @location(6) @interpolate(flat) y_range: vec2<u32>,",
+
+            edge_set_vert_outputs: "
+                output.edges = edges.xyz;
+                output.edge_y = f32(position.y);
+                output.y_range = y_range;",
+
+            edge_frag_inputs: "
+                @location(4) @interpolate(linear) edges: vec3<f32>,
+                @location(5) @interpolate(linear) edge_y: f32,
+                @location(6) @interpolate(flat) y_range: vec2<u32>,",
+
+            // Left and right edges span the pixels less than one pixel away from them along their
+            // minor axis, and cover those up to the edge; top and bottom ones span the first and
+            // last line of the polygon
+            edge_get_attrs: "
+                let edges_dx = dpdx(edges);
+                let edges_dy = dpdy(edges);
+                let pixels_per_unit = 1.0 / dpdy(edge_y);
+                var edge_flags = 0u;
+                var coverage = 31u;
+                var min_edge_dist = 1.0;
+                for (var i = 0; i < 3; i++) {
+                    let grad = max(abs(edges_dx[i]), abs(edges_dy[i]));
+                    if edges_dx[i] == 0.0 {
+                        continue;
+                    }
+                    let edge_dist = edges[i] / grad;
+                    if edge_dist < min_edge_dist {
+                        min_edge_dist = edge_dist;
+                        edge_flags = select(4u, 8u, edges_dx[i] > 0.0);
+                        coverage = min(u32(max(edge_dist + 0.5, 0.0) * 32.0), 31u);
+                    }
+                }
+                if (edge_y - f32(y_range.x)) * pixels_per_unit < 1.0 {
+                    edge_flags |= 1u;
+                }
+                if (f32(y_range.y) - edge_y) * pixels_per_unit < 1.0 {
+                    edge_flags |= 2u;
+                }",
+        }
+    }
+}
+
+const EDGE_VERT_ATTRIBS: [wgpu::VertexAttribute; 2] = [
+    wgpu::VertexAttribute {
+        format: wgpu::VertexFormat::Unorm8x4,
+        offset: 28,
+        shader_location: 6,
+    },
+    wgpu::VertexAttribute {
+        format: wgpu::VertexFormat::Uint16x2,
+        offset: 32,
+        shader_location: 7,
+    },
+];
+
 #[derive(Default)]
 struct WBufferCode {
     w_buffer_vert_outputs: &'static str,
@@ -113,7 +197,8 @@ impl TextureCode {
         TextureCode {
             texture_uniforms: format!(
                 "@group({bg_index}) @binding(0) var t_texture: texture_2d<f32>;
-                @group({bg_index}) @binding(1) var s_texture: sampler;",
+                @group({bg_index}) @binding(1) var s_texture: sampler;
+                @group({bg_index}) @binding(2) var<uniform> t_params: vec4<f32>;",
             ),
 
             texture_vert_inputs: "@location(3) uv: vec2<i32>,",
@@ -121,9 +206,10 @@ impl TextureCode {
             texture_set_vert_outputs: "output.uv = vec2<f32>(uv) * vec2<f32>(1.0 / 16.0);",
 
             texture_frag_inputs: "@location(1) uv: vec2<f32>,",
+            // Texture coordinates are normalized using the size of the original texture, as it
+            // could have been replaced by a higher-resolution one
             texture_get_color: "let t_color = textureSample(t_texture, s_texture, uv / \
-                                vec2<f32>(textureDimensions(t_texture))) * vec4<f32>(255.0 / \
-                                31.0);",
+                                t_params.xy) * t_params.z;",
         }
     }
 }
@@ -157,6 +243,34 @@ impl ToonCode {
     }
 }
 
+#[derive(Default)]
+struct ShadowCode {
+    shadow_uniforms: String,
+
+    shadow_frag_inputs: &'static str,
+    shadow_mask_test: &'static str,
+    shadow_id_test: &'static str,
+}
+
+impl ShadowCode {
+    fn new(bg_index: u32) -> ShadowCode {
+        ShadowCode {
+            shadow_uniforms: format!(
+                "@group({bg_index}) @binding(0) var t_shadow_attrs: texture_2d<u32>;
+                @group({bg_index}) @binding(1) var t_shadow_stencil: texture_2d<u32>;"
+            ),
+            shadow_frag_inputs: "@builtin(position) frag_position: vec4<f32>,",
+            shadow_mask_test: "let shadow_coords = vec2<i32>(frag_position.xy);
+                let shadow_stencil = textureLoad(t_shadow_stencil, shadow_coords, 0).r;
+                if (shadow_stencil & 0x80u) == 0u { discard; }",
+            shadow_id_test: "if (shadow_stencil & 0x7Fu) == 0u && \
+                             textureLoad(t_shadow_attrs, shadow_coords, 0).r == poly_id {
+                    discard;
+                }",
+        }
+    }
+}
+
 fn get_output_color(mode: u8, texture_mapping_enabled: bool) -> &'static str {
     if texture_mapping_enabled {
         match mode {
@@ -197,6 +311,42 @@ const PRIMITIVE_STATE: wgpu::PrimitiveState = wgpu::PrimitiveState {
     conservative: false,
 };
 
+// Bit 7 of the stencil buffer holds the shadow mask, while the lower 7 bits hold the ID of the last
+// translucent polygon drawn to each pixel (with bit 6 set), or 0 if the pixel is opaque
+pub(crate) const SHADOW_STENCIL_BIT: u32 = 0x80;
+
+// The shadow mask bit is never touched here: like in melonDS, it's only cleared at the start of
+// each group of shadow mask polygons, and shadow polygons check it in their shaders. Shadow
+// polygons are otherwise subject to the same translucent polygon ID check as other ones.
+fn trans_stencil_states() -> [wgpu::StencilState; 2] {
+    let opaque_face_state = wgpu::StencilFaceState {
+        compare: wgpu::CompareFunction::Always,
+        fail_op: wgpu::StencilOperation::Keep,
+        depth_fail_op: wgpu::StencilOperation::Keep,
+        pass_op: wgpu::StencilOperation::Zero,
+    };
+    let trans_face_state = wgpu::StencilFaceState {
+        compare: wgpu::CompareFunction::NotEqual,
+        fail_op: wgpu::StencilOperation::Keep,
+        depth_fail_op: wgpu::StencilOperation::Keep,
+        pass_op: wgpu::StencilOperation::Replace,
+    };
+    [
+        wgpu::StencilState {
+            front: opaque_face_state,
+            back: opaque_face_state,
+            read_mask: 0,
+            write_mask: 0x7F,
+        },
+        wgpu::StencilState {
+            front: trans_face_state,
+            back: trans_face_state,
+            read_mask: 0x7F,
+            write_mask: 0x7F,
+        },
+    ]
+}
+
 const TRANS_BLENDING: wgpu::BlendState = wgpu::BlendState {
     color: wgpu::BlendComponent {
         src_factor: wgpu::BlendFactor::SrcAlpha,
@@ -0,0 +1,372 @@
+use crate::{OutputAttachments, RenderingData};
+use core::{mem, slice};
+use dust_core::gpu::engine_3d::Color;
+
+#[repr(C)]
+pub(crate) struct Uniform {
+    // Density table entries, with an additional entry on both sides to handle depths before the
+    // fog offset and past the end of the table; four entries are packed in each element to match
+    // uniform array stride rules
+    fog_densities: [[u32; 4]; 9],
+    fog_color: [u32; 4],
+    edge_colors: [[u32; 4]; 8],
+    fog_offset: u32,
+    fog_shift: u32,
+    flags: u32,
+    clear_poly_id: u32,
+    clear_depth: u32,
+    resolution_scale: u32,
+    _padding: [u32; 2],
+}
+
+impl Uniform {
+    pub fn new(rendering: &RenderingData, resolution_scale_shift: u8) -> Self {
+        let mut fog_densities = [[0; 4]; 9];
+        for i in 0..0x22 {
+            fog_densities[i >> 2][i & 3] =
+                rendering.fog_densities[i.saturating_sub(1).min(0x1F)] as u32;
+        }
+        let rgb5_to_rgb6 = |color: Color| {
+            let [r, g, b, a] = color.cast::<u32>().to_array();
+            let [r, g, b] = [r, g, b].map(|c| if c != 0 { c << 1 | 1 } else { 0 });
+            [r, g, b, a]
+        };
+        let control = rendering.control;
+        Uniform {
+            fog_densities,
+            fog_color: rgb5_to_rgb6(rendering.fog_color),
+            edge_colors: rendering.edge_colors.map(rgb5_to_rgb6),
+            fog_offset: (rendering.fog_offset as u32) << 9,
+            fog_shift: control.fog_depth_shift() as u32,
+            flags: control.fog_enabled() as u32
+                | (control.fog_only_alpha() as u32) << 1
+                | (control.edge_marking_enabled() as u32) << 2
+                | (control.antialiasing_enabled() as u32) << 3,
+            clear_poly_id: rendering.clear_poly_id as u32,
+            clear_depth: rendering.clear_depth,
+            resolution_scale: 1 << resolution_scale_shift,
+            _padding: [0; 2],
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, mem::size_of::<Self>()) }
+    }
+}
+
+const SHADER_SRC: &str = "
+struct Uniform {
+    fog_densities: array<vec4<u32>, 9>,
+    fog_color: vec4<u32>,
+    edge_colors: array<vec4<u32>, 8>,
+    fog_offset: u32,
+    fog_shift: u32,
+    flags: u32,
+    clear_poly_id: u32,
+    clear_depth: u32,
+    resolution_scale: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Uniform;
+@group(0) @binding(1) var t_color: texture_2d<f32>;
+@group(0) @binding(2) var t_attrs: texture_2d<u32>;
+@group(0) @binding(3) var t_depth: texture_depth_2d;
+@group(0) @binding(4) var t_back_color: texture_2d<f32>;
+@group(0) @binding(5) var t_back_attrs: texture_2d<u32>;
+@group(0) @binding(6) var t_back_depth: texture_depth_2d;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn in_bounds(coords: vec2<i32>) -> bool {
+    return all(coords >= vec2<i32>(0)) && all(coords < vec2<i32>(textureDimensions(t_attrs)));
+}
+
+fn depth_at(t: texture_depth_2d, coords: vec2<i32>) -> u32 {
+    return u32(textureLoad(t, coords, 0) * 16777216.0);
+}
+
+// Returns the opaque polygon ID and depth of a pixel, treating pixels outside the screen like the
+// clear plane
+fn id_depth(coords: vec2<i32>) -> vec2<u32> {
+    if !in_bounds(coords) {
+        return vec2<u32>(params.clear_poly_id, params.clear_depth);
+    }
+    return vec2<u32>(
+        textureLoad(t_attrs, coords, 0).r,
+        depth_at(t_depth, coords),
+    );
+}
+
+// Whether a pixel with the given polygon ID and depth is in front of the one at `coords`, and the
+// latter belongs to a polygon with a different ID
+fn is_in_front(pixel: vec2<u32>, coords: vec2<i32>) -> bool {
+    let other = id_depth(coords);
+    return other.x != pixel.x && pixel.y < other.y;
+}
+
+fn fog_density_entry(i: u32) -> u32 {
+    return params.fog_densities[i >> 2u][i & 3u];
+}
+
+fn fog_density(depth: u32) -> f32 {
+    var i = 0u;
+    var frac = 0u;
+    if depth >= params.fog_offset {
+        let z = ((depth - params.fog_offset) >> 2u) << params.fog_shift;
+        i = min(z >> 17u, 32u);
+        if i < 32u {
+            frac = z & 0x1FFFFu;
+        }
+    }
+    let density =
+        (fog_density_entry(i) * (0x20000u - frac) + fog_density_entry(i + 1u) * frac) >> 17u;
+    return select(f32(density), 128.0, density >= 127u) * (1.0 / 128.0);
+}
+
+fn apply_fog(color: vec4<f32>, depth: u32) -> vec4<f32> {
+    let density = fog_density(depth);
+    let fog_color = vec4<f32>(params.fog_color) * vec4<f32>(vec3<f32>(1.0 / 63.0), 1.0 / 31.0);
+    if (params.flags & 2u) != 0u {
+        return vec4<f32>(color.rgb, mix(color.a, fog_color.a, density));
+    }
+    return mix(color, fog_color, density);
+}
+
+// Applies edge marking, fog and anti-aliasing to a single pixel, like soft-3d does for each line:
+// edge pixels are blended with the pixel behind them according to their coverage, after fog has
+// been applied to both
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let coords = vec2<i32>(position.xy);
+    var color = textureLoad(t_color, coords, 0);
+    let attrs = textureLoad(t_attrs, coords, 0);
+    let pixel = id_depth(coords);
+    var coverage = attrs.a;
+
+    // Only pixels on the edges of opaque polygons can be edge-marked
+    if (params.flags & 4u) != 0u && attrs.b != 0u {
+        let offset = i32(params.resolution_scale);
+        if is_in_front(pixel, coords - vec2<i32>(offset, 0))
+            || is_in_front(pixel, coords + vec2<i32>(offset, 0))
+            || is_in_front(pixel, coords - vec2<i32>(0, offset))
+            || is_in_front(pixel, coords + vec2<i32>(0, offset))
+        {
+            let edge_color = params.edge_colors[attrs.r >> 3u];
+            color = vec4<f32>(vec3<f32>(edge_color.rgb) * (1.0 / 63.0), color.a);
+            // Edge-marked pixels get blended halfway with the ones behind them
+            coverage = 0x10u;
+        }
+    }
+
+    let fog_enabled = (params.flags & 1u) != 0u;
+    if fog_enabled && attrs.g != 0u {
+        color = apply_fog(color, pixel.y);
+    }
+
+    // Only left and right edges are anti-aliased
+    if (params.flags & 8u) != 0u && (attrs.b & 0xCu) != 0u && coverage < 31u {
+        var back_color = textureLoad(t_back_color, coords, 0);
+        if fog_enabled && textureLoad(t_back_attrs, coords, 0).g != 0u {
+            back_color = apply_fog(back_color, depth_at(t_back_depth, coords));
+        }
+        let blended_color = mix(back_color, color, f32(coverage + 1u) * (1.0 / 32.0));
+        // Only alpha gets blended if the pixel behind is fully transparent
+        if back_color.a == 0.0 {
+            color.a = blended_color.a;
+        } else {
+            color = blended_color;
+        }
+    }
+
+    return color;
+}";
+
+pub(crate) fn create_bg_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("3D renderer final pass bind group layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(mem::size_of::<Uniform>() as u64),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Uint,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 4,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 5,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Uint,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 6,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+pub(crate) fn create_uniform_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("3D renderer final pass uniform buffer"),
+        size: mem::size_of::<Uniform>() as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+        mapped_at_creation: false,
+    })
+}
+
+pub(crate) fn create_bg(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    uniform_buffer: &wgpu::Buffer,
+    output_attachments: &OutputAttachments,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("3D renderer final pass bind group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&output_attachments.raw_color_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&output_attachments.attrs_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(
+                    &output_attachments.depth_texture_view,
+                ),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(&output_attachments.back_color_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: wgpu::BindingResource::TextureView(&output_attachments.back_attrs_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: wgpu::BindingResource::TextureView(
+                    &output_attachments.back_depth_texture_view,
+                ),
+            },
+        ],
+    })
+}
+
+pub(crate) fn create_pipeline(
+    device: &wgpu::Device,
+    bg_layout: &wgpu::BindGroupLayout,
+) -> wgpu::RenderPipeline {
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("3D renderer final pass pipeline layout"),
+        bind_group_layouts: &[bg_layout],
+        push_constant_ranges: &[],
+    });
+
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("3D renderer final pass shader module"),
+        source: wgpu::ShaderSource::Wgsl(SHADER_SRC.into()),
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("3D renderer final pass pipeline"),
+        layout: Some(&layout),
+
+        vertex: wgpu::VertexState {
+            module: &shader_module,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+
+        depth_stencil: None,
+
+        multisample: wgpu::MultisampleState::default(),
+
+        fragment: Some(wgpu::FragmentState {
+            module: &shader_module,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+
+        multiview: None,
+    })
+}
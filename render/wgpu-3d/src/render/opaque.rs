@@ -1,11 +1,15 @@
 use super::{
-    get_output_color, CommonCode, TextureCode, ToonCode, WBufferCode, COMMON_VERT_ATTRIBS,
-    PRIMITIVE_STATE, TEXTURE_VERT_ATTRIBS,
+    get_output_color, CommonCode, EdgeCode, TextureCode, ToonCode, WBufferCode,
+    COMMON_VERT_ATTRIBS, EDGE_VERT_ATTRIBS, PRIMITIVE_STATE, TEXTURE_VERT_ATTRIBS,
 };
-use crate::{PipelineKey, Vertex};
+use crate::{PipelineKey, Vertex, ATTRS_FORMAT};
 use core::mem;
 
-fn shader_module_src(pipeline: PipelineKey, texture_bg_index: u32) -> String {
+fn shader_module_src(
+    pipeline: PipelineKey,
+    texture_bg_index: u32,
+    front_depth_bg_index: u32,
+) -> String {
     let CommonCode {
         common_vert_inputs,
         common_vert_outputs,
@@ -39,17 +43,52 @@ fn shader_module_src(pipeline: PipelineKey, texture_bg_index: u32) -> String {
         TextureCode::new(texture_bg_index)
     );
 
+    let EdgeCode {
+        edge_vert_inputs,
+        edge_vert_outputs,
+        edge_set_vert_outputs,
+        edge_frag_inputs,
+        edge_get_attrs,
+    } = EdgeCode::new();
+
     let get_output_color = get_output_color(pipeline.mode(), pipeline.texture_mapping_enabled());
 
+    // The back layer only keeps the closest pixels behind the ones in the front layer, which are
+    // the ones anti-aliased edges get blended with
+    let (back_layer_uniforms, back_layer_frag_inputs, back_layer_test) = if pipeline.back_layer() {
+        (
+            format!(
+                "@group({front_depth_bg_index}) @binding(0) var t_front_depth: \
+                 texture_depth_2d;"
+            ),
+            "@builtin(position) frag_position: vec4<f32>,",
+            format!(
+                "if {} <= textureLoad(t_front_depth, vec2<i32>(frag_position.xy), 0) {{
+                    discard;
+                }}",
+                if pipeline.w_buffering() {
+                    "w"
+                } else {
+                    "frag_position.z"
+                }
+            ),
+        )
+    } else {
+        Default::default()
+    };
+
     format!(
         "
 {texture_uniforms}
 {toon_uniforms}
+{back_layer_uniforms}
 
 struct VertOutput {{
     {common_vert_outputs}
     {w_buffer_vert_outputs}
     {texture_vert_outputs}
+    @location(3) @interpolate(flat) id: u32,
+    {edge_vert_outputs}
 }}
 
 @vertex
@@ -57,11 +96,14 @@ fn vs_main(
     {common_vert_inputs}
     {texture_vert_inputs}
     @location(5) id: u32,
+    {edge_vert_inputs}
 ) -> VertOutput {{
     var output: VertOutput;
     {common_set_vert_outputs}
     {w_buffer_set_vert_outputs}
     {texture_set_vert_outputs}
+    output.id = id;
+    {edge_set_vert_outputs}
     return output;
 }}
 
@@ -75,7 +117,12 @@ fn fs_main(
     {common_frag_inputs}
     {w_buffer_frag_inputs}
     {texture_frag_inputs}
+    @location(3) @interpolate(flat) id: u32,
+    {edge_frag_inputs}
+    {back_layer_frag_inputs}
 ) -> FragOutput {{
+    {edge_get_attrs}
+    {back_layer_test}
     var output: FragOutput;
     {w_buffer_set_frag_outputs}
     {texture_get_color}
@@ -84,8 +131,10 @@ fn fs_main(
     if output.color.a < 1.0 {{
         discard;
     }}
+    output.attrs = vec4<u32>(id, {fog_enabled}u, edge_flags, coverage);
     return output;
-}}"
+}}",
+        fog_enabled = pipeline.fog_enabled() as u32,
     )
 }
 
@@ -94,6 +143,7 @@ pub(crate) fn create_pipeline(
     device: &wgpu::Device,
     toon_bg_layout: &wgpu::BindGroupLayout,
     texture_bg_layout: &wgpu::BindGroupLayout,
+    front_depth_bg_layout: &wgpu::BindGroupLayout,
 ) -> wgpu::RenderPipeline {
     let mut pipeline_bg_layouts = Vec::new();
 
@@ -106,6 +156,11 @@ pub(crate) fn create_pipeline(
         pipeline_bg_layouts.push(texture_bg_layout);
     }
 
+    let front_depth_bg_index = pipeline_bg_layouts.len() as u32;
+    if pipeline.back_layer() {
+        pipeline_bg_layouts.push(front_depth_bg_layout);
+    }
+
     let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some("3D renderer opaque pipeline layout"),
         bind_group_layouts: &pipeline_bg_layouts,
@@ -114,7 +169,9 @@ pub(crate) fn create_pipeline(
 
     let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
         label: Some("3D renderer opaque shader module"),
-        source: wgpu::ShaderSource::Wgsl(shader_module_src(pipeline, texture_bg_index).into()),
+        source: wgpu::ShaderSource::Wgsl(
+            shader_module_src(pipeline, texture_bg_index, front_depth_bg_index).into(),
+        ),
     });
 
     let mut attribs = COMMON_VERT_ATTRIBS.to_vec();
@@ -128,6 +185,7 @@ pub(crate) fn create_pipeline(
         offset: 24,
         shader_location: 5,
     });
+    attribs.extend_from_slice(&EDGE_VERT_ATTRIBS);
 
     device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label: Some("3D renderer opaque pipeline"),
@@ -162,11 +220,18 @@ pub(crate) fn create_pipeline(
         fragment: Some(wgpu::FragmentState {
             module: &shader_module,
             entry_point: "fs_main",
-            targets: &[Some(wgpu::ColorTargetState {
-                format: wgpu::TextureFormat::Rgba8Unorm,
-                blend: None,
-                write_mask: wgpu::ColorWrites::ALL,
-            })],
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format: wgpu::TextureFormat::Rgba8Unorm,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                Some(wgpu::ColorTargetState {
+                    format: ATTRS_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+            ],
         }),
 
         multiview: None,
@@ -0,0 +1,210 @@
+use super::{CommonCode, WBufferCode, COMMON_VERT_ATTRIBS, PRIMITIVE_STATE, SHADOW_STENCIL_BIT};
+use crate::{PipelineKey, Vertex, ATTRS_FORMAT};
+use core::mem;
+
+fn shader_module_src(pipeline: PipelineKey) -> String {
+    let CommonCode {
+        common_vert_inputs,
+        common_vert_outputs,
+        common_set_vert_outputs,
+        common_frag_inputs,
+        common_frag_outputs,
+    } = CommonCode::new();
+
+    let WBufferCode {
+        w_buffer_vert_outputs,
+        w_buffer_set_vert_outputs,
+        w_buffer_frag_inputs,
+        w_buffer_frag_outputs,
+        w_buffer_set_frag_outputs,
+    } = ifdef!(pipeline.w_buffering(), WBufferCode::new());
+
+    format!(
+        "
+struct VertOutput {{
+    {common_vert_outputs}
+    {w_buffer_vert_outputs}
+}}
+
+@vertex
+fn vs_main(
+    {common_vert_inputs}
+) -> VertOutput {{
+    var output: VertOutput;
+    {common_set_vert_outputs}
+    {w_buffer_set_vert_outputs}
+    return output;
+}}
+
+struct FragOutput {{
+    {common_frag_outputs}
+    {w_buffer_frag_outputs}
+}}
+
+@fragment
+fn fs_main(
+    {common_frag_inputs}
+    {w_buffer_frag_inputs}
+) -> FragOutput {{
+    var output: FragOutput;
+    {w_buffer_set_frag_outputs}
+    output.color = v_color;
+    output.attrs = vec4<u32>(0u);
+    return output;
+}}"
+    )
+}
+
+const CLEAR_SHADER_SRC: &str = "
+struct FragOutput {
+    @location(0) color: vec4<f32>,
+    @location(1) attrs: vec4<u32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> FragOutput {
+    var output: FragOutput;
+    output.color = vec4<f32>(0.0);
+    output.attrs = vec4<u32>(0u);
+    return output;
+}";
+
+// Neither pipeline writes any color or attributes, but their targets still need to match the
+// render pass' attachments
+const NO_WRITE_TARGETS: [Option<wgpu::ColorTargetState>; 2] = [
+    Some(wgpu::ColorTargetState {
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        blend: None,
+        write_mask: wgpu::ColorWrites::empty(),
+    }),
+    Some(wgpu::ColorTargetState {
+        format: ATTRS_FORMAT,
+        blend: None,
+        write_mask: wgpu::ColorWrites::empty(),
+    }),
+];
+
+fn stencil_state(face_state: wgpu::StencilFaceState) -> wgpu::StencilState {
+    wgpu::StencilState {
+        front: face_state,
+        back: face_state,
+        read_mask: 0,
+        write_mask: SHADOW_STENCIL_BIT,
+    }
+}
+
+pub(crate) fn create_pipeline(
+    pipeline: PipelineKey,
+    device: &wgpu::Device,
+) -> wgpu::RenderPipeline {
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("3D renderer shadow mask pipeline layout"),
+        bind_group_layouts: &[],
+        push_constant_ranges: &[],
+    });
+
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("3D renderer shadow mask shader module"),
+        source: wgpu::ShaderSource::Wgsl(shader_module_src(pipeline).into()),
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("3D renderer shadow mask pipeline"),
+        layout: Some(&layout),
+
+        vertex: wgpu::VertexState {
+            module: &shader_module,
+            entry_point: "vs_main",
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: mem::size_of::<Vertex>() as u64,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &COMMON_VERT_ATTRIBS,
+            }],
+        },
+
+        primitive: PRIMITIVE_STATE,
+
+        // Shadow masks set the stencil bit wherever they fail the depth test, without being drawn
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth32FloatStencil8,
+            depth_write_enabled: false,
+            depth_compare: if pipeline.depth_test_equal() {
+                wgpu::CompareFunction::Equal
+            } else {
+                wgpu::CompareFunction::Less
+            },
+            stencil: stencil_state(wgpu::StencilFaceState {
+                compare: wgpu::CompareFunction::Always,
+                fail_op: wgpu::StencilOperation::Keep,
+                depth_fail_op: wgpu::StencilOperation::Replace,
+                pass_op: wgpu::StencilOperation::Keep,
+            }),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+
+        multisample: wgpu::MultisampleState::default(),
+
+        fragment: Some(wgpu::FragmentState {
+            module: &shader_module,
+            entry_point: "fs_main",
+            targets: &NO_WRITE_TARGETS,
+        }),
+
+        multiview: None,
+    })
+}
+
+pub(crate) fn create_clear_pipeline(device: &wgpu::Device) -> wgpu::RenderPipeline {
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("3D renderer shadow mask clear pipeline layout"),
+        bind_group_layouts: &[],
+        push_constant_ranges: &[],
+    });
+
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("3D renderer shadow mask clear shader module"),
+        source: wgpu::ShaderSource::Wgsl(CLEAR_SHADER_SRC.into()),
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("3D renderer shadow mask clear pipeline"),
+        layout: Some(&layout),
+
+        vertex: wgpu::VertexState {
+            module: &shader_module,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+
+        primitive: PRIMITIVE_STATE,
+
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth32FloatStencil8,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Always,
+            stencil: stencil_state(wgpu::StencilFaceState {
+                compare: wgpu::CompareFunction::Always,
+                fail_op: wgpu::StencilOperation::Keep,
+                depth_fail_op: wgpu::StencilOperation::Keep,
+                pass_op: wgpu::StencilOperation::Zero,
+            }),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+
+        multisample: wgpu::MultisampleState::default(),
+
+        fragment: Some(wgpu::FragmentState {
+            module: &shader_module,
+            entry_point: "fs_main",
+            targets: &NO_WRITE_TARGETS,
+        }),
+
+        multiview: None,
+    })
+}
@@ -1,11 +1,16 @@
 use super::{
-    get_output_color, CommonCode, TextureCode, ToonCode, WBufferCode, COMMON_VERT_ATTRIBS,
-    PRIMITIVE_STATE, TEXTURE_VERT_ATTRIBS, TRANS_BLENDING,
+    get_output_color, trans_stencil_states, CommonCode, EdgeCode, ShadowCode, TextureCode,
+    ToonCode, WBufferCode, COMMON_VERT_ATTRIBS, EDGE_VERT_ATTRIBS, PRIMITIVE_STATE,
+    TEXTURE_VERT_ATTRIBS, TRANS_BLENDING,
 };
-use crate::{PipelineKey, Vertex};
+use crate::{PipelineKey, Vertex, ATTRS_FORMAT};
 use core::mem;
 
-pub(super) fn shader_module_src(pipeline: PipelineKey, texture_bg_index: u32) -> [String; 2] {
+pub(super) fn shader_module_src(
+    pipeline: PipelineKey,
+    texture_bg_index: u32,
+    shadow_bg_index: u32,
+) -> [String; 2] {
     let CommonCode {
         common_vert_inputs,
         common_vert_outputs,
@@ -39,13 +44,46 @@ pub(super) fn shader_module_src(pipeline: PipelineKey, texture_bg_index: u32) ->
         TextureCode::new(texture_bg_index)
     );
 
+    // Shadow polygons are only drawn where the shadow mask is set; their translucent pixels also
+    // aren't drawn over opaque ones belonging to polygons with their same ID
+    let ShadowCode {
+        shadow_uniforms,
+        shadow_frag_inputs,
+        shadow_mask_test,
+        shadow_id_test,
+    } = ifdef!(pipeline.is_shadow(), ShadowCode::new(shadow_bg_index));
+
+    let EdgeCode {
+        edge_vert_inputs,
+        edge_vert_outputs,
+        edge_set_vert_outputs,
+        edge_frag_inputs,
+        edge_get_attrs,
+    } = EdgeCode::new();
+
     let get_output_color = get_output_color(pipeline.mode(), pipeline.texture_mapping_enabled());
 
+    // Pixels drawn in the opaque pass replace the attributes below them, while translucent ones
+    // keep their edge flags and coverage, and only ever clear the fog flag (through the pipeline's
+    // write mask)
     [
-        "if output.color.a < 1.0 { discard; }",
-        "if (output.color.a < alpha_and_ref.alpha_ref) || (output.color.a >= 1.0) { discard; }",
+        (
+            edge_get_attrs,
+            "",
+            "if output.color.a < 1.0 { discard; }",
+            format!(
+                "output.attrs = vec4<u32>(poly_id, {}u, edge_flags, coverage);",
+                pipeline.fog_enabled() as u32
+            ),
+        ),
+        (
+            "",
+            shadow_id_test,
+            "if (output.color.a < alpha_and_ref.alpha_ref) || (output.color.a >= 1.0) { discard; }",
+            "output.attrs = vec4<u32>(0u);".to_string(),
+        ),
     ]
-    .map(|alpha_test| {
+    .map(|(edge_get_attrs, shadow_id_test, alpha_test, set_attrs)| {
         format!(
             "
 struct AlphaAndRefUniform {{
@@ -53,26 +91,31 @@ struct AlphaAndRefUniform {{
     alpha_ref: f32,
 }};
 
+@group(0) @binding(0) var<uniform> poly_id: u32;
 @group(1) @binding(0) var<uniform> alpha_and_ref: AlphaAndRefUniform;
 
 {texture_uniforms}
 {toon_uniforms}
+{shadow_uniforms}
 
 struct VertOutput {{
     {common_vert_outputs}
     {w_buffer_vert_outputs}
     {texture_vert_outputs}
+    {edge_vert_outputs}
 }}
 
 @vertex
 fn vs_main(
     {common_vert_inputs}
     {texture_vert_inputs}
+    {edge_vert_inputs}
 ) -> VertOutput {{
     var output: VertOutput;
     {common_set_vert_outputs}
     {w_buffer_set_vert_outputs}
     {texture_set_vert_outputs}
+    {edge_set_vert_outputs}
     output.v_color.a = alpha_and_ref.alpha;
     return output;
 }}
@@ -87,13 +130,19 @@ fn fs_main(
     {common_frag_inputs}
     {w_buffer_frag_inputs}
     {texture_frag_inputs}
+    {edge_frag_inputs}
+    {shadow_frag_inputs}
 ) -> FragOutput {{
+    {edge_get_attrs}
+    {shadow_mask_test}
+    {shadow_id_test}
     var output: FragOutput;
     {w_buffer_set_frag_outputs}
     {texture_get_color}
     {toon_get_color}
     {get_output_color}
     {alpha_test}
+    {set_attrs}
     return output;
 }}"
         )
@@ -107,6 +156,7 @@ pub(crate) fn create_pipeline(
     alpha_and_ref_bg_layout: &wgpu::BindGroupLayout,
     toon_bg_layout: &wgpu::BindGroupLayout,
     texture_bg_layout: &wgpu::BindGroupLayout,
+    shadow_bg_layout: &wgpu::BindGroupLayout,
 ) -> [wgpu::RenderPipeline; 2] {
     let mut bg_layouts = vec![id_bg_layout, alpha_and_ref_bg_layout];
 
@@ -119,6 +169,11 @@ pub(crate) fn create_pipeline(
         bg_layouts.push(texture_bg_layout);
     }
 
+    let shadow_bg_index = bg_layouts.len() as u32;
+    if pipeline.is_shadow() {
+        bg_layouts.push(shadow_bg_layout);
+    }
+
     let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some("3D renderer translucent pipeline layout"),
         bind_group_layouts: &bg_layouts,
@@ -126,7 +181,8 @@ pub(crate) fn create_pipeline(
     });
 
     let (opaque_shader_module, trans_shader_module) = {
-        let [opaque_src, trans_src] = shader_module_src(pipeline, texture_bg_index);
+        let [opaque_src, trans_src] =
+            shader_module_src(pipeline, texture_bg_index, shadow_bg_index);
         (
             device.create_shader_module(wgpu::ShaderModuleDescriptor {
                 label: Some("3D renderer translucent opaque pass shader module"),
@@ -144,13 +200,9 @@ pub(crate) fn create_pipeline(
     if pipeline.texture_mapping_enabled() {
         attribs.extend_from_slice(&TEXTURE_VERT_ATTRIBS);
     }
+    attribs.extend_from_slice(&EDGE_VERT_ATTRIBS);
 
-    let stencil_face_state = wgpu::StencilFaceState {
-        compare: wgpu::CompareFunction::NotEqual,
-        fail_op: wgpu::StencilOperation::Keep,
-        depth_fail_op: wgpu::StencilOperation::Keep,
-        pass_op: wgpu::StencilOperation::Replace,
-    };
+    let [opaque_stencil_state, trans_stencil_state] = trans_stencil_states();
 
     let opaque_desc = wgpu::RenderPipelineDescriptor {
         label: Some("3D renderer translucent pipeline opaque pass"),
@@ -176,7 +228,7 @@ pub(crate) fn create_pipeline(
             } else {
                 wgpu::CompareFunction::Less
             },
-            stencil: wgpu::StencilState::default(),
+            stencil: opaque_stencil_state,
             bias: wgpu::DepthBiasState::default(),
         }),
 
@@ -185,11 +237,18 @@ pub(crate) fn create_pipeline(
         fragment: Some(wgpu::FragmentState {
             module: &opaque_shader_module,
             entry_point: "fs_main",
-            targets: &[Some(wgpu::ColorTargetState {
-                format: wgpu::TextureFormat::Rgba8Unorm,
-                blend: None,
-                write_mask: wgpu::ColorWrites::ALL,
-            })],
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format: wgpu::TextureFormat::Rgba8Unorm,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                Some(wgpu::ColorTargetState {
+                    format: ATTRS_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+            ],
         }),
 
         multiview: None,
@@ -218,23 +277,29 @@ pub(crate) fn create_pipeline(
                 } else {
                     wgpu::CompareFunction::Less
                 },
-                stencil: wgpu::StencilState {
-                    front: stencil_face_state,
-                    back: stencil_face_state,
-                    read_mask: 0x7F,
-                    write_mask: 0x7F,
-                },
+                stencil: trans_stencil_state,
                 bias: wgpu::DepthBiasState::default(),
             }),
 
             fragment: Some(wgpu::FragmentState {
                 module: &trans_shader_module,
                 entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: wgpu::TextureFormat::Rgba8Unorm,
-                    blend: pipeline.alpha_blending_enabled().then_some(TRANS_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format: wgpu::TextureFormat::Rgba8Unorm,
+                        blend: pipeline.alpha_blending_enabled().then_some(TRANS_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: ATTRS_FORMAT,
+                        blend: None,
+                        write_mask: if pipeline.fog_enabled() {
+                            wgpu::ColorWrites::empty()
+                        } else {
+                            wgpu::ColorWrites::GREEN
+                        },
+                    }),
+                ],
             }),
 
             ..opaque_desc
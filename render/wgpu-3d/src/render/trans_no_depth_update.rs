@@ -1,8 +1,8 @@
 use super::{
-    trans::shader_module_src, COMMON_VERT_ATTRIBS, PRIMITIVE_STATE, TEXTURE_VERT_ATTRIBS,
-    TRANS_BLENDING,
+    trans::shader_module_src, trans_stencil_states, COMMON_VERT_ATTRIBS, EDGE_VERT_ATTRIBS,
+    PRIMITIVE_STATE, TEXTURE_VERT_ATTRIBS, TRANS_BLENDING,
 };
-use crate::{PipelineKey, Vertex};
+use crate::{PipelineKey, Vertex, ATTRS_FORMAT};
 use core::mem;
 
 pub(crate) fn create_pipeline(
@@ -12,6 +12,7 @@ pub(crate) fn create_pipeline(
     alpha_and_ref_bg_layout: &wgpu::BindGroupLayout,
     toon_bg_layout: &wgpu::BindGroupLayout,
     texture_bg_layout: &wgpu::BindGroupLayout,
+    shadow_bg_layout: &wgpu::BindGroupLayout,
 ) -> [wgpu::RenderPipeline; 2] {
     let mut bg_layouts = vec![id_bg_layout, alpha_and_ref_bg_layout];
 
@@ -24,6 +25,11 @@ pub(crate) fn create_pipeline(
         bg_layouts.push(texture_bg_layout);
     }
 
+    let shadow_bg_index = bg_layouts.len() as u32;
+    if pipeline.is_shadow() {
+        bg_layouts.push(shadow_bg_layout);
+    }
+
     let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some("3D renderer translucent no depth update pipeline layout"),
         bind_group_layouts: &bg_layouts,
@@ -31,7 +37,8 @@ pub(crate) fn create_pipeline(
     });
 
     let (opaque_shader_module, trans_shader_module) = {
-        let [opaque_src, trans_src] = shader_module_src(pipeline, texture_bg_index);
+        let [opaque_src, trans_src] =
+            shader_module_src(pipeline, texture_bg_index, shadow_bg_index);
         (
             device.create_shader_module(wgpu::ShaderModuleDescriptor {
                 label: Some("3D renderer translucent no depth update opaque pass shader module"),
@@ -51,13 +58,9 @@ pub(crate) fn create_pipeline(
     if pipeline.texture_mapping_enabled() {
         attribs.extend_from_slice(&TEXTURE_VERT_ATTRIBS);
     }
+    attribs.extend_from_slice(&EDGE_VERT_ATTRIBS);
 
-    let stencil_face_state = wgpu::StencilFaceState {
-        compare: wgpu::CompareFunction::NotEqual,
-        fail_op: wgpu::StencilOperation::Keep,
-        depth_fail_op: wgpu::StencilOperation::Keep,
-        pass_op: wgpu::StencilOperation::Replace,
-    };
+    let [opaque_stencil_state, trans_stencil_state] = trans_stencil_states();
 
     let opaque_desc = wgpu::RenderPipelineDescriptor {
         label: Some("3D renderer translucent no depth update pipeline opaque pass"),
@@ -83,7 +86,7 @@ pub(crate) fn create_pipeline(
             } else {
                 wgpu::CompareFunction::Less
             },
-            stencil: wgpu::StencilState::default(),
+            stencil: opaque_stencil_state,
             bias: wgpu::DepthBiasState::default(),
         }),
 
@@ -92,11 +95,18 @@ pub(crate) fn create_pipeline(
         fragment: Some(wgpu::FragmentState {
             module: &opaque_shader_module,
             entry_point: "fs_main",
-            targets: &[Some(wgpu::ColorTargetState {
-                format: wgpu::TextureFormat::Rgba8Unorm,
-                blend: None,
-                write_mask: wgpu::ColorWrites::ALL,
-            })],
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format: wgpu::TextureFormat::Rgba8Unorm,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                Some(wgpu::ColorTargetState {
+                    format: ATTRS_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+            ],
         }),
 
         multiview: None,
@@ -125,23 +135,29 @@ pub(crate) fn create_pipeline(
                 } else {
                     wgpu::CompareFunction::Less
                 },
-                stencil: wgpu::StencilState {
-                    front: stencil_face_state,
-                    back: stencil_face_state,
-                    read_mask: 0x7F,
-                    write_mask: 0x7F,
-                },
+                stencil: trans_stencil_state,
                 bias: wgpu::DepthBiasState::default(),
             }),
 
             fragment: Some(wgpu::FragmentState {
                 module: &trans_shader_module,
                 entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: wgpu::TextureFormat::Rgba8Unorm,
-                    blend: pipeline.alpha_blending_enabled().then_some(TRANS_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format: wgpu::TextureFormat::Rgba8Unorm,
+                        blend: pipeline.alpha_blending_enabled().then_some(TRANS_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: ATTRS_FORMAT,
+                        blend: None,
+                        write_mask: if pipeline.fog_enabled() {
+                            wgpu::ColorWrites::empty()
+                        } else {
+                            wgpu::ColorWrites::GREEN
+                        },
+                    }),
+                ],
             }),
 
             ..opaque_desc
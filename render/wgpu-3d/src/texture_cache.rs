@@ -0,0 +1,287 @@
+use crate::TextureKey;
+use ahash::AHashMap as HashMap;
+use core::{num::NonZeroU32, slice};
+use std::sync::Arc;
+use wgpu::util::DeviceExt;
+#[cfg(feature = "texture-replacement")]
+use {
+    ahash::AHashSet as HashSet,
+    std::{
+        fs::{self, File},
+        io::BufWriter,
+        path::{Path, PathBuf},
+    },
+};
+
+// Decoded textures that haven't been used for this many frames get evicted once no VRAM texture
+// refers to them anymore
+const MAX_UNUSED_FRAMES: u64 = 120;
+
+pub struct TextureContents {
+    pub view: wgpu::TextureView,
+    // (original width, original height, color scale, unused)
+    pub params_buffer: wgpu::Buffer,
+}
+
+#[cfg(feature = "texture-replacement")]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TexturePackConfig {
+    pub dump_dir: Option<PathBuf>,
+    pub replacement_dir: Option<PathBuf>,
+}
+
+pub struct TextureCache {
+    contents: HashMap<u64, (Arc<TextureContents>, u64)>,
+    cur_frame: u64,
+    #[cfg(feature = "texture-replacement")]
+    pack_config: TexturePackConfig,
+    #[cfg(feature = "texture-replacement")]
+    replacements: HashMap<u64, PathBuf>,
+    #[cfg(feature = "texture-replacement")]
+    dumped: HashSet<u64>,
+}
+
+// 64-bit FNV-1a over the decoded texels, which already depend on the texture data, palette and
+// format; this needs to be stable across runs, as the hashes are used in texture file names
+fn hash_texture(texture_key: TextureKey, decoded: &[u32]) -> u64 {
+    const PRIME: u64 = 0x0000_0100_0000_01B3;
+    let header = texture_key.width_shift() as u32
+        | (texture_key.height_shift() as u32) << 8
+        | (texture_key.format() as u32) << 16;
+    let mut hash = 0xCBF2_9CE4_8422_2325_u64;
+    for &word in [header].iter().chain(decoded) {
+        hash = (hash ^ word as u64).wrapping_mul(PRIME);
+    }
+    hash
+}
+
+fn create_contents(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    size: [u32; 2],
+    data: &[u8],
+    orig_size: [u32; 2],
+    color_scale: f32,
+) -> TextureContents {
+    let size = wgpu::Extent3d {
+        width: size[0],
+        height: size[1],
+        depth_or_array_layers: 1,
+    };
+
+    let raw = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("3D renderer texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+
+    queue.write_texture(
+        raw.as_image_copy(),
+        data,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: NonZeroU32::new(size.width << 2),
+            rows_per_image: None,
+        },
+        size,
+    );
+
+    let params = [orig_size[0] as f32, orig_size[1] as f32, color_scale, 0.0];
+    let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("3D renderer texture params"),
+        contents: unsafe { slice::from_raw_parts(params.as_ptr() as *const u8, 16) },
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    TextureContents {
+        view: raw.create_view(&wgpu::TextureViewDescriptor::default()),
+        params_buffer,
+    }
+}
+
+#[cfg(feature = "texture-replacement")]
+fn texture_file_name(hash: u64, width: u32, height: u32) -> String {
+    format!("tex_{width}x{height}_{hash:016x}.png")
+}
+
+#[cfg(feature = "texture-replacement")]
+fn parse_texture_file_name(path: &Path) -> Option<u64> {
+    if !path
+        .extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case("png"))
+    {
+        return None;
+    }
+    let stem = path.file_stem()?.to_str()?;
+    let hash = &stem[stem.rfind('_')? + 1..];
+    if hash.len() != 16 {
+        return None;
+    }
+    u64::from_str_radix(hash, 16).ok()
+}
+
+#[cfg(feature = "texture-replacement")]
+fn write_png(path: &Path, width: u32, height: u32, data: &[u8]) -> Result<(), png::EncodingError> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(data)
+}
+
+#[cfg(feature = "texture-replacement")]
+fn read_png(path: &Path) -> Option<(u32, u32, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(File::open(path).ok()?);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().ok()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).ok()?;
+    buffer.truncate(info.buffer_size());
+    let data = match info.color_type {
+        png::ColorType::Rgba => buffer,
+        png::ColorType::Rgb => buffer
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 0xFF])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buffer
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => buffer.iter().flat_map(|&p| [p, p, p, 0xFF]).collect(),
+        png::ColorType::Indexed => return None,
+    };
+    Some((info.width, info.height, data))
+}
+
+impl TextureCache {
+    pub fn new() -> Self {
+        TextureCache {
+            contents: HashMap::default(),
+            cur_frame: 0,
+            #[cfg(feature = "texture-replacement")]
+            pack_config: TexturePackConfig::default(),
+            #[cfg(feature = "texture-replacement")]
+            replacements: HashMap::default(),
+            #[cfg(feature = "texture-replacement")]
+            dumped: HashSet::default(),
+        }
+    }
+
+    pub fn start_frame(&mut self) {
+        self.cur_frame += 1;
+        let cur_frame = self.cur_frame;
+        self.contents.retain(|_, (contents, last_used)| {
+            Arc::strong_count(contents) > 1 || cur_frame - *last_used <= MAX_UNUSED_FRAMES
+        });
+    }
+
+    // Returns whether the config changed, in which case all cached textures are dropped
+    #[cfg(feature = "texture-replacement")]
+    pub fn set_pack_config(&mut self, config: TexturePackConfig) -> bool {
+        if config == self.pack_config {
+            return false;
+        }
+        self.replacements.clear();
+        if let Some(entries) = config
+            .replacement_dir
+            .as_ref()
+            .and_then(|dir| fs::read_dir(dir).ok())
+        {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if let Some(hash) = parse_texture_file_name(&path) {
+                    self.replacements.insert(hash, path);
+                }
+            }
+        }
+        self.dumped.clear();
+        self.contents.clear();
+        self.pack_config = config;
+        true
+    }
+
+    #[cfg(feature = "texture-replacement")]
+    fn dump(&mut self, hash: u64, width: u32, height: u32, decoded: &[u32]) {
+        let dump_dir = match &self.pack_config.dump_dir {
+            Some(dump_dir) => dump_dir,
+            None => return,
+        };
+        if !self.dumped.insert(hash) {
+            return;
+        }
+        let path = dump_dir.join(texture_file_name(hash, width, height));
+        if path.exists() || fs::create_dir_all(dump_dir).is_err() {
+            return;
+        }
+        let data = decoded
+            .iter()
+            .flat_map(|texel| texel.to_le_bytes().map(|c| c << 3 | c >> 2))
+            .collect::<Vec<_>>();
+        let _ = write_png(&path, width, height, &data);
+    }
+
+    #[cfg(feature = "texture-replacement")]
+    fn load_replacement(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        hash: u64,
+        orig_size: [u32; 2],
+    ) -> Option<TextureContents> {
+        let (width, height, data) = read_png(self.replacements.get(&hash)?)?;
+        let max_size = device.limits().max_texture_dimension_2d;
+        if width == 0 || height == 0 || width > max_size || height > max_size {
+            return None;
+        }
+        Some(create_contents(
+            device,
+            queue,
+            [width, height],
+            &data,
+            orig_size,
+            1.0,
+        ))
+    }
+
+    pub fn get_or_create(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture_key: TextureKey,
+        decoded: &[u32],
+    ) -> Arc<TextureContents> {
+        let width = 8 << texture_key.width_shift();
+        let height = 8 << texture_key.height_shift();
+        let hash = hash_texture(texture_key, decoded);
+
+        if let Some((contents, last_used)) = self.contents.get_mut(&hash) {
+            *last_used = self.cur_frame;
+            return Arc::clone(contents);
+        }
+
+        #[cfg(feature = "texture-replacement")]
+        self.dump(hash, width, height, decoded);
+
+        #[cfg(feature = "texture-replacement")]
+        let replacement = self.load_replacement(device, queue, hash, [width, height]);
+        #[cfg(not(feature = "texture-replacement"))]
+        let replacement = None;
+
+        let contents = Arc::new(replacement.unwrap_or_else(|| {
+            create_contents(
+                device,
+                queue,
+                [width, height],
+                unsafe { slice::from_raw_parts(decoded.as_ptr() as *const u8, decoded.len() * 4) },
+                [width, height],
+                255.0 / 31.0,
+            )
+        }));
+        self.contents
+            .insert(hash, (Arc::clone(&contents), self.cur_frame));
+        contents
+    }
+}
@@ -1,3 +1,5 @@
+#[cfg(feature = "texture-replacement")]
+use crate::TexturePackConfig;
 use crate::{GxData, Renderer};
 use dust_core::{
     gpu::{
@@ -200,6 +202,8 @@ impl AccelRendererRx for Rx {
 
 pub struct FrontendChannels {
     shared_data: Arc<SharedData>,
+    #[cfg(feature = "texture-replacement")]
+    texture_pack_config_tx: crossbeam_channel::Sender<TexturePackConfig>,
 }
 
 impl FrontendChannels {
@@ -208,6 +212,11 @@ impl FrontendChannels {
             .resolution_scale_shift
             .store(value, Ordering::Relaxed);
     }
+
+    #[cfg(feature = "texture-replacement")]
+    pub fn set_texture_pack_config(&self, config: TexturePackConfig) {
+        let _ = self.texture_pack_config_tx.send(config);
+    }
 }
 
 pub struct Rx2dData {
@@ -242,6 +251,8 @@ pub fn init(
     let last_submitted_frame: Arc<(AtomicU64, RwLock<Option<thread::Thread>>)> =
         Arc::new((AtomicU64::new(0), RwLock::new(None)));
     let last_submitted_frame_ = Arc::clone(&last_submitted_frame);
+    #[cfg(feature = "texture-replacement")]
+    let (texture_pack_config_tx, texture_pack_config_rx) = crossbeam_channel::unbounded();
 
     (
         Tx {
@@ -274,7 +285,12 @@ pub fn init(
                                     let scanline = &mut unsafe {
                                         &mut *shared_data.capture_scanline_buffer.get()
                                     }[y as usize];
-                                    raw_soft_renderer.render_line(y, scanline, rendering_data);
+                                    raw_soft_renderer.render_line(
+                                        y,
+                                        scanline,
+                                        &mut [],
+                                        rendering_data,
+                                    );
                                     let _ =
                                         shared_data.capture_processing_scanline.compare_exchange(
                                             y,
@@ -297,6 +313,11 @@ pub fn init(
                                             );
                                     }
 
+                                    #[cfg(feature = "texture-replacement")]
+                                    while let Ok(config) = texture_pack_config_rx.try_recv() {
+                                        renderer.set_texture_pack_config(config);
+                                    }
+
                                     let command_buffer =
                                         renderer.render_frame(&frame.rendering_data);
                                     renderer.queue().submit([command_buffer]);
@@ -321,6 +342,8 @@ pub fn init(
         },
         FrontendChannels {
             shared_data: shared_data_,
+            #[cfg(feature = "texture-replacement")]
+            texture_pack_config_tx,
         },
         Rx2dData {
             color_output_view,
@@ -0,0 +1,138 @@
+use std::{env, ffi::OsString, fmt, path::PathBuf, process};
+
+static USAGE: &str = "\
+Usage: dust-replay-3d [OPTIONS] <RECORDING> <OUTPUT>
+
+Renders a 3D frame recorded by dust-desktop to a PNG file, by replaying its GX commands through
+the geometry engine, starting from the state it had at the start of the frame. If the recorded
+commands don't reach the frame's SWAP_BUFFERS command, the vertex and polygon RAM contents
+captured when the frame's buffers were swapped are rendered instead.
+
+Options:
+    --renderer <RENDERER> The renderer to replay the frame with (soft, wgpu) [default: soft]
+    --scale <SHIFT>       Render at `1 << SHIFT` times the native resolution (0-2) [default: 0]
+    --snapshot            Render the captured vertex and polygon RAM contents without replaying
+                          the recorded GX commands
+    --commands            Print the recorded GX commands
+    -h, --help            Print this help message";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Renderer {
+    Soft,
+    Wgpu,
+}
+
+pub struct Args {
+    pub recording_path: PathBuf,
+    pub output_path: PathBuf,
+    pub renderer: Renderer,
+    pub resolution_scale_shift: u8,
+    pub from_snapshot: bool,
+    pub print_commands: bool,
+}
+
+pub enum Error {
+    MissingValue(&'static str),
+    InvalidValue(&'static str, OsString),
+    UnknownOption(OsString),
+    UnexpectedArg(OsString),
+    MissingArg(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MissingValue(option) => write!(f, "Missing value for `{option}`"),
+            Error::InvalidValue(option, value) => {
+                write!(
+                    f,
+                    "Invalid value for `{option}`: `{}`",
+                    value.to_string_lossy()
+                )
+            }
+            Error::UnknownOption(option) => {
+                write!(f, "Unknown option `{}`", option.to_string_lossy())
+            }
+            Error::UnexpectedArg(arg) => {
+                write!(f, "Unexpected argument `{}`", arg.to_string_lossy())
+            }
+            Error::MissingArg(arg) => write!(f, "Missing argument `{arg}`\n\n{USAGE}"),
+        }
+    }
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, Error> {
+        let mut recording_path = None;
+        let mut output_path = None;
+        let mut renderer = Renderer::Soft;
+        let mut resolution_scale_shift = 0;
+        let mut from_snapshot = false;
+        let mut print_commands = false;
+        let mut args = env::args_os().skip(1);
+
+        macro_rules! value {
+            ($option: literal) => {
+                args.next().ok_or(Error::MissingValue($option))?
+            };
+        }
+
+        while let Some(arg) = args.next() {
+            match arg.to_str() {
+                Some("-h" | "--help") => {
+                    println!("{USAGE}");
+                    process::exit(0);
+                }
+
+                Some("--renderer") => {
+                    let value = value!("--renderer");
+                    renderer = match value.to_str() {
+                        Some("soft") => Renderer::Soft,
+                        Some("wgpu") => Renderer::Wgpu,
+                        _ => return Err(Error::InvalidValue("--renderer", value)),
+                    };
+                }
+
+                Some("--scale") => {
+                    let value = value!("--scale");
+                    resolution_scale_shift = value
+                        .to_str()
+                        .and_then(|value| value.parse().ok())
+                        .filter(|&shift| shift <= 2)
+                        .ok_or_else(|| Error::InvalidValue("--scale", value.clone()))?;
+                }
+
+                Some("--snapshot") => {
+                    from_snapshot = true;
+                }
+
+                Some("--commands") => {
+                    print_commands = true;
+                }
+
+                Some(option) if option.starts_with('-') => {
+                    return Err(Error::UnknownOption(arg));
+                }
+
+                _ => {
+                    if recording_path.is_none() {
+                        recording_path = Some(PathBuf::from(arg));
+                    } else if output_path.is_none() {
+                        output_path = Some(PathBuf::from(arg));
+                    } else {
+                        return Err(Error::UnexpectedArg(arg));
+                    }
+                }
+            }
+        }
+
+        Ok(Args {
+            recording_path: recording_path.ok_or(Error::MissingArg("RECORDING"))?,
+            output_path: output_path.ok_or(Error::MissingArg("OUTPUT"))?,
+            renderer,
+            resolution_scale_shift,
+            from_snapshot,
+            print_commands,
+        })
+    }
+}
@@ -0,0 +1,219 @@
+#![feature(new_uninit)]
+
+mod cli;
+
+use dust_core::{
+    gpu::{
+        engine_3d::{Engine3d, Polygon, Recording, ScreenVertex},
+        Scanline, SCREEN_HEIGHT, SCREEN_WIDTH,
+    },
+    utils::PersistentReadSavestate,
+};
+use std::{fs, fs::File, io::BufWriter, num::NonZeroU32, path::Path, process, sync::Arc};
+
+fn render_soft(
+    recording: &Recording,
+    vert_ram: &[ScreenVertex],
+    poly_ram: &[Polygon],
+    resolution_scale_shift: u8,
+) -> Vec<u8> {
+    let mut data: Box<dust_soft_3d::RenderingData> = unsafe { Box::new_zeroed().assume_init() };
+    data.prepare(vert_ram, poly_ram, &recording.state);
+    data.copy_vram(&recording.texture, &recording.tex_pal, &recording.state);
+
+    let mut renderer = dust_soft_3d::Renderer::new();
+    renderer.set_resolution_scale_shift(resolution_scale_shift);
+    renderer.start_frame(&data);
+
+    let width = SCREEN_WIDTH << resolution_scale_shift;
+    let mut scanline = Scanline([0; SCREEN_WIDTH]);
+    let mut hi_res_scanlines = vec![0; width << resolution_scale_shift];
+    let mut pixels = Vec::with_capacity(width * (SCREEN_HEIGHT << resolution_scale_shift));
+    for y in 0..SCREEN_HEIGHT {
+        renderer.render_line(y as u8, &mut scanline, &mut hi_res_scanlines, &data);
+        if resolution_scale_shift == 0 {
+            pixels.extend_from_slice(&scanline.0);
+        } else {
+            pixels.extend_from_slice(&hi_res_scanlines);
+        }
+    }
+
+    pixels
+        .into_iter()
+        .flat_map(|pixel| {
+            let r = pixel as u8 & 0x3F;
+            let g = (pixel >> 6) as u8 & 0x3F;
+            let b = (pixel >> 12) as u8 & 0x3F;
+            let a = (pixel >> 18) as u8 & 0x1F;
+            [
+                r << 2 | r >> 4,
+                g << 2 | g >> 4,
+                b << 2 | b >> 4,
+                a << 3 | a >> 2,
+            ]
+        })
+        .collect()
+}
+
+fn render_wgpu(
+    recording: &Recording,
+    vert_ram: &[ScreenVertex],
+    poly_ram: &[Polygon],
+    resolution_scale_shift: u8,
+) -> Vec<u8> {
+    let instance = wgpu::Instance::new(wgpu::Backends::all());
+    let adapter =
+        futures_executor::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            compatible_surface: None,
+        }))
+        .expect("couldn't create graphics adapter");
+    let (device, queue) = futures_executor::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: None,
+            features: wgpu::Features::DEPTH32FLOAT_STENCIL8,
+            limits: wgpu::Limits {
+                max_texture_dimension_2d: 4096,
+                ..wgpu::Limits::downlevel_webgl2_defaults()
+            },
+        },
+        None,
+    ))
+    .expect("couldn't open connection to graphics device");
+    let device = Arc::new(device);
+    let queue = Arc::new(queue);
+
+    let mut frame: Box<dust_wgpu_3d::FrameData> = unsafe { Box::new_zeroed().assume_init() };
+    frame.gx.prepare(vert_ram, poly_ram, &recording.state);
+    frame.rendering.prepare(&recording.state);
+    frame
+        .rendering
+        .copy_vram(&recording.texture, &recording.tex_pal, 0xF, 0x3F);
+
+    let mut renderer = dust_wgpu_3d::Renderer::new(
+        Arc::clone(&device),
+        Arc::clone(&queue),
+        resolution_scale_shift,
+    );
+    let render_command_buffer = renderer.render_frame(&frame);
+
+    // The row size is always a multiple of `COPY_BYTES_PER_ROW_ALIGNMENT`, as the width is at
+    // least 256 pixels
+    let width = (SCREEN_WIDTH << resolution_scale_shift) as u32;
+    let height = (SCREEN_HEIGHT << resolution_scale_shift) as u32;
+    let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("3D replay output"),
+        size: (width * height * 4) as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("3D replay output copy"),
+    });
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            texture: renderer.output_texture(),
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &output_buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(width * 4),
+                rows_per_image: None,
+            },
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    queue.submit([render_command_buffer, encoder.finish()]);
+
+    let output_slice = output_buffer.slice(..);
+    output_slice.map_async(wgpu::MapMode::Read, |result| {
+        result.expect("couldn't map 3D replay output buffer");
+    });
+    device.poll(wgpu::Maintain::Wait);
+    let pixels = output_slice.get_mapped_range().to_vec();
+    output_buffer.unmap();
+    pixels
+}
+
+fn write_png(path: &Path, width: u32, height: u32, data: &[u8]) -> Result<(), png::EncodingError> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(data)
+}
+
+fn main() {
+    let args = match cli::Args::parse() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(1);
+        }
+    };
+
+    let recording = match fs::read(&args.recording_path) {
+        Ok(contents) => PersistentReadSavestate::new(&contents)
+            .and_then(|mut save| Recording::load(&mut save).map_err(drop)),
+        Err(err) => {
+            eprintln!("Couldn't read recording: {err}");
+            process::exit(1);
+        }
+    };
+    let recording = match recording {
+        Ok(recording) => recording,
+        Err(()) => {
+            eprintln!("Invalid recording");
+            process::exit(1);
+        }
+    };
+
+    if args.print_commands {
+        for command in &recording.commands {
+            println!("{:02X} {:08X}", command.command, command.param);
+        }
+    }
+
+    let mut engine_3d = Engine3d::new_replay(&recording.geometry);
+    let (vert_ram, poly_ram) = if args.from_snapshot {
+        (&recording.vert_ram[..], &recording.poly_ram[..])
+    } else if engine_3d.replay_commands(&recording.commands) {
+        (
+            &engine_3d.vert_ram()[..engine_3d.vert_ram_level() as usize],
+            &engine_3d.poly_ram()[..engine_3d.poly_ram_level() as usize],
+        )
+    } else {
+        eprintln!(
+            "Recorded commands don't end with SWAP_BUFFERS, rendering from the vertex and polygon \
+             RAM snapshot instead"
+        );
+        (&recording.vert_ram[..], &recording.poly_ram[..])
+    };
+
+    let pixels = match args.renderer {
+        cli::Renderer::Soft => {
+            render_soft(&recording, vert_ram, poly_ram, args.resolution_scale_shift)
+        }
+        cli::Renderer::Wgpu => {
+            render_wgpu(&recording, vert_ram, poly_ram, args.resolution_scale_shift)
+        }
+    };
+
+    if let Err(err) = write_png(
+        &args.output_path,
+        (SCREEN_WIDTH << args.resolution_scale_shift) as u32,
+        (SCREEN_HEIGHT << args.resolution_scale_shift) as u32,
+        &pixels,
+    ) {
+        eprintln!("Couldn't write output image: {err}");
+        process::exit(1);
+    }
+}